@@ -0,0 +1,148 @@
+mod runner;
+
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Parser;
+use common::agent::{AgentMessage, DaemonMessage, HEARTBEAT_INTERVAL_SECS};
+use common::{encode_frame, FrameReader};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+
+// Wait before reconnecting to the daemon, doubled after each failed attempt up to the maximum
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+// Reports (output, exits) waiting to be sent to the daemon; runs wait while it is full
+const REPORT_QUEUE: usize = 256;
+
+/// Runs the jobs a lunasched daemon sends it (jobs with a `target`) and reports their output
+/// and exit status back, over mutual TLS
+#[derive(Parser)]
+#[command(name = "lunasched-agent", author, version, about, long_about = None)]
+struct Args {
+    /// Agent listener of the daemon (agents.bind), as host or host:port; give several daemons
+    /// sharing jobs (`ha`) to connect to whichever leads
+    #[arg(long, env = "LUNASCHED_AGENT_DAEMON", required = true, value_delimiter = ',')]
+    daemon: Vec<String>,
+    /// Name jobs target this agent by (default: the host name)
+    #[arg(long, env = "LUNASCHED_AGENT_NAME")]
+    name: Option<String>,
+    /// Label jobs can target instead of the name, shared by agents that can run the same jobs
+    /// (repeatable)
+    #[arg(long = "label", env = "LUNASCHED_AGENT_LABELS", value_delimiter = ',')]
+    labels: Vec<String>,
+    /// This agent's certificate (PEM), signed by the CA the daemon trusts (agents.ca)
+    #[arg(long, env = "LUNASCHED_AGENT_CERT")]
+    cert: String,
+    /// Its private key (PEM)
+    #[arg(long, env = "LUNASCHED_AGENT_KEY")]
+    key: String,
+    /// CA (PEM) that signed the daemon's certificate
+    #[arg(long, env = "LUNASCHED_AGENT_CA")]
+    ca: String,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let tls = TlsConnector::from(Arc::new(common::tls::client_config(&args.cert, &args.key, &args.ca)?));
+    let name = args.name.clone().unwrap_or_else(host_name);
+    log::info!("Starting lunasched-agent v{} as {}", env!("CARGO_PKG_VERSION"), name);
+
+    let mut delay = RECONNECT_DELAY;
+    for daemon in args.daemon.iter().cycle() {
+        match session(&tls, daemon, &name, &args.labels).await {
+            Ok(()) => {
+                log::warn!("Daemon {} closed the connection", daemon);
+                delay = RECONNECT_DELAY;
+            }
+            Err(e) => log::warn!("Daemon {}: {}", daemon, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+    Ok(())
+}
+
+// Register with a daemon and run what it sends until the connection ends. Ok once registered.
+async fn session(tls: &TlsConnector, daemon: &str, name: &str, labels: &[String]) -> anyhow::Result<()> {
+    let (host, port) = match daemon.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| anyhow::anyhow!("invalid port in {}", daemon))?),
+        None => (daemon, common::DEFAULT_AGENT_PORT),
+    };
+    let tcp = TcpStream::connect((host, port)).await?;
+    tcp.set_nodelay(true)?;
+    let stream = tls.connect(ServerName::try_from(host.to_string())?, tcp).await?;
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut frames = FrameReader::default();
+    let mut buf = vec![0; 8192];
+
+    send(&mut writer, &AgentMessage::Register {
+        name: name.to_string(),
+        labels: labels.to_vec(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }).await?;
+    match read_message(&mut reader, &mut frames, &mut buf).await? {
+        Some(DaemonMessage::Registered) => log::info!("Registered with {} as {}", daemon, name),
+        Some(DaemonMessage::Refused(reason)) => return Err(anyhow::anyhow!("refused: {}", reason)),
+        Some(other) => return Err(anyhow::anyhow!("unexpected answer to registering: {:?}", other)),
+        None => return Err(anyhow::anyhow!("connection closed while registering")),
+    }
+
+    // Runs report through the queue; only this task writes to the connection
+    let (reports, mut queue) = mpsc::channel(REPORT_QUEUE);
+    let running = runner::Running::default();
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
+    let result = loop {
+        tokio::select! {
+            message = read_message(&mut reader, &mut frames, &mut buf) => match message {
+                Ok(Some(DaemonMessage::Run(run))) => runner::spawn(run, reports.clone(), running.clone()),
+                Ok(Some(other)) => log::debug!("Ignoring {:?} from the daemon", other),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            },
+            Some(report) = queue.recv() => {
+                if let Err(e) = send(&mut writer, &report).await {
+                    break Err(e.into());
+                }
+            },
+            _ = heartbeat.tick() => {
+                if let Err(e) = send(&mut writer, &AgentMessage::Heartbeat).await {
+                    break Err(e.into());
+                }
+            },
+        }
+    };
+    // The daemon records the runs it can no longer hear from as failed and may retry them;
+    // they must not go on alongside the retries
+    running.kill_all();
+    result
+}
+
+// Next message from the daemon; None once it closed the connection
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R, frames: &mut FrameReader, buf: &mut [u8]) -> anyhow::Result<Option<DaemonMessage>> {
+    loop {
+        if let Some(message) = frames.next()? {
+            return Ok(Some(message));
+        }
+        let n = reader.read(buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        frames.push(&buf[..n]);
+    }
+}
+
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, message: &AgentMessage) -> std::io::Result<()> {
+    writer.write_all(&encode_frame(message)?).await
+}
+
+fn host_name() -> String {
+    nix::unistd::gethostname().ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_else(|| "agent".to_string())
+}
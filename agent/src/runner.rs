@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use common::agent::{AgentMessage, OutputStream, RemoteExit, RemoteRun};
+use common::script::ScriptFile;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::{Pid, Uid, User};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::sync::mpsc;
+
+// PATH of a job's environment, as the daemon sets it
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+// Between SIGTERM and SIGKILL for a run past its timeout
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+// Longest line of output sent in one message
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+
+// Scripts of script jobs, written out while they run
+const SCRIPT_DIR: &str = "/var/run/lunasched-agent/scripts";
+
+/// Process groups of the runs in progress, by execution ID
+#[derive(Clone, Default)]
+pub struct Running(Arc<Mutex<HashMap<String, u32>>>);
+
+impl Running {
+    pub fn kill_all(&self) {
+        for (execution_id, pgid) in self.0.lock().unwrap().drain() {
+            log::warn!("Killing execution {}: the daemon that sent it is gone", execution_id);
+            signal_group(pgid, Signal::SIGKILL);
+        }
+    }
+}
+
+/// Run `run` as the daemon would run the job locally, reporting its output as it comes and
+/// then how it ended
+pub fn spawn(run: RemoteRun, reports: mpsc::Sender<AgentMessage>, running: Running) {
+    tokio::spawn(async move {
+        let exit = execute(&run, &reports, &running).await;
+        running.0.lock().unwrap().remove(&run.execution_id);
+        log::info!("Execution {} of job {} ended: {:?}", run.execution_id, run.job_name, exit);
+        let _ = reports.send(AgentMessage::Finished { execution_id: run.execution_id, exit }).await;
+    });
+}
+
+async fn execute(run: &RemoteRun, reports: &mpsc::Sender<AgentMessage>, running: &Running) -> RemoteExit {
+    let user = match User::from_name(&run.run_as) {
+        Ok(Some(user)) => user,
+        Ok(None) => return RemoteExit::NotStarted(format!("unknown user '{}'", run.run_as)),
+        Err(e) => return RemoteExit::NotStarted(format!("failed to look up user '{}': {}", run.run_as, e)),
+    };
+    let groups = match CString::new(run.run_as.as_str()).map_err(|e| e.to_string())
+        .and_then(|name| nix::unistd::getgrouplist(&name, user.gid).map_err(|e| e.to_string())) {
+        Ok(groups) => groups,
+        Err(e) => return RemoteExit::NotStarted(format!("failed to look up the groups of '{}': {}", run.run_as, e)),
+    };
+    let working_dir = run.working_dir.clone().unwrap_or_else(|| PathBuf::from("/tmp"));
+    if !working_dir.is_dir() {
+        return RemoteExit::NotStarted(format!("working directory {} does not exist", working_dir.display()));
+    }
+
+    // A script job's script is written out for the run, and run with the arguments sent
+    let script = match run.script {
+        Some(ref body) => match ScriptFile::write(SCRIPT_DIR, &run.execution_id, body, user.uid, user.gid) {
+            Ok(script) => Some(script),
+            Err(e) => return RemoteExit::NotStarted(format!("failed to write out the script: {}", e)),
+        },
+        None => None,
+    };
+    let command = match script {
+        Some(ref script) if run.command.is_empty() => script.path().display().to_string(),
+        Some(ref script) => format!("{} {}", script.path().display(), run.command),
+        None => run.command.clone(),
+    };
+
+    let mut cmd = tokio::process::Command::new("/bin/sh");
+    cmd.arg("-c").arg(&command);
+    cmd.env_clear();
+    cmd.envs([
+        ("HOME", user.dir.display().to_string()),
+        ("USER", user.name.clone()),
+        ("LOGNAME", user.name.clone()),
+        ("SHELL", user.shell.display().to_string()),
+        ("PATH", DEFAULT_PATH.to_string()),
+    ]);
+    cmd.envs(&run.env);
+    cmd.current_dir(&working_dir);
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let umask = run.umask.map(nix::sys::stat::Mode::from_bits_truncate);
+    let switch = (user.uid != Uid::effective()).then_some((user.uid, user.gid, groups));
+    // Only syscalls on values prepared before the fork, which keeps this fork-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            nix::unistd::setsid()?;
+            if let Some(mode) = umask {
+                nix::sys::stat::umask(mode);
+            }
+            if let Some((uid, gid, ref groups)) = switch {
+                nix::unistd::setgroups(groups)?;
+                nix::unistd::setgid(gid)?;
+                nix::unistd::setuid(uid)?;
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => return RemoteExit::NotStarted(e.to_string()),
+    };
+    // The child called setsid, so it leads a process group of the same ID
+    let pid = child.id().unwrap_or_default();
+    running.0.lock().unwrap().insert(run.execution_id.clone(), pid);
+    log::info!("Running execution {} of job {} as {}, pid {}", run.execution_id, run.job_name, run.run_as, pid);
+    let _ = reports.send(AgentMessage::Started { execution_id: run.execution_id.clone(), pid }).await;
+
+    let stdout = forward(child.stdout.take(), OutputStream::Stdout, &run.execution_id, reports);
+    let stderr = forward(child.stderr.take(), OutputStream::Stderr, &run.execution_id, reports);
+    let waited = match run.timeout_seconds {
+        Some(timeout) => match tokio::time::timeout(Duration::from_secs(timeout), child.wait()).await {
+            Ok(waited) => waited,
+            Err(_) => {
+                log::warn!("Execution {} of job {} exceeded its timeout of {}s, terminating it", run.execution_id, run.job_name, timeout);
+                signal_group(pid, Signal::SIGTERM);
+                tokio::time::sleep(KILL_GRACE).await;
+                signal_group(pid, Signal::SIGKILL);
+                child.wait().await
+            }
+        },
+        None => child.wait().await,
+    };
+    // All output goes out before the exit
+    let _ = stdout.await;
+    let _ = stderr.await;
+    match waited {
+        Ok(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => RemoteExit::Exited(code),
+            (None, Some(signal)) => RemoteExit::Signaled(signal),
+            (None, None) => RemoteExit::Exited(-1),
+        },
+        Err(e) => RemoteExit::NotStarted(format!("failed to wait: {}", e)),
+    }
+}
+
+// Send a child pipe to the daemon line by line
+fn forward<R>(pipe: Option<R>, stream: OutputStream, execution_id: &str, reports: &mpsc::Sender<AgentMessage>) -> tokio::task::JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let (execution_id, reports) = (execution_id.to_string(), reports.clone());
+    tokio::spawn(async move {
+        let mut reader = match pipe {
+            Some(pipe) => BufReader::new(pipe),
+            None => return,
+        };
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            // A line without a newline in sight is sent in pieces rather than held whole
+            match (&mut reader).take(MAX_LINE_BYTES).read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line).into_owned();
+                    let _ = reports.send(AgentMessage::Output { execution_id: execution_id.clone(), stream, text }).await;
+                }
+            }
+        }
+    })
+}
+
+fn signal_group(pgid: u32, signal: Signal) {
+    let _ = kill(Pid::from_raw(-(pgid as i32)), signal);
+}
@@ -0,0 +1,93 @@
+use common::bundle::BUNDLE_EXTENSION;
+use common::declarative::{self, JobFile, PlanAction};
+use common::{Job, Request, Response, SignedBundle};
+use crate::DaemonStream;
+
+/// Source name recorded on jobs applied from a file (its file name)
+/// The source jobs read from `path` are tagged with: its canonical path, so that files of the
+/// same name elsewhere don't share (and prune) each other's jobs
+pub(crate) fn file_source(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+pub async fn run(stream: &mut DaemonStream, path: &str, source: Option<String>, prune: bool, dry_run: bool) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+
+    // Signed bundles are verified here for early feedback; the daemon checks them against its trusted keys
+    let (source, job_file, bundle) = if path.ends_with(BUNDLE_EXTENSION) {
+        if source.is_some() {
+            return Err(anyhow::anyhow!("A bundle's source is the one it was signed with; --source can't change it"));
+        }
+        let bundle = SignedBundle::from_json(&contents)?;
+        bundle.verify()?;
+        println!("Bundle {} signed by {}", bundle.source, bundle.public_key);
+        (bundle.source.clone(), bundle.job_file()?, Some(bundle))
+    } else {
+        (source.unwrap_or_else(|| file_source(path)), JobFile::parse(path, &contents)?, None)
+    };
+    let desired = job_file.into_jobs(&source)?;
+
+    // Jobs as stored, without the tag defaults `list` merges in
+    let current: Vec<Job> = match crate::send_request(stream, &Request::ExportJobs { tag: Some(declarative::source_tag(&source)) }).await? {
+        Response::JobList(jobs) => jobs,
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+
+    let actions = declarative::plan(&current, desired.clone(), &source, prune);
+    if actions.is_empty() {
+        println!("No changes. Jobs are up to date with {}.", path);
+        return Ok(());
+    }
+
+    let (mut creates, mut updates, mut deletes) = (0, 0, 0);
+    println!("Plan for {}:", path);
+    for action in &actions {
+        match action {
+            PlanAction::Create(job) => {
+                creates += 1;
+                println!("  + create  {}", job.name);
+            }
+            PlanAction::Update { job, changes } => {
+                updates += 1;
+                println!("  ~ update  {} ({})", job.name, changes.join(", "));
+            }
+            PlanAction::Delete(id) => {
+                deletes += 1;
+                println!("  - delete  {}", id);
+            }
+        }
+    }
+    println!("Plan: {} to create, {} to update, {} to delete.", creates, updates, deletes);
+
+    if dry_run {
+        println!("Dry run: no changes applied.");
+        return Ok(());
+    }
+
+    // Applied by the daemon in one go: either every change is made or none is
+    let req = match bundle {
+        Some(bundle) => Request::ApplyBundle { bundle, prune },
+        None => Request::ApplyJobs { source, jobs: desired, prune },
+    };
+    match crate::send_request(stream, &req).await? {
+        Response::Applied(report) => {
+            for id in &report.created {
+                println!("create {}: done", id);
+            }
+            for id in &report.updated {
+                println!("update {}: done", id);
+            }
+            for id in &report.deleted {
+                println!("delete {}: done", id);
+            }
+            println!("Apply complete.");
+            Ok(())
+        }
+        Response::Error(e) => Err(anyhow::anyhow!(e)),
+        other => Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
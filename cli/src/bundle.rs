@@ -0,0 +1,53 @@
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use common::bundle::{self, SignedBundle, SigningKey, BUNDLE_EXTENSION};
+use crate::BundleCommands;
+
+pub fn run(action: BundleCommands) -> anyhow::Result<()> {
+    match action {
+        BundleCommands::Keygen { out } => keygen(&out),
+        BundleCommands::Sign { file, key, out } => sign(&file, &key, out),
+    }
+}
+
+fn keygen(out: &str) -> anyhow::Result<()> {
+    let mut secret = [0u8; 32];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut secret)?;
+    let key = SigningKey::from_bytes(&secret);
+
+    // The signing key must stay private to whoever distributes bundles
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(out)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", out, e))?;
+    writeln!(file, "{}", bundle::encode_signing_key(&key))?;
+
+    let public_key = bundle::encode_public_key(&key.verifying_key());
+    let pub_path = format!("{}.pub", out);
+    std::fs::write(&pub_path, format!("{}\n", public_key))?;
+
+    println!("Signing key written to {}", out);
+    println!("Public key written to {}", pub_path);
+    println!("Add it to `trusted_keys` in the daemon config of hosts that should accept your bundles:");
+    println!("  {}", public_key);
+    Ok(())
+}
+
+fn sign(path: &str, key_path: &str, out: Option<String>) -> anyhow::Result<()> {
+    let key_contents = std::fs::read_to_string(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", key_path, e))?;
+    let key = bundle::decode_signing_key(&key_contents)?;
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    let source = crate::apply::file_source(path);
+    let signed = SignedBundle::sign(&source, &contents, &key)?;
+    let job_count = signed.job_file()?.jobs.len();
+
+    let out = out.unwrap_or_else(|| format!("{}{}", path, BUNDLE_EXTENSION));
+    std::fs::write(&out, serde_json::to_string_pretty(&signed)?)?;
+    println!("Signed {} job(s) from {} into {}", job_count, path, out);
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+// Subcommands whose first argument is a job ID, completed from the daemon's jobs
+const JOB_ID_COMMANDS: [&str; 6] = ["start", "remove", "get", "history", "rename", "clone"];
+
+// Global options taking a value, which may come before the subcommand
+const VALUE_OPTIONS: [&str; 5] = ["--host", "--port", "--token", "--socket", "--output"];
+
+/// Print the completion script for a shell. Bash, zsh and fish also complete job IDs,
+/// asking the daemon through the hidden `__complete-job-ids` command.
+pub fn print(shell: Shell) {
+    let mut cmd = crate::Cli::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, &name, &mut std::io::stdout());
+
+    match shell {
+        Shell::Bash => print!(r#"
+_{name}_job_ids() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" command="" i
+    # The subcommand is the first word that is neither an option nor an option's value; a job
+    # ID is completed while no other word follows it
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        case "${{COMP_WORDS[i]}}" in
+            {options})
+                if [[ "${{COMP_WORDS[i+1]}}" == "=" ]]; then (( i += 2 )); else (( i++ )); fi
+                ;;
+            -*) ;;
+            *)
+                if [[ -n $command ]]; then command=""; break; fi
+                command="${{COMP_WORDS[i]}}"
+                ;;
+        esac
+    done
+    case "$command" in
+        {commands})
+            if [[ "$cur" != -* ]]; then
+                COMPREPLY=( $(compgen -W "$({name} __complete-job-ids 2>/dev/null)" -- "$cur") )
+                return 0
+            fi
+            ;;
+    esac
+    _{name} "$@"
+}}
+complete -F _{name}_job_ids -o nosort -o bashdefault -o default {name}
+"#, name = name, commands = JOB_ID_COMMANDS.join("|"), options = VALUE_OPTIONS.join("|")),
+        Shell::Zsh => print!(r#"
+_{name}_job_ids() {{
+    local command="" i
+    # As in bash: the first word that is neither an option nor its value, then nothing else
+    for (( i = 2; i < CURRENT; i++ )); do
+        case ${{words[i]}} in
+            ({options}) (( i++ )) ;;
+            (-*) ;;
+            (*)
+                if [[ -n $command ]]; then command=""; break; fi
+                command=${{words[i]}}
+                ;;
+        esac
+    done
+    if [[ $command == ({commands}) && ${{words[CURRENT]}} != -* ]]; then
+        local -a ids
+        ids=(${{(f)"$({name} __complete-job-ids 2>/dev/null)"}})
+        compadd -a ids
+        return
+    fi
+    _{name} "$@"
+}}
+compdef _{name}_job_ids {name}
+"#, name = name, commands = JOB_ID_COMMANDS.join("|"), options = VALUE_OPTIONS.join("|")),
+        Shell::Fish => print!(r#"
+complete -c {name} -n "__fish_seen_subcommand_from {commands}" -f -a "({name} __complete-job-ids 2>/dev/null)"
+"#, name = name, commands = JOB_ID_COMMANDS.join(" ")),
+        _ => {}
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{HashMap, HashSet};
+use common::declarative::JobDefinition;
+use common::{Job, Request, Response};
+use crate::DaemonStream;
+
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+// Leading words that wrap the real command and make poor job names
+const COMMAND_WRAPPERS: [&str; 9] = ["cd", "env", "exec", "nice", "ionice", "sudo", "timeout", "flock", "chronic"];
+
+/// A crontab line converted to lunasched terms
+#[derive(Debug)]
+struct CrontabEntry {
+    line: usize,
+    schedule: String, // lunasched schedule syntax
+    command: String,  // Shell command line, as written
+    shell: String,
+    env: HashMap<String, String>,
+    timezone: Option<String>,
+}
+
+/// Convert a crontab (the current user's, USER's, or a file) into jobs and add them after a preview
+pub async fn run(stream: &mut DaemonStream, user: Option<String>, file: Option<String>, dry_run: bool, yes: bool) -> anyhow::Result<()> {
+    let (contents, source) = match file {
+        Some(ref path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+            (contents, crate::apply::file_source(path))
+        }
+        None => (read_crontab(user.as_deref())?, match user {
+            Some(ref user) => format!("crontab-{}", user),
+            None => "crontab".to_string(),
+        }),
+    };
+
+    let (entries, warnings) = parse(&contents);
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+    if entries.is_empty() {
+        println!("No crontab entries to import.");
+        return Ok(());
+    }
+
+    let existing: HashSet<String> = match crate::send_request(stream, &Request::ListJobs).await? {
+        Response::JobListing(jobs) => jobs.into_iter().map(|listed| listed.job.name).collect(),
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+    let mut jobs = into_jobs(entries, &existing, &source)?;
+    // A user's crontab entries keep running as that user (the daemon allows this for root only)
+    if let Some(ref user) = user {
+        for job in &mut jobs {
+            job.run_as = Some(user.clone());
+        }
+    }
+
+    let mut table = comfy_table::Table::new();
+    table.set_header(vec!["Name", "Schedule", "Command"]);
+    for job in &jobs {
+        table.add_row(vec![job.name.clone(), job.schedule.to_string(), job.args.last().cloned().unwrap_or_default()]);
+    }
+    println!("{}", table);
+
+    if dry_run {
+        println!("Dry run: no jobs added.");
+        return Ok(());
+    }
+    if !yes && !crate::confirm(&format!("Add {} job(s)? [y/N] ", jobs.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    for job in jobs {
+        let label = format!("create {}", job.name);
+        match crate::send_request(stream, &Request::AddJob(job)).await? {
+            Response::JobAdded(id) => println!("{}: done ({})", label, id),
+            Response::Error(e) => {
+                failures += 1;
+                eprintln!("{}: failed: {}", label, e);
+            }
+            other => {
+                failures += 1;
+                eprintln!("{}: unexpected response: {:?}", label, other);
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(anyhow::anyhow!("{} job(s) failed to import", failures));
+    }
+    Ok(())
+}
+
+fn read_crontab(user: Option<&str>) -> anyhow::Result<String> {
+    let mut cmd = std::process::Command::new("crontab");
+    cmd.arg("-l");
+    if let Some(user) = user {
+        cmd.args(["-u", user]);
+    }
+    let output = cmd.output().map_err(|e| anyhow::anyhow!("Failed to run crontab -l: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("crontab -l failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Parse crontab contents. Lines that can't be converted are reported as warnings and skipped.
+fn parse(contents: &str) -> (Vec<CrontabEntry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    let mut env = HashMap::new();
+    let mut shell = "/bin/sh".to_string();
+    let mut timezone = None;
+
+    for (idx, raw) in contents.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Environment settings apply to the entries below them
+        if let Some((name, value)) = crate::env::parse_assignment(line) {
+            match name.as_str() {
+                "SHELL" => shell = value.clone(),
+                "CRON_TZ" => {
+                    timezone = Some(value);
+                    continue;
+                }
+                "MAILTO" => {
+                    warnings.push(format!("line {}: MAILTO is not supported; use notification_config instead", line_no));
+                    continue;
+                }
+                _ => {}
+            }
+            env.insert(name, value);
+            continue;
+        }
+
+        let (schedule, command) = match line.strip_prefix('@') {
+            Some(rest) => {
+                let (alias, command) = split_fields(rest, 1).map(|(f, c)| (f[0], c)).unwrap_or((rest, ""));
+                match alias_schedule(alias) {
+                    Some(schedule) => (schedule.to_string(), command),
+                    None => {
+                        warnings.push(format!("line {}: unknown schedule @{}", line_no, alias));
+                        continue;
+                    }
+                }
+            }
+            None => match split_fields(line, 5) {
+                Some((fields, command)) => match convert_schedule(&fields) {
+                    Ok(schedule) => {
+                        if fields[2] != "*" && fields[4] != "*" {
+                            warnings.push(format!("line {}: day-of-month and day-of-week are both set; \
+                                cron runs on either, lunasched only when both match", line_no));
+                        }
+                        (schedule, command)
+                    }
+                    Err(e) => {
+                        warnings.push(format!("line {}: {}", line_no, e));
+                        continue;
+                    }
+                },
+                None => {
+                    warnings.push(format!("line {}: expected five schedule fields and a command", line_no));
+                    continue;
+                }
+            },
+        };
+
+        if command.is_empty() {
+            warnings.push(format!("line {}: missing command", line_no));
+            continue;
+        }
+        // In crontabs an unescaped % starts the command's stdin
+        if command.replace("\\%", "").contains('%') {
+            warnings.push(format!("line {}: '%' (stdin input) is not supported; escape it as \\% to keep it literal", line_no));
+            continue;
+        }
+
+        entries.push(CrontabEntry {
+            line: line_no,
+            schedule,
+            command: command.replace("\\%", "%"),
+            shell: shell.clone(),
+            env: env.clone(),
+            timezone: timezone.clone(),
+        });
+    }
+    (entries, warnings)
+}
+
+// Split off `n` whitespace-separated fields, returning them and the rest of the line as written
+fn split_fields(line: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return None;
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Some((fields, rest.trim_end()))
+}
+
+fn alias_schedule(alias: &str) -> Option<&'static str> {
+    let schedule = match alias {
+        "reboot" => "@reboot",
+        "yearly" | "annually" => "0 0 0 1 1 *",
+        "monthly" => "0 0 0 1 * *",
+        "weekly" => "0 0 0 * * Sun",
+        "daily" | "midnight" => "0 0 0 * * *",
+        "hourly" => "0 0 * * * *",
+        _ => return None,
+    };
+    Some(schedule)
+}
+
+/// Five crontab fields to a lunasched cron expression, which has a leading seconds field
+/// and numbers days of the week from 1 (Sunday) instead of 0
+fn convert_schedule(fields: &[&str]) -> anyhow::Result<String> {
+    let dow = convert_day_of_week(fields[4])?;
+    Ok(format!("0 {} {} {} {} {}", fields[0], fields[1], fields[2], fields[3], dow))
+}
+
+fn convert_day_of_week(field: &str) -> anyhow::Result<String> {
+    let mut days: Vec<String> = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        // Wildcards and day names mean the same in both syntaxes
+        if range == "*" || range.chars().any(|c| c.is_ascii_alphabetic()) {
+            days.push(item.to_string());
+            continue;
+        }
+
+        let parse = |s: &str| -> anyhow::Result<usize> {
+            let n: usize = s.parse().map_err(|_| anyhow::anyhow!("invalid day of week '{}'", s))?;
+            if n > 7 {
+                return Err(anyhow::anyhow!("invalid day of week '{}'", s));
+            }
+            Ok(n)
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None if step.is_some() => (parse(range)?, 7),
+            None => {
+                let n = parse(range)?;
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(anyhow::anyhow!("invalid day-of-week range '{}'", range));
+        }
+        let step = match step {
+            Some(step) => step.parse::<usize>().ok().filter(|s| *s > 0)
+                .ok_or_else(|| anyhow::anyhow!("invalid step '{}'", item))?,
+            None => 1,
+        };
+        // Expand to names, since ranges like 5-7 (Fri-Sun) wrap in the 1-based numbering
+        for n in (start..=end).step_by(step) {
+            let name = DAY_NAMES[n % 7].to_string();
+            if !days.contains(&name) {
+                days.push(name);
+            }
+        }
+    }
+    Ok(days.join(","))
+}
+
+fn into_jobs(entries: Vec<CrontabEntry>, existing: &HashSet<String>, source: &str) -> anyhow::Result<Vec<Job>> {
+    let mut taken = existing.clone();
+    let mut jobs = Vec::new();
+    for entry in entries {
+        let base = format!("cron-{}", job_name(&entry.command));
+        let name = std::iter::once(base.clone())
+            .chain((2..).map(|n| format!("{}-{}", base, n)))
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap();
+        taken.insert(name.clone());
+
+        let definition: JobDefinition = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "schedule": entry.schedule,
+            "command": entry.shell,
+            "args": ["-c", entry.command],
+            "env": entry.env,
+            "timezone": entry.timezone,
+        }))?;
+        let job = definition.into_job(source)
+            .map_err(|e| anyhow::anyhow!("crontab line {}: {}", entry.line, e))?;
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+/// Name for a job from its command: the program it runs, without path or extension
+fn job_name(command: &str) -> String {
+    let program = command.split_whitespace()
+        .filter(|word| !word.contains('=') && !word.starts_with('-'))
+        .find(|word| !COMMAND_WRAPPERS.contains(&word.rsplit('/').next().unwrap_or(word)))
+        .unwrap_or("job");
+    let base = program.rsplit('/').next().unwrap_or(program);
+    let stem = base.split('.').next().unwrap_or(base);
+    let name: String = stem.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() { "job".to_string() } else { name.to_string() }
+}
@@ -0,0 +1,47 @@
+use common::{HistoryEntry, HistoryFilter, JobId, Request, Response};
+use crate::DaemonStream;
+
+fn pick(history: &[HistoryEntry], id: Option<i64>, default_idx: usize) -> anyhow::Result<&HistoryEntry> {
+    match id {
+        Some(id) => history.iter()
+            .find(|e| e.id == id)
+            .ok_or_else(|| anyhow::anyhow!("No history entry {} for this job", id)),
+        None => history.get(default_idx)
+            .ok_or_else(|| anyhow::anyhow!("Need at least two executions to compare")),
+    }
+}
+
+fn label(entry: &HistoryEntry) -> String {
+    format!("#{} {} ({})", entry.id, entry.run_at, entry.status)
+}
+
+/// Print a unified diff between the captured output of two executions of a job.
+/// Defaults to the two most recent; `from`/`to` select history entry IDs.
+pub async fn run(stream: &mut DaemonStream, job_id: &str, from: Option<i64>, to: Option<i64>) -> anyhow::Result<()> {
+    let limit = if from.is_none() && to.is_none() { Some(2) } else { None };
+    let req = Request::GetHistory { job_id: Some(JobId(job_id.to_string())), limit, filter: HistoryFilter::default() };
+
+    let history = match crate::send_request(stream, &req).await? {
+        Response::HistoryList(history) => history,
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+
+    // History is returned newest first
+    let new = pick(&history, to, 0)?;
+    let old = pick(&history, from, 1)?;
+    if old.id == new.id {
+        return Err(anyhow::anyhow!("Both sides refer to the same execution (#{})", old.id));
+    }
+
+    let old_output = old.combined_output();
+    let new_output = new.combined_output();
+    if old_output == new_output {
+        println!("Output unchanged between {} and {}", label(old), label(new));
+        return Ok(());
+    }
+
+    let diff = similar::TextDiff::from_lines(&old_output, &new_output);
+    print!("{}", diff.unified_diff().context_radius(3).header(&label(old), &label(new)));
+    Ok(())
+}
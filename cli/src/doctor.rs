@@ -0,0 +1,143 @@
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::time::Duration;
+use common::{CheckStatus, HealthCheck, Request, Response};
+use tokio::net::UnixStream;
+use crate::{Cli, DaemonStream, OutputFormat};
+
+// How long connecting to the daemon may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Difference between the daemon's clock and this one worth a warning
+const MAX_CLOCK_SKEW_SECS: i64 = 5;
+
+const START_DAEMON: &str = "Start the daemon: sudo systemctl start lunasched (or your own: lunasched-daemon --user)";
+
+/// Check the way to the daemon (its socket, or the remote host), then have the daemon check
+/// itself, and print every check with what to do about each problem. Fails if any check failed;
+/// warnings don't.
+pub async fn run(cli: &Cli) -> anyhow::Result<()> {
+    let mut checks = Vec::new();
+    let stream = match cli.host {
+        Some(ref host) => match crate::connect(cli).await {
+            Ok(stream) => {
+                checks.push(HealthCheck::ok("daemon", format!("Reachable at {}:{}", host, cli.port)));
+                Some(stream)
+            }
+            Err(e) => {
+                checks.push(HealthCheck::failed("daemon", format!("Could not connect to {}:{}: {}", host, cli.port, e),
+                    "Check that the daemon listens on TCP there, that the port is open, and --token (LUNASCHED_TOKEN)".to_string()));
+                None
+            }
+        },
+        None => connect_local(cli.socket.as_deref(), &mut checks).await,
+    };
+    if let Some(mut stream) = stream {
+        checks.extend(daemon_checks(&mut stream).await?);
+    }
+    report(checks, cli.output)
+}
+
+/// The daemon's own checks, with its clock compared to this host's
+pub async fn daemon_checks(stream: &mut DaemonStream) -> anyhow::Result<Vec<HealthCheck>> {
+    let sent_at = chrono::Utc::now();
+    let report = match crate::send_request(stream, &Request::HealthCheck).await? {
+        Response::Health(report) => report,
+        Response::Error(e) => return Ok(vec![HealthCheck::warning("daemon checks", format!("Refused: {}", e),
+            "Run doctor as root, or as a user with the view-all capability".to_string())]),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+    let mut checks = report.checks;
+    if let Ok(daemon_time) = chrono::DateTime::parse_from_rfc3339(&report.time) {
+        // The daemon read its clock somewhere between sending and answering
+        let round_trip = chrono::Utc::now() - sent_at;
+        let skew = (daemon_time.with_timezone(&chrono::Utc) - (sent_at + round_trip / 2)).num_seconds();
+        if skew.abs() > MAX_CLOCK_SKEW_SECS {
+            checks.push(HealthCheck::warning("clock skew", format!("The daemon's clock is {}s {} this host's", skew.abs(),
+                if skew > 0 { "ahead of" } else { "behind" }),
+                "Synchronize both hosts with NTP; times given to the daemon (once at, --since) are read by its clock".to_string()));
+        }
+    }
+    Ok(checks)
+}
+
+/// Print checks, with the remedy under each problem; fails if any check failed
+pub fn report(checks: Vec<HealthCheck>, output: OutputFormat) -> anyhow::Result<()> {
+    let failed = checks.iter().filter(|check| check.status == CheckStatus::Failed).count();
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&checks)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&checks)?),
+        OutputFormat::Table => print(&checks),
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+pub fn print(checks: &[HealthCheck]) {
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+    for check in checks {
+        let status = match check.status {
+            CheckStatus::Ok => "OK",
+            CheckStatus::Warning => "WARN",
+            CheckStatus::Failed => "FAIL",
+        };
+        println!("{:<5} {:<width$}  {}", status, check.name, check.detail, width = width);
+        if let Some(ref remedy) = check.remedy {
+            println!("{:<5} {:<width$}  -> {}", "", "", remedy, width = width);
+        }
+    }
+}
+
+// Find the socket as `connect_local` would and check it can be used, recording what was found
+async fn connect_local(socket: Option<&str>, checks: &mut Vec<HealthCheck>) -> Option<DaemonStream> {
+    let sockets = match socket {
+        Some(socket) => vec![socket.to_string()],
+        None => common::Paths::client_sockets(),
+    };
+    let path = match sockets.iter().find(|path| std::path::Path::new(path).exists()) {
+        Some(path) => path,
+        None => {
+            checks.push(HealthCheck::failed("socket", format!("No daemon socket at {}", sockets.join(" or ")), START_DAEMON.to_string()));
+            return None;
+        }
+    };
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            checks.push(HealthCheck::failed("socket", format!("Cannot read {}: {}", path, e),
+                "Check the permissions of the socket's directory".to_string()));
+            return None;
+        }
+    };
+    if !metadata.file_type().is_socket() {
+        checks.push(HealthCheck::failed("socket", format!("{} is not a socket", path),
+            format!("Remove {} and restart the daemon", path)));
+        return None;
+    }
+    let described = format!("{} (mode {:04o}, uid {}, gid {})", path, metadata.mode() & 0o7777, metadata.uid(), metadata.gid());
+
+    match tokio::time::timeout(CONNECT_TIMEOUT, UnixStream::connect(path)).await {
+        Ok(Ok(stream)) => {
+            checks.push(HealthCheck::ok("socket", described));
+            checks.push(HealthCheck::ok("daemon", "Accepting connections".to_string()));
+            Some(Box::new(stream))
+        }
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            checks.push(HealthCheck::failed("socket", format!("{}: permission denied", described),
+                "Join the socket's group (server.socket_group), or have the admin widen server.socket_mode".to_string()));
+            None
+        }
+        Ok(Err(e)) => {
+            checks.push(HealthCheck::ok("socket", described));
+            checks.push(HealthCheck::failed("daemon", format!("Not listening: {}", e), START_DAEMON.to_string()));
+            None
+        }
+        Err(_) => {
+            checks.push(HealthCheck::ok("socket", described));
+            checks.push(HealthCheck::failed("daemon", "Not responding".to_string(),
+                "Look for a hung daemon: sudo systemctl status lunasched, and its log".to_string()));
+            None
+        }
+    }
+}
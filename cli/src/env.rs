@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+/// `NAME=value` with a valid variable name; quotes around the value are dropped
+pub fn parse_assignment(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once('=')?;
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return None;
+    }
+    let value = value.trim();
+    let unquoted = ['"', '\''].iter()
+        .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+        .unwrap_or(value);
+    Some((name.to_string(), unquoted.to_string()))
+}
+
+/// Job env from `--env-file` (read first) and `--env KEY=VALUE` flags, which win on conflicts
+pub fn collect(files: &[String], flags: &[String]) -> anyhow::Result<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    for path in files {
+        env.extend(read_file(path)?);
+    }
+    for flag in flags {
+        // Only the name goes into the error, the value may be a secret
+        let (name, value) = parse_assignment(flag).ok_or_else(|| anyhow::anyhow!(
+            "Invalid --env '{}=...', expected KEY=VALUE with a name of letters, digits and _",
+            flag.split('=').next().unwrap_or_default()))?;
+        env.insert(name, value);
+    }
+    Ok(env)
+}
+
+/// Read a dotenv-style file: `NAME=value` lines, optionally prefixed with `export`.
+/// Blank lines and `#` comments are skipped.
+pub fn read_file(path: &str) -> anyhow::Result<HashMap<String, String>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    let mut env = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = parse_assignment(line)
+            .ok_or_else(|| anyhow::anyhow!("{}:{}: expected NAME=value", path, number + 1))?;
+        env.insert(name, value);
+    }
+    Ok(env)
+}
+
+/// Env sorted by name for display, with the values of secret names (built-in patterns
+/// like *_TOKEN and *_PASSWORD, plus the job's redact_env) masked. `secret://` references
+/// hold no secret themselves and are shown as they are.
+pub fn masked(env: &HashMap<String, String>, redact_env: &[String]) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = env.iter()
+        .map(|(name, value)| {
+            let value = if common::redact::is_secret_name(name, redact_env) && !value.starts_with("secret://") {
+                common::redact::REDACTED.to_string()
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    vars.sort();
+    vars
+}
@@ -0,0 +1,161 @@
+use std::io::{BufWriter, Stdout, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use common::declarative::JobExport;
+use common::{encode_frame, ConflictPolicy, FrameReader, HistoryEntry, Request, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::DaemonStream;
+
+const HISTORY_CSV_HEADER: [&str; 18] = ["id", "job_id", "execution_id", "run_at", "scheduled_at", "started_at", "status",
+    "exit_code", "signal", "attempt", "duration_ms", "stdout_bytes", "stderr_bytes", "resource_events", "hook_result", "output", "stdout", "stderr"];
+
+/// Write the daemon's jobs (optionally only those tagged `tag`) to `out` or stdout
+pub async fn export(stream: &mut DaemonStream, format: &str, tag: Option<String>, out: Option<String>) -> anyhow::Result<()> {
+    let jobs = match crate::send_request(stream, &Request::ExportJobs { tag }).await? {
+        Response::JobList(jobs) => jobs,
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+
+    let count = jobs.len();
+    let export = JobExport { jobs };
+    let contents = match format.to_lowercase().as_str() {
+        "yaml" | "yml" => export.to_yaml()?,
+        "json" => export.to_json()?,
+        _ => return Err(anyhow::anyhow!("Invalid format. Use: yaml or json")),
+    };
+
+    match out {
+        Some(path) => {
+            // Job env may hold secrets, so keep the file private
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path, e))?;
+            std::io::Write::write_all(&mut file, contents.as_bytes())?;
+            eprintln!("Exported {} job(s) to {}", count, path);
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+pub async fn import(stream: &mut DaemonStream, path: &str, on_conflict: &str) -> anyhow::Result<()> {
+    let on_conflict = match on_conflict.to_lowercase().as_str() {
+        "skip" => ConflictPolicy::Skip,
+        "overwrite" => ConflictPolicy::Overwrite,
+        "rename" => ConflictPolicy::Rename,
+        _ => return Err(anyhow::anyhow!("Invalid conflict policy. Use: skip, overwrite, or rename")),
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    let export = JobExport::parse(path, &contents)?;
+
+    let resp = crate::send_request(stream, &Request::ImportJobs { jobs: export.jobs, on_conflict }).await?;
+    if let Response::Error(e) = resp {
+        return Err(anyhow::anyhow!(e));
+    }
+    crate::print_response(resp);
+    Ok(())
+}
+
+/// Send `req` (an ExportHistory) and write the history the daemon streams back to stdout as
+/// `format` (csv or json) as it arrives
+pub async fn history(stream: &mut DaemonStream, req: &Request, format: &str) -> anyhow::Result<()> {
+    let mut writer = match format.to_lowercase().as_str() {
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(BufWriter::new(std::io::stdout()));
+            writer.write_record(HISTORY_CSV_HEADER)?;
+            HistoryWriter::Csv(writer)
+        }
+        "json" => HistoryWriter::Json { out: BufWriter::new(std::io::stdout()), written: 0 },
+        _ => return Err(anyhow::anyhow!("Invalid format. Use: csv or json")),
+    };
+    stream.write_all(&encode_frame(req)?).await?;
+
+    let mut frames = FrameReader::default();
+    let mut temp_buf = vec![0; 8192];
+    loop {
+        loop {
+            match frames.next::<Response>() {
+                Ok(Some(Response::HistoryChunk(history))) => {
+                    for entry in &history {
+                        writer.write(entry)?;
+                    }
+                }
+                Ok(Some(Response::HistoryEnd { count })) => {
+                    writer.finish()?;
+                    eprintln!("Exported {} execution(s)", count);
+                    return Ok(());
+                }
+                Ok(Some(Response::Error(e))) => return Err(anyhow::anyhow!(e)),
+                Ok(Some(other)) => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+                Ok(None) => break,
+                Err(e) => return Err(anyhow::anyhow!("Invalid response from daemon: {}", e)),
+            }
+        }
+
+        let n = stream.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Daemon closed the connection"));
+        }
+        frames.push(&temp_buf[..n]);
+    }
+}
+
+// Writes exported history entries one at a time: CSV rows, or the items of a JSON array
+#[allow(clippy::large_enum_variant)]
+enum HistoryWriter {
+    Csv(csv::Writer<BufWriter<Stdout>>),
+    Json { out: BufWriter<Stdout>, written: usize },
+}
+
+impl HistoryWriter {
+    fn write(&mut self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        match self {
+            HistoryWriter::Csv(writer) => {
+                let text = |value: &Option<String>| value.clone().unwrap_or_default();
+                let number = |value: Option<i64>| value.map(|n| n.to_string()).unwrap_or_default();
+                writer.write_record([
+                    entry.id.to_string(),
+                    entry.job_id.clone(),
+                    text(&entry.execution_id),
+                    entry.run_at.clone(),
+                    text(&entry.scheduled_at),
+                    text(&entry.started_at),
+                    entry.status.clone(),
+                    number(entry.exit_code.map(i64::from)),
+                    number(entry.signal.map(i64::from)),
+                    number(entry.attempt.map(i64::from)),
+                    number(entry.duration_ms),
+                    number(entry.stdout_bytes),
+                    number(entry.stderr_bytes),
+                    text(&entry.resource_events),
+                    text(&entry.hook_result),
+                    text(&entry.output),
+                    text(&entry.stdout),
+                    text(&entry.stderr),
+                ])?;
+            }
+            HistoryWriter::Json { out, written } => {
+                out.write_all(if *written == 0 { b"[\n  " } else { b",\n  " })?;
+                serde_json::to_writer(&mut *out, entry)?;
+                *written += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            HistoryWriter::Csv(mut writer) => writer.flush()?,
+            HistoryWriter::Json { mut out, written } => {
+                out.write_all(if written == 0 { b"[]\n" } else { b"\n]\n" })?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
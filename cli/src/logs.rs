@@ -0,0 +1,76 @@
+use std::io::{IsTerminal, Write};
+use crossterm::style::Stylize;
+use common::{encode_frame, FrameReader, JobId, Request, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::DaemonStream;
+
+/// Print the output of a job's running (or last) execution, streaming new output with `follow`
+pub async fn run(stream: &mut DaemonStream, job_id: &str, execution_id: Option<String>, follow: bool) -> anyhow::Result<()> {
+    let req = Request::GetJobOutput { job_id: JobId(job_id.to_string()), execution_id, follow };
+    print_output(stream, &req).await.map(|_| ())
+}
+
+/// Start a transient run (`Request::RunOnce` with `follow`) and print its output until it
+/// finishes. Returns its job and execution IDs.
+pub async fn run_once(stream: &mut DaemonStream, req: &Request) -> anyhow::Result<(String, String)> {
+    print_output(stream, req).await?
+        .ok_or_else(|| anyhow::anyhow!("The daemon did not report the execution it started"))
+}
+
+// Send a request answered with streamed output and print it. Returns the execution the
+// daemon said it started, if it did.
+async fn print_output(stream: &mut DaemonStream, req: &Request) -> anyhow::Result<Option<(String, String)>> {
+    stream.write_all(&encode_frame(req)?).await?;
+
+    // The daemon replies with several responses back to back
+    let mut frames = FrameReader::default();
+    let mut temp_buf = vec![0; 8192];
+    let mut started = None;
+    loop {
+        loop {
+            let resp = match frames.next::<Response>() {
+                Ok(Some(resp)) => resp,
+                Ok(None) => break,
+                Err(e) => return Err(anyhow::anyhow!("Invalid response from daemon: {}", e)),
+            };
+
+            match resp {
+                Response::ExecutionStarted { job_id, execution_id } => {
+                    eprintln!("-- started {} (execution {}) --", job_id, execution_id);
+                    started = Some((job_id, execution_id));
+                }
+                Response::OutputChunk(text) => {
+                    print!("{}", text);
+                    std::io::stdout().flush()?;
+                }
+                Response::OutputEnd { status } => {
+                    if let Some(status) = status {
+                        eprintln!("-- execution finished: {} --", colored_status(&status));
+                    }
+                    return Ok(started);
+                }
+                Response::Error(e) => return Err(anyhow::anyhow!(e)),
+                other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+            }
+        }
+
+        let n = stream.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Daemon closed the connection"));
+        }
+        frames.push(&temp_buf[..n]);
+    }
+}
+
+// A final status, green for success, red for failures and yellow for anything else, when
+// stderr is a terminal
+fn colored_status(status: &str) -> String {
+    if !std::io::stderr().is_terminal() {
+        return status.to_string();
+    }
+    match status {
+        "success" => status.green().to_string(),
+        "failed" | "Error" | "SpawnError" => status.red().to_string(),
+        _ => status.yellow().to_string(),
+    }
+}
@@ -1,24 +1,71 @@
+mod apply;
+mod bundle;
+mod completions;
+mod crontab;
+mod diff;
+mod doctor;
+mod env;
+mod export;
+mod logs;
+mod notify;
+mod rebalance;
+mod shell;
+mod top;
+mod validate;
+mod wait;
+mod watch;
+
 use clap::{Parser, Subcommand};
-use common::{Job, JobId, Request, Response};
-use tokio::net::UnixStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use common::{encode_frame, FrameReader, HistoryFilter, Job, JobId, Request, Response};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use std::collections::HashMap;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Manage a remote daemon over TCP instead of the local Unix socket
+    #[arg(long, global = true, env = "LUNASCHED_HOST")]
+    host: Option<String>,
+    /// TCP port of the remote daemon
+    #[arg(long, global = true, env = "LUNASCHED_PORT", default_value_t = common::DEFAULT_TCP_PORT)]
+    port: u16,
+    /// Shared secret configured as server.auth_token on the remote daemon
+    #[arg(long, global = true, env = "LUNASCHED_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Unix socket of the local daemon to manage, e.g. one set by server.socket_path
+    /// (default: your own daemon's if it is running, else the system daemon's)
+    #[arg(long, global = true, env = "LUNASCHED_SOCKET", conflicts_with = "host")]
+    socket: Option<String>,
+    /// Output format: table for people, json or yaml for scripts
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
     #[command(subcommand)]
     command: Commands,
 }
 
+/// How responses are printed (`--output`)
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+/// Connection to the daemon, over the Unix socket or TCP
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+pub type DaemonStream = Box<dyn Connection>;
+
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Add a new job
     Add {
         /// Name of the job
         #[arg(short, long)]
         name: String,
-        /// Schedule (e.g. "every 5s", "at 12:00", "*/5 * * * *")
+        /// Schedule (e.g. "every 5s", "at 12:00", "*/5 * * * *", "heartbeat 15m")
         #[arg(long)]
         schedule: Option<String>,
         /// Cron schedule (deprecated, use --schedule)
@@ -27,15 +74,43 @@ enum Commands {
         /// Every X duration (deprecated, use --schedule)
         #[arg(long)]
         every: Option<String>,
-        /// Command to run
+        /// Command to run (not needed for heartbeat schedules)
         #[arg(short, long)]
-        command: String,
-        /// Max retry attempts (0 = no retries)
-        #[arg(long, default_value = "0")]
-        max_retries: u32,
+        command: Option<String>,
+        /// Script to run instead of a command, stored whole by the daemon (args are passed to it)
+        #[arg(long, value_name = "FILE", conflicts_with = "command")]
+        script: Option<std::path::PathBuf>,
+        /// Max retry attempts (0 = no retries, even where a tag default sets some)
+        #[arg(long)]
+        max_retries: Option<u32>,
+        /// Disable the job after N consecutive runs fail with no retries left
+        #[arg(long, value_name = "N")]
+        disable_after_exhaustions: Option<u32>,
+        /// Disable the job after N executions in a row fail, retries included
+        #[arg(long, value_name = "N")]
+        disable_after_failures: Option<u32>,
+        /// Disable the job from this time on: a duration from now (30d), a local time
+        /// (YYYY-MM-DD HH:MM) or an RFC 3339 time
+        #[arg(long, value_name = "TIME")]
+        expires_at: Option<String>,
+        /// Spread each retry delay randomly by up to PERCENT either way
+        #[arg(long, value_name = "PERCENT")]
+        retry_jitter: Option<u32>,
         /// Timeout in seconds
         #[arg(long)]
         timeout: Option<u64>,
+        /// Exit codes that count as success (comma-separated, default: 0)
+        #[arg(long, value_delimiter = ',', value_name = "CODES")]
+        success_exit_codes: Vec<i32>,
+        /// Regex that fails the run when it matches stdout or stderr
+        #[arg(long, value_name = "REGEX")]
+        failure_pattern: Option<String>,
+        /// Fail runs that take longer than SECONDS (they are not killed; see --timeout)
+        #[arg(long, value_name = "SECONDS")]
+        max_duration: Option<u64>,
+        /// Bytes of stdout and of stderr kept in history per run (default: daemon config, 0 = no limit)
+        #[arg(long, value_name = "BYTES")]
+        max_output_bytes: Option<u64>,
         /// Jitter in seconds (random delay)
         #[arg(long, default_value = "0")]
         jitter: u64,
@@ -51,84 +126,743 @@ enum Commands {
         /// Command to run on failure
         #[arg(long)]
         on_failure: Option<String>,
+        /// Command to run when a failed run is scheduled for another attempt
+        #[arg(long)]
+        on_retry: Option<String>,
+        /// Seconds a hook may run before it is killed (default: 60)
+        #[arg(long, value_name = "SECONDS")]
+        hook_timeout: Option<u64>,
+        /// Job to start when a run succeeds (repeatable)
+        #[arg(long = "trigger-on-success", value_name = "JOB")]
+        on_success_trigger: Vec<String>,
+        /// Job to start when a run fails for good, after its retries (repeatable)
+        #[arg(long = "trigger-on-failure", value_name = "JOB")]
+        on_failure_trigger: Vec<String>,
+        /// Notify a channel when a run fails for good, as TYPE:TARGET, e.g. slack:URL,
+        /// email:ADDRESS, telegram:BOT_TOKEN/CHAT_ID, ntfy:TOPIC or syslog (repeatable)
+        #[arg(long, value_name = "CHANNEL")]
+        notify_on_failure: Vec<String>,
+        /// Notify a channel when a run succeeds, as for --notify-on-failure (repeatable)
+        #[arg(long, value_name = "CHANNEL")]
+        notify_on_success: Vec<String>,
+        /// Notify a channel when a run starts, as for --notify-on-failure (repeatable)
+        #[arg(long, value_name = "CHANNEL")]
+        notify_on_start: Vec<String>,
+        /// Notify a channel when a failed run will be retried, as for --notify-on-failure (repeatable)
+        #[arg(long, value_name = "CHANNEL")]
+        notify_on_retry: Vec<String>,
+        /// Notification message with placeholders, e.g. "{{job.name}} {{status}} in {{duration}}"
+        #[arg(long, value_name = "TEMPLATE")]
+        notify_template: Option<String>,
+        /// Only notify failures once N runs in a row have failed
+        #[arg(long, value_name = "N")]
+        alert_after_failures: Option<u32>,
+        /// Notify when under PERCENT of runs succeeded within --alert-window
+        #[arg(long, value_name = "PERCENT")]
+        alert_min_success_rate: Option<f64>,
+        /// Window of --alert-min-success-rate in seconds (default: 86400)
+        #[arg(long, value_name = "SECONDS", requires = "alert_min_success_rate")]
+        alert_window: Option<u64>,
         /// Job priority (Low, Normal, High, Critical)
         #[arg(long, default_value = "Normal")]
         priority: String,
         /// Execution mode (Sequential, Parallel, Exclusive)
         #[arg(long, default_value = "Sequential")]
         execution_mode: String,
+        /// Maximum overlapping executions of a Parallel job (0 = unlimited)
+        #[arg(long, default_value_t = 0)]
+        max_concurrent: u32,
+        /// Runs of a Parallel job beyond --max-concurrent: Skip or Queue
+        #[arg(long, default_value = "Skip")]
+        overflow_policy: String,
+        /// Windows missed while the daemon was down: Skip, RunOnce or RunAll
+        #[arg(long, default_value = "Skip")]
+        catch_up: String,
+        /// Seconds after host boot before catch-up and @reboot runs fire (default: daemon config)
+        #[arg(long)]
+        boot_delay: Option<u64>,
+        /// Remove a "once at" job after it has run, instead of disabling it
+        #[arg(long)]
+        delete_after_run: bool,
+        /// Fire an "every" job as soon as it is added instead of one interval later
+        #[arg(long)]
+        run_immediately: bool,
+        /// Run the job as this user instead of yourself (only root may pick another user)
+        #[arg(long = "user", value_name = "USER")]
+        run_as: Option<String>,
+        /// Start from this template (see `lunasched template`); flags override its settings
+        #[arg(long, value_name = "TEMPLATE")]
+        from_template: Option<String>,
+        /// Value for a {{NAME}} placeholder of the template (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", requires = "from_template")]
+        vars: Vec<String>,
+        /// Environment variable KEY=VALUE for the job (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Read environment variables from a file of KEY=VALUE lines (repeatable; --env wins)
+        #[arg(long = "env-file", value_name = "PATH")]
+        env_files: Vec<String>,
+        /// Working directory of the job (default: /tmp)
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<std::path::PathBuf>,
+        /// File mode creation mask in octal, e.g. 027 (default: the daemon's)
+        #[arg(long)]
+        umask: Option<String>,
+        /// Never overlap with other jobs of this group (policy set per group in the daemon config)
+        #[arg(long, value_name = "NAME")]
+        concurrency_group: Option<String>,
+        /// Env var names/patterns to redact from logs and history (comma-separated, e.g. "API_KEY,*_CREDS")
+        #[arg(long)]
+        redact_env: Option<String>,
+        /// Concurrency key template, e.g. "{{params.CUSTOMER}}": runs with different keys may overlap
+        #[arg(long)]
+        fence_key: Option<String>,
+        /// Snapshot command and environment of each execution for `lunasched rerun`
+        #[arg(long)]
+        capture_env: bool,
+        /// Run on a connected agent with this name or label instead of the daemon's host
+        #[arg(long, value_name = "AGENT")]
+        target: Option<String>,
+        /// Run in a container of this image, with the daemon's docker or podman
+        #[arg(long)]
+        image: Option<String>,
+        /// Volume of the container, as /host/path:/container/path[:ro] (repeatable; needs --image)
+        #[arg(long = "volume", value_name = "VOLUME")]
+        volumes: Vec<String>,
+        /// Network of the container, e.g. none or host (needs --image)
+        #[arg(long)]
+        network: Option<String>,
+        /// Run as a transient systemd service (systemd-run), with its limits as unit properties
+        #[arg(long, conflicts_with = "image")]
+        systemd: bool,
+        /// ProtectSystem= of the unit: yes, full or strict (needs --systemd)
+        #[arg(long, value_name = "MODE", requires = "systemd")]
+        protect_system: Option<String>,
+        /// Further property of the unit, e.g. ReadWritePaths=/var/log/app (repeatable; needs --systemd)
+        #[arg(long = "unit-property", value_name = "NAME=VALUE", requires = "systemd")]
+        unit_properties: Vec<String>,
+        /// Sandbox: setuid programs (sudo, su) can't raise the job's privileges
+        #[arg(long)]
+        no_new_privileges: bool,
+        /// Sandbox: no capabilities, even for a job running as root
+        #[arg(long)]
+        drop_capabilities: bool,
+        /// Sandbox: an empty /tmp of the job's own
+        #[arg(long)]
+        private_tmp: bool,
+        /// Sandbox: the root filesystem read-only
+        #[arg(long)]
+        read_only_root: bool,
+        /// Sandbox: no network access
+        #[arg(long)]
+        no_network: bool,
+        /// Leave a job of this name alone if there is one, rather than update it
+        #[arg(long)]
+        if_absent: bool,
         /// Arguments
         #[arg(last = true)]
         args: Vec<String>,
     },
     /// List all jobs
-    List,
+    List {
+        /// Refresh the table every SECONDS (default 2) until interrupted
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "2")]
+        watch: Option<u64>,
+        /// Only jobs whose last run failed with no retries left
+        #[arg(long)]
+        failed: bool,
+    },
+    /// Show running executions, oldest first
+    Ps {
+        /// Refresh the table every SECONDS (default 2) until interrupted
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "2")]
+        watch: Option<u64>,
+    },
     /// Start a job manually
     Start {
         id: String,
+        /// Run parameter KEY=VALUE, passed to the command as an env var (repeatable)
+        #[arg(long = "param")]
+        params: Vec<String>,
+        /// Wait for the run (and its retries) to finish, print its output and exit with its exit code
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Run a command once now as a transient job, the way the daemon runs jobs (user, environment,
+    /// limits, history), without adding it. Prints its output and exits with its exit code.
+    Run {
+        /// Name shown in logs and history (default: the command)
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Kill the run after SECONDS
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Memory limit in MB
+        #[arg(long, value_name = "MB")]
+        max_memory_mb: Option<u64>,
+        /// CPU quota, 1.0 = one core
+        #[arg(long)]
+        cpu_quota: Option<f32>,
+        /// Run as this user instead of yourself (only root may pick another user)
+        #[arg(long = "user", value_name = "USER")]
+        run_as: Option<String>,
+        /// Environment variable KEY=VALUE (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Read environment variables from a file of KEY=VALUE lines (repeatable; --env wins)
+        #[arg(long = "env-file", value_name = "PATH")]
+        env_files: Vec<String>,
+        /// Working directory (default: /tmp)
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<std::path::PathBuf>,
+        /// Return once the run has started instead of printing its output
+        #[arg(short, long)]
+        detach: bool,
+        /// Command and its arguments, after --
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Clear a failed job's state, re-enable it if the daemon disabled it, and run it now
+    Retry {
+        id: String,
+    },
+    /// Show the next times a schedule would fire, without adding a job
+    Preview {
+        /// Schedule expression, as for `add --schedule`
+        #[arg(long)]
+        schedule: String,
+        /// Timezone of calendar schedules, e.g. Europe/Berlin (default: the daemon's local time)
+        #[arg(long)]
+        timezone: Option<String>,
+        /// Number of fire times to show
+        #[arg(long, default_value = "10")]
+        count: usize,
+    },
+    /// Rerun a past execution with the exact command and environment it ran with
+    /// (requires capture_env on the job)
+    Rerun {
+        execution_id: String,
     },
     /// View job history
     History {
-        id: String,
-        /// Show all history (default: last 5 executions)
+        #[arg(required_unless_present = "all_jobs")]
+        id: Option<String>,
+        /// Show all history (default: last 5 executions, or 20 with --all-jobs)
         #[arg(long)]
         all: bool,
+        /// Show the executions of every job instead of one
+        #[arg(long, conflicts_with_all = ["id", "execution"])]
+        all_jobs: bool,
+        /// Only executions with this status; "failed" includes spawn errors
+        #[arg(long)]
+        status: Option<String>,
+        /// Only executions since then: a duration ago (24h), a date (YYYY-MM-DD) or an RFC 3339 time
+        #[arg(long)]
+        since: Option<String>,
+        /// Only executions whose output contains this text (case-insensitive)
+        #[arg(long, value_name = "TEXT")]
+        grep: Option<String>,
+        /// Write every matching execution, oldest first, to stdout as csv or json
+        #[arg(long, value_name = "FORMAT", conflicts_with_all = ["all", "watch", "execution"])]
+        export: Option<String>,
+        /// Refresh the table every SECONDS (default 2) until interrupted
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "2")]
+        watch: Option<u64>,
+        /// Show a single execution in detail
+        #[arg(long, value_name = "EXECUTION_ID", conflicts_with_all = ["all", "watch"])]
+        execution: Option<String>,
+        /// With --execution: show its lifecycle timeline (scheduled, spawned, first output, exit, ...)
+        #[arg(long, requires = "execution")]
+        timeline: bool,
     },
     /// Remove a job
     Remove {
         id: String,
     },
+    /// Change a job's name; its ID, history and settings stay
+    Rename {
+        id: String,
+        new_name: String,
+    },
+    /// Add a copy of a job (retries, env, notifications and all) under a new name
+    Clone {
+        id: String,
+        /// Name of the copy
+        #[arg(long)]
+        name: String,
+        /// Schedule of the copy instead of the original's (same syntax as `add --schedule`)
+        #[arg(long)]
+        schedule: Option<String>,
+        /// Add the copy disabled
+        #[arg(long)]
+        disabled: bool,
+    },
     /// Get job details
     Get {
         id: String,
     },
+    /// Show run counts, success rate and durations of a job
+    Stats {
+        id: String,
+    },
+    /// Export job definitions (with their IDs, owners and env) for `import`
+    Export {
+        /// Output format: yaml or json
+        #[arg(long, default_value = "yaml")]
+        format: String,
+        /// Only export jobs with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Import jobs written by `export` (YAML, or JSON by .json extension)
+    Import {
+        file: String,
+        /// Jobs whose ID already exists: skip, overwrite or rename
+        #[arg(long, default_value = "skip")]
+        on_conflict: String,
+    },
+    /// Convert crontab entries into jobs, previewing them before they are added
+    ImportCrontab {
+        /// Read this user's crontab (crontab -l -u USER) instead of your own
+        #[arg(long)]
+        user: Option<String>,
+        /// Crontab file to read instead of running crontab -l
+        file: Option<String>,
+        /// Only show the jobs that would be added
+        #[arg(long)]
+        dry_run: bool,
+        /// Add the jobs without asking
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Suggest spreading out start times where many jobs fire together, based on history
+    Rebalance {
+        /// History to analyze, in days
+        #[arg(long, default_value = "14")]
+        days: u32,
+        /// Jobs allowed to start in the same minute before it counts as crowded
+        #[arg(long, default_value = "5")]
+        max_per_slot: usize,
+        /// Apply the suggested changes (after asking)
+        #[arg(long)]
+        apply: bool,
+        /// With --apply: don't ask
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Show a unified diff of the captured output of two executions
+    DiffOutput {
+        id: String,
+        /// History entry ID of the older execution (default: second most recent)
+        #[arg(long)]
+        from: Option<i64>,
+        /// History entry ID of the newer execution (default: most recent)
+        #[arg(long)]
+        to: Option<i64>,
+    },
+    /// Show output of the running (or most recent) execution of a job
+    Logs {
+        id: String,
+        /// Execution ID of a running or recorded execution (default: the most recently started)
+        #[arg(long)]
+        execution: Option<String>,
+        /// Keep streaming output until the execution finishes
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Full-screen dashboard: running jobs, upcoming runs, recent failures and the retry backlog
+    Top {
+        /// Refresh every SECONDS
+        #[arg(short, long, value_name = "SECONDS", default_value_t = 2)]
+        interval: u64,
+    },
+    /// Stream job events (started, finished, failed, retried, schedule changes) as they happen
+    Watch {
+        /// Only show events of this job (repeatable)
+        #[arg(long = "job", value_name = "ID")]
+        jobs: Vec<String>,
+    },
+    /// Test a job's notification channels
+    Notify {
+        #[command(subcommand)]
+        action: NotifyCommands,
+    },
+    /// Send a heartbeat ping for a heartbeat job
+    Ping {
+        id: String,
+        /// Print the job's token for `POST /ping/<id>` on the daemon's http_bind instead
+        #[arg(long)]
+        show_token: bool,
+    },
+    /// Check the daemon and its host (socket, database, clock, users, log directories) and
+    /// say how to fix any problem found
+    Doctor,
+    /// Copy the daemon's database (jobs, history, secrets) to a file while it runs
+    Backup {
+        /// File to write, on the daemon's host
+        path: String,
+    },
+    /// Replace the daemon's jobs and history with those of a backup
+    Restore {
+        /// Backup to restore, on the daemon's host
+        path: String,
+        /// Don't ask
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// List the agents connected to the daemon, which run jobs added with --target
+    Agents,
+    /// Show daemon status and scheduler diagnostics
+    Status {
+        /// Include lock wait times and the jobs with the most expensive schedule evaluation
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Apply a declarative jobs file (YAML or JSON), showing the plan first
+    Apply {
+        /// Path to the jobs file
+        #[arg(required_unless_present = "file_option")]
+        file: Option<String>,
+        /// Path to the jobs file, as an option (-f jobs.yaml)
+        #[arg(short = 'f', long = "file", id = "file_option", value_name = "FILE", conflicts_with = "file")]
+        file_option: Option<String>,
+        /// Name the jobs' source (tagged `source:<name>`) instead of using the file's full path
+        #[arg(long)]
+        source: Option<String>,
+        /// Delete jobs previously applied from this file that are no longer declared
+        #[arg(long)]
+        prune: bool,
+        /// Only show the plan, don't change anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage workflows: named graphs of jobs that run after one another
+    Workflow {
+        #[command(subcommand)]
+        action: WorkflowCommands,
+    },
+    /// Manage job templates for `add --from-template`
+    Template {
+        #[command(subcommand)]
+        action: TemplateCommands,
+    },
+    /// Manage encrypted secrets, referenced from job env as secret://NAME
+    Secret {
+        #[command(subcommand)]
+        action: SecretCommands,
+    },
+    /// Create and sign job bundles for distribution to other hosts
+    Bundle {
+        #[command(subcommand)]
+        action: BundleCommands,
+    },
+    /// Check schedules and job files (as used by `apply`) without contacting the daemon
+    Validate {
+        /// Job files to check: YAML, JSON by .json extension, or signed bundles
+        files: Vec<String>,
+        /// A schedule expression to check (repeatable)
+        #[arg(long)]
+        schedule: Vec<String>,
+    },
+    /// Show who changed what: jobs added, removed and started, secrets set, and so on
+    Audit {
+        /// Only actions on this job
+        #[arg(long)]
+        job: Option<String>,
+        /// Only actions since a time: a duration ago (24h, 7d), a date (2024-05-01) or RFC 3339
+        #[arg(long)]
+        since: Option<String>,
+        /// Most recent entries to show
+        #[arg(long, default_value = "50")]
+        limit: usize,
+    },
+    /// Show recorded executions: the scheduled windows a restarted daemon won't fire again,
+    /// and executions that never finished
+    Windows {
+        /// Only executions of this job
+        #[arg(long)]
+        job: Option<String>,
+        /// Most recent executions to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Interactive prompt that runs commands over a single daemon connection
+    Shell,
+    /// Print a shell completion script, e.g. `source <(lunasched completions bash)`
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// Print the daemon's job IDs, one per line (used by the completion scripts)
+    #[command(name = "__complete-job-ids", hide = true)]
+    CompleteJobIds,
+}
+
+#[derive(Subcommand)]
+enum WorkflowCommands {
+    /// Create or replace a workflow from existing jobs
+    Add {
+        name: String,
+        /// A step: JOB, or JOB:AFTER[,AFTER...] to run it once those jobs succeeded (repeatable),
+        /// e.g. --step extract --step load:extract --step index:extract --step report:load,index
+        #[arg(long = "step", value_name = "JOB[:AFTER,...]", required = true)]
+        steps: Vec<String>,
+    },
+    /// List workflows
+    List,
+    /// Start a run of a workflow
+    Run {
+        name: String,
+    },
+    /// Show the latest runs of a workflow and the state of their steps
+    Status {
+        name: String,
+        /// Number of runs to show
+        #[arg(short = 'n', long, default_value_t = 1)]
+        limit: usize,
+    },
+    /// Delete a workflow (its past runs stay in history)
+    Rm {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// Create or replace a template. {{NAME}} placeholders in the command, args and env
+    /// values are filled in with `add --from-template TEMPLATE --var NAME=VALUE`.
+    Add {
+        name: String,
+        /// Command to run
+        #[arg(short, long)]
+        command: String,
+        /// Default schedule for jobs that don't set one
+        #[arg(long)]
+        schedule: Option<String>,
+        /// Environment variable KEY=VALUE (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Read environment variables from a file of KEY=VALUE lines (repeatable; --env wins)
+        #[arg(long = "env-file", value_name = "PATH")]
+        env_files: Vec<String>,
+        /// Tags added to jobs created from the template (comma-separated)
+        #[arg(long)]
+        tags: Option<String>,
+        /// Arguments
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// List templates
+    List,
+    /// Show a template and its variables
+    Show {
+        name: String,
+    },
+    /// Delete a template (jobs created from it are not affected)
+    Rm {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum NotifyCommands {
+    /// Send a test message through a job's notification channels and report delivery
+    Test {
+        id: String,
+        /// Only test the channel with this index (as numbered in the results)
+        #[arg(long)]
+        channel: Option<usize>,
+        /// Show the message each channel would send (with the job's template filled in from
+        /// its latest run) without sending anything
+        #[arg(long)]
+        preview: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretCommands {
+    /// Store a secret (the value is read from stdin if not given, keeping it out of shell history)
+    Set {
+        name: String,
+        value: Option<String>,
+    },
+    /// Print a secret's value (root only)
+    Get {
+        name: String,
+    },
+    /// Delete a secret
+    Rm {
+        name: String,
+    },
+    /// List secret names
+    List,
+}
+
+#[derive(Subcommand)]
+enum BundleCommands {
+    /// Generate an ed25519 signing key (public key is written to <out>.pub)
+    Keygen {
+        #[arg(long)]
+        out: String,
+    },
+    /// Sign a jobs file (YAML or JSON) into a bundle that `lunasched apply` accepts
+    Sign {
+        /// Path to the jobs file
+        file: String,
+        /// Path to the signing key
+        #[arg(long)]
+        key: String,
+        /// Output path (default: <file>.bundle)
+        #[arg(long)]
+        out: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let socket_path = common::DEFAULT_SOCKET_PATH;
 
-    // Add timeout to connection
-    let mut stream = match tokio::time::timeout(
-        std::time::Duration::from_secs(10),
-        UnixStream::connect(socket_path)
-    ).await {
-        Ok(Ok(stream)) => stream,
-        Ok(Err(e)) => {
-            eprintln!("Failed to connect to daemon at {}: {}", socket_path, e);
-            eprintln!("Is the lunasched daemon running? Try: sudo systemctl status lunasched");
-            return Err(e.into());
-        }
-        Err(_) => {
-            eprintln!("Connection timeout: daemon at {} is not responding", socket_path);
-            eprintln!("Is the lunasched daemon running? Try: sudo systemctl status lunasched");
-            return Err(anyhow::anyhow!("Connection timeout"));
+    // Bundle management, validation and completions work offline
+    if !cli.command.needs_daemon() {
+        return run_offline(cli.command);
+    }
+
+    // Reports on the connection itself, so it connects on its own
+    if let Commands::Doctor = cli.command {
+        return doctor::run(&cli).await;
+    }
+    // Follows the job's events on a connection of their own
+    if let Commands::Start { ref id, ref params, wait: true } = cli.command {
+        return wait::start(&cli, id, params.clone()).await;
+    }
+    let mut stream = connect(&cli).await?;
+    if let Commands::Shell = cli.command {
+        return shell::run(&mut stream, cli.output).await;
+    }
+    run(&mut stream, cli.command, cli.output).await
+}
+
+impl Commands {
+    fn needs_daemon(&self) -> bool {
+        !matches!(self, Commands::Bundle { .. } | Commands::Validate { .. } | Commands::Completions { .. })
+    }
+
+    /// Commands that stream or refresh until interrupted, or exit with a run's exit code, which
+    /// would end a shell session or leave its connection in the middle of a stream
+    fn holds_connection(&self) -> bool {
+        matches!(self,
+            Commands::List { watch: Some(_), .. } | Commands::Ps { watch: Some(_) } | Commands::History { watch: Some(_), .. }
+            | Commands::Logs { follow: true, .. } | Commands::Top { .. } | Commands::Watch { .. }
+            | Commands::Run { detach: false, .. } | Commands::Start { wait: true, .. })
+    }
+}
+
+fn run_offline(command: Commands) -> anyhow::Result<()> {
+    match command {
+        Commands::Bundle { action } => bundle::run(action),
+        Commands::Validate { files, schedule } => validate::run(&files, &schedule),
+        Commands::Completions { shell } => {
+            completions::print(shell);
+            Ok(())
         }
-    };
+        _ => unreachable!("only offline commands are run without a connection"),
+    }
+}
 
-    let req = match cli.command {
+/// Carry out one command against the daemon. The connection stays usable afterwards, so the
+/// shell runs every command it reads through here.
+async fn run(stream: &mut DaemonStream, command: Commands, output: OutputFormat) -> anyhow::Result<()> {
+    let req = match command {
         Commands::Add { 
-            name, schedule, cron, every, command, args,
-            max_retries, timeout, jitter, timezone, tags,
-            on_success, on_failure, priority, execution_mode
+            name, schedule, cron, every, command, script, args,
+            max_retries, disable_after_exhaustions, disable_after_failures, expires_at, retry_jitter, timeout, success_exit_codes, failure_pattern, max_duration, max_output_bytes, jitter, timezone, tags,
+            on_success, on_failure, on_retry, hook_timeout, on_success_trigger, on_failure_trigger, notify_on_failure, notify_on_success, notify_on_start, notify_on_retry, notify_template,
+            alert_after_failures, alert_min_success_rate, alert_window, priority, execution_mode, max_concurrent, overflow_policy, catch_up, boot_delay, delete_after_run, run_immediately, run_as, from_template, vars, env, env_files, cwd, umask, concurrency_group,
+            redact_env, fence_key, capture_env, target, image, volumes, network, systemd, protect_system, unit_properties,
+            no_new_privileges, drop_capabilities, private_tmp, read_only_root, no_network, if_absent
         } => {
+            // Settings the flags leave out come from the template, with its variables filled in
+            let template = match from_template {
+                Some(template_name) => match send_request(stream, &Request::GetTemplate(template_name.clone())).await? {
+                    Response::TemplateDetail(Some(template)) => {
+                        let mut values = HashMap::new();
+                        for var in &vars {
+                            let (key, value) = env::parse_assignment(var)
+                                .ok_or_else(|| anyhow::anyhow!("Invalid --var '{}', expected NAME=VALUE", var))?;
+                            values.insert(key, value);
+                        }
+                        let rendered = template.render(&values)?;
+                        Some((template, rendered))
+                    }
+                    Response::TemplateDetail(None) => return Err(anyhow::anyhow!("Template {} not found", template_name)),
+                    Response::Error(e) => return Err(anyhow::anyhow!(e)),
+                    other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+                },
+                None => None,
+            };
+            // A script is stored whole; the command only names it
+            let script = match script {
+                Some(path) => {
+                    let body = std::fs::read_to_string(&path)
+                        .map_err(|e| anyhow::anyhow!("Cannot read script {}: {}", path.display(), e))?;
+                    if body.len() > common::MAX_SCRIPT_BYTES {
+                        return Err(anyhow::anyhow!("Script {} is over {} KiB", path.display(), common::MAX_SCRIPT_BYTES / 1024));
+                    }
+                    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                    Some((name, body))
+                }
+                None => None,
+            };
+            let command = command
+                .or_else(|| script.as_ref().map(|(name, _)| name.clone()))
+                .or_else(|| template.as_ref().map(|(_, r)| r.command.clone()));
+            let args = match template {
+                Some((_, ref rendered)) if args.is_empty() => rendered.args.clone(),
+                _ => args,
+            };
+
             let schedule_config = if let Some(s) = schedule {
                 common::parse_schedule(&s)?
             } else if let Some(c) = cron {
+                common::validate_cron(&c)?;
                 common::ScheduleConfig::Cron(c)
             } else if let Some(e) = every {
                 common::parse_schedule(&format!("every {}", e))?
+            } else if let Some(s) = template.as_ref().and_then(|(t, _)| t.schedule.as_ref()) {
+                common::parse_schedule(s)?
             } else {
                 return Err(anyhow::anyhow!("Must specify --schedule"));
             };
 
+            let command = match (command, &schedule_config) {
+                (Some(c), _) => c,
+                (None, common::ScheduleConfig::Heartbeat(_)) => String::new(),
+                (None, _) => return Err(anyhow::anyhow!("Must specify --command")),
+            };
+            if run_immediately && !matches!(schedule_config, common::ScheduleConfig::Every(_)) {
+                return Err(anyhow::anyhow!("--run-immediately only applies to \"every\" schedules"));
+            }
+
             let retry_policy = common::RetryPolicy {
                 max_attempts: max_retries,
                 backoff_strategy: common::BackoffStrategy::Exponential,
                 initial_delay_seconds: 60,
                 max_delay_seconds: 3600,
+                disable_after_exhaustions,
+                jitter_percent: retry_jitter,
+            };
+            if matches!(retry_jitter, Some(percent) if percent > 100) {
+                return Err(anyhow::anyhow!("--retry-jitter must be between 0 and 100"));
+            }
+
+            let success_criteria = common::SuccessCriteria {
+                exit_codes: success_exit_codes,
+                failure_pattern,
+                max_duration_seconds: max_duration,
             };
+            success_criteria.validate().map_err(|e| anyhow::anyhow!(e))?;
 
             let resource_limits = common::ResourceLimits {
                 timeout_seconds: timeout,
@@ -139,11 +873,70 @@ async fn main() -> anyhow::Result<()> {
             let hooks = common::JobHooks {
                 on_success,
                 on_failure,
+                on_retry,
+                timeout_seconds: hook_timeout,
+            };
+
+            // Events without channels stay unset, leaving them to tag defaults
+            let channels = |specs: Vec<String>| -> anyhow::Result<Option<Vec<common::NotificationChannel>>> {
+                let channels = specs.iter().map(|spec| notify::parse_channel(spec)).collect::<anyhow::Result<Vec<_>>>()?;
+                Ok(Some(channels).filter(|c| !c.is_empty()))
+            };
+            let notification_config = common::NotificationConfig {
+                on_success: channels(notify_on_success)?,
+                on_failure: channels(notify_on_failure)?,
+                on_start: channels(notify_on_start)?,
+                on_retry: channels(notify_on_retry)?,
+                alerts: common::AlertPolicy {
+                    after_failures: alert_after_failures,
+                    min_success_rate: alert_min_success_rate,
+                    success_rate_window: alert_window,
+                },
+                template: notify_template,
             };
+            notification_config.validate().map_err(|e| anyhow::anyhow!(e))?;
 
-            let tags_vec = tags.map(|t| 
+            let mut tags_vec: Vec<String> = tags.map(|t| 
                 t.split(',').map(|s| s.trim().to_string()).collect()
             ).unwrap_or_default();
+            if let Some((ref template, _)) = template {
+                for tag in &template.tags {
+                    if !tags_vec.contains(tag) {
+                        tags_vec.push(tag.clone());
+                    }
+                }
+            }
+
+            let mut job_env = template.as_ref().map(|(_, r)| r.env.clone()).unwrap_or_default();
+            job_env.extend(env::collect(&env_files, &env)?);
+            let env = job_env;
+
+            let working_dir = match cwd {
+                Some(dir) if dir.is_relative() => Some(std::env::current_dir()?.join(dir)),
+                dir => dir,
+            };
+            let umask = match umask {
+                Some(m) => match u32::from_str_radix(m.trim_start_matches("0o"), 8) {
+                    Ok(mask) if mask <= 0o777 => Some(mask),
+                    _ => return Err(anyhow::anyhow!("Invalid --umask '{}', expected octal like 022", m)),
+                },
+                None => None,
+            };
+
+            let executor = match image {
+                Some(image) => common::Executor::Container { image, volumes, network },
+                None if !volumes.is_empty() || network.is_some() => {
+                    return Err(anyhow::anyhow!("--volume and --network need --image"));
+                }
+                None if systemd => common::Executor::Systemd { protect_system, properties: unit_properties },
+                None => common::Executor::Local,
+            };
+            executor.validate().map_err(|e| anyhow::anyhow!(e))?;
+            let sandbox = common::Sandbox { no_new_privileges, drop_capabilities, private_tmp, read_only_root, no_network };
+
+            let redact_env_vec = redact_env.map(|r|
+                r.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+            ).unwrap_or_default();
 
             // Parse priority
             let job_priority = match priority.to_lowercase().as_str() {
@@ -166,13 +959,30 @@ async fn main() -> anyhow::Result<()> {
                 }
             };
 
+            let overflow = match overflow_policy.to_lowercase().as_str() {
+                "skip" => common::OverflowPolicy::Skip,
+                "queue" => common::OverflowPolicy::Queue,
+                _ => {
+                    return Err(anyhow::anyhow!("Invalid overflow policy. Use: Skip or Queue"));
+                }
+            };
+
+            let catch_up_policy = match catch_up.to_lowercase().as_str() {
+                "skip" => common::CatchUpPolicy::Skip,
+                "runonce" | "run-once" => common::CatchUpPolicy::RunOnce,
+                "runall" | "run-all" => common::CatchUpPolicy::RunAll,
+                _ => {
+                    return Err(anyhow::anyhow!("Invalid catch-up policy. Use: Skip, RunOnce, or RunAll"));
+                }
+            };
+
             let job = Job {
-                id: JobId(name.clone()),
+                id: JobId::default(), // Assigned by the daemon; a job of the same name keeps its own
                 name,
                 schedule: schedule_config,
                 command,
                 args,
-                env: HashMap::new(),
+                env,
                 enabled: true,
                 owner: String::new(),
                 retry_policy,
@@ -182,100 +992,840 @@ async fn main() -> anyhow::Result<()> {
                 tags: tags_vec,
                 dependencies: vec![],
                 hooks,
-                max_concurrent: 0,
+                max_concurrent,
+                overflow_policy: overflow,
                 priority: job_priority,
                 execution_mode: exec_mode,
-                notification_config: common::NotificationConfig::default(),
+                notification_config,
+                redact_env: redact_env_vec,
+                fence_key,
+                capture_env,
+                catch_up: catch_up_policy,
+                boot_delay,
+                delete_after_run,
+                run_as,
+                working_dir,
+                umask,
+                concurrency_group,
+                success_criteria,
+                max_output_bytes,
+                run_immediately,
+                on_success_trigger: on_success_trigger.into_iter().map(JobId).collect(),
+                on_failure_trigger: on_failure_trigger.into_iter().map(JobId).collect(),
+                disable_after_failures,
+                expires_at: expires_at.as_deref().map(parse_expiry).transpose()?,
+                target,
+                executor,
+                sandbox,
+                script: script.map(|(_, body)| body),
+                disabled_reason: None,
             };
-            Request::AddJob(job)
+            Request::EnsureJob { job, if_absent }
         },
-        Commands::List => Request::ListJobs,
-        Commands::Start { id } => Request::StartJob(JobId(id)),
-        Commands::History { id, all } => Request::GetHistory { 
-            job_id: JobId(id), 
-            limit: if all { None } else { Some(5) } 
+        Commands::List { watch: Some(_), .. }
+        | Commands::History { watch: Some(_), .. }
+        | Commands::Ps { watch: Some(_) } if output != OutputFormat::Table => {
+            return Err(anyhow::anyhow!("--watch only works with --output table"));
         },
-        Commands::Remove { id } => Request::RemoveJob(JobId(id)),
-        Commands::Get { id } => Request::GetJob(JobId(id)),
-    };
-
-    let req_bytes = serde_json::to_vec(&req)?;
-    stream.write_all(&req_bytes).await?;
-
-    // Read complete response with proper buffering
-    let mut complete_buf = Vec::new();
-    let mut temp_buf = vec![0; 8192];
-    
-    loop {
-        let n = match tokio::time::timeout(
-            std::time::Duration::from_secs(30),
-            stream.read(&mut temp_buf)
-        ).await {
-            Ok(Ok(0)) => break,  // EOF
-            Ok(Ok(n)) => n,
-            Ok(Err(e)) => {
-                eprintln!("Failed to read response from daemon: {}", e);
-                return Err(e.into());
+        Commands::List { watch: Some(interval), failed } => {
+            let (req, command) = if failed { (Request::ListDeadLetters, "list --failed") } else { (Request::ListJobs, "list") };
+            return watch::run(stream, &req, command, interval).await;
+        },
+        Commands::List { watch: None, failed: true } => Request::ListDeadLetters,
+        Commands::List { watch: None, failed: false } => Request::ListJobs,
+        Commands::Ps { watch: Some(interval) } => {
+            return watch::run_with(stream, &Request::ListJobs, "ps", interval, print_ps).await;
+        },
+        Commands::Ps { watch: None } => {
+            let resp = send_request(stream, &Request::ListJobs).await?;
+            return match resp {
+                Response::JobListing(jobs) if output != OutputFormat::Table => {
+                    let running: Vec<common::RunningExecution> = running_executions(jobs).into_iter().map(|(_, e)| e).collect();
+                    print_value(&serde_json::to_value(running)?, output)
+                }
+                Response::JobListing(jobs) => {
+                    print_ps(Response::JobListing(jobs));
+                    Ok(())
+                }
+                other => output_response(other, output),
+            };
+        },
+        Commands::Retry { id } => Request::RetryJob(JobId(resolve_job(stream, &id).await?)),
+        Commands::Preview { schedule, timezone, count } => {
+            common::parse_schedule(&schedule)?;
+            Request::PreviewSchedule { schedule, timezone, count }
+        },
+        Commands::Windows { job, limit } => Request::GetExecutionWindows { job_id: job.map(JobId), limit },
+        Commands::Audit { job, since, limit } => {
+            let since = since.as_deref().map(parse_since).transpose()?;
+            Request::GetAuditLog { job_id: job.map(JobId), since, limit }
+        },
+        Commands::Run { name, timeout, max_memory_mb, cpu_quota, run_as, env, env_files, cwd, detach, command } => {
+            let (program, args) = command.split_first().expect("clap requires a command");
+            let working_dir = match cwd {
+                Some(dir) if dir.is_relative() => Some(std::env::current_dir()?.join(dir)),
+                dir => dir,
+            };
+            let job = Job {
+                id: JobId(String::new()), // Assigned by the daemon
+                name: name.unwrap_or_else(|| command.join(" ")),
+                schedule: common::ScheduleConfig::Once(chrono::Utc::now()),
+                command: program.clone(),
+                args: args.to_vec(),
+                env: env::collect(&env_files, &env)?,
+                enabled: false,
+                owner: String::new(),
+                retry_policy: common::RetryPolicy { max_attempts: Some(0), ..Default::default() },
+                resource_limits: common::ResourceLimits { timeout_seconds: timeout, max_memory_mb, cpu_quota },
+                jitter_seconds: 0,
+                timezone: None,
+                tags: vec![],
+                dependencies: vec![],
+                hooks: common::JobHooks::default(),
+                max_concurrent: 0,
+                overflow_policy: common::OverflowPolicy::Skip,
+                priority: common::JobPriority::Normal,
+                execution_mode: common::ExecutionMode::Sequential,
+                notification_config: common::NotificationConfig::default(),
+                redact_env: vec![],
+                fence_key: None,
+                capture_env: false,
+                catch_up: common::CatchUpPolicy::Skip,
+                boot_delay: None,
+                delete_after_run: false,
+                run_as,
+                working_dir,
+                umask: None,
+                concurrency_group: None,
+                success_criteria: common::SuccessCriteria::default(),
+                max_output_bytes: None,
+                run_immediately: false,
+                on_success_trigger: vec![],
+                on_failure_trigger: vec![],
+                disable_after_failures: None,
+                expires_at: None,
+                target: None,
+                executor: common::Executor::Local,
+                sandbox: common::Sandbox::default(),
+                script: None,
+                disabled_reason: None,
+            };
+            let req = Request::RunOnce { job, follow: !detach };
+            if detach {
+                req
+            } else {
+                let execution = logs::run_once(stream, &req).await?;
+                // Exit like the command did
+                let code = match send_request(stream, &Request::GetExecution {
+                    job_id: JobId(execution.0), execution_id: execution.1,
+                }).await? {
+                    Response::ExecutionDetail { entry: Some(entry), .. } => execution_exit_code(&entry),
+                    _ => 1,
+                };
+                std::process::exit(code);
             }
-            Err(_) => {
+        },
+        Commands::Start { wait: true, .. } => unreachable!("start --wait connects on its own"),
+        Commands::Start { id, params, .. } => start_request(resolve_job(stream, &id).await?, params)?,
+        Commands::History { id: Some(id), execution: Some(execution_id), timeline, .. } => {
+            let id = resolve_job(stream, &id).await?;
+            let req = Request::GetExecution { job_id: JobId(id), execution_id: execution_id.clone() };
+            let resp = send_request(stream, &req).await?;
+            if output != OutputFormat::Table {
+                return output_response(resp, output);
+            }
+            match resp {
+                Response::ExecutionDetail { entry: None, .. } => {
+                    return Err(anyhow::anyhow!("Execution {} not found", execution_id));
+                }
+                Response::ExecutionDetail { entry: Some(entry), timeline: events } => {
+                    print_execution(entry, "Execution");
+                    if timeline {
+                        print_timeline(&events);
+                    }
+                }
+                resp => print_response(resp),
+            }
+            return Ok(());
+        },
+        Commands::History { id, all, all_jobs, status, since, grep, watch, export, .. } => {
+            let id = match id {
+                Some(id) if !all_jobs => Some(resolve_job(stream, &id).await?),
+                _ => None,
+            };
+            let command = format!("history {}", id.as_deref().unwrap_or("--all-jobs"));
+            let limit = match (all, &id) {
+                (true, _) => None,
+                (false, Some(_)) => Some(5),
+                (false, None) => Some(20),
+            };
+            let filter = HistoryFilter { status, since: since.as_deref().map(parse_since).transpose()?, grep };
+            if let Some(format) = export {
+                let req = Request::ExportHistory { job_id: id.map(JobId), filter };
+                return export::history(stream, &req, &format).await;
+            }
+            let req = Request::GetHistory { job_id: id.map(JobId), limit, filter };
+            if let Some(interval) = watch {
+                return watch::run(stream, &req, &command, interval).await;
+            }
+            req
+        },
+        Commands::Rerun { execution_id } => Request::Rerun { execution_id },
+        Commands::Remove { id } => {
+            // Only an exact ID or name removes a job
+            let resolved = resolve_job(stream, &id).await?;
+            if resolved != id {
+                match send_request(stream, &Request::GetJob(JobId(resolved.clone()))).await? {
+                    Response::JobDetail(Some(job)) if job.name == id => {}
+                    _ => return Err(anyhow::anyhow!("'{}' only starts the ID or name of {}; give it in full to remove it", id, resolved)),
+                }
+            }
+            Request::RemoveJob(JobId(resolved))
+        },
+        Commands::Rename { id, new_name } => {
+            Request::RenameJob { job_id: JobId(resolve_job(stream, &id).await?), name: new_name }
+        },
+        Commands::Clone { id, name, schedule, disabled } => {
+            let schedule = schedule.as_deref().map(common::parse_schedule).transpose()?;
+            Request::CloneJob { job_id: JobId(resolve_job(stream, &id).await?), name, schedule, enabled: !disabled }
+        },
+        Commands::Get { id } => {
+            let id = resolve_job(stream, &id).await?;
+            let resp = send_request(stream, &Request::GetJob(JobId(id.clone()))).await?;
+            if output != OutputFormat::Table {
+                return output_response(resp, output);
+            }
+            let found = matches!(resp, Response::JobDetail(Some(_)));
+            print_response(resp);
+            if found {
+                let req = Request::GetHistory { job_id: Some(JobId(id.clone())), limit: Some(1), filter: HistoryFilter::default() };
+                if let Response::HistoryList(history) = send_request(stream, &req).await? {
+                    if let Some(entry) = history.into_iter().next() {
+                        print_execution(entry, "Last Execution");
+                    }
+                }
+                let req = Request::GetStatus { verbose: false };
+                if let Response::Status(status) = send_request(stream, &req).await? {
+                    for retry in status.pending_retries.into_iter().filter(|retry| retry.job_id == id) {
+                        println!("Next retry: attempt {} at {}", retry.attempt + 1,
+                            retry.next_attempt_at.unwrap_or_else(|| "-".to_string()));
+                    }
+                }
+            }
+            return Ok(());
+        },
+        Commands::Stats { id } => Request::GetMetrics(JobId(resolve_job(stream, &id).await?)),
+        Commands::DiffOutput { id, from, to } => {
+            let id = resolve_job(stream, &id).await?;
+            return diff::run(stream, &id, from, to).await;
+        },
+        Commands::Logs { id, execution, follow } => {
+            let id = resolve_job(stream, &id).await?;
+            return logs::run(stream, &id, execution, follow).await;
+        },
+        Commands::Top { .. } if output != OutputFormat::Table => {
+            return Err(anyhow::anyhow!("top only works with --output table"));
+        },
+        Commands::Top { interval } => {
+            return top::run(stream, interval).await;
+        },
+        Commands::Watch { jobs } => {
+            return watch::events(stream, jobs, output).await;
+        },
+        Commands::Notify { action: NotifyCommands::Test { id, channel, preview } } => {
+            let id = resolve_job(stream, &id).await?;
+            let resp = send_request(stream, &Request::TestNotification { job_id: JobId(id), channel, preview }).await?;
+            let failed = matches!(&resp, Response::NotificationTestResults(results) if results.iter().any(|r| r.error.is_some()));
+            output_response(resp, output)?;
+            if failed {
+                return Err(anyhow::anyhow!("One or more notification channels failed"));
+            }
+            return Ok(());
+        },
+        Commands::Ping { id, show_token: false } => Request::Ping(JobId(id)),
+        Commands::Ping { id, show_token: true } => Request::GetPingToken(JobId(id)),
+        Commands::Status { verbose } => Request::GetStatus { verbose },
+        Commands::Doctor => {
+            let checks = doctor::daemon_checks(stream).await?;
+            return doctor::report(checks, output);
+        },
+        Commands::Backup { path } => Request::BackupDatabase { path: daemon_path(path)? },
+        Commands::Agents => Request::ListAgents,
+        Commands::Restore { path, yes } => {
+            if !yes && !confirm("Replace all jobs and history in the daemon's database with the backup's? [y/N] ")? {
+                return Ok(());
+            }
+            Request::RestoreDatabase { path: daemon_path(path)? }
+        },
+        Commands::Export { format, tag, out } => {
+            return export::export(stream, &format, tag, out).await;
+        },
+        Commands::Import { file, on_conflict } => {
+            return export::import(stream, &file, &on_conflict).await;
+        },
+        Commands::ImportCrontab { user, file, dry_run, yes } => {
+            return crontab::run(stream, user, file, dry_run, yes).await;
+        },
+        Commands::Rebalance { days, max_per_slot, apply, yes } => {
+            return rebalance::run(stream, days, max_per_slot, apply, yes).await;
+        },
+        Commands::Apply { file, file_option, source, prune, dry_run } => {
+            let file = file.or(file_option).expect("clap requires a file");
+            return apply::run(stream, &file, source, prune, dry_run).await;
+        },
+        Commands::Workflow { action } => match action {
+            WorkflowCommands::Add { name, steps } => {
+                let steps = steps.iter().map(|spec| {
+                    let (job, after) = spec.split_once(':').unwrap_or((spec.as_str(), ""));
+                    common::WorkflowStep {
+                        job: job.trim().to_string(),
+                        after: after.split(',').map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect(),
+                    }
+                }).collect();
+                let workflow = common::Workflow { name, steps, owner: String::new() };
+                workflow.validate()?;
+                Request::AddWorkflow(workflow)
+            },
+            WorkflowCommands::List => Request::ListWorkflows,
+            WorkflowCommands::Run { name } => Request::RunWorkflow(name),
+            WorkflowCommands::Status { name, limit } => Request::GetWorkflowRuns { name, limit },
+            WorkflowCommands::Rm { name } => Request::RemoveWorkflow(name),
+        },
+        Commands::Template { action } => match action {
+            TemplateCommands::Add { name, command, schedule, env, env_files, tags, args } => {
+                if let Some(ref schedule) = schedule {
+                    common::parse_schedule(schedule)?;
+                }
+                Request::AddTemplate(common::JobTemplate {
+                    name,
+                    command,
+                    args,
+                    env: env::collect(&env_files, &env)?,
+                    schedule,
+                    tags: tags.map(|t| t.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+                        .unwrap_or_default(),
+                    owner: String::new(),
+                })
+            },
+            TemplateCommands::List => Request::ListTemplates,
+            TemplateCommands::Show { name } => Request::GetTemplate(name),
+            TemplateCommands::Rm { name } => Request::RemoveTemplate(name),
+        },
+        Commands::Secret { action } => match action {
+            SecretCommands::Set { name, value } => {
+                let value = match value {
+                    Some(value) => value,
+                    None => read_secret_value(&name)?,
+                };
+                Request::SetSecret { name, value }
+            },
+            SecretCommands::Get { name } => Request::GetSecret { name },
+            SecretCommands::Rm { name } => Request::RemoveSecret { name },
+            SecretCommands::List => Request::ListSecrets,
+        },
+        Commands::CompleteJobIds => {
+            if let Response::JobListing(jobs) = send_request(stream, &Request::ListJobs).await? {
+                for listed in jobs {
+                    println!("{}", listed.job.id);
+                }
+            }
+            return Ok(());
+        },
+        Commands::Shell => return Err(anyhow::anyhow!("Already in a shell")),
+        Commands::Bundle { .. } | Commands::Validate { .. } | Commands::Completions { .. } => {
+            unreachable!("offline commands are handled by run_offline")
+        }
+    };
+
+    let resp = send_request(stream, &req).await?;
+    output_response(resp, output)
+}
+
+// Jobs as a table, with what they are doing when known
+fn print_jobs(jobs: Vec<(Job, Option<common::JobRuntime>)>) {
+    if jobs.is_empty() {
+        println!("No jobs found.");
+        return;
+    }
+    let with_status = jobs.iter().any(|(_, status)| status.is_some());
+    let mut table = comfy_table::Table::new();
+    let mut header = vec!["ID", "Name", "Schedule", "Command", "Enabled", "Owner", "Source"];
+    if with_status {
+        header.push("Status");
+    }
+    table.set_header(header);
+
+    for (job, status) in jobs {
+        let source = common::declarative::job_source(&job).unwrap_or("-").to_string();
+        let mut row = vec![
+            job.id.0,
+            job.name,
+            job.schedule.to_string(),
+            job.command,
+            job.enabled.to_string(),
+            job.owner,
+            source,
+        ];
+        if with_status {
+            row.push(status.map(|status| format_runtime(&status)).unwrap_or_else(|| "-".to_string()));
+        }
+        table.add_row(row);
+    }
+    println!("{}", table);
+}
+
+// "Running (PID 4242, 1m 05s)", "Pending retry 2 (in 30s)", "Idle (next in 4m 55s)", ...
+fn format_runtime(status: &common::JobRuntime) -> String {
+    use common::JobRuntime;
+    let until = |at: &Option<String>| at.as_deref()
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+        .map(|at| (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds().max(0) as u64);
+    match status {
+        JobRuntime::Running { executions } => match executions.as_slice() {
+            [execution] => match execution.pid {
+                Some(pid) => format!("Running (PID {}, {})", pid, top::format_seconds(execution.duration_seconds)),
+                None => format!("Running ({})", top::format_seconds(execution.duration_seconds)),
+            },
+            executions => format!("Running x{} (oldest {})", executions.len(),
+                top::format_seconds(executions.iter().map(|e| e.duration_seconds).max().unwrap_or(0))),
+        },
+        JobRuntime::Queued { runs } => format!("Queued ({} run{})", runs, if *runs == 1 { "" } else { "s" }),
+        JobRuntime::PendingRetry { attempt, next_attempt_at } => match until(next_attempt_at) {
+            Some(wait) => format!("Pending retry {} (in {})", attempt + 1, top::format_seconds(wait)),
+            None => format!("Pending retry {}", attempt + 1),
+        },
+        JobRuntime::Idle { next_run } => match until(next_run) {
+            Some(wait) => format!("Idle (next in {})", top::format_seconds(wait)),
+            None => "Idle".to_string(),
+        },
+    }
+}
+
+// The running executions of a job listing, for `ps`
+fn running_executions(jobs: Vec<common::ListedJob>) -> Vec<(String, common::RunningExecution)> {
+    let mut running: Vec<(String, common::RunningExecution)> = jobs.into_iter()
+        .flat_map(|listed| match listed.status {
+            common::JobRuntime::Running { executions } => {
+                executions.into_iter().map(|execution| (listed.job.name.clone(), execution)).collect()
+            }
+            _ => Vec::new(),
+        })
+        .collect();
+    running.sort_by(|a, b| a.1.started_at.cmp(&b.1.started_at));
+    running
+}
+
+fn print_ps(resp: Response) {
+    let jobs = match resp {
+        Response::JobListing(jobs) => jobs,
+        other => return print_response(other),
+    };
+    let running = running_executions(jobs);
+    if running.is_empty() {
+        println!("No running executions.");
+        return;
+    }
+    let mut table = comfy_table::Table::new();
+    table.set_header(vec!["Execution ID", "Job", "Name", "PID", "Started", "Elapsed"]);
+    for (name, execution) in running {
+        let started = chrono::DateTime::parse_from_rfc3339(&execution.started_at)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or(execution.started_at);
+        table.add_row(vec![
+            execution.execution_id,
+            execution.job_id,
+            name,
+            execution.pid.map_or_else(|| "-".to_string(), |pid| pid.to_string()),
+            started,
+            top::format_seconds(execution.duration_seconds),
+        ]);
+    }
+    println!("{}", table);
+}
+
+/// Print a response in the chosen format. JSON and YAML hold the payload itself (`list` gives
+/// an array of jobs, `status` an object of DaemonStatus fields); errors are returned so the
+/// exit code shows them.
+fn output_response(resp: Response, format: OutputFormat) -> anyhow::Result<()> {
+    if format == OutputFormat::Table {
+        print_response(resp);
+        return Ok(());
+    }
+    // Secret env values are masked as in the table
+    let mask = |job: &mut Job| job.env = env::masked(&job.env, &job.redact_env).into_iter().collect();
+    let value = match resp {
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        Response::JobDetail(Some(mut job)) => {
+            mask(&mut job);
+            serde_json::to_value(job)?
+        }
+        Response::JobListing(mut jobs) => {
+            jobs.iter_mut().for_each(|listed| mask(&mut listed.job));
+            serde_json::to_value(jobs)?
+        }
+        Response::Ok => serde_json::json!({ "status": "ok" }),
+        Response::JobDetail(None) => return Err(anyhow::anyhow!("Job not found")),
+        other => match serde_json::to_value(other)? {
+            // Drop the variant name around the payload
+            serde_json::Value::Object(map) if map.len() == 1 => map.into_iter().next().map(|(_, v)| v).unwrap_or_default(),
+            value => value,
+        },
+    };
+    print_value(&value, format)
+}
+
+fn print_value(value: &serde_json::Value, format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        OutputFormat::Table => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Connect to the local daemon socket, or to a remote daemon when --host is given
+async fn connect(cli: &Cli) -> anyhow::Result<DaemonStream> {
+    let timeout = std::time::Duration::from_secs(10);
+
+    let host = match cli.host {
+        Some(ref host) => host,
+        None => return connect_local(cli.socket.as_deref(), timeout).await,
+    };
+
+    let addr = format!("{}:{}", host, cli.port);
+    let token = cli.token.clone()
+        .ok_or_else(|| anyhow::anyhow!("--token (or LUNASCHED_TOKEN) is required to connect to {}", addr))?;
+    let mut stream: DaemonStream = match tokio::time::timeout(timeout, TcpStream::connect(&addr)).await {
+        Ok(Ok(stream)) => Box::new(stream),
+        Ok(Err(e)) => {
+            eprintln!("Failed to connect to daemon at {}: {}", addr, e);
+            return Err(e.into());
+        }
+        Err(_) => {
+            eprintln!("Connection timeout: daemon at {} is not responding", addr);
+            return Err(anyhow::anyhow!("Connection timeout"));
+        }
+    };
+
+    match send_request(&mut stream, &Request::Authenticate(token)).await? {
+        Response::Ok => Ok(stream),
+        Response::Error(e) => Err(anyhow::anyhow!("{}: {}", addr, e)),
+        other => Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Connect to the given socket, or to the user's own daemon if one is listening, else to the
+/// system daemon
+async fn connect_local(socket: Option<&str>, timeout: std::time::Duration) -> anyhow::Result<DaemonStream> {
+    let sockets = match socket {
+        Some(socket) => vec![socket.to_string()],
+        None => common::Paths::client_sockets(),
+    };
+    let (last, preferred) = sockets.split_last().expect("at least one socket path");
+    for socket_path in preferred {
+        if !std::path::Path::new(socket_path).exists() {
+            continue;
+        }
+        // A socket left behind by a user daemon that is gone falls through to the system one
+        if let Ok(Ok(stream)) = tokio::time::timeout(timeout, UnixStream::connect(socket_path)).await {
+            return Ok(Box::new(stream));
+        }
+    }
+    let socket_path = last;
+
+    // Add timeout to connection
+    match tokio::time::timeout(timeout, UnixStream::connect(socket_path)).await {
+        Ok(Ok(stream)) => Ok(Box::new(stream)),
+        Ok(Err(e)) => {
+            eprintln!("Failed to connect to daemon at {}: {}", socket_path, e);
+            eprintln!("Is the lunasched daemon running? Try: sudo systemctl status lunasched");
+            eprintln!("(or start a private one as your user: lunasched-daemon --user)");
+            Err(e.into())
+        }
+        Err(_) => {
+            eprintln!("Connection timeout: daemon at {} is not responding", socket_path);
+            eprintln!("Is the lunasched daemon running? Try: sudo systemctl status lunasched");
+            Err(anyhow::anyhow!("Connection timeout"))
+        }
+    }
+}
+
+/// The ID of the job `reference` names: its ID, its name, or the start of either when only
+/// one job matches. Errors suggest jobs for ambiguous and unknown references.
+async fn resolve_job(stream: &mut DaemonStream, reference: &str) -> anyhow::Result<String> {
+    match send_request(stream, &Request::ResolveJob(reference.to_string())).await? {
+        Response::JobDetail(Some(job)) => Ok(job.id.0),
+        Response::JobDetail(None) => Err(anyhow::anyhow!("Job not found: {}", reference)),
+        Response::Error(e) => Err(anyhow::anyhow!(e)),
+        other => Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Send a request over an open daemon connection and wait for its response
+async fn send_request(stream: &mut DaemonStream, req: &Request) -> anyhow::Result<Response> {
+    let req_bytes = encode_frame(req)?;
+    stream.write_all(&req_bytes).await?;
+
+    // Read complete response with proper buffering
+    let mut frames = FrameReader::default();
+    let mut temp_buf = vec![0; 8192];
+    
+    loop {
+        let n = match tokio::time::timeout(
+            std::time::Duration::from_secs(30),
+            stream.read(&mut temp_buf)
+        ).await {
+            Ok(Ok(0)) => break,  // EOF
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => {
+                eprintln!("Failed to read response from daemon: {}", e);
+                return Err(e.into());
+            }
+            Err(_) => {
                 eprintln!("Read timeout: daemon is not responding to the request");
                 eprintln!("The daemon may be stuck or overloaded. Check logs at: {}", common::DEFAULT_LOG_FILE);
                 return Err(anyhow::anyhow!("Read timeout"));
             }
         };
         
-        complete_buf.extend_from_slice(&temp_buf[0..n]);
+        frames.push(&temp_buf[0..n]);
         
-        // Try to parse - if successful, we have complete response
-        if let Ok(resp) = serde_json::from_slice::<Response>(&complete_buf) {
-            // Successfully parsed, handle response
-            match resp {
+        if let Some(resp) = frames.next::<Response>()? {
+            return Ok(resp);
+        }
+        
+        // If buffer grows too large, something is wrong
+        if frames.buffered() > 10 * 1024 * 1024 {  // 10MB limit
+            eprintln!("Response too large: {} bytes", frames.buffered());
+            return Err(anyhow::anyhow!("Response too large"));
+        }
+    }
+    
+    // If we get here, connection closed before complete response
+    Err(anyhow::anyhow!("Connection closed before receiving complete response"))
+}
+
+/// Start of an `--since` range as RFC 3339: a duration ago, a local date, or a timestamp
+fn parse_since(value: &str) -> anyhow::Result<String> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(t.to_rfc3339());
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+            .ok_or_else(|| anyhow::anyhow!("No local midnight on {}", date))?;
+        return Ok(midnight.to_rfc3339());
+    }
+    match common::parse_duration(value) {
+        Ok(secs) => Ok((chrono::Utc::now() - chrono::Duration::seconds(secs as i64)).to_rfc3339()),
+        Err(_) => Err(anyhow::anyhow!("Invalid --since '{}': expected a duration ago (24h), a date (YYYY-MM-DD) or an RFC 3339 time", value)),
+    }
+}
+
+/// Time of `--expires-at`: a duration from now, a local time, or a timestamp
+fn parse_expiry(value: &str) -> anyhow::Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&chrono::Utc));
+    }
+    let local = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.and_hms_opt(0, 0, 0).unwrap()));
+    if let Ok(local) = local {
+        return local.and_local_timezone(chrono::Local).earliest()
+            .map(|t| t.with_timezone(&chrono::Utc))
+            .ok_or_else(|| anyhow::anyhow!("Time '{}' does not exist in the local timezone", value));
+    }
+    match common::parse_duration(value) {
+        Ok(secs) => Ok(chrono::Utc::now() + chrono::Duration::seconds(secs as i64)),
+        Err(_) => Err(anyhow::anyhow!("Invalid --expires-at '{}': expected a duration from now (30d), a local time (YYYY-MM-DD HH:MM) or an RFC 3339 time", value)),
+    }
+}
+
+/// Read a secret value from stdin (the first line, without its newline)
+fn read_secret_value(name: &str) -> anyhow::Result<String> {
+    use std::io::IsTerminal;
+    if std::io::stdin().is_terminal() {
+        eprint!("Value for secret {}: ", name);
+    }
+    let mut value = String::new();
+    std::io::stdin().read_line(&mut value)?;
+    let value = value.trim_end_matches(['\n', '\r']).to_string();
+    if value.is_empty() {
+        return Err(anyhow::anyhow!("Empty value for secret {}", name));
+    }
+    Ok(value)
+}
+
+/// A path for the daemon to open; the daemon doesn't share our working directory
+fn daemon_path(path: String) -> anyhow::Result<String> {
+    if std::path::Path::new(&path).is_absolute() {
+        return Ok(path);
+    }
+    Ok(std::env::current_dir()?.join(path).display().to_string())
+}
+
+/// Ask a yes/no question on the terminal; anything but y/yes is a no
+fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    use std::io::Write;
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Start request for a job, with its run parameters (KEY=VALUE) if any
+fn start_request(id: String, params: Vec<String>) -> anyhow::Result<Request> {
+    if params.is_empty() {
+        return Ok(Request::StartJob(JobId(id)));
+    }
+    let mut param_map = HashMap::new();
+    for param in params {
+        match param.split_once('=') {
+            Some((key, value)) => param_map.insert(key.to_string(), value.to_string()),
+            None => return Err(anyhow::anyhow!("Invalid --param '{}', expected KEY=VALUE", param)),
+        };
+    }
+    Ok(Request::StartJobWithParams { job_id: JobId(id), params: param_map })
+}
+
+/// The CLI's exit code for an execution it waited for: 0 for success, else the command's own
+/// exit code, 128 + the signal that killed it (as shells report it), or 1
+fn execution_exit_code(entry: &common::HistoryEntry) -> i32 {
+    match (entry.status.as_str(), entry.exit_code, entry.signal) {
+        ("success", _, _) => 0,
+        (_, Some(code), _) if code != 0 => code,
+        (_, None, Some(signal)) => 128 + signal,
+        _ => 1,
+    }
+}
+
+fn format_duration_ms(duration_ms: Option<i64>) -> String {
+    match duration_ms {
+        Some(ms) if ms >= 1000 => format!("{:.1}s", ms as f64 / 1000.0),
+        Some(ms) => format!("{}ms", ms),
+        None => "-".to_string(),
+    }
+}
+
+/// A history status, green for success, red for failures and yellow for anything else
+/// (e.g. "unknown" for executions whose outcome the daemon didn't see)
+fn status_cell(status: &str) -> comfy_table::Cell {
+    let color = match status {
+        "success" => comfy_table::Color::Green,
+        "failed" | "Error" | "SpawnError" => comfy_table::Color::Red,
+        _ => comfy_table::Color::Yellow,
+    };
+    comfy_table::Cell::new(status).fg(color)
+}
+
+/// Print one history entry in detail (`lunasched get`, `history --execution`)
+fn print_execution(entry: common::HistoryEntry, title: &str) {
+    use comfy_table::Cell;
+    fn preview(text: &str) -> String {
+        let text = text.trim_end();
+        match text.char_indices().nth(200) {
+            Some((idx, _)) => format!("{}...", &text[..idx]),
+            None => text.to_string(),
+        }
+    }
+
+    let mut table = comfy_table::Table::new();
+    table.set_header(vec![title, ""]);
+    if let Some(ref execution_id) = entry.execution_id {
+        table.add_row(vec![Cell::new("Execution ID"), Cell::new(execution_id)]);
+    }
+    table.add_row(vec![Cell::new("Status"), status_cell(&entry.status)]);
+    if let Some(exit_code) = entry.exit_code {
+        table.add_row(vec![Cell::new("Exit Code"), Cell::new(exit_code.to_string())]);
+    }
+    if let Some(signal) = entry.signal {
+        table.add_row(vec![Cell::new("Signal"), Cell::new(signal.to_string())]);
+    }
+    if let Some(attempt) = entry.attempt {
+        table.add_row(vec![Cell::new("Attempt"), Cell::new(attempt.to_string())]);
+    }
+    if let Some(ref scheduled_at) = entry.scheduled_at {
+        table.add_row(vec![Cell::new("Scheduled"), Cell::new(scheduled_at)]);
+    }
+    table.add_row(vec![Cell::new("Started"), Cell::new(entry.started_at.as_ref().unwrap_or(&entry.run_at))]);
+    if entry.duration_ms.is_some() {
+        table.add_row(vec![Cell::new("Duration"), Cell::new(format_duration_ms(entry.duration_ms))]);
+    }
+    if let Some(ref events) = entry.resource_events {
+        table.add_row(vec![Cell::new("Resource Events"), Cell::new(events)]);
+    }
+    if let Some(ref hook_result) = entry.hook_result {
+        table.add_row(vec![Cell::new("Hook"), Cell::new(hook_result)]);
+    }
+    if let Some(hook_output) = entry.hook_output.as_deref().filter(|output| !output.trim().is_empty()) {
+        table.add_row(vec![Cell::new("Hook Output"), Cell::new(preview(hook_output))]);
+    }
+    if let Some(ref output) = entry.output {
+        table.add_row(vec![Cell::new("Message"), Cell::new(preview(output))]);
+    }
+    if let (Some(stdout_bytes), Some(stderr_bytes)) = (entry.stdout_bytes, entry.stderr_bytes) {
+        table.add_row(vec![Cell::new("Output Bytes"),
+            Cell::new(format!("stdout {}, stderr {}", stdout_bytes, stderr_bytes))]);
+    }
+    if let Some(ref log_path) = entry.log_path {
+        table.add_row(vec![Cell::new("Log File"), Cell::new(log_path)]);
+    }
+    if let Some(ref stdout) = entry.stdout {
+        table.add_row(vec![Cell::new("Stdout"), Cell::new(preview(stdout))]);
+    }
+    if let Some(ref stderr) = entry.stderr {
+        table.add_row(vec![Cell::new("Stderr"), Cell::new(preview(stderr))]);
+    }
+    println!("{}", table);
+}
+
+/// Print an execution's lifecycle events with the time elapsed since the first one
+fn print_timeline(events: &[common::TimelineEvent]) {
+    if events.is_empty() {
+        println!("No timeline recorded for this execution.");
+        return;
+    }
+    let parse = |at: &str| chrono::DateTime::parse_from_rfc3339(at).ok();
+    let origin = parse(&events[0].at);
+    let mut table = comfy_table::Table::new();
+    table.set_header(vec!["Event", "Time", "Elapsed", "Detail"]);
+    for event in events {
+        let elapsed = match (origin, parse(&event.at)) {
+            (Some(origin), Some(at)) => format!("+{}", format_duration_ms(Some((at - origin).num_milliseconds()))),
+            _ => "-".to_string(),
+        };
+        let time = match parse(&event.at) {
+            Some(at) => at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            None => event.at.clone(),
+        };
+        table.add_row(vec![
+            event.event.clone(),
+            time,
+            elapsed,
+            event.detail.clone().unwrap_or_default(),
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn print_response(resp: Response) {
+    match resp {
         Response::Ok => println!("Success"),
+        Response::JobAdded(id) => println!("Saved job {}", id),
+        Response::JobEnsured { id, outcome: common::SaveOutcome::Created, .. } => println!("Created job {}", id),
+        Response::JobEnsured { id, outcome: common::SaveOutcome::Updated, .. } => println!("Updated job {}", id),
+        Response::JobEnsured { id, outcome: common::SaveOutcome::Unchanged, .. } => println!("Job {} unchanged", id),
         Response::Error(e) => eprintln!("Error: {}", e),
-        Response::JobList(jobs) => {
-            if jobs.is_empty() {
-                println!("No jobs found.");
-            } else {
-                let mut table = comfy_table::Table::new();
-                table.set_header(vec!["ID", "Name", "Schedule", "Command", "Enabled", "Owner"]);
-                
-                for job in jobs {
-                    let schedule_str = match job.schedule {
-                        common::ScheduleConfig::Cron(s) => s,
-                        common::ScheduleConfig::Every(s) => format!("every {}s", s),
-                        common::ScheduleConfig::Calendar(p) => {
-                            let time = format!("{:02}:{:02}:{:02}", p.time.0, p.time.1, p.time.2);
-                            if let Some(days) = p.days_of_week {
-                                format!("on {:?} at {}", days, time)
-                            } else if let Some((n, d)) = p.nth_weekday {
-                                format!("on {}th day {} at {}", n, d, time)
-                            } else {
-                                format!("at {}", time)
-                            }
-                        }
-                    };
-                    
-                    table.add_row(vec![
-                        job.id.0,
-                        job.name,
-                        schedule_str,
-                        job.command,
-                        job.enabled.to_string(),
-                        job.owner,
-                    ]);
-                }
-                println!("{}", table);
-            }
-        },
+        Response::JobList(jobs) => print_jobs(jobs.into_iter().map(|job| (job, None)).collect()),
+        Response::JobListing(jobs) => print_jobs(jobs.into_iter().map(|listed| (listed.job, Some(listed.status))).collect()),
         Response::HistoryList(history) => {
             if history.is_empty() {
                 println!("No history found.");
             } else {
                 let mut table = comfy_table::Table::new();
-                table.set_header(vec!["Run At", "Job ID", "Status", "Output"]);
+                table.set_header(vec!["#", "Run At", "Job ID", "Execution", "Status", "Exit", "Signal", "Attempt", "Duration", "Output"]);
                 
                 for entry in history {
-                    let output_str = entry.output.unwrap_or_default();
+                    let output_str = entry.combined_output();
                     let output_preview: String = output_str.chars().take(50).collect();
                     let output_display = if output_str.len() > 50 {
                         format!("{}...", output_preview)
@@ -283,11 +1833,18 @@ async fn main() -> anyhow::Result<()> {
                         output_preview
                     };
                     
+                    let number = |n: Option<String>| comfy_table::Cell::new(n.unwrap_or_else(|| "-".to_string()));
                     table.add_row(vec![
-                        entry.run_at,
-                        entry.job_id,
-                        entry.status,
-                        output_display.replace("\n", " "),
+                        comfy_table::Cell::new(entry.id),
+                        comfy_table::Cell::new(entry.run_at),
+                        comfy_table::Cell::new(entry.job_id),
+                        comfy_table::Cell::new(entry.execution_id.unwrap_or_else(|| "-".to_string())),
+                        status_cell(&entry.status),
+                        number(entry.exit_code.map(|c| c.to_string())),
+                        number(entry.signal.map(|s| s.to_string())),
+                        number(entry.attempt.map(|a| a.to_string())),
+                        comfy_table::Cell::new(format_duration_ms(entry.duration_ms)),
+                        comfy_table::Cell::new(output_display.replace("\n", " ")),
                     ]);
                 }
                 println!("{}", table);
@@ -300,27 +1857,158 @@ async fn main() -> anyhow::Result<()> {
                     table.add_row(vec![Cell::new("ID"), Cell::new(&job.id.0)]);
                     table.add_row(vec![Cell::new("Name"), Cell::new(&job.name)]);
                     table.add_row(vec![Cell::new("Command"), Cell::new(&job.command)]);
-                    table.add_row(vec![Cell::new("Args"), Cell::new(&format!("{:?}", job.args))]);
-                    table.add_row(vec![Cell::new("Enabled"), Cell::new(&job.enabled.to_string())]);
+                    if let Some(ref script) = job.script {
+                        table.add_row(vec![Cell::new("Script"), Cell::new(format!("{} lines, {} bytes (shown with --output yaml)",
+                            script.lines().count(), script.len()))]);
+                    }
+                    table.add_row(vec![Cell::new("Args"), Cell::new(format!("{:?}", job.args))]);
+                    table.add_row(vec![Cell::new("Enabled"), Cell::new(job.enabled.to_string())]);
+                    if let (false, Some(reason)) = (job.enabled, &job.disabled_reason) {
+                        table.add_row(vec![Cell::new("Disabled Because"), Cell::new(reason)]);
+                    }
                     table.add_row(vec![Cell::new("Owner"), Cell::new(&job.owner)]);
-                    table.add_row(vec![Cell::new("Priority"), Cell::new(&format!("{:?}", job.priority))]);
-                    table.add_row(vec![Cell::new("Execution Mode"), Cell::new(&format!("{:?}", job.execution_mode))]);
-                    table.add_row(vec![Cell::new("Schedule"), Cell::new(&format!("{:?}", job.schedule))]);
+                    if let Some(source) = common::declarative::job_source(&job) {
+                        table.add_row(vec![Cell::new("Source"), Cell::new(source)]);
+                    }
+                    table.add_row(vec![Cell::new("Priority"), Cell::new(format!("{:?}", job.priority))]);
+                    table.add_row(vec![Cell::new("Execution Mode"), Cell::new(format!("{:?}", job.execution_mode))]);
+                    if job.execution_mode == common::ExecutionMode::Parallel {
+                        let limit = if job.max_concurrent == 0 { "unlimited".to_string() } else { job.max_concurrent.to_string() };
+                        table.add_row(vec![Cell::new("Max Concurrent"), Cell::new(&limit)]);
+                        table.add_row(vec![Cell::new("Overflow Policy"), Cell::new(format!("{:?}", job.overflow_policy))]);
+                    }
+                    table.add_row(vec![Cell::new("Schedule"), Cell::new(job.schedule.to_string())]);
                     
                     if !job.tags.is_empty() {
-                        table.add_row(vec![Cell::new("Tags"), Cell::new(&job.tags.join(", "))]);
+                        table.add_row(vec![Cell::new("Tags"), Cell::new(job.tags.join(", "))]);
+                    }
+                    if !job.env.is_empty() {
+                        let vars: Vec<String> = env::masked(&job.env, &job.redact_env).into_iter()
+                            .map(|(name, value)| format!("{}={}", name, value))
+                            .collect();
+                        table.add_row(vec![Cell::new("Env"), Cell::new(vars.join("\n"))]);
+                    }
+                    if !job.redact_env.is_empty() {
+                        table.add_row(vec![Cell::new("Redacted Env"), Cell::new(job.redact_env.join(", "))]);
                     }
                     if let Some(tz) = &job.timezone {
                         table.add_row(vec![Cell::new("Timezone"), Cell::new(tz)]);
                     }
+                    if let Some(fence_key) = &job.fence_key {
+                        table.add_row(vec![Cell::new("Fence Key"), Cell::new(fence_key)]);
+                    }
+                    if job.capture_env {
+                        table.add_row(vec![Cell::new("Capture Env"), Cell::new("yes")]);
+                    }
+                    if job.catch_up != common::CatchUpPolicy::Skip {
+                        table.add_row(vec![Cell::new("Catch Up"), Cell::new(format!("{:?}", job.catch_up))]);
+                    }
+                    if job.delete_after_run {
+                        table.add_row(vec![Cell::new("Delete After Run"), Cell::new("yes")]);
+                    }
+                    if job.run_immediately {
+                        table.add_row(vec![Cell::new("Run Immediately"), Cell::new("yes")]);
+                    }
+                    if let Some(limit) = job.disable_after_failures {
+                        table.add_row(vec![Cell::new("Disable After Failures"), Cell::new(limit.to_string())]);
+                    }
+                    if let Some(expires_at) = job.expires_at {
+                        table.add_row(vec![Cell::new("Expires At"), Cell::new(expires_at.with_timezone(&chrono::Local).to_rfc3339())]);
+                    }
+                    if let Some(ref target) = job.target {
+                        table.add_row(vec![Cell::new("Target"), Cell::new(target)]);
+                    }
+                    if let common::Executor::Container { ref image, ref volumes, ref network } = job.executor {
+                        let mut container = image.clone();
+                        for volume in volumes {
+                            container.push_str(&format!(", volume {}", volume));
+                        }
+                        if let Some(network) = network {
+                            container.push_str(&format!(", network {}", network));
+                        }
+                        table.add_row(vec![Cell::new("Container"), Cell::new(&container)]);
+                    }
+                    if let common::Executor::Systemd { ref protect_system, ref properties } = job.executor {
+                        let mut unit = vec!["systemd-run".to_string()];
+                        if let Some(mode) = protect_system {
+                            unit.push(format!("ProtectSystem={}", mode));
+                        }
+                        unit.extend(properties.iter().cloned());
+                        table.add_row(vec![Cell::new("Unit"), Cell::new(unit.join(", "))]);
+                    }
+                    if !job.sandbox.is_empty() {
+                        let sandbox = &job.sandbox;
+                        let restrictions: Vec<&str> = [
+                            (sandbox.no_new_privileges, "no new privileges"),
+                            (sandbox.drop_capabilities, "no capabilities"),
+                            (sandbox.private_tmp, "private /tmp"),
+                            (sandbox.read_only_root, "read-only root"),
+                            (sandbox.no_network, "no network"),
+                        ].iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect();
+                        table.add_row(vec![Cell::new("Sandbox"), Cell::new(restrictions.join(", "))]);
+                    }
+                    if let Some(ref run_as) = job.run_as {
+                        table.add_row(vec![Cell::new("Run As"), Cell::new(run_as)]);
+                    }
+                    if let Some(ref working_dir) = job.working_dir {
+                        table.add_row(vec![Cell::new("Working Dir"), Cell::new(working_dir.display().to_string())]);
+                    }
+                    if let Some(umask) = job.umask {
+                        table.add_row(vec![Cell::new("Umask"), Cell::new(format!("{:03o}", umask))]);
+                    }
+                    if !job.success_criteria.is_default() {
+                        let criteria = &job.success_criteria;
+                        let mut parts = Vec::new();
+                        if !criteria.exit_codes.is_empty() {
+                            let codes: Vec<String> = criteria.exit_codes.iter().map(|c| c.to_string()).collect();
+                            parts.push(format!("exit codes {}", codes.join(",")));
+                        }
+                        if let Some(ref pattern) = criteria.failure_pattern {
+                            parts.push(format!("fails on /{}/", pattern));
+                        }
+                        if let Some(max) = criteria.max_duration_seconds {
+                            parts.push(format!("max {}s", max));
+                        }
+                        table.add_row(vec![Cell::new("Success Criteria"), Cell::new(parts.join("; "))]);
+                    }
+                    if let Some(max_output_bytes) = job.max_output_bytes {
+                        table.add_row(vec![Cell::new("Max Output"), Cell::new(format!("{} bytes", max_output_bytes))]);
+                    }
+                    if let Some(ref group) = job.concurrency_group {
+                        table.add_row(vec![Cell::new("Concurrency Group"), Cell::new(group)]);
+                    }
+                    if let Some(boot_delay) = job.boot_delay {
+                        table.add_row(vec![Cell::new("Boot Delay"), Cell::new(format!("{}s", boot_delay))]);
+                    }
                     if job.jitter_seconds > 0 {
-                        table.add_row(vec![Cell::new("Jitter"), Cell::new(&format!("{}s", job.jitter_seconds))]);
+                        table.add_row(vec![Cell::new("Jitter"), Cell::new(format!("{}s", job.jitter_seconds))]);
+                    }
+                    if job.retry_policy.retries() > 0 {
+                        table.add_row(vec![Cell::new("Max Retries"), Cell::new(job.retry_policy.retries().to_string())]);
+                        if let Some(percent) = job.retry_policy.jitter_percent {
+                            table.add_row(vec![Cell::new("Retry Jitter"), Cell::new(format!("±{}%", percent))]);
+                        }
+                    }
+                    if let Some(ref on_retry) = job.hooks.on_retry {
+                        table.add_row(vec![Cell::new("Retry Hook"), Cell::new(on_retry)]);
                     }
-                    if job.retry_policy.max_attempts > 0 {
-                        table.add_row(vec![Cell::new("Max Retries"), Cell::new(&job.retry_policy.max_attempts.to_string())]);
+                    if let Some(timeout) = job.hooks.timeout_seconds {
+                        table.add_row(vec![Cell::new("Hook Timeout"), Cell::new(format!("{}s", timeout))]);
+                    }
+                    let triggers = [("on success", &job.on_success_trigger), ("on failure", &job.on_failure_trigger)];
+                    let triggers: Vec<String> = triggers.iter()
+                        .filter(|(_, ids)| !ids.is_empty())
+                        .map(|(event, ids)| format!("{}: {}", event, ids.iter().map(|id| id.0.as_str()).collect::<Vec<_>>().join(", ")))
+                        .collect();
+                    if !triggers.is_empty() {
+                        table.add_row(vec![Cell::new("Triggers"), Cell::new(triggers.join("\n"))]);
+                    }
+                    let notifications = notify::describe(&job.notification_config);
+                    if !notifications.is_empty() {
+                        table.add_row(vec![Cell::new("Notifications"), Cell::new(notifications.join("\n"))]);
                     }
                     if let Some(timeout) = job.resource_limits.timeout_seconds {
-                        table.add_row(vec![Cell::new("Timeout"), Cell::new(&format!("{}s", timeout))]);
+                        table.add_row(vec![Cell::new("Timeout"), Cell::new(format!("{}s", timeout))]);
                     }
                 
                 println!("{}", table);
@@ -328,18 +2016,418 @@ async fn main() -> anyhow::Result<()> {
                 println!("Job not found.");
             }
         },
+        Response::OutputChunk(text) => print!("{}", text),
+        Response::OutputEnd { .. } => {},
+        Response::HistoryChunk(history) => print_response(Response::HistoryList(history)),
+        Response::HistoryEnd { count } => println!("{} entries", count),
+        Response::Event(event) => println!("{} {} ({})", event.kind, event.job_name, event.job_id),
+        Response::EventsLost(skipped) => println!("{} events skipped", skipped),
+        Response::NotificationTestResults(results) => {
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec!["#", "Event", "Channel", "Target", "Result"]);
+            for result in results {
+                table.add_row(vec![
+                    result.index.to_string(),
+                    result.event,
+                    result.channel,
+                    result.target,
+                    match (result.error, result.message) {
+                        (Some(e), _) => format!("Failed: {}", e),
+                        (None, Some(message)) => message,
+                        (None, None) => "Delivered".to_string(),
+                    },
+                ]);
+            }
+            println!("{}", table);
+        },
+        Response::Imported(report) => {
+            for id in &report.created {
+                println!("create {}: done", id);
+            }
+            for id in &report.overwritten {
+                println!("overwrite {}: done", id);
+            }
+            for (id, new_id) in &report.renamed {
+                println!("rename {} -> {}: done", id, new_id);
+            }
+            for id in &report.skipped {
+                println!("skip {}: already exists", id);
+            }
+            println!("Imported: {} created, {} overwritten, {} renamed, {} skipped.",
+                report.created.len(), report.overwritten.len(), report.renamed.len(), report.skipped.len());
+        },
+        Response::ExecutionDetail { entry, timeline } => {
+            match entry {
+                Some(entry) => print_execution(entry, "Execution"),
+                None => println!("Execution not found."),
+            }
+            print_timeline(&timeline);
+        },
+        Response::WorkflowList(workflows) => {
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec!["Name", "Owner", "Steps"]);
+            for workflow in workflows {
+                let steps: Vec<String> = workflow.steps.iter().map(|step| if step.after.is_empty() {
+                    step.job.clone()
+                } else {
+                    format!("{} (after {})", step.job, step.after.join(", "))
+                }).collect();
+                table.add_row(vec![workflow.name, workflow.owner, steps.join("\n")]);
+            }
+            println!("{}", table);
+        },
+        Response::WorkflowStarted { run_id } => println!("Workflow run {} started", run_id),
+        Response::Health(report) => doctor::print(&report.checks),
+        Response::Backup(report) => println!("{}: {} jobs, {} history entries, schema version {}, {} bytes; integrity check passed",
+            report.path, report.jobs, report.history_entries, report.schema_version, report.size_bytes),
+        Response::AgentList(agents) => {
+            if agents.is_empty() {
+                println!("No agents connected.");
+            } else {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Name", "Labels", "Address", "Connected At", "Running"]);
+                for agent in agents {
+                    table.add_row(vec![agent.name, agent.labels.join(", "), agent.addr, agent.connected_at, agent.running.to_string()]);
+                }
+                println!("{}", table);
+            }
+        },
+        Response::ExecutionStarted { job_id, execution_id } => {
+            println!("Started {} (execution {}); follow it with: lunasched logs {} --follow", job_id, execution_id, job_id)
+        },
+        Response::WorkflowRuns(runs) => {
+            if runs.is_empty() {
+                println!("No runs recorded.");
+            }
+            for run in runs {
+                println!("Run {} of {}: {} (started {}{})", run.run_id, run.workflow, run.status(), run.started_at,
+                    run.finished_at.as_deref().map(|at| format!(", finished {}", at)).unwrap_or_default());
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Step", "State", "Execution ID", "Started", "Finished"]);
+                for step in run.steps {
+                    table.add_row(vec![
+                        step.job,
+                        step.state.to_string(),
+                        step.execution_id.unwrap_or_else(|| "-".to_string()),
+                        step.started_at.unwrap_or_else(|| "-".to_string()),
+                        step.finished_at.unwrap_or_else(|| "-".to_string()),
+                    ]);
+                }
+                println!("{}", table);
+            }
+        },
+        Response::JobStats(stats) => {
+            use comfy_table::Cell;
+            let never = || "never".to_string();
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec![format!("Stats for {}", stats.job_id), String::new()]);
+            table.add_row(vec![Cell::new("Runs"), Cell::new(stats.total_runs)]);
+            table.add_row(vec![Cell::new("Succeeded"), Cell::new(stats.successes)]);
+            table.add_row(vec![Cell::new("Failed"), Cell::new(stats.failures)]);
+            let rate = stats.success_rate().map_or_else(|| "-".to_string(), |rate| format!("{:.1}%", rate));
+            table.add_row(vec![Cell::new("Success Rate"), Cell::new(rate)]);
+            let avg = stats.avg_duration_ms.map_or_else(|| "-".to_string(), |ms| format_duration_ms(Some(ms as i64)));
+            table.add_row(vec![Cell::new("Avg Duration"), Cell::new(avg)]);
+            let last = stats.last_duration_ms.map_or_else(|| "-".to_string(), |ms| format_duration_ms(Some(ms as i64)));
+            table.add_row(vec![Cell::new("Last Duration"), Cell::new(last)]);
+            table.add_row(vec![Cell::new("Last Run"), Cell::new(stats.last_run_at.unwrap_or_else(never))]);
+            table.add_row(vec![Cell::new("Last Success"), Cell::new(stats.last_success_at.unwrap_or_else(never))]);
+            table.add_row(vec![Cell::new("Last Failure"), Cell::new(stats.last_failure_at.unwrap_or_else(never))]);
+            println!("{}", table);
+        },
+        Response::ExecutionWindows(windows) => {
+            if windows.is_empty() {
+                println!("No executions recorded.");
+            } else {
+                let local = |time: &str| chrono::DateTime::parse_from_rfc3339(time)
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|_| time.to_string());
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Job", "Scheduled", "Started", "Finished", "PID", "Window", "Execution ID"]);
+                for window in windows {
+                    table.add_row(vec![
+                        window.job_id.clone(),
+                        local(&window.scheduled_time),
+                        local(&window.started_at),
+                        window.finished_at.as_deref().map(local).unwrap_or_else(|| "-".to_string()),
+                        window.pid.map_or_else(|| "-".to_string(), |pid| pid.to_string()),
+                        if window.fired_window { "fired" } else { "-" }.to_string(),
+                        window.execution_id,
+                    ]);
+                }
+                println!("{}", table);
+            }
+        },
+        Response::AuditLog(entries) => {
+            if entries.is_empty() {
+                println!("No audit entries.");
+            } else {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Time (UTC)", "User", "Action", "Job", "Details", "Outcome"]);
+                for entry in entries {
+                    let user = match entry.uid {
+                        Some(uid) => format!("{} ({})", entry.user, uid),
+                        None => entry.user,
+                    };
+                    table.add_row(vec![
+                        entry.at,
+                        user,
+                        entry.action,
+                        entry.job_id.unwrap_or_else(|| "-".to_string()),
+                        entry.summary,
+                        entry.outcome,
+                    ]);
+                }
+                println!("{}", table);
+            }
+        },
+        Response::ScheduleTimes(times) => {
+            if times.is_empty() {
+                println!("The schedule never fires.");
+            }
+            let now = chrono::Utc::now();
+            for time in times {
+                match chrono::DateTime::parse_from_rfc3339(&time) {
+                    Ok(t) => {
+                        let wait = (t.with_timezone(&chrono::Utc) - now).num_seconds();
+                        let relative = if wait > 0 { format!("in {}", top::format_seconds(wait as u64)) } else { "now".to_string() };
+                        println!("{}  ({})", t.format("%a %Y-%m-%d %H:%M:%S %:z"), relative);
+                    }
+                    Err(_) => println!("{}", time),
+                }
+            }
+        },
+        Response::DeadLetterList(dead_letters) => {
+            if dead_letters.is_empty() {
+                println!("No failed jobs.");
+            } else {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["ID", "Name", "Failed At", "Attempts", "Failed Runs", "State", "Last Error"]);
+                for dead_letter in dead_letters {
+                    table.add_row(vec![
+                        dead_letter.job_id,
+                        dead_letter.job_name,
+                        dead_letter.failed_at,
+                        dead_letter.attempts.to_string(),
+                        dead_letter.exhaustions.to_string(),
+                        if dead_letter.disabled { "Failed (disabled)" } else { "Failed" }.to_string(),
+                        dead_letter.error,
+                    ]);
+                }
+                println!("{}", table);
+                println!("Re-arm a job with: lunasched retry <id>");
+            }
+        },
+        Response::TemplateList(templates) => {
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec!["Name", "Owner", "Command", "Variables", "Schedule"]);
+            for template in templates {
+                let variables: Vec<String> = template.variables().into_iter().collect();
+                let command = std::iter::once(template.command.clone()).chain(template.args.iter().cloned())
+                    .collect::<Vec<_>>().join(" ");
+                table.add_row(vec![
+                    template.name,
+                    template.owner,
+                    command,
+                    variables.join(", "),
+                    template.schedule.unwrap_or_else(|| "-".to_string()),
+                ]);
+            }
+            println!("{}", table);
+        },
+        Response::TemplateDetail(None) => println!("Template not found."),
+        Response::TemplateDetail(Some(template)) => {
+            use comfy_table::Cell;
+            let mut table = comfy_table::Table::new();
+            table.add_row(vec![Cell::new("Name"), Cell::new(&template.name)]);
+            table.add_row(vec![Cell::new("Owner"), Cell::new(&template.owner)]);
+            table.add_row(vec![Cell::new("Command"), Cell::new(&template.command)]);
+            if !template.args.is_empty() {
+                table.add_row(vec![Cell::new("Args"), Cell::new(template.args.join(" "))]);
+            }
+            if !template.env.is_empty() {
+                let vars: Vec<String> = env::masked(&template.env, &[]).into_iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                table.add_row(vec![Cell::new("Env"), Cell::new(vars.join("\n"))]);
+            }
+            if let Some(ref schedule) = template.schedule {
+                table.add_row(vec![Cell::new("Schedule"), Cell::new(schedule)]);
+            }
+            if !template.tags.is_empty() {
+                table.add_row(vec![Cell::new("Tags"), Cell::new(template.tags.join(", "))]);
+            }
+            let variables: Vec<String> = template.variables().into_iter().collect();
+            if !variables.is_empty() {
+                table.add_row(vec![Cell::new("Variables"), Cell::new(variables.join(", "))]);
+            }
+            println!("{}", table);
+        },
+        Response::SecretValue(value) => println!("{}", value),
+        Response::SecretList(secrets) => {
+            if secrets.is_empty() {
+                println!("No secrets stored.");
+                return;
+            }
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec!["Name", "Updated"]);
+            for secret in secrets {
+                table.add_row(vec![secret.name, secret.updated_at]);
+            }
+            println!("{}", table);
+        },
+        Response::RebalancePlan(suggestions) => {
+            for suggestion in suggestions {
+                match (suggestion.target, suggestion.jitter_seconds) {
+                    (Some(target), _) => println!("{}: move from {} to {}", suggestion.job_name, suggestion.slot, target),
+                    (None, Some(jitter)) => println!("{}: jitter {}s at {}", suggestion.job_name, jitter, suggestion.slot),
+                    (None, None) => {}
+                }
+            }
+        },
+        Response::Applied(report) => {
+            println!("Applied: {} created, {} updated, {} deleted.",
+                report.created.len(), report.updated.len(), report.deleted.len());
+        },
+        Response::Status(status) => {
+            use comfy_table::Cell;
+            let profile = status.profile;
+            let mut table = comfy_table::Table::new();
+            table.add_row(vec![Cell::new("Version"), Cell::new(&status.version)]);
+            table.add_row(vec![Cell::new("Uptime"), Cell::new(format!("{}s", status.uptime_seconds))]);
+            table.add_row(vec![Cell::new("Jobs"), Cell::new(status.job_count.to_string())]);
+            table.add_row(vec![Cell::new("Running"), Cell::new(status.running_count.to_string())]);
+            let db_state = match status.db_error {
+                _ if !status.db_attached => "UNAVAILABLE".to_string(),
+                Some(ref e) => format!("UNHEALTHY: {}", e),
+                None => "attached".to_string(),
+            };
+            table.add_row(vec![Cell::new("Database"), Cell::new(&db_state)]);
+            if let Some(ref db_path) = status.db_path {
+                table.add_row(vec![Cell::new("Database Path"), Cell::new(db_path)]);
+            }
+            if let Some(ref ha) = status.ha {
+                let role = if ha.leader { "leader" } else { "standby" };
+                table.add_row(vec![Cell::new("Role"), Cell::new(format!("{} ({})", role, ha.node))]);
+                let leader = match (&ha.lease_holder, &ha.lease_expires_at) {
+                    (Some(holder), Some(expires_at)) => format!("{} (lease until {}, {})", holder, expires_at, ha.lease_source),
+                    _ => format!("UNKNOWN: lease unreadable ({})", ha.lease_source),
+                };
+                table.add_row(vec![Cell::new("Leader"), Cell::new(&leader)]);
+            }
+            if status.history_buffered > 0 {
+                table.add_row(vec![Cell::new("Buffered History"), Cell::new(format!("{} records (not yet persisted)", status.history_buffered))]);
+            }
+            if status.history_dropped > 0 {
+                table.add_row(vec![Cell::new("Lost History"), Cell::new(format!("{} records", status.history_dropped))]);
+            }
+            table.add_row(vec![Cell::new("Retry Backlog"), Cell::new(status.retry_backlog.to_string())]);
+            table.add_row(vec![Cell::new("Queued Runs"), Cell::new(status.queued_runs.to_string())]);
+            table.add_row(vec![Cell::new("Ticks"), Cell::new(profile.ticks.to_string())]);
+            table.add_row(vec![Cell::new("Tick Time (last/avg/max)"), Cell::new(format!(
+                "{:.2}ms / {:.2}ms / {:.2}ms", profile.last_tick_ms, profile.avg_tick_ms, profile.max_tick_ms))]);
+            table.add_row(vec![Cell::new("Slow Ticks"), Cell::new(profile.slow_ticks.to_string())]);
+            println!("{}", table);
+
+            if !status.running.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Execution ID", "Job ID", "PID", "Started", "Duration"]);
+                for execution in status.running {
+                    table.add_row(vec![
+                        execution.execution_id,
+                        execution.job_id,
+                        execution.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                        execution.started_at,
+                        format!("{}s", execution.duration_seconds),
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !status.next_runs.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Job ID", "Name", "Next Run"]);
+                for next in status.next_runs {
+                    table.add_row(vec![
+                        next.job_id,
+                        next.job_name,
+                        next.next_run.unwrap_or_else(|| "-".to_string()),
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !status.pending_retries.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Job ID", "Next Attempt", "At"]);
+                for retry in status.pending_retries {
+                    table.add_row(vec![
+                        retry.job_id,
+                        (retry.attempt + 1).to_string(),
+                        retry.next_attempt_at.unwrap_or_else(|| "-".to_string()),
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !status.recent_failures.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Recent Failure", "Job ID", "Status", "Exit Code"]);
+                for entry in status.recent_failures {
+                    table.add_row(vec![
+                        entry.run_at,
+                        entry.job_id,
+                        entry.status,
+                        entry.exit_code.map_or("-".to_string(), |code| code.to_string()),
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !status.notification_failures.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Failed Notification", "Job ID", "Event", "Channel", "Attempts", "Error"]);
+                for failure in status.notification_failures {
+                    table.add_row(vec![
+                        failure.created_at,
+                        failure.job_id,
+                        failure.event,
+                        failure.channel,
+                        failure.attempts.to_string(),
+                        failure.error,
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !profile.lock_waits.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Lock Site", "Acquisitions", "Avg Wait", "Max Wait"]);
+                for wait in profile.lock_waits {
+                    table.add_row(vec![
+                        wait.site,
+                        wait.count.to_string(),
+                        format!("{:.3}ms", wait.avg_ms),
+                        format!("{:.3}ms", wait.max_ms),
+                    ]);
+                }
+                println!("{}", table);
+            }
+
+            if !profile.top_jobs.is_empty() {
+                let mut table = comfy_table::Table::new();
+                table.set_header(vec!["Job ID", "Evaluations", "Avg Cost", "Max Cost", "Total"]);
+                for job in profile.top_jobs {
+                    table.add_row(vec![
+                        job.job_id,
+                        job.evaluations.to_string(),
+                        format!("{:.1}us", job.avg_us),
+                        format!("{:.1}us", job.max_us),
+                        format!("{:.2}ms", job.total_ms),
+                    ]);
+                }
+                println!("{}", table);
+            }
+        },
     }
-            
-            return Ok(());
-        }
-        
-        // If buffer grows too large, something is wrong
-        if complete_buf.len() > 10 * 1024 * 1024 {  // 10MB limit
-            eprintln!("Response too large: {} bytes", complete_buf.len());
-            return Err(anyhow::anyhow!("Response too large"));
-        }
-    }
-    
-    // If we get here, connection closed before complete response
-    Err(anyhow::anyhow!("Connection closed before receiving complete response"))
 }
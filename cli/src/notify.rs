@@ -0,0 +1,61 @@
+use common::{NotificationChannel, NotificationConfig};
+
+/// Parse a `--notify-on-*` channel, written TYPE:TARGET:
+/// email:ADDRESS, webhook:URL, slack:URL, discord:URL, telegram:BOT_TOKEN/CHAT_ID,
+/// ntfy:TOPIC or ntfy:SERVER_URL/TOPIC, and syslog or syslog:FACILITY
+pub fn parse_channel(spec: &str) -> anyhow::Result<NotificationChannel> {
+    let (kind, target) = spec.split_once(':').unwrap_or((spec, ""));
+    let channel = match (kind.to_lowercase().as_str(), target) {
+        ("syslog", "") => NotificationChannel::Syslog { facility: None },
+        ("syslog", facility) => NotificationChannel::Syslog { facility: Some(facility.to_string()) },
+        (_, "") => return Err(anyhow::anyhow!("Invalid notification channel '{}', expected TYPE:TARGET", spec)),
+        ("email", to) => NotificationChannel::Email { to: to.to_string(), subject: None },
+        ("webhook", url) => NotificationChannel::Webhook { url: url.to_string(), headers: None },
+        ("slack", url) => NotificationChannel::Slack { webhook_url: url.to_string() },
+        ("discord", url) => NotificationChannel::Discord { webhook_url: url.to_string() },
+        ("telegram", target) => {
+            let (bot_token, chat_id) = target.rsplit_once('/')
+                .ok_or_else(|| anyhow::anyhow!("Invalid telegram channel, expected telegram:BOT_TOKEN/CHAT_ID"))?;
+            NotificationChannel::Telegram { bot_token: bot_token.to_string(), chat_id: chat_id.to_string() }
+        }
+        ("ntfy", target) => {
+            let (server, topic) = match target.rsplit_once('/') {
+                Some((server, topic)) if target.contains("://") => (Some(server.to_string()), topic),
+                _ => (None, target),
+            };
+            NotificationChannel::Ntfy { topic: topic.to_string(), server, token: None, priority: None }
+        }
+        (other, _) => return Err(anyhow::anyhow!(
+            "Unknown notification channel type '{}' (expected email, webhook, slack, discord, telegram, ntfy or syslog)", other)),
+    };
+    channel.validate().map_err(|e| anyhow::anyhow!(e))?;
+    Ok(channel)
+}
+
+/// Lines describing a job's notification settings for `lunasched get`, without secrets
+pub fn describe(config: &NotificationConfig) -> Vec<String> {
+    let mut lines = Vec::new();
+    let events = [
+        ("start", &config.on_start),
+        ("success", &config.on_success),
+        ("failure", &config.on_failure),
+        ("retry", &config.on_retry),
+    ];
+    for (event, channels) in events {
+        for channel in channels.iter().flatten() {
+            lines.push(format!("on {}: {} {}", event, channel.kind(), channel.target()));
+        }
+    }
+    let alerts = &config.alerts;
+    if let Some(limit) = alerts.after_failures {
+        lines.push(format!("alert after {} failed runs in a row", limit));
+    }
+    if let Some(rate) = alerts.min_success_rate {
+        let window = alerts.success_rate_window.unwrap_or(common::AlertPolicy::DEFAULT_SUCCESS_RATE_WINDOW);
+        lines.push(format!("alert under {}% success within {}s", rate, window));
+    }
+    if let Some(ref template) = config.template {
+        lines.push(format!("template: {}", template));
+    }
+    lines
+}
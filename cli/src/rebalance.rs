@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use common::{RebalanceSuggestion, Request, Response, ScheduleConfig};
+use crate::DaemonStream;
+
+/// Show how to spread out start times where many jobs fire together, and optionally apply it
+pub async fn run(stream: &mut DaemonStream, days: u32, max_per_slot: usize, apply: bool, yes: bool) -> anyhow::Result<()> {
+    let suggestions = match crate::send_request(stream, &Request::SuggestRebalance { days, max_per_slot }).await? {
+        Response::RebalancePlan(suggestions) => suggestions,
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    };
+    if suggestions.is_empty() {
+        println!("No start time in the last {} days has more than {} jobs; nothing to rebalance.", days, max_per_slot);
+        return Ok(());
+    }
+
+    let mut slots: BTreeMap<&str, Vec<&RebalanceSuggestion>> = BTreeMap::new();
+    for suggestion in &suggestions {
+        slots.entry(suggestion.slot.as_str()).or_default().push(suggestion);
+    }
+    for (slot, group) in &slots {
+        let mut targets: Vec<&str> = group.iter().filter_map(|s| s.target.as_deref()).collect();
+        targets.sort_unstable();
+        let jittered = group.len() - targets.len();
+        let mut advice = Vec::new();
+        match (targets.first(), targets.last()) {
+            (Some(first), Some(last)) if first != last => advice.push(format!("move {} to {}–{}", targets.len(), first, last)),
+            (Some(first), _) => advice.push(format!("move {} to {}", targets.len(), first)),
+            _ => {}
+        }
+        if jittered > 0 {
+            advice.push(format!("add jitter to {}", jittered));
+        }
+        println!("{}: {} of the {} jobs starting together: {}", slot, group.len(), group[0].slot_jobs, advice.join(", "));
+    }
+
+    let mut table = comfy_table::Table::new();
+    table.set_header(vec!["Job", "Starts", "Change"]);
+    for suggestion in &suggestions {
+        let change = match (&suggestion.schedule, &suggestion.target, suggestion.jitter_seconds) {
+            (Some(schedule), Some(target), _) => format!("move to {} (cron: {})", target, schedule),
+            (_, _, Some(jitter)) => format!("jitter {}s", jitter),
+            _ => "-".to_string(),
+        };
+        table.add_row(vec![suggestion.job_name.clone(), suggestion.slot.clone(), change]);
+    }
+    println!("{}", table);
+
+    if !apply {
+        println!("Run with --apply to make these changes.");
+        return Ok(());
+    }
+    if !yes && !crate::confirm(&format!("Update {} job(s)? [y/N] ", suggestions.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    for suggestion in suggestions {
+        let label = format!("update {}", suggestion.job_id);
+        // The job as stored: `get` merges in tag defaults, which must not be saved with it
+        let mut job = match crate::send_request(stream, &Request::ResolveJob(suggestion.job_id.0.clone())).await? {
+            Response::JobDetail(Some(job)) => job,
+            Response::JobDetail(None) => {
+                failures += 1;
+                eprintln!("{}: failed: job no longer exists", label);
+                continue;
+            }
+            Response::Error(e) => {
+                failures += 1;
+                eprintln!("{}: failed: {}", label, e);
+                continue;
+            }
+            other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+        };
+        if let Some(schedule) = suggestion.schedule {
+            job.schedule = ScheduleConfig::Cron(schedule);
+        }
+        if let Some(jitter) = suggestion.jitter_seconds {
+            job.jitter_seconds = jitter;
+        }
+        match crate::send_request(stream, &Request::AddJob(job)).await? {
+            Response::JobAdded(_) => println!("{}: done", label),
+            Response::Error(e) => {
+                failures += 1;
+                eprintln!("{}: failed: {}", label, e);
+            }
+            other => {
+                failures += 1;
+                eprintln!("{}: unexpected response: {:?}", label, other);
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(anyhow::anyhow!("{} job(s) failed to update", failures));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::io::{BufRead, IsTerminal, Write};
+use clap::{Parser, ValueEnum};
+use crate::{Cli, DaemonStream, OutputFormat};
+
+const PROMPT: &str = "lunasched> ";
+
+/// Read commands from stdin and run each over `stream`, so a whole session costs one connection
+/// (and one authentication with a remote daemon). Commands are written as on the command line,
+/// without the leading `lunasched`; `exit`, `quit` or end of input leaves.
+pub async fn run(stream: &mut DaemonStream, output: OutputFormat) -> anyhow::Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    let output_name = output.to_possible_value().map(|v| v.get_name().to_string());
+    let mut line = String::new();
+    loop {
+        if interactive {
+            print!("{}", PROMPT);
+            std::io::stdout().flush()?;
+        }
+        line.clear();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            if interactive {
+                println!();
+            }
+            return Ok(());
+        }
+
+        let words = match split_words(line.trim()) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some(word) if word.starts_with('#') => continue,
+            Some("exit") | Some("quit") => return Ok(()),
+            _ => {}
+        }
+
+        // Commands without their own --output use the one the shell was started with
+        let mut args = vec!["lunasched".to_string()];
+        if let Some(ref name) = output_name {
+            if !words.iter().any(|w| w == "--output" || w.starts_with("--output=")) {
+                args.extend(["--output".to_string(), name.clone()]);
+            }
+        }
+        args.extend(words);
+        let cli = match Cli::try_parse_from(args) {
+            Ok(cli) => cli,
+            Err(e) => {
+                // Also how `help` and `--help` are shown
+                let _ = e.print();
+                continue;
+            }
+        };
+
+        if cli.command.holds_connection() {
+            eprintln!("Error: this command streams until interrupted or ends the session; run it outside the shell \
+                (for run, --detach works here)");
+            continue;
+        }
+        let result = if cli.command.needs_daemon() {
+            crate::run(stream, cli.command, cli.output).await
+        } else {
+            crate::run_offline(cli.command)
+        };
+        if let Err(e) = result {
+            // There is no carrying on once the connection itself is gone
+            if e.downcast_ref::<std::io::Error>().is_some() {
+                return Err(e.context("Lost the connection to the daemon"));
+            }
+            eprintln!("Error: {}", e);
+        }
+    }
+}
+
+/// Split a command line into words. Single and double quotes group words, and a backslash
+/// takes the next character literally (outside single quotes).
+fn split_words(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                let escaped = chars.next().ok_or_else(|| anyhow::anyhow!("Line ends with a lone backslash"))?;
+                word.push(escaped);
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if let Some(q) = quote {
+        return Err(anyhow::anyhow!("Unclosed {} quote", q));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_words;
+
+    #[test]
+    fn words_and_quotes() {
+        assert_eq!(split_words("  add --name  a ").unwrap(), ["add", "--name", "a"]);
+        assert_eq!(split_words(r#"add --command "echo hi" 'a "b"'"#).unwrap(), ["add", "--command", "echo hi", r#"a "b""#]);
+        assert_eq!(split_words(r#"x"y z"w"#).unwrap(), ["xy zw"]);
+        assert_eq!(split_words("'' \"\"").unwrap(), ["", ""]);
+        assert!(split_words("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(split_words(r"a\ b c").unwrap(), ["a b", "c"]);
+        assert_eq!(split_words(r#""say \"hi\"""#).unwrap(), [r#"say "hi""#]);
+        // Literal inside single quotes
+        assert_eq!(split_words(r"'a\b'").unwrap(), [r"a\b"]);
+        assert!(split_words("a\\").is_err());
+    }
+
+    #[test]
+    fn unclosed_quote() {
+        assert!(split_words("echo 'hi").is_err());
+        assert!(split_words("echo \"hi").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local, Utc};
+use common::{DaemonStatus, Request, Response};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use crate::DaemonStream;
+
+type Term = Terminal<CrosstermBackend<std::io::Stdout>>;
+
+/// Puts the terminal back the way it was, also when `top` exits with an error
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = crossterm::execute!(std::io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// Full-screen dashboard of the daemon, polling its status every `interval` seconds.
+/// Read-only; q, Esc or Ctrl-C quits.
+pub async fn run(stream: &mut DaemonStream, interval: u64) -> anyhow::Result<()> {
+    let interval = Duration::from_secs(interval.max(1));
+    terminal::enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    crossterm::execute!(std::io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+
+    loop {
+        let status = match crate::send_request(stream, &Request::GetStatus { verbose: false }).await? {
+            Response::Status(status) => status,
+            Response::Error(e) => return Err(anyhow::anyhow!(e)),
+            other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+        };
+        draw(&mut terminal, &status)?;
+        if wait_for_quit(interval)? {
+            return Ok(());
+        }
+    }
+}
+
+// Wait up to `interval` for a key press; true if it asks to quit
+fn wait_for_quit(interval: Duration) -> anyhow::Result<bool> {
+    let deadline = Instant::now() + interval;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || !tokio::task::block_in_place(|| event::poll(left))? {
+            return Ok(false);
+        }
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+fn draw(terminal: &mut Term, status: &DaemonStatus) -> anyhow::Result<()> {
+    terminal.draw(|frame| {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Percentage(50), Constraint::Min(6)])
+            .split(frame.size());
+        let top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[1]);
+        let bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(rows[2]);
+
+        draw_header(frame, rows[0], status);
+        draw_running(frame, top[0], status);
+        draw_upcoming(frame, top[1], status);
+        draw_failures(frame, bottom[0], status);
+        draw_retries(frame, bottom[1], status);
+    })?;
+    Ok(())
+}
+
+fn draw_header(frame: &mut Frame, area: Rect, status: &DaemonStatus) {
+    let db = match status.db_error {
+        _ if !status.db_attached => "database UNAVAILABLE".to_string(),
+        Some(ref e) => format!("database UNHEALTHY: {}", e),
+        None => "database ok".to_string(),
+    };
+    let summary = format!(
+        "lunasched {}  up {}  |  {} jobs, {} running, {} queued, {} awaiting retry  |  {}  |  q to quit",
+        status.version, format_seconds(status.uptime_seconds), status.job_count, status.running_count,
+        status.queued_runs, status.retry_backlog, db,
+    );
+    let style = if status.db_attached && status.db_error.is_none() {
+        Style::default()
+    } else {
+        Style::default().fg(Color::Red)
+    };
+    let header = Paragraph::new(Line::styled(summary, style))
+        .block(Block::default().borders(Borders::ALL).title(Local::now().format(" %H:%M:%S ").to_string()));
+    frame.render_widget(header, area);
+}
+
+fn draw_running(frame: &mut Frame, area: Rect, status: &DaemonStatus) {
+    let rows = status.running.iter().map(|execution| Row::new(vec![
+        execution.job_id.clone(),
+        execution.pid.map_or("-".to_string(), |pid| pid.to_string()),
+        format_seconds(execution.duration_seconds),
+        execution.execution_id.clone(),
+    ]));
+    let widths = [Constraint::Percentage(30), Constraint::Length(8), Constraint::Length(10), Constraint::Min(10)];
+    frame.render_widget(table(rows, widths, ["Job", "PID", "Elapsed", "Execution"], "Running"), area);
+}
+
+fn draw_upcoming(frame: &mut Frame, area: Rect, status: &DaemonStatus) {
+    let now = Utc::now();
+    let rows = status.next_runs.iter()
+        .filter_map(|next| {
+            let at = parse_time(next.next_run.as_deref()?)?;
+            Some(Row::new(vec![
+                next.job_id.clone(),
+                at.with_timezone(&Local).format("%m-%d %H:%M:%S").to_string(),
+                format!("in {}", format_seconds((at - now).num_seconds().max(0) as u64)),
+            ]))
+        });
+    let widths = [Constraint::Percentage(40), Constraint::Length(15), Constraint::Min(10)];
+    frame.render_widget(table(rows, widths, ["Job", "Next Run", ""], "Upcoming"), area);
+}
+
+fn draw_failures(frame: &mut Frame, area: Rect, status: &DaemonStatus) {
+    let rows = status.recent_failures.iter().map(|entry| Row::new(vec![
+        entry.run_at.clone(),
+        entry.job_id.clone(),
+        entry.status.clone(),
+        entry.exit_code.map_or("-".to_string(), |code| code.to_string()),
+    ]).style(Style::default().fg(Color::Red)));
+    let widths = [Constraint::Length(20), Constraint::Percentage(40), Constraint::Length(11), Constraint::Min(5)];
+    frame.render_widget(table(rows, widths, ["When", "Job", "Status", "Exit"], "Recent Failures"), area);
+}
+
+fn draw_retries(frame: &mut Frame, area: Rect, status: &DaemonStatus) {
+    let now = Utc::now();
+    let rows = status.pending_retries.iter().map(|retry| {
+        let due = retry.next_attempt_at.as_deref()
+            .and_then(parse_time)
+            .map(|at| format!("in {}", format_seconds((at - now).num_seconds().max(0) as u64)))
+            .unwrap_or_else(|| "-".to_string());
+        Row::new(vec![retry.job_id.clone(), (retry.attempt + 1).to_string(), due])
+    });
+    let widths = [Constraint::Percentage(50), Constraint::Length(8), Constraint::Min(8)];
+    frame.render_widget(table(rows, widths, ["Job", "Attempt", "Due"], "Retry Backlog"), area);
+}
+
+fn table<'a, R, const N: usize>(rows: R, widths: [Constraint; N], header: [&'a str; N], title: &'a str) -> Table<'a>
+where
+    R: IntoIterator<Item = Row<'a>>,
+{
+    Table::new(rows, widths)
+        .header(Row::new(header.to_vec()).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(format!(" {} ", title)))
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+// "42s", "5m 03s", "3h 20m", "2d 4h"
+pub fn format_seconds(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {:02}s", s / 60, s % 60),
+        s if s < 86400 => format!("{}h {:02}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d {}h", s / 86400, s % 86400 / 3600),
+    }
+}
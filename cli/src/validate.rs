@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+use common::bundle::BUNDLE_EXTENSION;
+use common::declarative::JobFile;
+use common::SignedBundle;
+
+/// Check schedule expressions and job files (YAML, JSON or signed bundles) without the daemon,
+/// reporting every problem found; fails if there was any
+pub fn run(files: &[String], schedules: &[String]) -> anyhow::Result<()> {
+    if files.is_empty() && schedules.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to validate: pass job files and/or --schedule"));
+    }
+
+    let mut problems = 0;
+    for schedule in schedules {
+        match common::parse_schedule(schedule) {
+            Ok(_) => println!("OK       schedule '{}'", schedule),
+            Err(e) => {
+                problems += 1;
+                println!("INVALID  schedule '{}': {}", schedule, e);
+            }
+        }
+    }
+    for path in files {
+        let errors = check_file(path);
+        if errors.is_empty() {
+            println!("OK       {}", path);
+        } else {
+            problems += errors.len();
+            println!("INVALID  {}", path);
+            for error in errors {
+                println!("  - {}", error.replace('\n', "\n    "));
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(anyhow::anyhow!("{} problem(s) found", problems));
+    }
+    Ok(())
+}
+
+// Every problem with a job file, checked job by job as `apply` would
+fn check_file(path: &str) -> Vec<String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => return vec![format!("Failed to read {}: {}", path, e)],
+    };
+    let job_file = if path.ends_with(BUNDLE_EXTENSION) {
+        SignedBundle::from_json(&contents)
+            .and_then(|bundle| bundle.verify().map(|_| bundle))
+            .and_then(|bundle| bundle.job_file())
+    } else {
+        JobFile::parse(path, &contents)
+    };
+    let job_file = match job_file {
+        Ok(job_file) => job_file,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let source = crate::apply::file_source(path);
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+    for def in job_file.jobs {
+        if !seen.insert(def.name.clone()) {
+            errors.push(format!("Duplicate job name '{}'", def.name));
+            continue;
+        }
+        if let Err(e) = def.into_job(&source) {
+            errors.push(e.to_string());
+        }
+    }
+    errors
+}
@@ -0,0 +1,106 @@
+use common::{encode_frame, EventFilter, FrameReader, JobEvent, JobEventKind, JobId, Request, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::{Cli, DaemonStream};
+
+/// `lunasched start --wait`: start a job, print the output of the run it started (and of its
+/// retries) until the run is over, and exit with its exit code. The run is found through the
+/// job's events, followed on a connection of their own.
+pub async fn start(cli: &Cli, id: &str, params: Vec<String>) -> anyhow::Result<()> {
+    let mut stream = crate::connect(cli).await?;
+    let job_id = crate::resolve_job(&mut stream, id).await?;
+    // Subscribed before the start, so the run's Started event can't be missed
+    let mut events = Events::subscribe(crate::connect(cli).await?, &job_id).await?;
+    match crate::send_request(&mut stream, &crate::start_request(job_id.clone(), params)?).await? {
+        Response::Ok => {}
+        Response::Error(e) => return Err(anyhow::anyhow!(e)),
+        other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+
+    // The job's next run to start is the one started here (it may have been queued)
+    let mut execution: Option<String> = None;
+    loop {
+        let event = events.next().await?;
+        match event.kind {
+            JobEventKind::Started if execution.is_none() => {
+                eprintln!("-- started {} (execution {}) --", job_id, event.execution_id.as_deref().unwrap_or("-"));
+                execution = event.execution_id;
+                crate::logs::run(&mut stream, &job_id, execution.clone(), true).await?;
+            }
+            // Waiting goes on with the retry, the job's next run to start
+            JobEventKind::Retried if execution.is_some() && event.execution_id == execution => {
+                eprintln!("-- attempt failed ({}), retrying --", event.detail.as_deref().unwrap_or("-"));
+                execution = None;
+            }
+            // A run that could not be spawned fails without having started
+            JobEventKind::Finished | JobEventKind::Failed if execution.is_none() || event.execution_id == execution => {
+                if execution.is_none() {
+                    eprintln!("-- {} --", event.detail.as_deref().unwrap_or("failed"));
+                }
+                let entry = match event.execution_id {
+                    Some(execution_id) => match crate::send_request(&mut stream, &Request::GetExecution {
+                        job_id: JobId(job_id.clone()), execution_id,
+                    }).await? {
+                        Response::ExecutionDetail { entry, .. } => entry,
+                        _ => None,
+                    },
+                    None => None,
+                };
+                let code = match entry {
+                    Some(entry) => crate::execution_exit_code(&entry),
+                    None if event.kind == JobEventKind::Finished => 0,
+                    None => 1,
+                };
+                std::process::exit(code);
+            }
+            _ => {}
+        }
+    }
+}
+
+// A connection turned into a stream of the job's events
+struct Events {
+    stream: DaemonStream,
+    frames: FrameReader,
+    buf: Vec<u8>,
+}
+
+impl Events {
+    async fn subscribe(mut stream: DaemonStream, job_id: &str) -> anyhow::Result<Self> {
+        let kinds = vec![JobEventKind::Started, JobEventKind::Finished, JobEventKind::Failed, JobEventKind::Retried];
+        let req = Request::Subscribe { filters: EventFilter { job_ids: vec![job_id.to_string()], kinds } };
+        stream.write_all(&encode_frame(&req)?).await?;
+        let mut events = Events { stream, frames: FrameReader::default(), buf: vec![0; 8192] };
+        // The daemon acknowledges with Ok once the subscription is in place
+        match events.response().await? {
+            Response::Ok => Ok(events),
+            Response::Error(e) => Err(anyhow::anyhow!(e)),
+            other => Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+        }
+    }
+
+    async fn next(&mut self) -> anyhow::Result<JobEvent> {
+        match self.response().await? {
+            Response::Event(event) => Ok(event),
+            // The end of the run may be among them
+            Response::EventsLost(skipped) => Err(anyhow::anyhow!(
+                "Fell behind the job's events ({} skipped); see lunasched history for how the run went", skipped)),
+            Response::Error(e) => Err(anyhow::anyhow!(e)),
+            other => Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+        }
+    }
+
+    async fn response(&mut self) -> anyhow::Result<Response> {
+        loop {
+            match self.frames.next::<Response>() {
+                Ok(Some(resp)) => return Ok(resp),
+                Ok(None) => {}
+                Err(e) => return Err(anyhow::anyhow!("Invalid response from daemon: {}", e)),
+            }
+            let n = self.stream.read(&mut self.buf).await?;
+            if n == 0 {
+                return Err(anyhow::anyhow!("Daemon closed the connection"));
+            }
+            self.frames.push(&self.buf[..n]);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+use common::{encode_frame, EventFilter, FrameReader, JobEvent, Request, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::{DaemonStream, OutputFormat};
+
+// Clear the screen and move the cursor home, so each refresh redraws in place
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Re-send `req` every `interval` seconds and redraw its table, like watch(1). Runs until interrupted.
+pub async fn run(stream: &mut DaemonStream, req: &Request, command: &str, interval: u64) -> anyhow::Result<()> {
+    run_with(stream, req, command, interval, crate::print_response).await
+}
+
+/// As `run`, drawing each response with `print`
+pub async fn run_with(stream: &mut DaemonStream, req: &Request, command: &str, interval: u64, print: fn(Response)) -> anyhow::Result<()> {
+    let interval = std::time::Duration::from_secs(interval.max(1));
+    loop {
+        // Fetch before clearing so a slow daemon doesn't leave a blank screen
+        let resp = crate::send_request(stream, req).await?;
+        print!("{}", CLEAR_SCREEN);
+        println!("Every {}s: lunasched {}    {}", interval.as_secs(), command,
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+        println!();
+        print(resp);
+        std::io::stdout().flush()?;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Print scheduler events as they happen (all jobs, or only `job_ids`). Runs until interrupted.
+pub async fn events(stream: &mut DaemonStream, job_ids: Vec<String>, output: OutputFormat) -> anyhow::Result<()> {
+    let req = Request::Subscribe { filters: EventFilter { job_ids, kinds: Vec::new() } };
+    stream.write_all(&encode_frame(&req)?).await?;
+
+    // The daemon acknowledges with Ok, then sends one response per line for as long as we listen
+    let mut frames = FrameReader::default();
+    let mut temp_buf = vec![0; 8192];
+    loop {
+        loop {
+            let resp = match frames.next::<Response>() {
+                Ok(Some(resp)) => resp,
+                Ok(None) => break,
+                Err(e) => return Err(anyhow::anyhow!("Invalid response from daemon: {}", e)),
+            };
+
+            match resp {
+                Response::Ok => {}
+                Response::Event(event) => print_event(&event, output)?,
+                Response::EventsLost(skipped) => eprintln!("-- fell behind, {} events skipped --", skipped),
+                Response::Error(e) => return Err(anyhow::anyhow!(e)),
+                other => return Err(anyhow::anyhow!("Unexpected response from daemon: {:?}", other)),
+            }
+        }
+
+        let n = stream.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Daemon closed the connection"));
+        }
+        frames.push(&temp_buf[..n]);
+    }
+}
+
+fn print_event(event: &JobEvent, output: OutputFormat) -> anyhow::Result<()> {
+    match output {
+        // One document per event, so the stream can be piped into jq and friends
+        OutputFormat::Json => println!("{}", serde_json::to_string(event)?),
+        OutputFormat::Yaml => print!("---\n{}", serde_yaml::to_string(event)?),
+        OutputFormat::Table => {
+            let at = chrono::DateTime::parse_from_rfc3339(&event.at)
+                .map(|at| at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|_| event.at.clone());
+            let execution = event.execution_id.as_deref().map(|id| format!(" [{}]", id)).unwrap_or_default();
+            let detail = event.detail.as_deref().map(|d| format!(": {}", d)).unwrap_or_default();
+            println!("{}  {:<16} {} ({}){}{}", at, event.kind.to_string(), event.job_name, event.job_id, execution, detail);
+        }
+    }
+    std::io::stdout().flush()?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+/// How often an agent tells the daemon it is still there, when it has nothing else to say
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 15;
+
+/// Sent by `lunasched-agent` to the daemon, framed like IPC messages (see `encode_frame`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AgentMessage {
+    /// First message on a connection; runs are routed to agents by name or label
+    Register { name: String, labels: Vec<String>, version: String },
+    Started { execution_id: String, pid: u32 },
+    Output { execution_id: String, stream: OutputStream, text: String }, // One or more whole lines
+    Finished { execution_id: String, exit: RemoteExit },
+    Heartbeat,
+}
+
+/// Sent by the daemon to a connected agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum DaemonMessage {
+    Registered,
+    Refused(String), // The connection is closed after this
+    Run(RemoteRun),
+}
+
+/// An execution for an agent to run, with everything the daemon resolved for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteRun {
+    pub execution_id: String,
+    pub job_id: String,
+    pub job_name: String,
+    pub command: String, // Run through /bin/sh -c
+    pub env: HashMap<String, String>, // Job env with secrets resolved, and run parameters
+    pub run_as: String,
+    pub working_dir: Option<PathBuf>, // Defaults to /tmp
+    pub umask: Option<u32>,
+    pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub script: Option<String>, // Written to a file and run; `command` then holds only its arguments
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// How a remote execution ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RemoteExit {
+    Exited(i32), // Exit code
+    Signaled(i32), // Killed by this signal
+    NotStarted(String), // Why it could not be spawned
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+pub use ed25519_dalek::SigningKey;
+use crate::declarative::JobFile;
+
+/// File extension of signed bundles; `lunasched apply` treats these files as bundles
+pub const BUNDLE_EXTENSION: &str = ".bundle";
+
+// Prefixed to the signed message so bundle signatures can't be replayed as anything else
+const SIGNATURE_CONTEXT: &str = "lunasched-bundle-v1";
+
+/// A jobs file signed with an ed25519 key, for distributing job sets to many hosts.
+/// The daemon only applies bundles signed by one of its configured `trusted_keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedBundle {
+    /// Name of the original jobs file; used as the source tag and to pick YAML or JSON parsing
+    pub source: String,
+    /// Raw contents of the jobs file
+    pub contents: String,
+    /// Base64 ed25519 public key of the signer
+    pub public_key: String,
+    /// Base64 ed25519 signature over the source and contents
+    pub signature: String,
+}
+
+impl SignedBundle {
+    pub fn sign(source: &str, contents: &str, key: &SigningKey) -> Result<Self> {
+        // Refuse to sign something that wouldn't apply
+        JobFile::parse(source, contents)?;
+
+        let signature = key.sign(&signed_message(source, contents));
+        Ok(Self {
+            source: source.to_string(),
+            contents: contents.to_string(),
+            public_key: encode_public_key(&key.verifying_key()),
+            signature: STANDARD.encode(signature.to_bytes()),
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| anyhow!("Invalid bundle: {}", e))
+    }
+
+    /// Check the signature against the embedded public key
+    pub fn verify(&self) -> Result<()> {
+        let key = decode_public_key(&self.public_key)?;
+        let bytes: [u8; 64] = STANDARD.decode(&self.signature)
+            .map_err(|e| anyhow!("Invalid bundle signature encoding: {}", e))?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid bundle signature length"))?;
+        key.verify(&signed_message(&self.source, &self.contents), &Signature::from_bytes(&bytes))
+            .map_err(|_| anyhow!("Bundle signature verification failed"))
+    }
+
+    /// Check that the bundle is validly signed by one of `trusted_keys`
+    pub fn verify_trusted(&self, trusted_keys: &[String]) -> Result<()> {
+        if trusted_keys.is_empty() {
+            return Err(anyhow!("No trusted bundle keys configured"));
+        }
+        let signer = decode_public_key(&self.public_key)?;
+        let trusted = trusted_keys.iter()
+            .filter_map(|k| decode_public_key(k).ok())
+            .any(|k| k == signer);
+        if !trusted {
+            return Err(anyhow!("Bundle signed by untrusted key {}", self.public_key));
+        }
+        self.verify()
+    }
+
+    pub fn job_file(&self) -> Result<JobFile> {
+        JobFile::parse(&self.source, &self.contents)
+    }
+}
+
+fn signed_message(source: &str, contents: &str) -> Vec<u8> {
+    format!("{}\n{}\n{}", SIGNATURE_CONTEXT, source, contents).into_bytes()
+}
+
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.to_bytes())
+}
+
+pub fn decode_public_key(encoded: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = STANDARD.decode(encoded.trim())
+        .map_err(|e| anyhow!("Invalid public key encoding: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid public key length"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("Invalid public key: {}", e))
+}
+
+/// Signing keys are stored as base64 of the 32-byte ed25519 secret
+pub fn encode_signing_key(key: &SigningKey) -> String {
+    STANDARD.encode(key.to_bytes())
+}
+
+pub fn decode_signing_key(encoded: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = STANDARD.decode(encoded.trim())
+        .map_err(|e| anyhow!("Invalid signing key encoding: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid signing key length"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
@@ -0,0 +1,419 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crate::job::{Job, JobId, ScheduleConfig, RetryPolicy, ResourceLimits, Executor, Sandbox, JobHooks, JobPriority,
+                 ExecutionMode, NotificationConfig, OverflowPolicy, CatchUpPolicy, SuccessCriteria};
+use crate::schedule::parse_schedule;
+
+/// Tag prefix recording where a declaratively managed job came from (e.g. "source:config")
+pub const SOURCE_TAG_PREFIX: &str = "source:";
+
+/// A job as written in a declarative file (apply files, config.yaml `jobs:`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobDefinition {
+    pub name: String,
+    pub schedule: String, // Same syntax as `lunasched add --schedule`
+    #[serde(default)]
+    pub command: String, // May be empty for heartbeat jobs
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    #[serde(default)]
+    pub resource_limits: ResourceLimits,
+    #[serde(default)]
+    pub jitter_seconds: u64,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<JobId>,
+    #[serde(default)]
+    pub hooks: JobHooks,
+    #[serde(default)]
+    pub max_concurrent: u32,
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    #[serde(default)]
+    pub priority: JobPriority,
+    #[serde(default)]
+    pub execution_mode: ExecutionMode,
+    #[serde(default)]
+    pub notification_config: NotificationConfig,
+    #[serde(default)]
+    pub redact_env: Vec<String>,
+    #[serde(default)]
+    pub fence_key: Option<String>,
+    #[serde(default)]
+    pub capture_env: bool,
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    #[serde(default)]
+    pub boot_delay: Option<u64>,
+    #[serde(default)]
+    pub delete_after_run: bool,
+    #[serde(default)]
+    pub run_as: Option<String>,
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub umask: Option<u32>, // Write as octal: 0o022
+    #[serde(default)]
+    pub concurrency_group: Option<String>,
+    #[serde(default)]
+    pub success_criteria: SuccessCriteria,
+    #[serde(default)]
+    pub max_output_bytes: Option<u64>,
+    #[serde(default)]
+    pub run_immediately: bool,
+    #[serde(default)]
+    pub on_success_trigger: Vec<JobId>,
+    #[serde(default)]
+    pub on_failure_trigger: Vec<JobId>,
+    #[serde(default)]
+    pub disable_after_failures: Option<u32>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>, // RFC 3339
+    #[serde(default)]
+    pub target: Option<String>, // Agent name or label
+    #[serde(default)]
+    pub executor: Executor,
+    #[serde(default)]
+    pub sandbox: Sandbox,
+    #[serde(default)]
+    pub script: Option<String>, // Run instead of a command line; command may then name it
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl JobDefinition {
+    /// Convert into a Job, tagging it with the given source
+    pub fn into_job(self, source: &str) -> Result<Job> {
+        let schedule = parse_schedule(&self.schedule)
+            .map_err(|e| anyhow!("Job '{}': invalid schedule '{}': {}", self.name, self.schedule, e))?;
+        if self.command.is_empty() && self.script.is_none() && !matches!(schedule, ScheduleConfig::Heartbeat(_)) {
+            return Err(anyhow!("Job '{}': missing command", self.name));
+        }
+        if let Some(ref dir) = self.working_dir {
+            if !dir.is_absolute() {
+                return Err(anyhow!("Job '{}': working_dir must be an absolute path, got {}", self.name, dir.display()));
+            }
+        }
+        if matches!(self.umask, Some(umask) if umask > 0o777) {
+            return Err(anyhow!("Job '{}': umask must be between 0o000 and 0o777", self.name));
+        }
+        self.success_criteria.validate().map_err(|e| anyhow!("Job '{}': {}", self.name, e))?;
+        self.notification_config.validate().map_err(|e| anyhow!("Job '{}': {}", self.name, e))?;
+        self.executor.validate().map_err(|e| anyhow!("Job '{}': {}", self.name, e))?;
+        if self.target.is_some() && self.executor != Executor::Local {
+            return Err(anyhow!("Job '{}': agents (target) run jobs with the Local executor only", self.name));
+        }
+        if self.target.is_some() && !self.sandbox.is_empty() {
+            return Err(anyhow!("Job '{}': agents (target) don't sandbox jobs", self.name));
+        }
+
+        let mut tags: Vec<String> = self.tags.into_iter()
+            .filter(|t| !t.starts_with(SOURCE_TAG_PREFIX))
+            .collect();
+        tags.push(source_tag(source));
+
+        Ok(Job {
+            id: JobId::default(), // Matched to an existing job by name, or assigned by the daemon
+            name: self.name,
+            schedule,
+            command: self.command,
+            args: self.args,
+            env: self.env,
+            enabled: self.enabled,
+            owner: String::new(),
+            retry_policy: self.retry_policy,
+            resource_limits: self.resource_limits,
+            jitter_seconds: self.jitter_seconds,
+            timezone: self.timezone,
+            tags,
+            dependencies: self.dependencies,
+            hooks: self.hooks,
+            max_concurrent: self.max_concurrent,
+            overflow_policy: self.overflow_policy,
+            priority: self.priority,
+            execution_mode: self.execution_mode,
+            notification_config: self.notification_config,
+            redact_env: self.redact_env,
+            fence_key: self.fence_key,
+            capture_env: self.capture_env,
+            catch_up: self.catch_up,
+            boot_delay: self.boot_delay,
+            delete_after_run: self.delete_after_run,
+            run_as: self.run_as,
+            working_dir: self.working_dir,
+            umask: self.umask,
+            concurrency_group: self.concurrency_group,
+            success_criteria: self.success_criteria,
+            max_output_bytes: self.max_output_bytes,
+            run_immediately: self.run_immediately,
+            on_success_trigger: self.on_success_trigger,
+            on_failure_trigger: self.on_failure_trigger,
+            disable_after_failures: self.disable_after_failures,
+            expires_at: self.expires_at,
+            target: self.target,
+            executor: self.executor,
+            sandbox: self.sandbox,
+            script: self.script,
+            disabled_reason: None,
+        })
+    }
+}
+
+/// Complete job definitions as written by `lunasched export` and read by `lunasched import`.
+/// Unlike a JobFile, jobs keep their IDs, owners and tags as they were on the exporting host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobExport {
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+}
+
+impl JobExport {
+    /// Parse an export, choosing JSON or YAML by extension
+    pub fn parse(path: &str, contents: &str) -> Result<Self> {
+        if path.ends_with(".json") {
+            Ok(serde_json::from_str(contents)?)
+        } else {
+            Ok(serde_yaml::from_str(contents)?)
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// Top-level layout of a declarative jobs file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobFile {
+    #[serde(default)]
+    pub jobs: Vec<JobDefinition>,
+}
+
+impl JobFile {
+    /// Parse a jobs file, choosing JSON or YAML by extension
+    pub fn parse(path: &str, contents: &str) -> Result<Self> {
+        if path.ends_with(".json") {
+            Ok(serde_json::from_str(contents)?)
+        } else {
+            Ok(serde_yaml::from_str(contents)?)
+        }
+    }
+
+    /// Convert all definitions into jobs, rejecting duplicate names
+    pub fn into_jobs(self, source: &str) -> Result<Vec<Job>> {
+        let mut seen = HashSet::new();
+        let mut jobs = Vec::new();
+        for def in self.jobs {
+            if !seen.insert(def.name.clone()) {
+                return Err(anyhow!("Duplicate job name '{}'", def.name));
+            }
+            jobs.push(def.into_job(source)?);
+        }
+        Ok(jobs)
+    }
+}
+
+pub fn source_tag(source: &str) -> String {
+    format!("{}{}", SOURCE_TAG_PREFIX, source)
+}
+
+/// Returns the declarative source of a job, if it has one
+pub fn job_source(job: &Job) -> Option<&str> {
+    job.tags.iter().find_map(|t| t.strip_prefix(SOURCE_TAG_PREFIX))
+}
+
+/// Names of the fields that differ between two definitions of the same job (owner is ignored)
+pub fn changed_fields(current: &Job, desired: &Job) -> Vec<String> {
+    let current = serde_json::to_value(current).unwrap_or_default();
+    let desired = serde_json::to_value(desired).unwrap_or_default();
+
+    match (current, desired) {
+        (serde_json::Value::Object(cur), serde_json::Value::Object(des)) => des
+            .iter()
+            .filter(|(k, v)| k.as_str() != "owner" && cur.get(k.as_str()) != Some(*v))
+            .map(|(k, _)| k.clone())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PlanAction {
+    Create(Job),
+    Update { job: Job, changes: Vec<String> },
+    Delete(JobId),
+}
+
+/// Compute the actions needed to move `current` to `desired`.
+/// Declared jobs are matched to current ones by name among the jobs of the same source; a
+/// matched job keeps its ID, and triggers naming jobs are stored by their IDs. New jobs are
+/// created without an ID, which the daemon assigns.
+/// Pruning only ever deletes jobs tagged with the same source.
+pub fn plan(current: &[Job], desired: Vec<Job>, source: &str, prune: bool) -> Vec<PlanAction> {
+    let existing: HashMap<&str, &Job> = current.iter()
+        .filter(|j| job_source(j) == Some(source))
+        .map(|j| (j.name.as_str(), j))
+        .collect();
+    let desired_names: HashSet<String> = desired.iter().map(|j| j.name.clone()).collect();
+    let mut actions = Vec::new();
+
+    for mut job in desired {
+        for target in job.on_success_trigger.iter_mut().chain(job.on_failure_trigger.iter_mut()) {
+            if let Some(named) = existing.get(target.0.as_str()) {
+                *target = named.id.clone();
+            }
+        }
+        match existing.get(job.name.as_str()) {
+            None => actions.push(PlanAction::Create(job)),
+            Some(cur) => {
+                job.id = cur.id.clone();
+                let changes = changed_fields(cur, &job);
+                if !changes.is_empty() {
+                    actions.push(PlanAction::Update { job, changes });
+                }
+            }
+        }
+    }
+
+    if prune {
+        for job in current {
+            if job_source(job) == Some(source) && !desired_names.contains(&job.name) {
+                actions.push(PlanAction::Delete(job.id.clone()));
+            }
+        }
+    }
+
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(source: &str, yaml: &str) -> Vec<Job> {
+        JobFile::parse("jobs.yaml", yaml).unwrap().into_jobs(source).unwrap()
+    }
+
+    // Jobs as the daemon holds them: with IDs
+    fn stored(source: &str, yaml: &str) -> Vec<Job> {
+        let mut jobs = jobs(source, yaml);
+        for job in &mut jobs {
+            job.id = JobId(format!("id-{}", job.name));
+        }
+        jobs
+    }
+
+    const CURRENT: &str = "
+jobs:
+  - { name: backup, schedule: every 1h, command: backup.sh }
+  - { name: report, schedule: at 08:00, command: report.sh }
+";
+
+    #[test]
+    fn unchanged_jobs_need_nothing() {
+        assert!(plan(&stored("config", CURRENT), jobs("config", CURRENT), "config", true).is_empty());
+    }
+
+    #[test]
+    fn matched_jobs_keep_their_ids() {
+        let desired = jobs("config", "
+jobs:
+  - { name: backup, schedule: every 2h, command: backup.sh }
+  - { name: cleanup, schedule: every 1d, command: cleanup.sh }
+");
+        let actions = plan(&stored("config", CURRENT), desired, "config", false);
+        assert_eq!(actions.len(), 2);
+        match &actions[0] {
+            PlanAction::Update { job, changes } => {
+                assert_eq!(job.id.0, "id-backup");
+                assert_eq!(changes, &["schedule"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(&actions[1], PlanAction::Create(job) if job.name == "cleanup" && job.id.0.is_empty()));
+    }
+
+    #[test]
+    fn triggers_are_stored_by_id() {
+        let desired = jobs("config", "
+jobs:
+  - { name: backup, schedule: every 1h, command: backup.sh, on_success_trigger: [report, elsewhere] }
+  - { name: report, schedule: at 08:00, command: report.sh }
+");
+        let actions = plan(&stored("config", CURRENT), desired, "config", false);
+        match actions.as_slice() {
+            [PlanAction::Update { job, changes }] => {
+                assert_eq!(job.on_success_trigger, vec![JobId("id-report".to_string()), JobId("elsewhere".to_string())]);
+                assert_eq!(changes, &["on_success_trigger"]);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn pruning_stays_within_the_source() {
+        let mut current = stored("config", CURRENT);
+        current.extend(stored("other", "jobs: [{ name: backup, schedule: every 1h, command: b.sh }]"));
+        let desired = jobs("config", "jobs: [{ name: report, schedule: at 08:00, command: report.sh }]");
+
+        let actions = plan(&current, desired.clone(), "config", true);
+        match actions.as_slice() {
+            [PlanAction::Delete(id)] => assert_eq!(id.0, "id-backup"),
+            other => panic!("{:?}", other),
+        }
+        // The other source's job isn't touched, even with the same name
+        assert_eq!(current.iter().filter(|j| j.id.0 == "id-backup").count(), 2);
+        assert!(plan(&current, desired, "config", false).is_empty());
+    }
+
+    #[test]
+    fn jobs_of_other_sources_are_not_matched() {
+        let current = stored("other", CURRENT);
+        let actions = plan(&current, jobs("config", CURRENT), "config", true);
+        assert_eq!(actions.len(), 2);
+        assert!(actions.iter().all(|a| matches!(a, PlanAction::Create(_))));
+    }
+
+    #[test]
+    fn duplicate_names_are_refused() {
+        let file = JobFile::parse("jobs.yaml", "
+jobs:
+  - { name: a, schedule: every 1h, command: x }
+  - { name: a, schedule: every 2h, command: y }
+").unwrap();
+        assert!(file.into_jobs("config").is_err());
+    }
+
+    #[test]
+    fn matched_jobs_take_the_stored_id() {
+        // As an apply batch may carry IDs of its own
+        let mut desired = jobs("config", CURRENT);
+        desired[0].id = JobId("id-report".to_string());
+        desired[0].command = "backup2.sh".to_string();
+        match plan(&stored("config", CURRENT), desired, "config", true).as_slice() {
+            [PlanAction::Update { job, changes }] => {
+                assert_eq!(job.id.0, "id-backup");
+                assert_eq!(changes, &["command"]);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
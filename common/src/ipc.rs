@@ -1,23 +1,315 @@
 use serde::{Deserialize, Serialize};
-use crate::job::{Job, JobId};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use crate::bundle::SignedBundle;
+use crate::job::{Job, JobId, ScheduleConfig};
+use crate::template::JobTemplate;
+use crate::workflow::{Workflow, WorkflowRun};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
     AddJob(Job),
+    // Answered with JobEnsured; a job identical to the one saved (or with `if_absent`, any job
+    // of its ID or name) is left alone
+    EnsureJob { job: Job, if_absent: bool },
     RemoveJob(JobId),
     ListJobs,
     GetJob(JobId),
     StartJob(JobId),
-    GetHistory { job_id: JobId, limit: Option<usize> },
+    StartJobWithParams { job_id: JobId, params: HashMap<String, String> },
+    GetHistory {
+        job_id: Option<JobId>, // None for every job's history
+        limit: Option<usize>,
+        #[serde(default)]
+        filter: HistoryFilter,
+    },
+    Ping(JobId), // Heartbeat ping for a passive job
+    GetPingToken(JobId), // Token for pinging a heartbeat job over HTTP; answered with SecretValue
+    GetStatus { verbose: bool },
+    ApplyBundle { bundle: SignedBundle, prune: bool },
+    // Jobs of a jobs file (`JobFile::into_jobs`), reconciled with those applied from `source`
+    // before, all or nothing; answered with Applied
+    ApplyJobs { source: String, jobs: Vec<Job>, prune: bool },
+    Authenticate(String), // Shared secret; must be the first request on TCP connections
+    GetJobOutput { job_id: JobId, execution_id: Option<String>, follow: bool },
+    TestNotification {
+        job_id: JobId,
+        channel: Option<usize>, // Channel index as listed by `notify test`
+        #[serde(default)]
+        preview: bool, // Render each channel's message without sending it
+    },
+    Rerun { execution_id: String }, // Replay a captured execution (see Job::capture_env)
+    ExportJobs { tag: Option<String> }, // Answered with JobList
+    ImportJobs { jobs: Vec<Job>, on_conflict: ConflictPolicy },
+    GetExecution { job_id: JobId, execution_id: String }, // History record plus lifecycle timeline
+    SuggestRebalance { days: u32, max_per_slot: usize },
+    // Secrets of the requesting user, referenced from job env as secret://NAME
+    SetSecret { name: String, value: String },
+    GetSecret { name: String },
+    RemoveSecret { name: String },
+    ListSecrets,
+    AddTemplate(JobTemplate), // Creates or replaces; owner is set by the daemon
+    ListTemplates,
+    GetTemplate(String),
+    RemoveTemplate(String),
+    Subscribe { filters: EventFilter }, // Turns the connection into a stream of Event responses
+    AddWorkflow(Workflow), // Creates or replaces; owner is set by the daemon
+    ListWorkflows,
+    RemoveWorkflow(String),
+    RunWorkflow(String), // Answered with WorkflowStarted
+    GetWorkflowRuns { name: String, limit: usize }, // Newest first
+    ListDeadLetters, // Jobs whose last run failed for good
+    RetryJob(JobId), // Clear the job's failed state, re-enable it and start it
+    PreviewSchedule { schedule: String, timezone: Option<String>, count: usize }, // Answered with ScheduleTimes
+    GetAuditLog { job_id: Option<JobId>, since: Option<String>, limit: usize }, // Since is RFC 3339; newest first
+    GetMetrics(JobId), // Answered with JobStats
+    // Run a transient job now without adding it; answered with ExecutionStarted, then with its
+    // output as for GetJobOutput when following (socket only)
+    RunOnce { job: Job, follow: bool },
+    HealthCheck, // Answered with Health; the checks `lunasched doctor` leaves to the daemon
+    GetExecutionWindows { job_id: Option<JobId>, limit: usize }, // Newest first
+    ResolveJob(String), // ID, name or the start of either; answered with JobDetail or an Error with suggestions
+    RenameJob { job_id: JobId, name: String },
+    // Add a copy of a job under a new name, optionally with another schedule; answered with JobAdded
+    CloneJob { job_id: JobId, name: String, schedule: Option<ScheduleConfig>, enabled: bool },
+    // Every entry GetHistory would find, oldest first, streamed as HistoryChunks followed by
+    // HistoryEnd (socket only)
+    ExportHistory {
+        job_id: Option<JobId>,
+        #[serde(default)]
+        filter: HistoryFilter,
+    },
+    // Paths are on the daemon's host; both are answered with Backup
+    BackupDatabase { path: String }, // Online copy of the database, checked before it is kept
+    RestoreDatabase { path: String }, // Replace the database's contents with a backup's
+    ListAgents, // Answered with AgentList
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum Response {
     Ok,
     Error(String),
-    JobList(Vec<Job>),
+    JobList(Vec<Job>), // Answers ExportJobs
+    JobListing(Vec<ListedJob>), // Answers ListJobs
     JobDetail(Option<Job>),
+    JobAdded(JobId), // Answers AddJob with the ID the job was given (or kept)
+    JobEnsured { id: JobId, outcome: SaveOutcome, hash: String }, // hash: Job::content_hash of the job as saved
     HistoryList(Vec<HistoryEntry>),
+    Status(DaemonStatus),
+    Applied(ApplyReport),
+    OutputChunk(String), // Streamed in reply to GetJobOutput, followed by OutputEnd
+    OutputEnd { status: Option<String> },
+    HistoryChunk(Vec<HistoryEntry>), // Streamed in reply to ExportHistory, followed by HistoryEnd
+    HistoryEnd { count: u64 },
+    NotificationTestResults(Vec<NotificationTestResult>),
+    Imported(ImportReport),
+    ExecutionDetail { entry: Option<HistoryEntry>, timeline: Vec<TimelineEvent> },
+    RebalancePlan(Vec<RebalanceSuggestion>),
+    SecretValue(String),
+    SecretList(Vec<SecretInfo>),
+    TemplateList(Vec<JobTemplate>),
+    TemplateDetail(Option<JobTemplate>),
+    Event(JobEvent), // Streamed in reply to Subscribe, one JSON document per line
+    EventsLost(u64), // The subscriber fell behind and this many events were skipped
+    WorkflowList(Vec<Workflow>),
+    WorkflowRuns(Vec<WorkflowRun>),
+    WorkflowStarted { run_id: String },
+    DeadLetterList(Vec<DeadLetter>),
+    ScheduleTimes(Vec<String>), // RFC 3339, in the requested timezone or the daemon's local time
+    AuditLog(Vec<AuditEntry>),
+    JobStats(JobStats),
+    ExecutionStarted { job_id: String, execution_id: String },
+    Health(HealthReport),
+    Backup(BackupReport),
+    ExecutionWindows(Vec<ExecutionWindow>),
+    AgentList(Vec<AgentInfo>),
+}
+
+/// What happened to a job in a `JobEvent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobEventKind {
+    Started,
+    Finished, // Exited successfully
+    Failed,   // Failed with no retries left, or could not be spawned
+    Retried,  // Failed and a retry was scheduled
+    ScheduleChanged, // Job added, updated or removed
+    Disabled, // Disabled by the daemon (failures, expiry); the detail says why
+}
+
+impl std::fmt::Display for JobEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobEventKind::Started => write!(f, "started"),
+            JobEventKind::Finished => write!(f, "finished"),
+            JobEventKind::Failed => write!(f, "failed"),
+            JobEventKind::Retried => write!(f, "retried"),
+            JobEventKind::ScheduleChanged => write!(f, "schedule_changed"),
+            JobEventKind::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+/// A scheduler event, as streamed to `Subscribe` connections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    pub kind: JobEventKind,
+    pub at: String, // RFC 3339, millisecond precision
+    pub job_id: String,
+    pub job_name: String,
+    pub execution_id: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// Which events a subscription receives; an empty list matches everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+    #[serde(default)]
+    pub job_ids: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<JobEventKind>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &JobEvent) -> bool {
+        (self.job_ids.is_empty() || self.job_ids.contains(&event.job_id))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+    }
+}
+
+/// A job whose last run failed with no retries left. It keeps its schedule unless
+/// `RetryPolicy::disable_after_exhaustions` disabled it; a successful run or `RetryJob` clears it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub job_id: String,
+    pub job_name: String,
+    pub failed_at: String, // RFC 3339
+    pub error: String, // Of the last attempt
+    pub attempts: u32, // Of the last run, retries included
+    pub exhaustions: u32, // Consecutive runs that failed for good
+    pub disabled: bool, // Disabled by the daemon after too many exhaustions
+}
+
+/// Run counters of a job, updated as each execution is recorded
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobStats {
+    pub job_id: String,
+    pub total_runs: u64,
+    pub successes: u64,
+    pub failures: u64, // Runs neither successful nor of unknown outcome (adopted after a restart)
+    pub avg_duration_ms: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub last_run_at: Option<String>, // UTC, "YYYY-MM-DD HH:MM:SS"
+    pub last_success_at: Option<String>,
+    pub last_failure_at: Option<String>,
+}
+
+impl JobStats {
+    /// Share of runs with a known outcome that succeeded, in percent
+    pub fn success_rate(&self) -> Option<f64> {
+        let decided = self.successes + self.failures;
+        (decided > 0).then(|| self.successes as f64 * 100.0 / decided as f64)
+    }
+}
+
+/// A started execution as recorded for restarts: scheduled windows already fired aren't fired
+/// again, and executions never finished are looked for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionWindow {
+    pub job_id: String,
+    pub execution_id: String,
+    pub scheduled_time: String, // RFC 3339
+    pub started_at: String, // RFC 3339
+    pub finished_at: Option<String>, // None while running, or if the daemon stopped first
+    pub pid: Option<u32>,
+    pub fired_window: bool, // A scheduled run (not a manual start, retry or catch-up)
+}
+
+/// A request that changed something (or was refused), as recorded in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: String, // UTC, "YYYY-MM-DD HH:MM:SS"
+    pub uid: Option<u32>, // Peer uid of local clients
+    pub user: String, // Account name, "remote" for TCP clients or "api"
+    pub action: String, // e.g. "add", "remove", "start"
+    pub job_id: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>, // Of the job it is about
+    pub summary: String, // Never holds secret or env values
+    pub outcome: String, // "ok", "denied: ..." or "error: ..."
+}
+
+/// A stored secret, without its value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub name: String,
+    pub updated_at: String,
+}
+
+/// A change to one job that spreads out a crowded start time (`lunasched rebalance`).
+/// Either `schedule` and `target` (the job moves to another minute) or `jitter_seconds` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalanceSuggestion {
+    pub job_id: JobId,
+    pub job_name: String,
+    pub slot: String, // Crowded start time, HH:MM in the daemon's local time
+    pub slot_jobs: usize, // Jobs regularly starting in that minute
+    pub schedule: Option<String>, // New cron expression
+    pub target: Option<String>, // HH:MM the job moves to
+    pub jitter_seconds: Option<u64>,
+}
+
+/// A point in an execution's lifecycle ("scheduled", "spawned", "first_output", "killed", ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    pub event: String,
+    pub at: String, // RFC 3339, millisecond precision
+    pub detail: Option<String>,
+}
+
+/// What `ImportJobs` does with a job whose ID already exists
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    Skip,      // Keep the existing job
+    Overwrite, // Replace it
+    Rename,    // Import under a free ID (`<id>-2`, `<id>-3`, ...)
+}
+
+/// What `EnsureJob` did with a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveOutcome {
+    Created,
+    Updated,
+    Unchanged, // Identical, or kept as it was for `if_absent`
+}
+
+impl std::fmt::Display for SaveOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveOutcome::Created => write!(f, "created"),
+            SaveOutcome::Updated => write!(f, "updated"),
+            SaveOutcome::Unchanged => write!(f, "unchanged"),
+        }
+    }
+}
+
+/// Job IDs affected by an import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub renamed: Vec<(String, String)>, // (ID in the file, ID it was imported as)
+    pub skipped: Vec<String>,
+}
+
+/// Narrows the history GetHistory answers with; unset fields match every execution
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryFilter {
+    pub status: Option<String>, // "failed" also matches Error and SpawnError
+    pub since: Option<String>, // RFC 3339
+    pub grep: Option<String>, // Text in the output (message, stdout or stderr), case-insensitive
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,5 +318,338 @@ pub struct HistoryEntry {
     pub job_id: String,
     pub run_at: String, // DateTime string
     pub status: String,
-    pub output: Option<String>,
+    pub output: Option<String>, // Status message; executions record stdout/stderr instead
+    // Per-execution details (absent for heartbeat events and entries recorded before v6)
+    #[serde(default)]
+    pub execution_id: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub duration_ms: Option<i64>,
+    #[serde(default)]
+    pub scheduled_at: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<String>,
+    #[serde(default)]
+    pub stdout: Option<String>,
+    #[serde(default)]
+    pub stderr: Option<String>,
+    #[serde(default)]
+    pub resource_events: Option<String>, // Memory/CPU limit events, e.g. "oom_kill=1"
+    #[serde(default)]
+    pub stdout_bytes: Option<i64>, // Written by the process; stdout keeps only the last max_output_bytes
+    #[serde(default)]
+    pub stderr_bytes: Option<i64>,
+    #[serde(default)]
+    pub log_path: Option<String>, // Full output of the execution, while the file is kept
+    #[serde(default)]
+    pub hook_result: Option<String>, // e.g. "on_success: exit code 0 in 35ms"
+    #[serde(default)]
+    pub hook_output: Option<String>,
+    #[serde(default)]
+    pub signal: Option<i32>, // Signal that killed the process; it has no exit code then
+    #[serde(default)]
+    pub attempt: Option<u32>, // 1 for the first run, 2 for its first retry, and so on
+}
+
+impl HistoryEntry {
+    /// Everything captured for the entry as one text: the message, or stdout and stderr
+    pub fn combined_output(&self) -> String {
+        match (&self.stdout, &self.stderr) {
+            (None, None) => self.output.clone().unwrap_or_default(),
+            (stdout, stderr) => format!("Stdout:\n{}\nStderr:\n{}",
+                stdout.as_deref().unwrap_or(""), stderr.as_deref().unwrap_or("")),
+        }
+    }
+}
+
+/// Outcome of sending a test message through one configured notification channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationTestResult {
+    pub index: usize,
+    pub event: String,
+    pub channel: String,
+    pub target: String,
+    pub error: Option<String>, // None when delivered
+    #[serde(default)]
+    pub message: Option<String>, // The rendered message, when previewing
+}
+
+/// Job IDs changed by reconciling a declarative job set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub job_count: usize,
+    pub running_count: usize,
+    #[serde(default)]
+    pub db_attached: bool,
+    #[serde(default)]
+    pub history_buffered: usize, // Records waiting for the database
+    #[serde(default)]
+    pub history_dropped: u64, // Records lost to a full buffer
+    #[serde(default)]
+    pub db_path: Option<String>,
+    #[serde(default)]
+    pub db_error: Option<String>, // Set if the attached database failed a health query
+    #[serde(default)]
+    pub retry_backlog: usize, // Runs waiting for a retry attempt
+    #[serde(default)]
+    pub queued_runs: usize, // Runs waiting for a free execution slot
+    #[serde(default)]
+    pub running: Vec<RunningExecution>,
+    #[serde(default)]
+    pub next_runs: Vec<NextRun>,
+    #[serde(default)]
+    pub pending_retries: Vec<PendingRetry>,
+    #[serde(default)]
+    pub recent_failures: Vec<HistoryEntry>, // Newest first
+    #[serde(default)]
+    pub notification_failures: Vec<NotificationFailure>, // Undeliverable in the last 24h, newest first
+    pub profile: SchedulerProfile,
+    #[serde(default)]
+    pub ha: Option<HaStatus>, // Set when the daemon takes part in leader election
+}
+
+/// An agent connected to the daemon (`lunasched agents`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentInfo {
+    pub name: String,
+    pub labels: Vec<String>,
+    pub addr: String,
+    pub connected_at: String, // RFC 3339
+    pub running: usize, // Executions it runs for the daemon
+}
+
+/// This daemon's part in leader election (`ha` in the config)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HaStatus {
+    pub node: String, // This daemon's name in the lease
+    pub leader: bool, // Whether this daemon runs jobs; a standby doesn't
+    pub lease_holder: Option<String>, // The leader, as last seen; None if the lease could not be read
+    pub lease_expires_at: Option<String>, // RFC 3339
+    pub lease_source: String, // Where the lease is kept
+}
+
+/// How a health check came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning, // Works, but something is off
+    Failed,
+}
+
+/// One check of `lunasched doctor`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub remedy: Option<String>, // What to do about a warning or failure
+}
+
+impl HealthCheck {
+    pub fn ok(name: &str, detail: String) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Ok, detail, remedy: None }
+    }
+
+    pub fn warning(name: &str, detail: String, remedy: String) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Warning, detail, remedy: Some(remedy) }
+    }
+
+    pub fn failed(name: &str, detail: String, remedy: String) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Failed, detail, remedy: Some(remedy) }
+    }
+}
+
+/// A database copy made by BackupDatabase, or restored by RestoreDatabase, as checked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupReport {
+    pub path: String,
+    pub size_bytes: u64,
+    pub schema_version: i32,
+    pub jobs: usize,
+    pub history_entries: u64,
+}
+
+/// The daemon's side of `lunasched doctor`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+    pub time: String, // The daemon's clock (RFC 3339), for comparing with the client's
+}
+
+/// A notification that could not be delivered, retries included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationFailure {
+    pub job_id: String,
+    pub event: String,
+    pub channel: String,
+    pub created_at: String, // UTC, "YYYY-MM-DD HH:MM:SS"
+    pub attempts: u32,
+    pub error: String, // Of the last attempt
+}
+
+/// A failed run waiting for its next retry attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRetry {
+    pub job_id: String,
+    pub attempt: u32,
+    pub next_attempt_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningExecution {
+    pub execution_id: String,
+    pub job_id: String,
+    pub pid: Option<u32>,
+    pub started_at: String,
+    pub duration_seconds: u64,
+}
+
+/// What a job is doing right now, as listed by `ListJobs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobRuntime {
+    Running { executions: Vec<RunningExecution> }, // Oldest first
+    Queued { runs: usize }, // Waiting for a free execution slot
+    PendingRetry { attempt: u32, next_attempt_at: Option<String> },
+    Idle { next_run: Option<String> }, // None for disabled and heartbeat jobs
+}
+
+/// A job with its runtime state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListedJob {
+    #[serde(flatten)]
+    pub job: Job,
+    pub status: JobRuntime,
+}
+
+/// When a job is next due; None for disabled and heartbeat jobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NextRun {
+    pub job_id: String,
+    pub job_name: String,
+    pub next_run: Option<String>,
+}
+
+/// Scheduler loop diagnostics. `lock_waits` and `top_jobs` are only filled in for verbose status.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulerProfile {
+    pub ticks: u64,
+    pub last_tick_ms: f64,
+    pub avg_tick_ms: f64,
+    pub max_tick_ms: f64,
+    pub slow_ticks: u64,
+    pub lock_waits: Vec<LockWaitStats>,
+    pub top_jobs: Vec<JobEvalStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockWaitStats {
+    pub site: String,
+    pub count: u64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Cumulative cost of evaluating a job's schedule on each tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvalStats {
+    pub job_id: String,
+    pub evaluations: u64,
+    pub avg_us: f64,
+    pub max_us: f64,
+    pub total_ms: f64,
+}
+
+/// Encode a message for the daemon socket. A connection is a session: the client may send any
+/// number of requests, each a JSON document followed by a newline, reading the response(s) to
+/// one before sending the next. Most requests get a single response; `GetJobOutput` streams
+/// chunks until `OutputEnd` and `Subscribe` streams events until the client hangs up.
+pub fn encode_frame<T: Serialize>(message: &T) -> serde_json::Result<Vec<u8>> {
+    let mut frame = serde_json::to_vec(message)?;
+    frame.push(b'\n');
+    Ok(frame)
+}
+
+/// Received bytes, cut into messages as they complete. Splits on document boundaries rather
+/// than newlines, so peers that leave the newline out are understood too.
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Next complete message, or `None` until more bytes arrive
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<T: DeserializeOwned>(&mut self) -> serde_json::Result<Option<T>> {
+        let mut documents = serde_json::Deserializer::from_slice(&self.buf).into_iter::<T>();
+        match documents.next() {
+            Some(Ok(message)) => {
+                let consumed = documents.byte_offset();
+                self.buf.drain(..consumed);
+                Ok(Some(message))
+            }
+            Some(Err(e)) if e.is_eof() => Ok(None),
+            Some(Err(e)) => Err(e),
+            None => {
+                // Only whitespace left
+                self.buf.clear();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Bytes waiting for the rest of their message
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_split_across_reads() {
+        let mut frames = FrameReader::default();
+        let bytes = [encode_frame(&Response::Ok).unwrap(), encode_frame(&Response::Error("x".to_string())).unwrap()].concat();
+        let (first, second) = bytes.split_at(3);
+
+        frames.push(first);
+        assert!(frames.next::<Response>().unwrap().is_none());
+        frames.push(second);
+        assert!(matches!(frames.next::<Response>().unwrap(), Some(Response::Ok)));
+        assert!(matches!(frames.next::<Response>().unwrap(), Some(Response::Error(e)) if e == "x"));
+        assert!(frames.next::<Response>().unwrap().is_none());
+        assert_eq!(frames.buffered(), 0);
+    }
+
+    #[test]
+    fn frames_without_newlines() {
+        let mut frames = FrameReader::default();
+        frames.push(br#"{"a":1}{"a":2}"#);
+        assert_eq!(frames.next::<serde_json::Value>().unwrap(), Some(serde_json::json!({"a": 1})));
+        assert_eq!(frames.next::<serde_json::Value>().unwrap(), Some(serde_json::json!({"a": 2})));
+    }
+
+    #[test]
+    fn frames_reject_garbage() {
+        let mut frames = FrameReader::default();
+        frames.push(b"{\"a\":]\n");
+        assert!(frames.next::<serde_json::Value>().is_err());
+    }
 }
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+/// A job's stable identity: a UUID given by the daemon when the job is added (jobs added before
+/// that keep their old IDs, which equal their names). Empty in a job that has none yet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct JobId(pub String);
 
 impl std::fmt::Display for JobId {
@@ -10,19 +14,131 @@ impl std::fmt::Display for JobId {
     }
 }
 
+/// A calendar schedule: fires at each of its times on every day matching all of the day
+/// restrictions that are set
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CalendarParams {
-    pub days_of_week: Option<Vec<u32>>, // 0=Mon, 6=Sun (chrono-like but 0-indexed from Mon for simplicity in parsing? Or use chrono::Weekday)
-    // Actually let's use u32 for simplicity in serialization: 1=Mon, 7=Sun to match ISO/Chrono
-    pub nth_weekday: Option<(u32, u32)>, // (n, weekday) e.g. (1, 1) = 1st Monday
+    pub days_of_week: Option<Vec<u32>>, // 1=Mon, 7=Sun to match ISO/Chrono
+    pub nth_weekday: Option<(u32, u32)>, // (n, weekday) e.g. (1, 1) = 1st Monday; n up to 5
     pub time: (u32, u32, u32), // H, M, S
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_times: Vec<(u32, u32, u32)>, // Further times of day ("at 09:00 and 18:00")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days_of_month: Option<Vec<u32>>, // 1-31; months without the day are skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_weekday: Option<u32>, // Last such weekday of the month, e.g. 5 = last Friday
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub months: Option<Vec<u32>>, // 1=Jan, 12=Dec
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CalendarParams {
+    /// All times of day, in the order given
+    pub fn times(&self) -> impl Iterator<Item = (u32, u32, u32)> + '_ {
+        std::iter::once(self.time).chain(self.extra_times.iter().copied())
+    }
+
+    /// Whether the schedule fires on `date` (at its times)
+    pub fn matches_date(&self, date: chrono::NaiveDate) -> bool {
+        use chrono::Datelike;
+        let weekday = date.weekday().number_from_monday();
+        if self.days_of_week.as_ref().is_some_and(|days| !days.contains(&weekday)) {
+            return false;
+        }
+        if let Some((n, nth)) = self.nth_weekday {
+            if weekday != nth || (date.day() - 1) / 7 + 1 != n {
+                return false;
+            }
+        }
+        if let Some(last) = self.last_weekday {
+            // Last of its weekday when a week later is already next month
+            let next_week = date + chrono::Duration::days(7);
+            if weekday != last || next_week.month() == date.month() {
+                return false;
+            }
+        }
+        if self.days_of_month.as_ref().is_some_and(|days| !days.contains(&date.day())) {
+            return false;
+        }
+        if self.months.as_ref().is_some_and(|months| !months.contains(&date.month())) {
+            return false;
+        }
+        true
+    }
+}
+
+/// How a job is scheduled. Serialized as the text `parse_schedule` reads (see its Display).
+#[derive(Debug, Clone)]
 pub enum ScheduleConfig {
     Cron(String),
     Every(u64),
     Calendar(CalendarParams),
+    Heartbeat(u64), // Passive: expects a ping at least every N seconds
+    Reboot, // Once per host boot, when the daemon first starts
+    Once(DateTime<Utc>), // A single run at this time (late if the daemon was down), then the job is disabled
+    Window(WindowParams), // A fixed interval within hours of the day ("every 15m between 09:00 and 17:00")
+}
+
+// Most runs a day a windowed interval may have, as a calendar schedule lists them all
+const MAX_WINDOW_RUNS: u64 = 1440;
+
+impl ScheduleConfig {
+    /// Check a schedule that didn't come through `parse_schedule`, e.g. one sent by a client
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ScheduleConfig::Cron(expression) => crate::schedule::validate_cron(expression).map_err(|e| e.to_string()),
+            ScheduleConfig::Every(seconds) | ScheduleConfig::Heartbeat(seconds) if *seconds < crate::TICK_INTERVAL_SECS => {
+                Err(format!("Interval of {}s is shorter than the {}s scheduler tick", seconds, crate::TICK_INTERVAL_SECS))
+            }
+            ScheduleConfig::Calendar(params) => match params.times().find(|&(h, m, s)| h > 23 || m > 59 || s > 59) {
+                Some((h, m, s)) => Err(format!("Invalid time {:02}:{:02}:{:02}", h, m, s)),
+                None => Ok(()),
+            },
+            ScheduleConfig::Window(params) => {
+                if let Some(&(h, m, s)) = [params.start, params.end].iter().find(|&&(h, m, s)| h > 23 || m > 59 || s > 59) {
+                    return Err(format!("Invalid time {:02}:{:02}:{:02}", h, m, s));
+                }
+                if params.interval < crate::TICK_INTERVAL_SECS {
+                    return Err(format!("Interval of {}s is shorter than the {}s scheduler tick", params.interval, crate::TICK_INTERVAL_SECS));
+                }
+                let (start, end) = (WindowParams::seconds(params.start), WindowParams::seconds(params.end));
+                if start > end {
+                    return Err("The window must end after it starts; split a window past midnight into two jobs".to_string());
+                }
+                if (end - start) / params.interval + 1 > MAX_WINDOW_RUNS {
+                    return Err(format!("A window may fire at most {} times a day", MAX_WINDOW_RUNS));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A windowed interval: fires at `start` and every `interval` after it up to `end` (inclusive),
+/// on each day matching the day restrictions of `days`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowParams {
+    pub interval: u64, // Seconds
+    pub start: (u32, u32, u32), // H, M, S
+    pub end: (u32, u32, u32),
+    pub days: CalendarParams, // Only its days and months are used
+}
+
+impl WindowParams {
+    fn seconds((h, m, s): (u32, u32, u32)) -> u64 {
+        (h * 3600 + m * 60 + s) as u64
+    }
+
+    /// The same schedule as a calendar with a time for each run of the day
+    pub fn calendar(&self) -> CalendarParams {
+        let (start, end) = (Self::seconds(self.start), Self::seconds(self.end));
+        let extra_times = (start..=end)
+            .step_by(self.interval.max(1) as usize)
+            .skip(1)
+            .map(|t| ((t / 3600) as u32, (t / 60 % 60) as u32, (t % 60) as u32))
+            .collect();
+        CalendarParams { time: self.start, extra_times, ..self.days.clone() }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -34,112 +150,460 @@ pub enum BackoffStrategy {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetryPolicy {
-    pub max_attempts: u32,
+    /// Retries after a failed run; unset takes the tag default, if any, else none
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
     pub backoff_strategy: BackoffStrategy,
     pub initial_delay_seconds: u64,
     pub max_delay_seconds: u64,
+    /// Disable the job after this many consecutive runs fail with no retries left
+    #[serde(default)]
+    pub disable_after_exhaustions: Option<u32>,
+    /// Spread each retry delay randomly by up to this percentage either way, so jobs that
+    /// failed together don't all retry at the same moment
+    #[serde(default)]
+    pub jitter_percent: Option<u32>,
+}
+
+impl RetryPolicy {
+    /// Retries to make after a failed run
+    pub fn retries(&self) -> u32 {
+        self.max_attempts.unwrap_or(0)
+    }
 }
 
 impl Default for RetryPolicy {
     fn default() -> Self {
         Self {
-            max_attempts: 0, // No retries by default
+            max_attempts: None, // No retries by default
             backoff_strategy: BackoffStrategy::Exponential,
             initial_delay_seconds: 60,
             max_delay_seconds: 3600,
+            disable_after_exhaustions: None,
+            jitter_percent: None,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Default)]
 pub struct ResourceLimits {
     pub timeout_seconds: Option<u64>,
     pub max_memory_mb: Option<u64>,
     pub cpu_quota: Option<f32>, // 0.0-1.0, 1.0 = 100% of one core
 }
 
-impl Default for ResourceLimits {
-    fn default() -> Self {
-        Self {
-            timeout_seconds: None,
-            max_memory_mb: None,
-            cpu_quota: None,
+
+/// Longest script a job may carry (see `Job::script`)
+pub const MAX_SCRIPT_BYTES: usize = 1024 * 1024;
+
+/// What runs a job's command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Default)]
+pub enum Executor {
+    #[default]
+    Local, // /bin/sh on the daemon's host (or its target agent), as the job's user
+    Container {
+        image: String,
+        #[serde(default)]
+        volumes: Vec<String>, // "/host/path:/container/path[:ro]", as for `docker run -v`
+        #[serde(default)]
+        network: Option<String>, // e.g. "none" or "host"; default: the engine's bridge
+    },
+    /// A transient systemd service (`systemd-run`) as the job's user, with its limits and
+    /// sandbox as unit properties, and the sandboxing below
+    Systemd {
+        #[serde(default)]
+        protect_system: Option<String>, // ProtectSystem=: "yes", "full" or "strict"
+        #[serde(default)]
+        properties: Vec<String>, // Further unit properties, e.g. "ReadWritePaths=/var/log/app"
+    },
+}
+
+
+impl Executor {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Executor::Local => {}
+            Executor::Container { image, volumes, .. } => {
+                if image.trim().is_empty() {
+                    return Err("Container executor needs an image".to_string());
+                }
+                for volume in volumes {
+                    let mut parts = volume.split(':');
+                    let (host, container) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+                    if !host.starts_with('/') || !container.starts_with('/') {
+                        return Err(format!("Invalid volume '{}': expected /host/path:/container/path[:options]", volume));
+                    }
+                }
+            }
+            Executor::Systemd { protect_system, properties, .. } => {
+                if let Some(mode) = protect_system {
+                    if !["yes", "true", "no", "false", "full", "strict"].contains(&mode.as_str()) {
+                        return Err(format!("Invalid protect_system '{}' (expected yes, full or strict)", mode));
+                    }
+                }
+                if let Some(property) = properties.iter().find(|property| !property.contains('=')) {
+                    return Err(format!("Invalid unit property '{}': expected NAME=VALUE", property));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Restrictions on a job's processes beyond running as its user, for scripts that shouldn't
+/// be trusted with everything that user can do
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Sandbox {
+    #[serde(default)]
+    pub no_new_privileges: bool, // setuid programs (sudo, su) can't raise privileges
+    #[serde(default)]
+    pub drop_capabilities: bool, // Empty capability bounding set: no root powers, even as root
+    #[serde(default)]
+    pub private_tmp: bool, // An empty /tmp of its own, gone when the run ends
+    #[serde(default)]
+    pub read_only_root: bool, // The root filesystem read-only (other mounts stay as they are)
+    #[serde(default)]
+    pub no_network: bool, // A network namespace of its own, with no interface up
+}
+
+impl Sandbox {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// When a run counts as successful. The default is exit code 0 with no other conditions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SuccessCriteria {
+    #[serde(default)]
+    pub exit_codes: Vec<i32>, // Exit codes meaning success; empty = only 0
+    #[serde(default)]
+    pub failure_pattern: Option<String>, // Regex; a match in stdout or stderr fails the run
+    #[serde(default)]
+    pub max_duration_seconds: Option<u64>, // Slower runs fail, but are not killed (see timeout_seconds)
+}
+
+impl SuccessCriteria {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref pattern) = self.failure_pattern {
+            regex::Regex::new(pattern).map_err(|e| format!("Invalid failure_pattern: {}", e))?;
         }
+        Ok(())
+    }
+
+    /// Why a finished run failed, or None if it succeeded. `exit_code` is None when the
+    /// process was killed by a signal, which is always a failure.
+    pub fn failure(&self, exit_code: Option<i32>, stdout: &str, stderr: &str, duration: std::time::Duration) -> Option<String> {
+        let code = match exit_code {
+            Some(code) => code,
+            None => return Some("Killed by signal".to_string()),
+        };
+        let exit_ok = if self.exit_codes.is_empty() { code == 0 } else { self.exit_codes.contains(&code) };
+        if !exit_ok {
+            return Some(format!("Exit code: {}", code));
+        }
+        if let Some(max) = self.max_duration_seconds {
+            if duration > std::time::Duration::from_secs(max) {
+                return Some(format!("Ran for {}s, over max_duration_seconds ({}s)", duration.as_secs(), max));
+            }
+        }
+        if let Some(ref pattern) = self.failure_pattern {
+            let regex = match regex::Regex::new(pattern) {
+                Ok(regex) => regex,
+                Err(e) => return Some(format!("Invalid failure_pattern: {}", e)),
+            };
+            if regex.is_match(stdout) {
+                return Some(format!("stdout matched failure_pattern '{}'", pattern));
+            }
+            if regex.is_match(stderr) {
+                return Some(format!("stderr matched failure_pattern '{}'", pattern));
+            }
+        }
+        None
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Default)]
 pub struct JobHooks {
     pub on_failure: Option<String>,
     pub on_success: Option<String>,
+    #[serde(default)]
+    pub on_retry: Option<String>, // Run when a failed run is scheduled for another attempt
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>, // Hooks still running after this are killed; defaults to 60s
 }
 
-impl Default for JobHooks {
-    fn default() -> Self {
-        Self {
-            on_failure: None,
-            on_success: None,
-        }
-    }
-}
 
 // New v1.2.0 structures
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Default)]
 pub enum JobPriority {
     Low,
+    #[default]
     Normal,
     High,
     Critical,
 }
 
-impl Default for JobPriority {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Default)]
 pub enum ExecutionMode {
+    #[default]
     Sequential,  // Wait for previous execution to finish
     Parallel,    // Allow multiple executions
     Exclusive,   // Only one instance across all jobs
 }
 
-impl Default for ExecutionMode {
-    fn default() -> Self {
-        Self::Sequential
-    }
+
+/// What to do on startup about Cron/Calendar windows missed while the daemon was down
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Default)]
+pub enum CatchUpPolicy {
+    #[default]
+    Skip,    // Forget them
+    RunOnce, // Run once for the most recent missed window
+    RunAll,  // Run once per missed window, oldest first
+}
+
+
+/// What happens to runs of a Parallel job beyond its `max_concurrent` limit
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Default)]
+pub enum OverflowPolicy {
+    #[default]
+    Skip,  // Drop the run
+    Queue, // Start it once an execution finishes
 }
 
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Default)]
 pub struct NotificationConfig {
     pub on_success: Option<Vec<NotificationChannel>>,
     pub on_failure: Option<Vec<NotificationChannel>>,
     pub on_start: Option<Vec<NotificationChannel>>,
+    #[serde(default)]
+    pub on_retry: Option<Vec<NotificationChannel>>, // A failed run is scheduled for another attempt
+    #[serde(default)]
+    pub alerts: AlertPolicy,
+    /// Message sent instead of the built-in one, with {{placeholder}}s (see MESSAGE_PLACEHOLDERS)
+    #[serde(default)]
+    pub template: Option<String>,
 }
 
-impl Default for NotificationConfig {
-    fn default() -> Self {
-        Self {
-            on_success: None,
-            on_failure: None,
-            on_start: None,
+
+/// When failures are worth a notification. Without thresholds every run that fails for good
+/// notifies on_failure. With them, failures notify once when a threshold is crossed, and
+/// on_success hears when the job is healthy again.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertPolicy {
+    /// Notify when this many runs in a row have failed for good
+    #[serde(default)]
+    pub after_failures: Option<u32>,
+    /// Notify when the percentage of successful runs within the window falls below this
+    #[serde(default)]
+    pub min_success_rate: Option<f64>,
+    /// Window of min_success_rate in seconds (default: 24 hours)
+    #[serde(default)]
+    pub success_rate_window: Option<u64>,
+}
+
+impl AlertPolicy {
+    pub const DEFAULT_SUCCESS_RATE_WINDOW: u64 = 24 * 3600;
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl NotificationConfig {
+    /// Check every channel's settings, so a typo fails when the job is added rather than
+    /// when the first notification is due
+    pub fn validate(&self) -> Result<(), String> {
+        let events = [
+            ("on_success", &self.on_success),
+            ("on_failure", &self.on_failure),
+            ("on_start", &self.on_start),
+            ("on_retry", &self.on_retry),
+        ];
+        for (event, channels) in events {
+            for channel in channels.iter().flatten() {
+                channel.validate().map_err(|e| format!("Invalid {} channel: {}", event, e))?;
+            }
         }
+        if let Some(ref template) = self.template {
+            check_message_template(template)?;
+        }
+        Ok(())
     }
 }
 
+/// Placeholders a notification message template may use
+pub const MESSAGE_PLACEHOLDERS: &[&str] = &[
+    "job.id", "job.name", "event", "status", "message", "duration", "exit_code", "output_tail", "execution_id",
+];
+
+/// Fail naming the placeholders of `template` that aren't in MESSAGE_PLACEHOLDERS
+pub fn check_message_template(template: &str) -> Result<(), String> {
+    let mut unknown = Vec::new();
+    render_message(template, |name| {
+        if !MESSAGE_PLACEHOLDERS.contains(&name) {
+            unknown.push(name.to_string());
+        }
+        None
+    });
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Unknown placeholder(s) in notification template: {} (expected {})",
+            unknown.join(", "), MESSAGE_PLACEHOLDERS.join(", ")))
+    }
+}
+
+/// Substitute `{{name}}` placeholders with `value(name)`; those it has no value for are kept
+/// as written
+pub fn render_message(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        match value(rest[start + 2..start + end].trim()) {
+            Some(v) => out.push_str(&v),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NotificationChannel {
     Email { to: String, subject: Option<String> },
     Webhook { url: String, headers: Option<HashMap<String, String>> },
     Discord { webhook_url: String },
     Slack { webhook_url: String },
+    Telegram { bot_token: String, chat_id: String },
+    Ntfy {
+        topic: String,
+        server: Option<String>,   // Default: https://ntfy.sh
+        token: Option<String>,    // Access token for protected topics
+        priority: Option<u8>,     // 1 (min) to 5 (max); default: 4 for failures, 3 otherwise
+    },
+    Syslog { facility: Option<String> }, // Local syslog/journald via /dev/log; default facility: daemon
+}
+
+/// Syslog facilities a Syslog channel may name, with their codes
+pub const SYSLOG_FACILITIES: &[(&str, u8)] = &[
+    ("user", 1), ("mail", 2), ("daemon", 3), ("auth", 4), ("cron", 9),
+    ("local0", 16), ("local1", 17), ("local2", 18), ("local3", 19),
+    ("local4", 20), ("local5", 21), ("local6", 22), ("local7", 23),
+];
+
+impl NotificationChannel {
+    pub const NTFY_SERVER: &'static str = "https://ntfy.sh";
+    pub const SYSLOG_FACILITY: &'static str = "daemon";
+
+    /// Channel type, as written in config files
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotificationChannel::Email { .. } => "email",
+            NotificationChannel::Webhook { .. } => "webhook",
+            NotificationChannel::Discord { .. } => "discord",
+            NotificationChannel::Slack { .. } => "slack",
+            NotificationChannel::Telegram { .. } => "telegram",
+            NotificationChannel::Ntfy { .. } => "ntfy",
+            NotificationChannel::Syslog { .. } => "syslog",
+        }
+    }
+
+    /// Where the channel delivers to, safe to display (webhook URLs are cut to their origin
+    /// since paths often embed tokens)
+    pub fn target(&self) -> String {
+        let url = match self {
+            NotificationChannel::Email { to, .. } => return to.clone(),
+            NotificationChannel::Webhook { url, .. } => url,
+            NotificationChannel::Discord { webhook_url } | NotificationChannel::Slack { webhook_url } => webhook_url,
+            NotificationChannel::Telegram { chat_id, .. } => return format!("chat {}", chat_id),
+            // On a public server the topic name is all it takes to publish
+            NotificationChannel::Ntfy { server, .. } => server.as_deref().unwrap_or(Self::NTFY_SERVER),
+            NotificationChannel::Syslog { facility } => return facility.as_deref().unwrap_or(Self::SYSLOG_FACILITY).to_string(),
+        };
+        match url.match_indices('/').nth(2) {
+            Some((idx, _)) => url[..idx].to_string(),
+            None => url.to_string(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let check_url = |url: &str| {
+            if url.starts_with("https://") || url.starts_with("http://") {
+                Ok(())
+            } else {
+                Err(format!("'{}' is not an http(s) URL", url))
+            }
+        };
+        match self {
+            NotificationChannel::Email { to, .. } => {
+                if !to.contains('@') {
+                    return Err(format!("'{}' is not an email address", to));
+                }
+            }
+            NotificationChannel::Webhook { url, .. } => check_url(url)?,
+            NotificationChannel::Discord { webhook_url } | NotificationChannel::Slack { webhook_url } => check_url(webhook_url)?,
+            NotificationChannel::Telegram { bot_token, chat_id } => {
+                // Tokens from @BotFather look like "123456:ABC-DEF..."
+                let well_formed = bot_token.split_once(':').is_some_and(|(id, secret)| {
+                    !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) && !secret.is_empty()
+                });
+                if !well_formed {
+                    return Err("Telegram bot_token must look like <bot id>:<secret>".to_string());
+                }
+                if chat_id.is_empty() {
+                    return Err("Telegram chat_id is empty".to_string());
+                }
+            }
+            NotificationChannel::Ntfy { topic, server, priority, .. } => {
+                if topic.is_empty() || topic.len() > 64 || !topic.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+                    return Err(format!("ntfy topic '{}' must be 1-64 letters, digits, '-' or '_'", topic));
+                }
+                if let Some(server) = server {
+                    check_url(server)?;
+                }
+                if matches!(priority, Some(p) if !(1..=5).contains(p)) {
+                    return Err("ntfy priority must be between 1 and 5".to_string());
+                }
+            }
+            NotificationChannel::Syslog { facility } => {
+                if let Some(facility) = facility {
+                    if !SYSLOG_FACILITIES.iter().any(|(name, _)| name == facility) {
+                        let names: Vec<&str> = SYSLOG_FACILITIES.iter().map(|(name, _)| *name).collect();
+                        return Err(format!("Unknown syslog facility '{}' (expected one of {})", facility, names.join(", ")));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
-    pub id: JobId,
-    pub name: String,
+    #[serde(default)]
+    pub id: JobId, // Assigned by the daemon when empty
+    pub name: String, // Unique among its owner's jobs; may be changed
     pub schedule: ScheduleConfig,
     pub command: String,
     pub args: Vec<String>,
@@ -162,7 +626,9 @@ pub struct Job {
     #[serde(default)]
     pub hooks: JobHooks,
     #[serde(default)]
-    pub max_concurrent: u32, // 0 = unlimited
+    pub max_concurrent: u32, // Parallel mode only; 0 = unlimited
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
     
     // Phase 2 fields (v1.2.0)
     #[serde(default)]
@@ -171,6 +637,167 @@ pub struct Job {
     pub execution_mode: ExecutionMode,
     #[serde(default)]
     pub notification_config: NotificationConfig,
+
+    /// Env var names/patterns (e.g. "*_KEY") whose values are scrubbed from logs and history
+    #[serde(default)]
+    pub redact_env: Vec<String>,
+
+    /// Concurrency key template (e.g. "{{params.CUSTOMER}}"). Runs whose rendered keys differ
+    /// may overlap; runs with the same key are serialized.
+    #[serde(default)]
+    pub fence_key: Option<String>,
+
+    /// Snapshot the resolved command and environment of every execution so it can be
+    /// replayed exactly with `lunasched rerun`
+    #[serde(default)]
+    pub capture_env: bool,
+
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+
+    #[serde(default)]
+    pub boot_delay: Option<u64>, // Seconds after host boot before startup runs fire; overrides the config default
+
+    /// Remove a Once job after its run (and any retries) instead of leaving it disabled
+    #[serde(default)]
+    pub delete_after_run: bool,
+
+    /// User the job's processes run as instead of its owner; only root-owned jobs may set
+    /// a user other than the owner
+    #[serde(default)]
+    pub run_as: Option<String>,
+
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>, // Defaults to /tmp
+
+    #[serde(default)]
+    pub umask: Option<u32>, // Defaults to the daemon's umask
+
+    /// Jobs sharing a group never run at the same time. A run that comes due while the
+    /// group is busy is skipped or queued according to the group's policy in the config.
+    #[serde(default)]
+    pub concurrency_group: Option<String>,
+
+    #[serde(default)]
+    pub success_criteria: SuccessCriteria,
+
+    #[serde(default)]
+    pub max_output_bytes: Option<u64>, // Kept of stdout and of stderr each; defaults to the daemon config, 0 = no limit
+
+    /// Fire an Every job as soon as it is added rather than one interval later
+    #[serde(default)]
+    pub run_immediately: bool,
+
+    /// Jobs queued to run when a run of this one succeeds
+    #[serde(default)]
+    pub on_success_trigger: Vec<JobId>,
+
+    /// Jobs queued to run when a run of this one fails for good (after its retries)
+    #[serde(default)]
+    pub on_failure_trigger: Vec<JobId>,
+
+    /// Disable the job after this many executions in a row fail, retries included
+    #[serde(default)]
+    pub disable_after_failures: Option<u32>,
+
+    /// Disable the job once this time has passed, rather than schedule it again
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Run on a connected agent (`lunasched-agent`) of this name or carrying this label,
+    /// rather than on the daemon's host
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// Run in a container rather than directly on the host
+    #[serde(default)]
+    pub executor: Executor,
+
+    /// Restrictions on the job's processes
+    #[serde(default)]
+    pub sandbox: Sandbox,
+
+    /// Script run instead of a command line, `command` then only naming it: written to a file
+    /// for each execution and run with `args`, so it needn't be installed where the job runs
+    #[serde(default)]
+    pub script: Option<String>,
+
+    /// Why the daemon disabled the job (too many failures, expired); set by the daemon only
+    #[serde(default)]
+    pub disabled_reason: Option<String>,
+}
+
+impl Job {
+    /// Check the executor, and that it and the sandbox can apply where the job runs:
+    /// containers, systemd units and sandboxes are on the daemon's host only. Also checks the
+    /// size of the script.
+    pub fn check_executor(&self) -> Result<(), String> {
+        self.executor.validate()?;
+        if matches!(self.script, Some(ref script) if script.len() > MAX_SCRIPT_BYTES) {
+            return Err(format!("Script of job {} is over {} KiB", self.name, MAX_SCRIPT_BYTES / 1024));
+        }
+        if self.target.is_some() && self.executor != Executor::Local {
+            return Err(format!("Job {} has a target; agents run jobs with the Local executor only", self.name));
+        }
+        if self.target.is_some() && !self.sandbox.is_empty() {
+            return Err(format!("Job {} has a target; agents don't sandbox jobs", self.name));
+        }
+        Ok(())
+    }
+
+    /// SHA-256 (hex) of the job's definition, leaving out what the daemon sets: its ID, owner
+    /// and why it was disabled. Two definitions of a job hash alike when saving one over the
+    /// other would change nothing.
+    pub fn content_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+        // Objects serialize with sorted keys, so env maps hash alike whatever their order
+        let mut definition = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = definition.as_object_mut() {
+            for field in ["id", "owner", "disabled_reason"] {
+                fields.remove(field);
+            }
+        }
+        Sha256::digest(definition.to_string().as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// User the job's processes run as: `run_as`, else the owner ("lunasched" if there is none)
+    pub fn run_as_user(&self) -> &str {
+        match self.run_as {
+            Some(ref user) => user,
+            None if self.owner.is_empty() => "lunasched",
+            None => &self.owner,
+        }
+    }
+
+    /// Key identifying a run for concurrency checks: the job ID, plus the rendered
+    /// fence key when one is set
+    pub fn run_key(&self, params: &HashMap<String, String>) -> String {
+        match self.fence_key {
+            Some(ref template) => format!("{}[{}]", self.id.0, render_params(template, params)),
+            None => self.id.0.clone(),
+        }
+    }
+}
+
+/// Substitute `{{params.NAME}}` placeholders; unknown parameters render as empty strings
+pub fn render_params(template: &str, params: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = rest[start + 2..start + end].trim();
+        match placeholder.strip_prefix("params.") {
+            Some(name) => out.push_str(params.get(name).map(|v| v.as_str()).unwrap_or("")),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -180,3 +807,41 @@ pub enum JobStatus {
     Failed(i32), // Exit code
     Success,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_fills_known_placeholders() {
+        let rendered = render_message("{{job.name}} {{ status }} (exit {{exit_code}}) {{nope}}", |name| match name {
+            "job.name" => Some("backup".to_string()),
+            "status" => Some("failed".to_string()),
+            "exit_code" => Some("2".to_string()),
+            _ => None,
+        });
+        assert_eq!(rendered, "backup failed (exit 2) {{nope}}");
+    }
+
+    #[test]
+    fn render_leaves_unclosed_braces() {
+        let value = |_: &str| Some("x".to_string());
+        assert_eq!(render_message("a {{status", value), "a {{status");
+        assert_eq!(render_message("{{status}} }} {{", value), "x }} {{");
+        assert_eq!(render_message("", value), "");
+    }
+
+    #[test]
+    fn render_does_not_expand_values() {
+        // A value that looks like a placeholder is output as it is
+        assert_eq!(render_message("{{message}}!", |_| Some("{{message}}".to_string())), "{{message}}!");
+    }
+
+    #[test]
+    fn check_template_names_unknown_placeholders() {
+        assert!(check_message_template("{{job.name}} {{event}}: {{output_tail}}").is_ok());
+        assert!(check_message_template("no placeholders").is_ok());
+        let error = check_message_template("{{job.name}} {{jobname}} {{ statuss }}").unwrap_err();
+        assert!(error.starts_with("Unknown placeholder(s) in notification template: jobname, statuss"), "{}", error);
+    }
+}
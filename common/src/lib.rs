@@ -1,25 +1,42 @@
 // https://www.youtube.com/watch?v=xvFZjo5PgG0
 
+pub mod agent;
+pub mod bundle;
+pub mod declarative;
 pub mod ipc;
 pub mod job;
+pub mod paths;
+pub mod redact;
 pub mod schedule;
+pub mod script;
+pub mod template;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod workflow;
 
-pub use ipc::{Request, Response, HistoryEntry};
-pub use job::{Job, JobId, ScheduleConfig, CalendarParams, JobStatus, 
-             RetryPolicy, ResourceLimits, JobHooks, BackoffStrategy,
-             JobPriority, ExecutionMode, NotificationConfig, NotificationChannel};
-pub use schedule::parse_schedule;
+pub use ipc::{Request, Response, HistoryEntry, HistoryFilter, ApplyReport, ConflictPolicy, ImportReport, SaveOutcome, RebalanceSuggestion, SecretInfo, JobEvent, JobEventKind, EventFilter, TimelineEvent, DaemonStatus, HaStatus, AgentInfo, RunningExecution, JobRuntime, ListedJob, NextRun, PendingRetry, SchedulerProfile, LockWaitStats, JobEvalStats, NotificationTestResult, NotificationFailure, DeadLetter, AuditEntry, ExecutionWindow, JobStats, CheckStatus, HealthCheck, HealthReport, BackupReport, FrameReader, encode_frame};
+pub use job::{Job, JobId, ScheduleConfig, CalendarParams, WindowParams, JobStatus, 
+             RetryPolicy, ResourceLimits, Executor, Sandbox, MAX_SCRIPT_BYTES, JobHooks, BackoffStrategy,
+             JobPriority, ExecutionMode, OverflowPolicy, CatchUpPolicy, NotificationConfig, NotificationChannel, SYSLOG_FACILITIES, MESSAGE_PLACEHOLDERS, render_message, check_message_template, AlertPolicy, SuccessCriteria};
+pub use schedule::{parse_duration, parse_schedule, validate_cron, DurationError};
+pub use declarative::{JobDefinition, JobFile, PlanAction};
+pub use bundle::SignedBundle;
+pub use paths::Paths;
+pub use template::JobTemplate;
+pub use workflow::{Workflow, WorkflowRun, WorkflowStep, StepRun, StepState};
 
-// Production paths (follow FHS - Filesystem Hierarchy Standard)
+// Production paths (follow FHS - Filesystem Hierarchy Standard); see `Paths` for per-user ones
 pub const DEFAULT_SOCKET_PATH: &str = "/var/run/lunasched/lunasched.sock";
 pub const DEFAULT_DB_PATH: &str = "/var/lib/lunasched/lunasched.db";
 pub const DEFAULT_CONFIG_PATH: &str = "/etc/lunasched/config.yaml";
 pub const DEFAULT_LOG_FILE: &str = "/var/log/lunasched/daemon.log";
 pub const DEFAULT_JOBS_LOG_FILE: &str = "/var/log/lunasched/jobs.log";
+pub const DEFAULT_EXECUTION_LOG_DIR: &str = "/var/log/lunasched/jobs"; // <job_id>/<execution_id>.log
+pub const DEFAULT_TCP_PORT: u16 = 7464;
+pub const DEFAULT_AGENT_PORT: u16 = 7465; // Agents connect here (agents.bind)
+pub const DEFAULT_SECRETS_KEY_PATH: &str = "/etc/lunasched/secrets.key";
+pub const DEFAULT_LOCK_FILE: &str = "/var/run/lunasched/lunasched.pid"; // Held by the running daemon
+
+/// How often the daemon checks for due jobs; the finest interval a schedule can have
+pub const TICK_INTERVAL_SECS: u64 = 1;
 
-// Fallback paths for non-root users
-pub const USER_SOCKET_PATH: &str = "/tmp/lunasched.sock";
-pub const USER_DB_PATH: &str = "lunasched.db";
-pub const USER_CONFIG_PATH: &str = "~/.config/lunasched/config.yaml";
-pub const USER_LOG_FILE: &str = "lunasched-daemon.log";
-pub const USER_JOBS_LOG_FILE: &str = "lunasched-jobs.log";
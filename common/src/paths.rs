@@ -0,0 +1,129 @@
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Where a daemon keeps its files. The system daemon (run as root) uses the FHS paths; a
+/// daemon run by any other user gets a private instance under that user's XDG directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub socket: String,
+    pub db: String,
+    pub config: String,
+    pub log_file: String,
+    pub jobs_log_file: String,
+    pub execution_log_dir: String, // <job_id>/<execution_id>.log
+    pub secrets_key: String,
+    pub lock_file: String, // Held by the running daemon
+    pub reboot_marker: String, // Present once @reboot jobs ran since the host (or session) started
+    pub script_dir: String, // Scripts of script jobs, written out while they run
+    pub user_mode: bool,
+}
+
+impl Paths {
+    /// Production paths (follow FHS - Filesystem Hierarchy Standard)
+    pub fn system() -> Self {
+        Self {
+            socket: crate::DEFAULT_SOCKET_PATH.to_string(),
+            db: crate::DEFAULT_DB_PATH.to_string(),
+            config: crate::DEFAULT_CONFIG_PATH.to_string(),
+            log_file: crate::DEFAULT_LOG_FILE.to_string(),
+            jobs_log_file: crate::DEFAULT_JOBS_LOG_FILE.to_string(),
+            execution_log_dir: crate::DEFAULT_EXECUTION_LOG_DIR.to_string(),
+            secrets_key: crate::DEFAULT_SECRETS_KEY_PATH.to_string(),
+            lock_file: crate::DEFAULT_LOCK_FILE.to_string(),
+            reboot_marker: "/var/run/lunasched/reboot-jobs.done".to_string(),
+            script_dir: "/var/run/lunasched/scripts".to_string(),
+            user_mode: false,
+        }
+    }
+
+    /// Per-user paths: the socket, lock and running scripts in $XDG_RUNTIME_DIR/lunasched
+    /// (/run/user/$UID/lunasched without one), the database in $XDG_DATA_HOME/lunasched, config and secrets key in
+    /// $XDG_CONFIG_HOME/lunasched, and logs in $XDG_STATE_HOME/lunasched
+    pub fn user() -> Self {
+        let runtime = user_runtime_dir();
+        let data = xdg_dir("XDG_DATA_HOME", ".local/share");
+        let config = xdg_dir("XDG_CONFIG_HOME", ".config");
+        let state = xdg_dir("XDG_STATE_HOME", ".local/state");
+        let path = |dir: &PathBuf, name: &str| dir.join(name).display().to_string();
+        Self {
+            socket: path(&runtime, "lunasched.sock"),
+            db: path(&data, "lunasched.db"),
+            config: path(&config, "config.yaml"),
+            log_file: path(&state, "daemon.log"),
+            jobs_log_file: path(&state, "jobs.log"),
+            execution_log_dir: path(&state, "jobs"),
+            secrets_key: path(&config, "secrets.key"),
+            lock_file: path(&runtime, "lunasched.pid"),
+            reboot_marker: path(&runtime, "reboot-jobs.done"),
+            script_dir: path(&runtime, "scripts"),
+            user_mode: true,
+        }
+    }
+
+    pub fn for_mode(user_mode: bool) -> Self {
+        if user_mode { Self::user() } else { Self::system() }
+    }
+
+    /// Sockets a client tries when none is given, in order: the user's own daemon first, so a
+    /// private instance takes precedence once it is running, then the system daemon. A user
+    /// socket is only tried if it is the user's own (see `is_own_socket`), and never for root.
+    pub fn client_sockets() -> Vec<String> {
+        let user = Self::user().socket;
+        let system = Self::system().socket;
+        if user == system || nix::unistd::geteuid().is_root() || !is_own_socket(Path::new(&user)) {
+            vec![system]
+        } else {
+            vec![user, system]
+        }
+    }
+}
+
+/// Create `dir` (mode 0700) if needed and make sure it is private to the current user: a
+/// directory they own that no one else can enter, so no one else can put a socket in it
+pub fn ensure_private_dir(dir: &Path) -> anyhow::Result<()> {
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    check_private_dir(dir).map_err(anyhow::Error::msg)
+}
+
+fn check_private_dir(dir: &Path) -> Result<(), String> {
+    let metadata = std::fs::symlink_metadata(dir).map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?;
+    let uid = nix::unistd::geteuid().as_raw();
+    if !metadata.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    if metadata.uid() != uid {
+        return Err(format!("{} is owned by uid {}, not by this user (uid {})", dir.display(), metadata.uid(), uid));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(format!("{} is open to other users (mode {:04o}); it must be 0700", dir.display(), metadata.mode() & 0o7777));
+    }
+    Ok(())
+}
+
+/// Whether `socket` is a socket owned by the current user in a directory private to them,
+/// as a user daemon makes it; anything else may have been put there by another user
+pub fn is_own_socket(socket: &Path) -> bool {
+    let private_dir = socket.parent().is_some_and(|dir| check_private_dir(dir).is_ok());
+    private_dir && std::fs::symlink_metadata(socket)
+        .is_ok_and(|metadata| metadata.uid() == nix::unistd::geteuid().as_raw())
+}
+
+fn home_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+// $VAR/lunasched if VAR is set to an absolute path (as the XDG spec requires), else ~/FALLBACK/lunasched
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match std::env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("lunasched"),
+        _ => home_dir().join(fallback).join("lunasched"),
+    }
+}
+
+fn user_runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("lunasched"),
+        _ => PathBuf::from(format!("/run/user/{}/lunasched", nix::unistd::geteuid())),
+    }
+}
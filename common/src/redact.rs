@@ -0,0 +1,70 @@
+/// Env var name patterns that are always treated as secrets
+pub const BUILTIN_SECRET_PATTERNS: &[&str] = &["*_TOKEN", "*_PASSWORD", "*_SECRET"];
+
+pub const REDACTED: &str = "[REDACTED]";
+
+// Shorter values would scrub unrelated text all over the output
+const MIN_SECRET_LEN: usize = 4;
+
+/// Case-insensitive glob match supporting `*` wildcards (e.g. "*_TOKEN", "AWS_*")
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.to_uppercase().chars().collect();
+    let n: Vec<char> = name.to_uppercase().chars().collect();
+
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<usize> = None;
+    let mut mark = 0;
+
+    while ni < n.len() {
+        if pi < p.len() && p[pi] != '*' && p[pi] == n[ni] {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some(pi);
+            mark = ni;
+            pi += 1;
+        } else if let Some(s) = star {
+            pi = s + 1;
+            mark += 1;
+            ni = mark;
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+/// Whether an env var name matches the built-in or any of the given patterns
+pub fn is_secret_name(name: &str, patterns: &[String]) -> bool {
+    BUILTIN_SECRET_PATTERNS.iter().any(|p| matches_pattern(p, name))
+        || patterns.iter().any(|p| matches_pattern(p, name))
+}
+
+/// Values of the env vars whose names are considered secret, longest first
+pub fn secret_values<'a, I>(env: I, patterns: &[String]) -> Vec<String>
+where
+    I: IntoIterator<Item = (&'a String, &'a String)>,
+{
+    let mut values: Vec<String> = env.into_iter()
+        .filter(|(k, v)| v.len() >= MIN_SECRET_LEN && is_secret_name(k, patterns))
+        .map(|(_, v)| v.clone())
+        .collect();
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    values.dedup();
+    values
+}
+
+/// Replace every occurrence of the given secret values in text
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    let mut out = text.to_string();
+    for secret in secrets {
+        if out.contains(secret.as_str()) {
+            out = out.replace(secret.as_str(), REDACTED);
+        }
+    }
+    out
+}
+
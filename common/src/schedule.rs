@@ -1,34 +1,312 @@
-use crate::job::{ScheduleConfig, CalendarParams};
+use crate::job::{ScheduleConfig, CalendarParams, WindowParams};
 use anyhow::{anyhow, Result};
+use std::fmt;
 
 pub fn parse_schedule(s: &str) -> Result<ScheduleConfig> {
-    if s.starts_with("every ") {
-        let duration_str = s.trim_start_matches("every ").trim();
+    parse(s, true)
+}
+
+// With `check_cron` unset, anything that isn't another kind of schedule is taken for a cron
+// expression as it is: a stored expression may no longer fire, but it still reads back
+fn parse(s: &str, check_cron: bool) -> Result<ScheduleConfig> {
+    if s == "@reboot" {
+        Ok(ScheduleConfig::Reboot)
+    } else if let Some(time) = s.strip_prefix("once at ") {
+        parse_once(time.trim())
+    } else if let Some(rest) = s.strip_prefix("every ") {
+        match rest.split_once(" between ") {
+            Some((interval, window)) => parse_window(interval, window),
+            None => Ok(ScheduleConfig::Every(parse_duration(rest.trim())?)),
+        }
+    } else if s.starts_with("heartbeat ") {
+        let duration_str = s.trim_start_matches("heartbeat ").trim();
         let seconds = parse_duration(duration_str)?;
-        Ok(ScheduleConfig::Every(seconds))
-    } else if s.starts_with("at ") || s.starts_with("on ") {
-        parse_calendar(s)
+        Ok(ScheduleConfig::Heartbeat(seconds))
+    } else if s.starts_with("at ") || s.starts_with("on ") || s.starts_with("in ") {
+        parse_calendar(s).map(ScheduleConfig::Calendar)
     } else {
-        Ok(ScheduleConfig::Cron(s.to_string()))
+        let expression = s.strip_prefix("cron:").unwrap_or(s).trim();
+        if check_cron {
+            validate_cron(expression)?;
+        }
+        Ok(ScheduleConfig::Cron(expression.to_string()))
+    }
+}
+
+/// The schedule in the syntax `parse_schedule` reads, which gives the same schedule back
+impl fmt::Display for ScheduleConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleConfig::Cron(expression) => write!(f, "{}", expression),
+            ScheduleConfig::Every(seconds) => write!(f, "every {}", format_duration(*seconds)),
+            ScheduleConfig::Calendar(params) => {
+                let times: Vec<String> = params.times().map(format_time).collect();
+                write!(f, "{}at {}", format_days(params), times.join(" and "))
+            }
+            ScheduleConfig::Heartbeat(seconds) => write!(f, "heartbeat {}", format_duration(*seconds)),
+            ScheduleConfig::Reboot => write!(f, "@reboot"),
+            ScheduleConfig::Once(at) => write!(f, "once at {}", at.to_rfc3339()),
+            ScheduleConfig::Window(params) => {
+                let days = format_days(&params.days);
+                write!(f, "every {} between {} and {}", format_duration(params.interval),
+                    format_time(params.start), format_time(params.end))?;
+                if !days.is_empty() {
+                    write!(f, " {}", days.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl serde::Serialize for ScheduleConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ScheduleConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        use chrono::{DateTime, Utc};
+
+        // Exports written before schedules were serialized as text
+        #[derive(serde::Deserialize)]
+        enum Tagged {
+            Cron(String),
+            Every(u64),
+            Calendar(CalendarParams),
+            Heartbeat(u64),
+            Reboot,
+            Once(DateTime<Utc>),
+            Window(WindowParams),
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Tagged(Tagged),
+            Text(String),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => parse(&text, false).map_err(serde::de::Error::custom)?,
+            Repr::Tagged(Tagged::Cron(expression)) => ScheduleConfig::Cron(expression),
+            Repr::Tagged(Tagged::Every(seconds)) => ScheduleConfig::Every(seconds),
+            Repr::Tagged(Tagged::Calendar(params)) => ScheduleConfig::Calendar(params),
+            Repr::Tagged(Tagged::Heartbeat(seconds)) => ScheduleConfig::Heartbeat(seconds),
+            Repr::Tagged(Tagged::Reboot) => ScheduleConfig::Reboot,
+            Repr::Tagged(Tagged::Once(at)) => ScheduleConfig::Once(at),
+            Repr::Tagged(Tagged::Window(params)) => ScheduleConfig::Window(params),
+        })
+    }
+}
+
+// Largest units first: 5400 -> "1h30m"
+fn format_duration(seconds: u64) -> String {
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+    let mut out = String::new();
+    let mut rest = seconds;
+    for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if rest >= size {
+            out.push_str(&format!("{}{}", rest / size, unit));
+            rest %= size;
+        }
     }
+    out
+}
+
+// HH:MM, or HH:MM:SS with seconds
+fn format_time((h, m, s): (u32, u32, u32)) -> String {
+    if s == 0 { format!("{:02}:{:02}", h, m) } else { format!("{:02}:{:02}:{:02}", h, m, s) }
 }
 
-fn parse_duration(s: &str) -> Result<u64> {
-    let (num, unit) = s.split_at(s.len() - 1);
-    let n: u64 = num.parse()?;
-    match unit {
-        "s" => Ok(n),
-        "m" => Ok(n * 60),
-        "h" => Ok(n * 3600),
-        _ => Err(anyhow!("Unknown unit: {}", unit)),
+// The "on" and "in" clauses of a calendar schedule, each followed by a space; empty for every day
+fn format_days(params: &CalendarParams) -> String {
+    const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let weekday = |day: u32| WEEKDAYS.get(day.wrapping_sub(1) as usize).copied().unwrap_or("?");
+    let list = |items: Vec<String>| items.join(",");
+
+    let mut out = String::new();
+    let on = if let Some(ref days) = params.days_of_week {
+        Some(match days.as_slice() {
+            [1, 2, 3, 4, 5] => "weekdays".to_string(),
+            [6, 7] => "weekends".to_string(),
+            _ => list(days.iter().map(|&day| weekday(day).to_string()).collect()),
+        })
+    } else if let Some((n, day)) = params.nth_weekday {
+        Some(format!("{} {}", ordinal(n), weekday(day)))
+    } else if let Some(day) = params.last_weekday {
+        Some(format!("last {}", weekday(day)))
+    } else {
+        params.days_of_month.as_ref().map(|days| format!("the {}", list(days.iter().map(|&day| ordinal(day)).collect())))
+    };
+    if let Some(on) = on {
+        out.push_str(&format!("on {} ", on));
+    }
+    if let Some(ref months) = params.months {
+        let names = months.iter().map(|&month| MONTHS.get(month.wrapping_sub(1) as usize).copied().unwrap_or("?").to_string());
+        out.push_str(&format!("in {} ", list(names.collect())));
+    }
+    out
+}
+
+// 1st, 2nd, 3rd, 4th, ... 11th, 12th, 13th, ... 21st
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+const CRON_FIELDS: [&str; 7] = ["seconds", "minutes", "hours", "day of month", "month", "day of week", "year"];
+
+/// Check a cron expression (6 or 7 fields, seconds first), pointing at the field at fault
+pub fn validate_cron(expression: &str) -> Result<()> {
+    use std::str::FromStr;
+
+    // Each field with its offset in the expression
+    let mut fields = Vec::new();
+    let mut start = None;
+    for (i, c) in expression.char_indices().chain(std::iter::once((expression.len(), ' '))) {
+        match (c.is_whitespace(), start) {
+            (true, Some(from)) => {
+                fields.push((from, &expression[from..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+
+    if !(6..=7).contains(&fields.len()) {
+        let hint = match fields.len() {
+            5 => format!(" For a crontab-style schedule, put a seconds field in front: '0 {}'", expression),
+            _ => String::new(),
+        };
+        return Err(anyhow!("Cron expression '{}' has {} fields, expected 6 or 7 \
+            (seconds minutes hours day-of-month month day-of-week [year]).{}", expression, fields.len(), hint));
+    }
+
+    let schedule = match cron::Schedule::from_str(expression) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            // Find the field at fault by parsing each one with wildcards everywhere else
+            for (i, (offset, field)) in fields.iter().enumerate() {
+                let probe: Vec<&str> = (0..fields.len()).map(|j| if j == i { *field } else { "*" }).collect();
+                if cron::Schedule::from_str(&probe.join(" ")).is_err() {
+                    return Err(anyhow!("Invalid {} field '{}' at position {} of cron expression:\n  {}\n  {}{}",
+                        CRON_FIELDS[i], field, offset + 1, expression, " ".repeat(*offset), "^".repeat(field.len())));
+                }
+            }
+            return Err(anyhow!("Invalid cron expression '{}': {}", expression, e));
+        }
+    };
+    if schedule.upcoming(chrono::Utc).next().is_none() {
+        return Err(anyhow!("Cron expression '{}' never fires", expression));
+    }
+    Ok(())
+}
+
+// "once at 2024-12-01 03:00[:00]" in local time, or an RFC 3339 timestamp with its own offset
+fn parse_once(s: &str) -> Result<ScheduleConfig> {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(ScheduleConfig::Once(time.with_timezone(&Utc)));
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M"))
+        .map_err(|_| anyhow!("Invalid time '{}', expected YYYY-MM-DD HH:MM[:SS]", s))?;
+    let local = Local.from_local_datetime(&naive).earliest()
+        .ok_or_else(|| anyhow!("Time '{}' does not exist in the local timezone", s))?;
+    Ok(ScheduleConfig::Once(local.with_timezone(&Utc)))
+}
+
+const DURATION_FORMATS: &str = "Use a number with a unit (ms, s, m, h, d), or several: 30s, 90m, 1h30m, 1d, 2000ms";
+
+/// Why the duration of an `every` or `heartbeat` schedule was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum DurationError {
+    Invalid(String),
+    MissingUnit(String),
+    UnknownUnit { input: String, unit: String },
+    // The scheduler ticks once every TICK_INTERVAL_SECS, so finer intervals can't be honored
+    NotWholeSeconds(String),
+    TooShort(String),
+}
+
+impl std::fmt::Display for DurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DurationError::Invalid(input) => write!(f, "Invalid duration '{}'", input)?,
+            DurationError::MissingUnit(input) => write!(f, "Missing unit in duration '{}'", input)?,
+            DurationError::UnknownUnit { input, unit } => write!(f, "Unknown unit '{}' in duration '{}'", unit, input)?,
+            DurationError::NotWholeSeconds(input) => write!(f, "Duration '{}' is not a whole number of seconds", input)?,
+            DurationError::TooShort(input) => write!(f, "Duration '{}' is shorter than {}s", input, crate::TICK_INTERVAL_SECS)?,
+        }
+        write!(f, ". {}; the scheduler checks jobs every {}s, so durations are whole seconds of at least that.",
+            DURATION_FORMATS, crate::TICK_INTERVAL_SECS)
+    }
+}
+
+impl std::error::Error for DurationError {}
+
+/// Seconds in a duration such as "45s", "90m", "1h30m", "1d" or "2000ms" (spaces between
+/// parts are allowed)
+pub fn parse_duration(s: &str) -> std::result::Result<u64, DurationError> {
+    let input = s.trim().to_string();
+    let compact: String = input.split_whitespace().collect();
+    if compact.is_empty() {
+        return Err(DurationError::Invalid(input));
     }
+
+    let mut total_ms: u64 = 0;
+    let mut rest = compact.as_str();
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let n: u64 = rest[..digits].parse().map_err(|_| DurationError::Invalid(input.clone()))?;
+        rest = &rest[digits..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let (unit, remainder) = rest.split_at(unit_len);
+        rest = remainder;
+        let unit_ms: u64 = match unit {
+            "ms" => 1,
+            "s" => 1_000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            "d" => 86_400_000,
+            "" => return Err(DurationError::MissingUnit(input)),
+            _ => return Err(DurationError::UnknownUnit { input, unit: unit.to_string() }),
+        };
+        total_ms = n.checked_mul(unit_ms)
+            .and_then(|ms| total_ms.checked_add(ms))
+            .ok_or_else(|| DurationError::Invalid(input.clone()))?;
+    }
+
+    if !total_ms.is_multiple_of(1000) {
+        return Err(DurationError::NotWholeSeconds(input));
+    }
+    if total_ms / 1000 < crate::TICK_INTERVAL_SECS {
+        return Err(DurationError::TooShort(input));
+    }
+    Ok(total_ms / 1000)
 }
 
-fn parse_calendar(s: &str) -> Result<ScheduleConfig> {
+fn parse_calendar(s: &str) -> Result<CalendarParams> {
     // Examples:
     // "at 14:30"
+    // "at 09:00 and 18:00"
     // "on Mon,Wed at 09:00"
-    // "on 1st Mon at 10:00"
+    // "on weekdays at 08:30"
+    // "on 1st Mon at 10:00", "on last Fri at 17:00"
+    // "on the 1st,15th at 06:00"
+    // "on the 1st in Jan,Jul at 00:00"
 
     let (date_part, time_part) = if let Some(idx) = s.find(" at ") {
         let (d, t) = s.split_at(idx);
@@ -39,39 +317,112 @@ fn parse_calendar(s: &str) -> Result<ScheduleConfig> {
         return Err(anyhow!("Missing 'at' time specification"));
     };
 
-    // Parse time
-    let time_parts: Vec<&str> = time_part.split(':').collect();
-    let (h, m, s) = match time_parts.len() {
-        2 => (time_parts[0].parse()?, time_parts[1].parse()?, 0),
-        3 => (time_parts[0].parse()?, time_parts[1].parse()?, time_parts[2].parse()?),
-        _ => return Err(anyhow!("Invalid time format. Use HH:MM or HH:MM:SS")),
-    };
+    let mut times = Vec::new();
+    for time in time_part.split(',').flat_map(|t| t.split(" and ")) {
+        times.push(parse_time(time.trim())?);
+    }
 
-    let mut days_of_week = None;
-    let mut nth_weekday = None;
+    let mut params = CalendarParams {
+        days_of_week: None,
+        nth_weekday: None,
+        time: times[0],
+        extra_times: times[1..].to_vec(),
+        days_of_month: None,
+        last_weekday: None,
+        months: None,
+    };
 
-    if date_part.starts_with("on ") {
-        let specs = date_part.trim_start_matches("on ").trim();
-        
-        // Check for "1st Mon", "2nd Fri", etc.
-        if let Some(captures) = parse_nth_weekday(specs) {
-            nth_weekday = Some(captures);
+    // "on ..." and "in ..." clauses, in either order
+    let mut clauses: Vec<(String, Vec<&str>)> = Vec::new();
+    for word in date_part.split_whitespace() {
+        let keyword = word.to_lowercase();
+        if keyword == "on" || keyword == "in" {
+            if clauses.iter().any(|(k, _)| *k == keyword) {
+                return Err(anyhow!("Only one '{}' clause is allowed", keyword));
+            }
+            clauses.push((keyword, Vec::new()));
         } else {
-            // Assume comma separated days: Mon,Wed
-            let mut days = Vec::new();
-            for day_str in specs.split(',') {
-                let day = parse_weekday(day_str.trim())?;
-                days.push(day);
+            match clauses.last_mut() {
+                Some((_, words)) => words.push(word),
+                None => return Err(anyhow!("Expected 'on' or 'in' before '{}'", word)),
             }
-            days_of_week = Some(days);
         }
     }
+    for (keyword, words) in clauses {
+        let spec = words.join(" ");
+        if spec.is_empty() {
+            return Err(anyhow!("Missing days after '{}'", keyword));
+        }
+        if keyword == "in" {
+            params.months = Some(parse_list(&spec, parse_month)?);
+        } else {
+            parse_days(&spec, &mut params)?;
+        }
+    }
+
+    Ok(params)
+}
+
+// "every 15m between 09:00 and 17:00 [on DAYS] [in MONTHS]", split after "every" and "between"
+fn parse_window(interval: &str, window: &str) -> Result<ScheduleConfig> {
+    let interval = parse_duration(interval.trim())?;
+    let (start, rest) = window.trim().split_once(" and ")
+        .ok_or_else(|| anyhow!("Expected 'between HH:MM and HH:MM'"))?;
+    let rest = rest.trim();
+    let (end, days) = rest.split_once(' ').unwrap_or((rest, ""));
+    let (start, end) = (parse_time(start.trim())?, parse_time(end)?);
+
+    // The day clauses are those of a calendar schedule
+    let days = parse_calendar(&format!("{} at 00:00", days.trim()))?;
+    let schedule = ScheduleConfig::Window(WindowParams { interval, start, end, days });
+    schedule.validate().map_err(|e| anyhow!(e))?;
+    Ok(schedule)
+}
+
+// HH:MM or HH:MM:SS
+fn parse_time(s: &str) -> Result<(u32, u32, u32)> {
+    let parts: Vec<&str> = s.split(':').collect();
+    let (h, m, sec) = match parts.len() {
+        2 => (parts[0].parse()?, parts[1].parse()?, 0),
+        3 => (parts[0].parse()?, parts[1].parse()?, parts[2].parse()?),
+        _ => return Err(anyhow!("Invalid time '{}'. Use HH:MM or HH:MM:SS", s)),
+    };
+    if h > 23 || m > 59 || sec > 59 {
+        return Err(anyhow!("Invalid time '{}'", s));
+    }
+    Ok((h, m, sec))
+}
+
+// The days of an "on" clause
+fn parse_days(spec: &str, params: &mut CalendarParams) -> Result<()> {
+    let lower = spec.to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    match words.as_slice() {
+        ["weekdays"] => params.days_of_week = Some(vec![1, 2, 3, 4, 5]),
+        ["weekends"] => params.days_of_week = Some(vec![6, 7]),
+        ["the", days @ ..] => params.days_of_month = Some(parse_list(&days.join(" "), parse_day_of_month)?),
+        ["last", day] => params.last_weekday = Some(parse_weekday(day)?),
+        [n, day] if parse_ordinal(n).is_some() => {
+            let n = parse_ordinal(n).filter(|n| (1..=5).contains(n))
+                .ok_or_else(|| anyhow!("Only the 1st to 5th or last weekday of a month exists"))?;
+            params.nth_weekday = Some((n, parse_weekday(day)?));
+        }
+        _ => match parse_list(spec, parse_weekday) {
+            Ok(days) => params.days_of_week = Some(days),
+            Err(_) => match parse_list(spec, parse_day_of_month) {
+                Ok(days) => params.days_of_month = Some(days),
+                Err(_) => return Err(anyhow!(
+                    "Invalid days '{}'. Use weekdays (Mon,Wed), 'weekdays', 'weekends', \
+                     '2nd Tue', 'last Fri' or days of the month ('the 1st,15th')", spec)),
+            },
+        },
+    }
+    Ok(())
+}
 
-    Ok(ScheduleConfig::Calendar(CalendarParams {
-        days_of_week,
-        nth_weekday,
-        time: (h, m, s),
-    }))
+// Comma separated items
+fn parse_list(spec: &str, parse: fn(&str) -> Result<u32>) -> Result<Vec<u32>> {
+    spec.split(',').map(|item| parse(item.trim())).collect()
 }
 
 fn parse_weekday(s: &str) -> Result<u32> {
@@ -87,25 +438,172 @@ fn parse_weekday(s: &str) -> Result<u32> {
     }
 }
 
-fn parse_nth_weekday(s: &str) -> Option<(u32, u32)> {
-    // e.g. "1st Mon"
-    let parts: Vec<&str> = s.split_whitespace().collect();
-    if parts.len() != 2 {
-        return None;
+fn parse_month(s: &str) -> Result<u32> {
+    // Full names or three-letter abbreviations, any case
+    s.parse::<chrono::Month>()
+        .map(|month| month.number_from_month())
+        .map_err(|_| anyhow!("Invalid month: {}", s))
+}
+
+fn parse_day_of_month(s: &str) -> Result<u32> {
+    parse_ordinal(s).or_else(|| s.parse().ok())
+        .filter(|day| (1..=31).contains(day))
+        .ok_or_else(|| anyhow!("Invalid day of the month: {}", s))
+}
+
+// "1st", "2nd", "3rd", "15th"
+fn parse_ordinal(s: &str) -> Option<u32> {
+    let s = s.to_lowercase();
+    let digits = s.strip_suffix("st")
+        .or_else(|| s.strip_suffix("nd"))
+        .or_else(|| s.strip_suffix("rd"))
+        .or_else(|| s.strip_suffix("th"))?;
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn calendar(s: &str) -> CalendarParams {
+        match parse_schedule(s).unwrap() {
+            ScheduleConfig::Calendar(params) => params,
+            other => panic!("'{}' parsed as {:?}", s, other),
+        }
     }
 
-    let n_str = parts[0].to_lowercase();
-    let day_str = parts[1];
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
 
-    let n = if n_str.starts_with("1st") { 1 }
-    else if n_str.starts_with("2nd") { 2 }
-    else if n_str.starts_with("3rd") { 3 }
-    else if n_str.starts_with("4th") { 4 }
-    else { return None; };
+    #[test]
+    fn calendar_clauses() {
+        let params = calendar("at 09:00 and 18:30:15");
+        assert_eq!(params.times().collect::<Vec<_>>(), vec![(9, 0, 0), (18, 30, 15)]);
+        assert!(params.days_of_week.is_none() && params.months.is_none());
 
-    if let Ok(day) = parse_weekday(day_str) {
-        Some((n, day))
-    } else {
-        None
+        assert_eq!(calendar("on weekdays at 08:30").days_of_week, Some(vec![1, 2, 3, 4, 5]));
+        assert_eq!(calendar("on Mon,wednesday at 08:30").days_of_week, Some(vec![1, 3]));
+        assert_eq!(calendar("on 2nd Tue at 10:00").nth_weekday, Some((2, 2)));
+        assert_eq!(calendar("on last Fri at 17:00").last_weekday, Some(5));
+        assert_eq!(calendar("on the 1st,15th at 06:00").days_of_month, Some(vec![1, 15]));
+
+        // "in" may come before or after "on"
+        for s in ["on the 1st in Jan,July at 00:00", "in Jan,July on the 1st at 00:00"] {
+            let params = calendar(s);
+            assert_eq!(params.days_of_month, Some(vec![1]));
+            assert_eq!(params.months, Some(vec![1, 7]));
+        }
+    }
+
+    #[test]
+    fn calendar_rejects() {
+        for s in [
+            "on Mon",                 // no time
+            "at 24:00",
+            "on Mon on Tue at 09:00",
+            "on at 09:00",
+            "on 6th Mon at 09:00",
+            "on the 32nd at 09:00",
+            "in Smarch at 09:00",
+            "Mon at 09:00",
+        ] {
+            assert!(parse_schedule(s).is_err(), "'{}' was accepted", s);
+        }
+    }
+
+    #[test]
+    fn calendar_reads_back() {
+        for s in [
+            "at 09:00 and 18:00",
+            "on weekdays at 08:30",
+            "on Mon,Wed at 09:00:30",
+            "on 1st Mon at 10:00",
+            "on last Fri at 17:00",
+            "on the 1st,15th in Jan,Jul at 06:00",
+        ] {
+            assert_eq!(parse_schedule(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn matches_date() {
+        // 2024-03-01 is a Friday
+        let weekdays = calendar("on weekdays at 09:00");
+        assert!(weekdays.matches_date(date(2024, 3, 1)));
+        assert!(!weekdays.matches_date(date(2024, 3, 2)));
+
+        let second_tuesday = calendar("on 2nd Tue at 09:00");
+        assert!(second_tuesday.matches_date(date(2024, 3, 12)));
+        assert!(!second_tuesday.matches_date(date(2024, 3, 5)));
+        assert!(!second_tuesday.matches_date(date(2024, 3, 13)));
+
+        let last_friday = calendar("on last Fri at 09:00");
+        assert!(last_friday.matches_date(date(2024, 3, 29)));
+        assert!(!last_friday.matches_date(date(2024, 3, 22)));
+        assert!(last_friday.matches_date(date(2024, 2, 23)));
+
+        let first_of_quarter = calendar("on the 1st in Jan,Apr,Jul,Oct at 00:00");
+        assert!(first_of_quarter.matches_date(date(2024, 4, 1)));
+        assert!(!first_of_quarter.matches_date(date(2024, 5, 1)));
+        assert!(!first_of_quarter.matches_date(date(2024, 4, 2)));
+
+        // Months without the day are skipped
+        let thirty_first = calendar("on the 31st at 00:00");
+        assert!(thirty_first.matches_date(date(2024, 1, 31)));
+        assert!(!thirty_first.matches_date(date(2024, 4, 30)));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("45s"), Ok(45));
+        assert_eq!(parse_duration("90m"), Ok(5400));
+        assert_eq!(parse_duration("1h30m"), Ok(5400));
+        assert_eq!(parse_duration(" 1d 2h "), Ok(93_600));
+        assert_eq!(parse_duration("2000ms"), Ok(2));
+        assert_eq!(parse_duration("1s500ms"), Err(DurationError::NotWholeSeconds("1s500ms".to_string())));
+        assert_eq!(parse_duration("0s"), Err(DurationError::TooShort("0s".to_string())));
+        assert_eq!(parse_duration("10"), Err(DurationError::MissingUnit("10".to_string())));
+        assert_eq!(parse_duration("5w"), Err(DurationError::UnknownUnit { input: "5w".to_string(), unit: "w".to_string() }));
+        assert_eq!(parse_duration(""), Err(DurationError::Invalid(String::new())));
+        assert_eq!(parse_duration("h"), Err(DurationError::Invalid("h".to_string())));
+        assert!(matches!(parse_duration("99999999999999999d"), Err(DurationError::Invalid(_))));
+    }
+
+    #[test]
+    fn durations_read_back() {
+        for s in ["every 45s", "every 1h30m", "every 1d", "heartbeat 2d3h4m5s"] {
+            assert_eq!(parse_schedule(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn cron_fields() {
+        assert!(validate_cron("0 */5 * * * *").is_ok());
+        assert!(validate_cron("0 0 12 * * Mon-Fri 2099").is_ok());
+
+        let error = validate_cron("*/5 * * * *").unwrap_err().to_string();
+        assert!(error.contains("has 5 fields"), "{}", error);
+        assert!(error.contains("'0 */5 * * * *'"), "{}", error);
+    }
+
+    #[test]
+    fn cron_caret_under_field() {
+        let error = validate_cron("0 0  99 * * *").unwrap_err().to_string();
+        assert!(error.starts_with("Invalid hours field '99' at position 6"), "{}", error);
+        let lines: Vec<&str> = error.lines().collect();
+        assert_eq!(lines[1], "  0 0  99 * * *");
+        assert_eq!(lines[2], "       ^^");
+
+        let error = validate_cron("0 0 0 * Foo *").unwrap_err().to_string();
+        assert!(error.starts_with("Invalid month field 'Foo'"), "{}", error);
+        assert_eq!(error.lines().nth(2), Some("          ^^^"));
+    }
+
+    #[test]
+    fn cron_never_fires() {
+        let error = validate_cron("0 0 0 1 1 * 2001").unwrap_err().to_string();
+        assert!(error.contains("never fires"), "{}", error);
     }
 }
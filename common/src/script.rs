@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use nix::unistd::{Gid, Uid};
+
+/// A job's script written out for one execution (by the daemon, or by the agent running it);
+/// removed when dropped, once the run ended
+pub struct ScriptFile {
+    path: PathBuf,
+}
+
+impl ScriptFile {
+    /// Write `body` to `<dir>/<execution_id>`, owned by `uid`:`gid` and readable and executable
+    /// by that user alone. `dir` is created if need be, open to pass through but not to list.
+    pub fn write(dir: &str, execution_id: &str, body: &str, uid: Uid, gid: Gid) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o711))?;
+        let path = Path::new(dir).join(execution_id);
+        let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o700).open(&path)?;
+        // Removed from here on, whatever fails
+        let script = Self { path };
+        file.write_all(body.as_bytes())?;
+        if uid != Uid::effective() {
+            nix::unistd::chown(&script.path, Some(uid), Some(gid))?;
+        }
+        file.set_permissions(std::fs::Permissions::from_mode(0o500))?;
+        Ok(script)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScriptFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove script {}: {}", self.path.display(), e);
+        }
+    }
+}
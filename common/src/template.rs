@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use anyhow::{anyhow, Result};
+
+/// Reusable job skeleton: `{{var}}` placeholders in the command, args and env values are
+/// filled in when a job is created from it (`lunasched add --from-template NAME --var k=v`).
+/// `{{params.NAME}}` placeholders are run parameters and are left for the job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobTemplate {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub schedule: Option<String>, // Used when the job doesn't set one
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub owner: String, // Set by the daemon
+}
+
+/// Command, args and env of a template with its variables substituted
+#[derive(Debug, Clone)]
+pub struct RenderedTemplate {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+}
+
+impl JobTemplate {
+    /// Names of the variables used in the command, args and env values
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        for text in self.texts() {
+            for_each_variable(text, |name| {
+                names.insert(name.to_string());
+            });
+        }
+        names
+    }
+
+    /// Substitute every variable; fails naming the variables `vars` doesn't provide
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<RenderedTemplate> {
+        let missing: Vec<String> = self.variables().into_iter().filter(|name| !vars.contains_key(name)).collect();
+        if !missing.is_empty() {
+            return Err(anyhow!("Template '{}' needs --var for: {}", self.name, missing.join(", ")));
+        }
+        Ok(RenderedTemplate {
+            command: render_vars(&self.command, vars),
+            args: self.args.iter().map(|arg| render_vars(arg, vars)).collect(),
+            env: self.env.iter().map(|(k, v)| (k.clone(), render_vars(v, vars))).collect(),
+        })
+    }
+
+    fn texts(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.command).chain(self.args.iter()).chain(self.env.values())
+    }
+}
+
+// Call `f` with the name of each `{{name}}` placeholder, skipping `{{params.NAME}}`
+fn for_each_variable(text: &str, mut f: impl FnMut(&str)) {
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+        let placeholder = rest[start + 2..start + end].trim();
+        if is_variable(placeholder) {
+            f(placeholder);
+        }
+        rest = &rest[start + end + 2..];
+    }
+}
+
+fn is_variable(placeholder: &str) -> bool {
+    !placeholder.is_empty() && placeholder.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Substitute `{{var}}` placeholders; anything else between braces is kept as written
+pub fn render_vars(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = rest[start + 2..start + end].trim();
+        match vars.get(placeholder) {
+            Some(value) if is_variable(placeholder) => out.push_str(value),
+            _ => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,58 @@
+use std::io::BufReader;
+use std::sync::Arc;
+use anyhow::{anyhow, Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+/// Certificates of a PEM file, the leaf first for a chain
+pub fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read certificates from {}", path))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates in {}", path));
+    }
+    Ok(certs)
+}
+
+/// The private key of a PEM file (PKCS#8, PKCS#1 or SEC1)
+pub fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read the key in {}", path))?
+        .ok_or_else(|| anyhow!("No private key in {}", path))
+}
+
+fn load_roots(ca: &str) -> Result<Arc<RootCertStore>> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca)? {
+        roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", ca))?;
+    }
+    Ok(Arc::new(roots))
+}
+
+/// TLS for the daemon's agent listener: its certificate, and agents must present one signed by `ca`
+pub fn server_config(cert: &str, key: &str, ca: &str) -> Result<ServerConfig> {
+    let verifier = WebPkiClientVerifier::builder(load_roots(ca)?).build()?;
+    Ok(ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(load_certs(cert)?, load_key(key)?)?)
+}
+
+/// TLS for an agent: the daemon's certificate must be signed by `ca`, and the agent presents its own
+pub fn client_config(cert: &str, key: &str, ca: &str) -> Result<ClientConfig> {
+    Ok(ClientConfig::builder()
+        .with_root_certificates(load_roots(ca)?)
+        .with_client_auth_cert(load_certs(cert)?, load_key(key)?)?)
+}
+
+/// Check that a peer's certificate was issued to `name`: one of its DNS subject alternative
+/// names matches it
+pub fn check_certificate_name(cert: &CertificateDer<'_>, name: &str) -> Result<()> {
+    let server_name = ServerName::try_from(name).map_err(|_| anyhow!("{} is not a valid host name", name))?;
+    webpki::EndEntityCert::try_from(cert)
+        .and_then(|cert| cert.verify_is_valid_for_subject_name(&server_name))
+        .map_err(|_| anyhow!("its certificate was not issued to {}", name))
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use anyhow::{anyhow, Result};
+
+/// A named DAG of existing jobs. A step starts once every step in its `after` list has
+/// succeeded: several steps after the same one fan out, a step after several fans in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub name: String,
+    pub steps: Vec<WorkflowStep>,
+    #[serde(default)]
+    pub owner: String, // Set by the daemon
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowStep {
+    pub job: String, // Job ID; a job appears at most once per workflow
+    #[serde(default)]
+    pub after: Vec<String>, // Jobs of other steps that must succeed first
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped, // A step it waits for failed or was skipped
+}
+
+impl std::fmt::Display for StepState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepState::Pending => write!(f, "pending"),
+            StepState::Running => write!(f, "running"),
+            StepState::Succeeded => write!(f, "succeeded"),
+            StepState::Failed => write!(f, "failed"),
+            StepState::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRun {
+    pub job: String,
+    pub state: StepState,
+    pub execution_id: Option<String>, // Of the current attempt; None while a retry is pending
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+/// One run of a workflow and the progress of each of its steps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub run_id: String,
+    pub workflow: String,
+    pub owner: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub steps: Vec<StepRun>,
+}
+
+impl WorkflowRun {
+    /// "running" until every step is settled, then "failed" if any step failed, else "succeeded"
+    pub fn status(&self) -> &'static str {
+        if self.steps.iter().any(|s| matches!(s.state, StepState::Pending | StepState::Running)) {
+            "running"
+        } else if self.steps.iter().any(|s| s.state == StepState::Failed) {
+            "failed"
+        } else {
+            "succeeded"
+        }
+    }
+
+    pub fn step_mut(&mut self, job: &str) -> Option<&mut StepRun> {
+        self.steps.iter_mut().find(|s| s.job == job)
+    }
+}
+
+impl Workflow {
+    /// Check that step jobs are unique, `after` names steps of this workflow and there are no cycles
+    pub fn validate(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            return Err(anyhow!("Workflow '{}' has no steps", self.name));
+        }
+        let mut jobs = HashSet::new();
+        for step in &self.steps {
+            if !jobs.insert(step.job.as_str()) {
+                return Err(anyhow!("Job '{}' appears more than once in workflow '{}'", step.job, self.name));
+            }
+        }
+        for step in &self.steps {
+            for after in &step.after {
+                if after == &step.job {
+                    return Err(anyhow!("Step '{}' can't run after itself", step.job));
+                }
+                if !jobs.contains(after.as_str()) {
+                    return Err(anyhow!("Step '{}' runs after '{}', which is not a step of workflow '{}'",
+                        step.job, after, self.name));
+                }
+            }
+        }
+
+        // Peel off steps whose prerequisites are all placed; whatever remains is on a cycle
+        let mut placed: HashSet<&str> = HashSet::new();
+        while placed.len() < self.steps.len() {
+            let ready: Vec<&str> = self.steps.iter()
+                .filter(|s| !placed.contains(s.job.as_str()) && s.after.iter().all(|a| placed.contains(a.as_str())))
+                .map(|s| s.job.as_str())
+                .collect();
+            if ready.is_empty() {
+                let mut cycle: Vec<&str> = self.steps.iter()
+                    .map(|s| s.job.as_str())
+                    .filter(|job| !placed.contains(job))
+                    .collect();
+                cycle.sort();
+                return Err(anyhow!("Workflow '{}' has a dependency cycle among: {}", self.name, cycle.join(", ")));
+            }
+            placed.extend(ready);
+        }
+        Ok(())
+    }
+
+    /// A fresh run with every step pending
+    pub fn new_run(&self, run_id: String, started_at: String) -> WorkflowRun {
+        WorkflowRun {
+            run_id,
+            workflow: self.name.clone(),
+            owner: self.owner.clone(),
+            started_at,
+            finished_at: None,
+            steps: self.steps.iter().map(|step| StepRun {
+                job: step.job.clone(),
+                state: StepState::Pending,
+                execution_id: None,
+                started_at: None,
+                finished_at: None,
+            }).collect(),
+        }
+    }
+
+    /// Pending steps whose prerequisites have all succeeded
+    pub fn ready_steps(&self, run: &WorkflowRun) -> Vec<String> {
+        let states: HashMap<&str, StepState> = run.steps.iter().map(|s| (s.job.as_str(), s.state)).collect();
+        self.steps.iter()
+            .filter(|step| states.get(step.job.as_str()) == Some(&StepState::Pending))
+            .filter(|step| step.after.iter().all(|a| states.get(a.as_str()) == Some(&StepState::Succeeded)))
+            .map(|step| step.job.clone())
+            .collect()
+    }
+
+    /// Mark pending steps that can no longer run (a prerequisite failed or was skipped) as skipped,
+    /// through the whole graph. Returns the newly skipped jobs.
+    pub fn skip_blocked(&self, run: &mut WorkflowRun) -> Vec<String> {
+        let mut skipped = Vec::new();
+        loop {
+            let blocked: Vec<String> = self.steps.iter()
+                .filter(|step| run.steps.iter().any(|s| s.job == step.job && s.state == StepState::Pending))
+                .filter(|step| step.after.iter().any(|a| {
+                    run.steps.iter().any(|s| &s.job == a && matches!(s.state, StepState::Failed | StepState::Skipped))
+                }))
+                .map(|step| step.job.clone())
+                .collect();
+            if blocked.is_empty() {
+                return skipped;
+            }
+            for job in blocked {
+                if let Some(step) = run.step_mut(&job) {
+                    step.state = StepState::Skipped;
+                }
+                skipped.push(job);
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use common::Job;
+use nix::unistd::{Gid, Uid, User};
+use std::ffi::CString;
+
+// PATH of a job's environment, as sudo's secure_path would set it
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// The system account a job's processes run as
+#[derive(Debug, Clone)]
+pub struct Account {
+    user: User,
+    groups: Vec<Gid>,
+}
+
+impl Account {
+    /// Look up a user and its supplementary groups
+    pub fn lookup(name: &str) -> std::io::Result<Self> {
+        let user = User::from_name(name)?
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("unknown user '{}'", name)))?;
+        let groups = nix::unistd::getgrouplist(&CString::new(name)?, user.gid)?;
+        Ok(Self { user, groups })
+    }
+
+    /// Base environment of a login as this user; the job's own env is set on top
+    pub fn login_env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("HOME", self.user.dir.display().to_string()),
+            ("USER", self.user.name.clone()),
+            ("LOGNAME", self.user.name.clone()),
+            ("SHELL", self.user.shell.display().to_string()),
+            ("PATH", DEFAULT_PATH.to_string()),
+        ]
+    }
+
+    /// User and primary group IDs, e.g. to run a container as this user
+    pub fn ids(&self) -> (Uid, Gid) {
+        (self.user.uid, self.user.gid)
+    }
+
+    /// Switch the child to this account between fork and exec. Call after registering other
+    /// pre_exec hooks, which then still run with the daemon's privileges (joining the cgroup
+    /// needs them). Nothing to do when the daemon already runs as this user.
+    pub fn switch_to(&self, cmd: &mut tokio::process::Command) {
+        if self.user.uid == Uid::effective() {
+            return;
+        }
+        let (uid, gid, groups) = (self.user.uid, self.user.gid, self.groups.clone());
+        // Only syscalls on values prepared before the fork, which keeps this fork-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                nix::unistd::setgroups(&groups)?;
+                nix::unistd::setgid(gid)?;
+                nix::unistd::setuid(uid)?;
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Reject a job whose `run_as` its owner may not use (only root may pick another user),
+/// or that names no existing user. The users of jobs run by agents are theirs to look up.
+pub fn check_run_as(job: &Job) -> Result<(), String> {
+    let user = match job.run_as {
+        Some(ref user) => user,
+        None => return Ok(()),
+    };
+    if *user != job.owner && job.owner != "root" {
+        return Err(format!("Permission denied: Job {} is owned by {} and cannot run as {}; only root may run jobs as another user",
+            job.id, job.owner, user));
+    }
+    if job.target.is_some() {
+        return Ok(());
+    }
+    Account::lookup(user)
+        .map(|_| ())
+        .map_err(|e| format!("Job {} cannot run as {}: {}", job.id, user, e))
+}
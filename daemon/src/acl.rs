@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use nix::unistd::{Group, Uid, User};
+use serde::Deserialize;
+use common::{Job, JobId, Request, Response};
+
+/// What a rule lets its users do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    Add,     // Create and update jobs (also by import), templates and workflows
+    Remove,  // Remove jobs, templates and workflows
+    Start,   // Start, retry and rerun jobs, run workflows, send test notifications
+    ViewAll, // Read any job with its history and output, subscribe to events
+    Admin,   // All of the above, plus secrets, signed bundles and the audit log
+}
+
+/// Grants `capabilities` to the listed users and to members of the listed groups. With `tags`,
+/// the grant only covers jobs carrying one of them; requests that aren't about a particular
+/// job then need a rule without tags.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AclRule {
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl AclRule {
+    fn grants(&self, capability: Capability, job: Option<&Job>) -> bool {
+        let capable = self.capabilities.iter().any(|&c| c == capability || c == Capability::Admin);
+        let in_scope = self.tags.is_empty()
+            || job.is_some_and(|job| job.tags.iter().any(|tag| self.tags.contains(tag)));
+        capable && in_scope
+    }
+}
+
+/// The account of a local client, looked up once per connection
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    groups: Vec<String>,
+}
+
+impl Identity {
+    /// Resolve a peer uid to its user name and group names. Accounts without a passwd entry
+    /// are known by their uid only, so only rules naming that number match them.
+    pub fn lookup(uid: u32) -> Self {
+        let user = match User::from_uid(Uid::from_raw(uid)) {
+            Ok(Some(user)) => user,
+            _ => return Self { name: uid.to_string(), groups: Vec::new() },
+        };
+        let gids = CString::new(user.name.as_str()).ok()
+            .and_then(|name| nix::unistd::getgrouplist(&name, user.gid).ok())
+            .unwrap_or_else(|| vec![user.gid]);
+        let groups = gids.into_iter()
+            .filter_map(|gid| Group::from_gid(gid).ok().flatten())
+            .map(|group| group.name)
+            .collect();
+        Self { name: user.name, groups }
+    }
+
+    fn matches(&self, rule: &AclRule) -> bool {
+        rule.users.contains(&self.name) || rule.groups.iter().any(|g| self.groups.contains(g))
+    }
+}
+
+/// Rules in effect for one client
+struct Grants<'a> {
+    rules: Vec<&'a AclRule>,
+}
+
+impl<'a> Grants<'a> {
+    fn new(rules: &'a [AclRule], who: &Identity) -> Self {
+        Self { rules: rules.iter().filter(|rule| who.matches(rule)).collect() }
+    }
+
+    fn allows(&self, capability: Capability, job: Option<&Job>) -> bool {
+        self.rules.iter().any(|rule| rule.grants(capability, job))
+    }
+
+    /// Reading a job takes view-all, or any capability over the job
+    fn can_view(&self, job: Option<&Job>) -> bool {
+        [Capability::ViewAll, Capability::Add, Capability::Remove, Capability::Start]
+            .into_iter()
+            .any(|capability| self.allows(capability, job))
+    }
+}
+
+/// Check a request from a local, non-root client against the rules (which are in effect only
+/// when some are configured). Job lists in the response are cut down by `filter_response`.
+pub fn authorize(rules: &[AclRule], who: &Identity, request: &Request, jobs: &HashMap<String, Job>) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let grants = Grants::new(rules, who);
+    let job = |id: &JobId| jobs.get(&id.0);
+    let denied = |capability: Capability, what: &str| {
+        Err(format!("Permission denied: {} lacks the {} capability for {}", who.name, capability_name(capability), what))
+    };
+    let need = |capability: Capability, id: &JobId| {
+        if grants.allows(capability, job(id)) { Ok(()) } else { denied(capability, &format!("job {}", id.0)) }
+    };
+    let need_global = |capability: Capability| {
+        if grants.allows(capability, None) { Ok(()) } else { denied(capability, "this request") }
+    };
+    // Changing a job takes the capability over both its new and its current version, and
+    // having it trigger other jobs the capability to start them
+    let need_triggers = |new: &Job| {
+        new.on_success_trigger.iter().chain(&new.on_failure_trigger).try_for_each(|id| need(Capability::Start, id))
+    };
+    let need_add = |new: &Job| {
+        let current = jobs.get(&new.id.0);
+        if grants.allows(Capability::Add, Some(new)) && (current.is_none() || grants.allows(Capability::Add, current)) {
+            need_triggers(new)
+        } else {
+            denied(Capability::Add, &format!("job {}", new.id.0))
+        }
+    };
+
+    match request {
+        // Lists are filtered rather than refused
+        Request::Authenticate(_)
+        | Request::Ping(_)
+        | Request::GetStatus { .. }
+        | Request::PreviewSchedule { .. }
+        | Request::ListJobs
+        | Request::ResolveJob(_)
+        | Request::ExportJobs { .. }
+        | Request::ListTemplates
+        | Request::GetTemplate(_)
+        | Request::ListWorkflows
+        | Request::GetWorkflowRuns { .. } => Ok(()),
+        Request::GetJob(id)
+        | Request::GetHistory { job_id: Some(id), .. }
+        | Request::ExportHistory { job_id: Some(id), .. }
+        | Request::GetJobOutput { job_id: id, .. }
+        | Request::GetExecution { job_id: id, .. }
+        | Request::GetExecutionWindows { job_id: Some(id), .. }
+        | Request::GetMetrics(id) => {
+            if grants.can_view(job(id)) { Ok(()) } else { denied(Capability::ViewAll, &format!("job {}", id.0)) }
+        }
+        Request::AddJob(new) | Request::EnsureJob { job: new, .. } => need_add(new),
+        // A transient run is a job added and started in one go
+        Request::RunOnce { job: new, .. } => {
+            if grants.allows(Capability::Add, Some(new)) && grants.allows(Capability::Start, Some(new)) {
+                need_triggers(new)
+            } else {
+                denied(Capability::Start, "transient runs")
+            }
+        }
+        Request::ImportJobs { jobs: new, .. } => new.iter().try_for_each(need_add),
+        // Declared jobs replace those of the same name applied from the source before, and
+        // pruning removes the rest of those
+        Request::ApplyJobs { source, jobs: new, prune } => {
+            let tag = common::declarative::source_tag(source);
+            let applied: Vec<&Job> = jobs.values().filter(|job| job.tags.contains(&tag)).collect();
+            for declared in new {
+                need_add(declared)?;
+                if let Some(current) = applied.iter().find(|job| job.name == declared.name) {
+                    need(Capability::Add, &current.id)?;
+                }
+            }
+            if !*prune {
+                return Ok(());
+            }
+            applied.iter()
+                .filter(|job| !new.iter().any(|declared| declared.name == job.name))
+                .try_for_each(|job| need(Capability::Remove, &job.id))
+        }
+        Request::RemoveJob(id) => need(Capability::Remove, id),
+        Request::RenameJob { job_id: id, .. } => need(Capability::Add, id),
+        // The copy has the job's tags, and its env
+        Request::CloneJob { job_id: id, .. } => {
+            if grants.can_view(job(id)) { need(Capability::Add, id) } else { denied(Capability::ViewAll, &format!("job {}", id.0)) }
+        }
+        Request::StartJob(id)
+        | Request::StartJobWithParams { job_id: id, .. }
+        | Request::RetryJob(id)
+        | Request::GetPingToken(id)
+        | Request::TestNotification { job_id: id, .. } => need(Capability::Start, id),
+        Request::Rerun { .. } | Request::RunWorkflow(_) => need_global(Capability::Start),
+        Request::AddTemplate(_) | Request::AddWorkflow(_) => need_global(Capability::Add),
+        Request::RemoveTemplate(_) | Request::RemoveWorkflow(_) => need_global(Capability::Remove),
+        // Health checks cover every job's user
+        Request::Subscribe { .. }
+        | Request::ListDeadLetters
+        | Request::SuggestRebalance { .. }
+        | Request::HealthCheck
+        | Request::ListAgents
+        | Request::GetHistory { job_id: None, .. }
+        | Request::ExportHistory { job_id: None, .. }
+        | Request::GetExecutionWindows { job_id: None, .. } => {
+            need_global(Capability::ViewAll)
+        }
+        Request::ApplyBundle { .. }
+        | Request::SetSecret { .. }
+        | Request::GetSecret { .. }
+        | Request::RemoveSecret { .. }
+        | Request::ListSecrets
+        | Request::GetAuditLog { .. }
+        // A backup holds every job and secret; a restore replaces them
+        | Request::BackupDatabase { .. }
+        | Request::RestoreDatabase { .. } => need_global(Capability::Admin),
+    }
+}
+
+/// Drop the jobs a client may not see from a job list
+pub fn filter_response(rules: &[AclRule], who: &Identity, response: Response) -> Response {
+    if rules.is_empty() {
+        return response;
+    }
+    match response {
+        Response::JobList(jobs) => {
+            let grants = Grants::new(rules, who);
+            Response::JobList(jobs.into_iter().filter(|job| grants.can_view(Some(job))).collect())
+        }
+        Response::JobListing(jobs) => {
+            let grants = Grants::new(rules, who);
+            Response::JobListing(jobs.into_iter().filter(|listed| grants.can_view(Some(&listed.job))).collect())
+        }
+        // Resolving a job reference finds any job
+        Response::JobDetail(Some(job)) if !Grants::new(rules, who).can_view(Some(&job)) => Response::JobDetail(None),
+        other => other,
+    }
+}
+
+fn capability_name(capability: Capability) -> &'static str {
+    match capability {
+        Capability::Add => "add",
+        Capability::Remove => "remove",
+        Capability::Start => "start",
+        Capability::ViewAll => "view-all",
+        Capability::Admin => "admin",
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use common::agent::{AgentMessage, DaemonMessage, OutputStream, RemoteExit, RemoteRun};
+use common::{encode_frame, AgentInfo, FrameReader};
+use dashmap::DashMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::TlsAcceptor;
+use crate::config::AgentsConfig;
+use crate::leader::Leadership;
+
+// Output of a remote execution buffered between the agent's connection and its readers
+const OUTPUT_BUFFER: usize = 64 * 1024;
+
+/// An agent that sends nothing for this long is taken for gone; agents send a heartbeat
+/// every `common::agent::HEARTBEAT_INTERVAL_SECS`
+const AGENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Agents connected to the daemon, by name
+#[derive(Default)]
+pub struct AgentRegistry {
+    agents: DashMap<String, Agent>,
+    sessions: AtomicU64,
+}
+
+struct Agent {
+    labels: Vec<String>,
+    addr: String,
+    connected_at: DateTime<Utc>,
+    running: Arc<AtomicUsize>,
+    runs: mpsc::UnboundedSender<(RemoteRun, RunSink)>,
+    session: u64, // Tells the connection an entry belongs to, when an agent reconnects
+}
+
+// Where the messages about one remote execution go
+struct RunSink {
+    stdout: DuplexStream,
+    stderr: DuplexStream,
+    exit: oneshot::Sender<RemoteExit>,
+}
+
+/// An execution running on an agent, as the daemon sees it
+pub struct RemoteProcess {
+    pub agent: String,
+    pub stdout: Option<DuplexStream>,
+    pub stderr: Option<DuplexStream>,
+    exit: oneshot::Receiver<RemoteExit>,
+}
+
+impl RemoteProcess {
+    /// Wait for the agent to report how the execution ended, as the exit status a local
+    /// process would have had
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        match (&mut self.exit).await {
+            Ok(RemoteExit::Exited(code)) => Ok(ExitStatus::from_raw((code & 0xff) << 8)),
+            Ok(RemoteExit::Signaled(signal)) => Ok(ExitStatus::from_raw(signal & 0x7f)),
+            Ok(RemoteExit::NotStarted(e)) => Err(std::io::Error::other(format!("agent {} could not start it: {}", self.agent, e))),
+            Err(_) => Err(std::io::Error::new(std::io::ErrorKind::ConnectionAborted,
+                format!("lost the connection to agent {}", self.agent))),
+        }
+    }
+}
+
+impl AgentRegistry {
+    /// Send a run to the connected agent named `target`, or the least busy one labelled with it
+    pub fn dispatch(&self, target: &str, run: RemoteRun) -> std::io::Result<RemoteProcess> {
+        let name = match self.agents.contains_key(target) {
+            true => Some(target.to_string()),
+            false => self.agents.iter()
+                .filter(|agent| agent.labels.iter().any(|label| label == target))
+                .min_by_key(|agent| agent.running.load(Ordering::SeqCst))
+                .map(|agent| agent.key().clone()),
+        };
+        let agent = name.and_then(|name| self.agents.get(&name)).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound,
+            format!("no agent connected for target {}", target)))?;
+
+        let (stdout_sink, stdout) = tokio::io::duplex(OUTPUT_BUFFER);
+        let (stderr_sink, stderr) = tokio::io::duplex(OUTPUT_BUFFER);
+        let (exit_sink, exit) = oneshot::channel();
+        let sink = RunSink { stdout: stdout_sink, stderr: stderr_sink, exit: exit_sink };
+        agent.runs.send((run, sink)).map_err(|_| std::io::Error::new(std::io::ErrorKind::ConnectionAborted,
+            format!("agent {} disconnected", agent.key())))?;
+        agent.running.fetch_add(1, Ordering::SeqCst);
+        Ok(RemoteProcess { agent: agent.key().clone(), stdout: Some(stdout), stderr: Some(stderr), exit })
+    }
+
+    /// Connected agents, by name
+    pub fn list(&self) -> Vec<AgentInfo> {
+        let mut agents: Vec<AgentInfo> = self.agents.iter()
+            .map(|agent| AgentInfo {
+                name: agent.key().clone(),
+                labels: agent.labels.clone(),
+                addr: agent.addr.clone(),
+                connected_at: agent.connected_at.to_rfc3339(),
+                running: agent.running.load(Ordering::SeqCst),
+            })
+            .collect();
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+        agents
+    }
+}
+
+/// Accept agents on `config.bind`. Connections are mutual TLS: agents must present a
+/// certificate signed by `config.ca`, issued to the name they register under.
+pub async fn serve(config: &AgentsConfig, registry: Arc<AgentRegistry>, leadership: Option<Arc<Leadership>>) -> anyhow::Result<()> {
+    let tls = common::tls::server_config(&config.cert, &config.key, &config.ca)?;
+    let acceptor = TlsAcceptor::from(Arc::new(tls));
+    let listener = TcpListener::bind(&config.bind).await?;
+    let allowed_labels = Arc::new(config.labels.clone());
+    tokio::spawn(async move {
+        loop {
+            let (socket, addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("Failed to accept an agent connection: {}", e);
+                    continue;
+                }
+            };
+            let _ = socket.set_nodelay(true);
+            let (acceptor, registry, leadership) = (acceptor.clone(), registry.clone(), leadership.clone());
+            let allowed_labels = allowed_labels.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(socket).await {
+                    Ok(stream) => {
+                        let cert = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first())
+                            .map(|cert| CertificateDer::from(cert.to_vec()));
+                        let peer = Peer { addr: addr.to_string(), cert, allowed_labels: &allowed_labels };
+                        serve_agent(stream, peer, &registry, leadership).await
+                    }
+                    Err(e) => log::warn!("TLS handshake with agent at {} failed: {}", addr, e),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// Who is on the other end of an agent connection
+struct Peer<'a> {
+    addr: String,
+    cert: Option<CertificateDer<'static>>, // The certificate it authenticated with
+    allowed_labels: &'a HashMap<String, Vec<String>>, // `agents.labels`
+}
+
+// Register an agent, then pass it runs and route what it reports back to them until it goes away
+async fn serve_agent<S: AsyncRead + AsyncWrite + Unpin>(stream: S, peer: Peer<'_>, registry: &AgentRegistry, leadership: Option<Arc<Leadership>>) {
+    let addr = peer.addr;
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut frames = FrameReader::default();
+    let mut buf = vec![0; 8192];
+    let (name, labels, version) = match read_message(&mut reader, &mut frames, &mut buf).await {
+        Ok(Some(AgentMessage::Register { name, labels, version })) => (name, labels, version),
+        Ok(Some(_)) => {
+            let _ = send(&mut writer, &DaemonMessage::Refused("Register first".to_string())).await;
+            return;
+        }
+        Ok(None) => return,
+        Err(e) => {
+            log::warn!("Agent at {} failed to register: {}", addr, e);
+            return;
+        }
+    };
+    // An agent is whoever its certificate names: any agent could claim any name otherwise
+    let verified = match peer.cert {
+        Some(ref cert) => common::tls::check_certificate_name(cert, &name),
+        None => Err(anyhow::anyhow!("it presented no certificate")),
+    };
+    if let Err(e) = verified {
+        log::warn!("Refused agent {} at {}: {}", name, addr, e);
+        let _ = send(&mut writer, &DaemonMessage::Refused(format!("Cannot register as {}: {}", name, e))).await;
+        return;
+    }
+    // Labels decide which jobs an agent is sent, so they come from the config
+    let allowed = peer.allowed_labels.get(&name).map(Vec::as_slice).unwrap_or_default();
+    let (labels, ignored): (Vec<String>, Vec<String>) = labels.into_iter().partition(|label| allowed.contains(label));
+    if !ignored.is_empty() {
+        log::warn!("Agent {} asked for labels {} that agents.labels doesn't give it; ignoring them", name, ignored.join(", "));
+    }
+    // Runs only start on the leader, so agents register there
+    if let Some(leadership) = leadership.filter(|leadership| !leadership.is_leader()) {
+        let _ = send(&mut writer, &DaemonMessage::Refused(leadership.standby_error())).await;
+        return;
+    }
+    if send(&mut writer, &DaemonMessage::Registered).await.is_err() {
+        return;
+    }
+
+    let (runs, mut queue) = mpsc::unbounded_channel();
+    let running = Arc::new(AtomicUsize::new(0));
+    let session = registry.sessions.fetch_add(1, Ordering::SeqCst);
+    log::info!("Agent {} v{} connected from {} (labels: {})", name, version, addr,
+        if labels.is_empty() { "none".to_string() } else { labels.join(", ") });
+    // A reconnecting agent replaces its old connection, whose runs are then lost
+    registry.agents.insert(name.clone(), Agent {
+        labels,
+        addr: addr.clone(),
+        connected_at: Utc::now(),
+        running: running.clone(),
+        runs,
+        session,
+    });
+
+    let mut sinks: HashMap<String, RunSink> = HashMap::new();
+    let reason = loop {
+        tokio::select! {
+            run = queue.recv() => match run {
+                Some((run, sink)) => {
+                    let execution_id = run.execution_id.clone();
+                    log::info!(job_id = run.job_id.as_str(), execution_id = execution_id.as_str();
+                        "Sending job {} to agent {}", run.job_name, name);
+                    if let Err(e) = send(&mut writer, &DaemonMessage::Run(run)).await {
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        break format!("failed to send a run: {}", e);
+                    }
+                    sinks.insert(execution_id, sink);
+                }
+                None => break "replaced by a new connection".to_string(),
+            },
+            read = tokio::time::timeout(AGENT_TIMEOUT, read_message(&mut reader, &mut frames, &mut buf)) => match read {
+                Ok(Ok(Some(message))) => handle(message, &name, &mut sinks, &running).await,
+                Ok(Ok(None)) => break "connection closed".to_string(),
+                Ok(Err(e)) => break e.to_string(),
+                Err(_) => break format!("nothing heard for {}s", AGENT_TIMEOUT.as_secs()),
+            },
+        }
+    };
+
+    registry.agents.remove_if(&name, |_, agent| agent.session == session);
+    if sinks.is_empty() {
+        log::info!("Agent {} disconnected: {}", name, reason);
+    } else {
+        // Dropping the sinks ends the executions' output and fails their wait
+        log::warn!("Agent {} disconnected with {} execution(s) running: {}", name, sinks.len(), reason);
+        running.fetch_sub(sinks.len(), Ordering::SeqCst);
+    }
+}
+
+async fn handle(message: AgentMessage, agent: &str, sinks: &mut HashMap<String, RunSink>, running: &AtomicUsize) {
+    match message {
+        AgentMessage::Register { .. } | AgentMessage::Heartbeat => {}
+        AgentMessage::Started { execution_id, pid } => {
+            log::info!(execution_id = execution_id.as_str(); "Execution {} started on agent {} as pid {}", execution_id, agent, pid);
+        }
+        AgentMessage::Output { execution_id, stream, text } => {
+            if let Some(sink) = sinks.get_mut(&execution_id) {
+                let pipe = match stream {
+                    OutputStream::Stdout => &mut sink.stdout,
+                    OutputStream::Stderr => &mut sink.stderr,
+                };
+                // The execution's readers may be gone if it was abandoned; its output with them
+                let _ = pipe.write_all(text.as_bytes()).await;
+            }
+        }
+        AgentMessage::Finished { execution_id, exit } => match sinks.remove(&execution_id) {
+            Some(sink) => {
+                running.fetch_sub(1, Ordering::SeqCst);
+                let _ = sink.exit.send(exit);
+            }
+            None => log::debug!("Agent {} finished unknown execution {}", agent, execution_id),
+        },
+    }
+}
+
+// Next message from the agent; None once it closed the connection
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R, frames: &mut FrameReader, buf: &mut [u8]) -> anyhow::Result<Option<AgentMessage>> {
+    loop {
+        if let Some(message) = frames.next()? {
+            return Ok(Some(message));
+        }
+        let n = reader.read(buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        frames.push(&buf[..n]);
+    }
+}
+
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, message: &DaemonMessage) -> std::io::Result<()> {
+    writer.write_all(&encode_frame(message)?).await
+}
@@ -0,0 +1,49 @@
+use rusqlite::Connection;
+
+/// Copy history into a standalone SQLite file for BI tools, so they never touch the live
+/// database. Job env, command lines and output are left out. Returns the executions copied.
+pub fn export_snapshot(db_path: &str, snapshot_path: &str) -> anyhow::Result<i64> {
+    // Build next to the target and rename over it, so readers never see a partial snapshot
+    let tmp_path = format!("{}.tmp", snapshot_path);
+    let _ = std::fs::remove_file(&tmp_path);
+
+    let conn = Connection::open(&tmp_path)?;
+    conn.execute("ATTACH DATABASE ?1 AS live", [format!("file:{}?mode=ro", db_path)])?;
+    conn.execute_batch(
+        "CREATE TABLE executions AS
+             SELECT id, job_id, run_at, status, execution_id, exit_code, duration_ms,
+                    scheduled_at, started_at, resource_events, hook_result
+             FROM live.history WHERE execution_id IS NOT NULL;
+         CREATE INDEX idx_executions_job_id ON executions(job_id);
+
+         CREATE TABLE events AS
+             SELECT id, job_id, run_at, status FROM live.history WHERE execution_id IS NULL;
+
+         CREATE TABLE retry_attempts AS SELECT * FROM live.retry_attempts;
+
+         CREATE TABLE jobs AS
+             SELECT id, name, schedule_type, schedule_value, enabled, owner, tags FROM live.jobs;
+
+         CREATE TABLE job_stats AS
+             SELECT job_id,
+                    COUNT(*) AS executions,
+                    SUM(status = 'success') AS successes,
+                    SUM(status != 'success') AS failures,
+                    AVG(duration_ms) AS avg_duration_ms,
+                    MAX(duration_ms) AS max_duration_ms,
+                    MAX(run_at) AS last_run_at
+             FROM executions GROUP BY job_id;
+
+         CREATE TABLE snapshot_info (created_at TEXT NOT NULL, lunasched_version TEXT NOT NULL);",
+    )?;
+    conn.execute(
+        "INSERT INTO snapshot_info (created_at, lunasched_version) VALUES (?1, ?2)",
+        [chrono::Utc::now().to_rfc3339(), env!("CARGO_PKG_VERSION").to_string()],
+    )?;
+    let executions: i64 = conn.query_row("SELECT COUNT(*) FROM executions", [], |row| row.get(0))?;
+    conn.execute("DETACH DATABASE live", [])?;
+    drop(conn);
+
+    std::fs::rename(&tmp_path, snapshot_path)?;
+    Ok(executions)
+}
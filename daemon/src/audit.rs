@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::Utc;
+use common::{AuditEntry, Job, Request, Response};
+use crate::db;
+use crate::profiler::SchedulerProfiler;
+use crate::scheduler::Scheduler;
+
+// Longest summary kept; job commands can be long
+const MAX_SUMMARY_LEN: usize = 200;
+
+/// Who sent a request
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub uid: Option<u32>,
+    pub name: String,
+}
+
+/// What an audited request does
+pub struct Action {
+    name: &'static str,
+    job_id: Option<String>,
+    owner: Option<String>, // Of the job, as it was before the request
+    summary: String,
+}
+
+/// The action of a request that changes something (or reads a secret); None for other reads.
+/// `jobs` are the jobs before the request, for the owner of the job it is about.
+pub fn describe(request: &Request, jobs: &HashMap<String, Job>) -> Option<Action> {
+    let action = |name, job_id: Option<&str>, summary: String| {
+        let owner = job_id.and_then(|id| jobs.get(id)).map(|job| job.owner.clone());
+        Some(Action { name, job_id: job_id.map(str::to_string), owner, summary })
+    };
+    // A new job is its sender's
+    let add = |job: &Job, summary: String| {
+        Some(Action { name: "add", job_id: Some(job.id.0.clone()), owner: Some(job.owner.clone()), summary })
+    };
+    match request {
+        Request::AddJob(job) => add(job, format!("{}: {}", job.name, job.command)),
+        Request::EnsureJob { job, if_absent } => {
+            let if_absent = if *if_absent { " (if absent)" } else { "" };
+            add(job, format!("{}: {}{}", job.name, job.command, if_absent))
+        }
+        Request::RemoveJob(id) => action("remove", Some(&id.0), String::new()),
+        Request::RenameJob { job_id, name } => action("rename", Some(&job_id.0), format!("to {}", name)),
+        Request::CloneJob { job_id, name, .. } => action("clone", Some(&job_id.0), format!("as {}", name)),
+        Request::StartJob(id) => action("start", Some(&id.0), String::new()),
+        Request::RunOnce { job, .. } => action("run", None, format!("{} {}", job.command, job.args.join(" "))),
+        Request::StartJobWithParams { job_id, params } => {
+            // Parameter values may be sensitive; their names are enough to tell runs apart
+            let mut names: Vec<&str> = params.keys().map(String::as_str).collect();
+            names.sort();
+            action("start", Some(&job_id.0), format!("params: {}", names.join(", ")))
+        }
+        Request::RetryJob(id) => action("retry", Some(&id.0), String::new()),
+        Request::Rerun { execution_id } => action("rerun", None, format!("execution {}", execution_id)),
+        Request::TestNotification { preview: true, .. } => None,
+        Request::TestNotification { job_id, channel, .. } => {
+            let summary = channel.map(|c| format!("channel {}", c)).unwrap_or_default();
+            action("notify-test", Some(&job_id.0), summary)
+        }
+        Request::ImportJobs { jobs, on_conflict } => {
+            let ids: Vec<&str> = jobs.iter().map(|job| job.id.0.as_str()).collect();
+            action("import", None, format!("{} ({:?} on conflict)", ids.join(", "), on_conflict))
+        }
+        Request::ApplyJobs { source, jobs, prune } => {
+            let prune = if *prune { ", prune" } else { "" };
+            action("apply", None, format!("{} ({} jobs{})", source, jobs.len(), prune))
+        }
+        Request::ApplyBundle { bundle, prune } => {
+            let prune = if *prune { ", prune" } else { "" };
+            action("apply-bundle", None, format!("{} signed by {}{}", bundle.source, bundle.public_key, prune))
+        }
+        Request::SetSecret { name, .. } => action("set-secret", None, name.clone()),
+        Request::GetSecret { name } => action("get-secret", None, name.clone()),
+        Request::GetPingToken(id) => action("ping-token", Some(&id.0), String::new()),
+        Request::RemoveSecret { name } => action("remove-secret", None, name.clone()),
+        Request::AddTemplate(template) => action("add-template", None, template.name.clone()),
+        Request::RemoveTemplate(name) => action("remove-template", None, name.clone()),
+        Request::AddWorkflow(workflow) => action("add-workflow", None, workflow.name.clone()),
+        Request::RemoveWorkflow(name) => action("remove-workflow", None, name.clone()),
+        Request::RunWorkflow(name) => action("run-workflow", None, name.clone()),
+        Request::BackupDatabase { path } => action("backup", None, path.clone()),
+        Request::RestoreDatabase { path } => action("restore", None, path.clone()),
+        Request::Authenticate(_)
+        | Request::ListJobs
+        | Request::GetJob(_)
+        | Request::GetHistory { .. }
+        | Request::ExportHistory { .. }
+        | Request::Ping(_)
+        | Request::GetStatus { .. }
+        | Request::GetJobOutput { .. }
+        | Request::ExportJobs { .. }
+        | Request::GetExecution { .. }
+        | Request::SuggestRebalance { .. }
+        | Request::ListSecrets
+        | Request::ListTemplates
+        | Request::GetTemplate(_)
+        | Request::Subscribe { .. }
+        | Request::ListWorkflows
+        | Request::GetWorkflowRuns { .. }
+        | Request::ListDeadLetters
+        | Request::PreviewSchedule { .. }
+        | Request::GetAuditLog { .. }
+        | Request::HealthCheck
+        | Request::GetExecutionWindows { .. }
+        | Request::ResolveJob(_)
+        | Request::ListAgents
+        | Request::GetMetrics(_) => None,
+    }
+}
+
+/// Outcome of a request as recorded: "ok", or the error the client was given
+pub fn outcome(response: &Response) -> String {
+    match response {
+        Response::Error(e) => format!("error: {}", e),
+        _ => "ok".to_string(),
+    }
+}
+
+/// Add an entry to the audit log. Failures are logged; they never fail the request.
+pub fn record(scheduler: &Arc<RwLock<Scheduler>>, profiler: &SchedulerProfiler, actor: &Actor, action: Action, outcome: String) {
+    let db = match profiler.read(scheduler, "ipc").db.clone() {
+        Some(db) => db,
+        None => return,
+    };
+    let mut summary = action.summary;
+    if summary.len() > MAX_SUMMARY_LEN {
+        let mut end = MAX_SUMMARY_LEN;
+        while !summary.is_char_boundary(end) {
+            end -= 1;
+        }
+        summary.truncate(end);
+        summary.push_str("...");
+    }
+    let entry = AuditEntry {
+        at: db::format_timestamp(&Utc::now()),
+        uid: actor.uid,
+        user: actor.name.clone(),
+        action: action.name.to_string(),
+        job_id: action.job_id,
+        owner: action.owner,
+        summary,
+        outcome,
+    };
+    let result = db.lock().unwrap().record_audit(&entry);
+    if let Err(e) = result {
+        log::warn!("Failed to record audit entry for {} by {}: {}", entry.action, entry.user, e);
+    }
+}
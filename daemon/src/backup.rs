@@ -0,0 +1,97 @@
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use common::BackupReport;
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use crate::db::Db;
+use crate::migrations::{Migrator, SCHEMA_VERSION};
+
+// Copy every page in one step: a single read transaction, which in WAL mode doesn't hold up
+// writers. Copying in steps would start over each time the daemon wrote in between.
+const ALL_PAGES: std::os::raw::c_int = -1;
+
+/// Copy the database at `db_path` to `dest` with SQLite's online backup while the daemon goes
+/// on using it, and check the copy. The copy is made next to `dest` and only moved there once
+/// it checks out, so a failed backup leaves no half-written file behind.
+pub fn backup(db_path: &str, dest: &str) -> anyhow::Result<BackupReport> {
+    let partial = format!("{}.partial", dest);
+    let _ = std::fs::remove_file(&partial);
+    // Job env may hold secrets, so keep the copy private
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&partial)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", partial, e))?;
+    let result = copy(db_path, &partial).and_then(|_| check(&partial, dest));
+    match result {
+        Ok(report) => {
+            std::fs::rename(&partial, dest).map_err(|e| anyhow::anyhow!("Failed to move {} to {}: {}", partial, dest, e))?;
+            Ok(report)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Replace the contents of the attached database with the backup at `source`. The backup is
+/// copied and checked, and migrated if it comes from an older daemon, beside the database
+/// first; the live database is only written once that copy is known to be good.
+pub fn restore(db: &Arc<Mutex<Db>>, db_path: &str, source: &str) -> anyhow::Result<BackupReport> {
+    if !Path::new(source).is_file() {
+        return Err(anyhow::anyhow!("{} is not a file", source));
+    }
+    let staged = format!("{}.restore", db_path);
+    let _ = std::fs::remove_file(&staged);
+    let result = copy(source, &staged)
+        .and_then(|_| migrate(&staged))
+        .and_then(|_| check(&staged, source))
+        .and_then(|report| {
+            db.lock().unwrap().restore_from(&staged)?;
+            Ok(report)
+        });
+    let _ = std::fs::remove_file(&staged);
+    result
+}
+
+fn copy(from: &str, to: &str) -> anyhow::Result<()> {
+    let source = Connection::open_with_flags(from, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", from, e))?;
+    let mut dest = Connection::open(to).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", to, e))?;
+    Backup::new(&source, &mut dest)?.run_to_completion(ALL_PAGES, Duration::ZERO, None)?;
+    Ok(())
+}
+
+// A backup from an older daemon is brought up to this one's schema; one from a newer daemon
+// would lose what this one doesn't know about
+fn migrate(path: &str) -> anyhow::Result<()> {
+    let conn = Connection::open(path)?;
+    let version = Db::from_connection(conn).schema_version()
+        .map_err(|_| anyhow::anyhow!("Not a lunasched database"))?;
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!("The backup has schema version {}, newer than this daemon's ({}); upgrade the daemon first",
+            version, SCHEMA_VERSION));
+    }
+    Migrator::new(Connection::open(path)?).run_migrations()?;
+    Ok(())
+}
+
+// Integrity check of a copy, and what it holds. `path` names the copy in the report.
+fn check(copy: &str, path: &str) -> anyhow::Result<BackupReport> {
+    let db = Db::from_connection(Connection::open(copy)?);
+    let problems = db.integrity_check()?;
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!("The copy failed its integrity check: {}", problems.join("; ")));
+    }
+    Ok(BackupReport {
+        path: path.to_string(),
+        size_bytes: std::fs::metadata(copy)?.len(),
+        schema_version: db.schema_version()?,
+        jobs: db.load_jobs()?.len(),
+        history_entries: db.history_count()?,
+    })
+}
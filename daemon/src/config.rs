@@ -0,0 +1,562 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use crate::acl::AclRule;
+use common::{Job, JobDefinition, NotificationConfig, OverflowPolicy, ResourceLimits, RetryPolicy};
+
+/// Source tag value for jobs declared in the config file
+pub const CONFIG_SOURCE: &str = "config";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerConfig {
+    /// Bind address for the optional HTTP listener serving /metrics (e.g. "127.0.0.1:9464")
+    #[serde(default)]
+    pub http_bind: Option<String>,
+    /// Bind address for CLI connections over TCP (e.g. "127.0.0.1:7464"); requires auth_token
+    /// and tcp_user. Only loopback addresses are accepted, as the token travels in plain text:
+    /// remote clients reach it through a tunnel.
+    #[serde(default)]
+    pub tcp_bind: Option<String>,
+    /// Shared secret TCP clients must present
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// Account authenticated TCP clients act as, for ownership and the ACL; never root
+    #[serde(default)]
+    pub tcp_user: Option<String>,
+    /// Bearer token for the JSON API on http_bind (/jobs...); the API is off without it
+    #[serde(default)]
+    pub api_token: Option<String>,
+    /// Account API clients act as, for ownership and the ACL; required with api_token
+    #[serde(default)]
+    pub api_user: Option<String>,
+    /// Let api_user be root, so API clients may add root's jobs and run them as any user
+    #[serde(default)]
+    pub api_allow_root: bool,
+    /// Unix socket for local clients, instead of the system or user default. Its instance lock
+    /// is kept next to it (<socket>.pid), so daemons on different sockets can run side by side.
+    /// Read at startup only.
+    #[serde(default)]
+    pub socket_path: Option<String>,
+    /// Permissions of the socket, in octal (e.g. "0660"); 0666, or 0600 for a user instance,
+    /// when unset. Read at startup only.
+    #[serde(default, deserialize_with = "octal_mode")]
+    pub socket_mode: Option<u32>,
+    /// Group the socket belongs to, so with socket_mode "0660" only its members can connect.
+    /// Read at startup only.
+    #[serde(default)]
+    pub socket_group: Option<String>,
+    /// Limits on IPC clients (socket and TCP), so a runaway script can't swamp the daemon.
+    /// Read at startup only.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub rate_limit: RateLimits,
+}
+
+/// Connection and request limits for IPC clients, counted per uid (TCP clients per address
+/// until they authenticate, then as tcp_user); 0 turns a limit off
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimits {
+    /// Connections open at once, from all clients
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Connections open at once from one uid
+    #[serde(default = "default_max_connections_per_uid")]
+    pub max_connections_per_uid: usize,
+    /// New connections a second from one uid, on average
+    #[serde(default = "default_connections_per_second")]
+    pub connections_per_second: u32,
+    /// Requests a second from one uid, on average, over all its connections
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: u32,
+    /// Connections and requests a uid may make in a burst above those rates
+    #[serde(default = "default_rate_burst")]
+    pub burst: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: default_max_connections(),
+            max_connections_per_uid: default_max_connections_per_uid(),
+            connections_per_second: default_connections_per_second(),
+            requests_per_second: default_requests_per_second(),
+            burst: default_rate_burst(),
+        }
+    }
+}
+
+fn default_max_connections() -> usize {
+    1024
+}
+
+fn default_max_connections_per_uid() -> usize {
+    64
+}
+
+fn default_connections_per_second() -> u32 {
+    20
+}
+
+fn default_requests_per_second() -> u32 {
+    100
+}
+
+fn default_rate_burst() -> u32 {
+    200
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingConfig {
+    /// Destinations for the output of tagged jobs, instead of the jobs log. Read at startup only.
+    #[serde(default)]
+    pub routes: Vec<LogRoute>,
+    /// Per-execution log files kept for each job under /var/log/lunasched/jobs (0 = all)
+    #[serde(default = "default_execution_logs_kept")]
+    pub execution_logs_kept: usize,
+    /// Rotation of daemon.log, jobs.log and route files. Read at startup only.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub rotation: LogRotation,
+    /// Record format of daemon.log, jobs.log and route files. Read at startup only.
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            execution_logs_kept: default_execution_logs_kept(),
+            rotation: LogRotation::default(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+fn default_execution_logs_kept() -> usize {
+    crate::output::DEFAULT_EXECUTION_LOGS_KEPT
+}
+
+/// How log records are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[date][time][target][LEVEL] message`
+    #[default]
+    Text,
+    /// One JSON object per line: timestamp, level, target, message, and job_id/execution_id when known
+    Json,
+}
+
+/// When log files are rotated and what is kept of them
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogRotation {
+    /// Rotate once a file reaches this size (0 = never by size)
+    #[serde(default = "default_rotation_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Also rotate when a new hour, day or week begins
+    #[serde(default)]
+    pub interval: Option<RotationInterval>,
+    /// Rotated files kept per log (0 = all)
+    #[serde(default = "default_rotation_keep")]
+    pub keep: usize,
+    /// Gzip rotated files, and execution logs once their execution finishes
+    #[serde(default = "default_rotation_compress")]
+    pub compress: bool,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_rotation_max_size_mb(),
+            interval: None,
+            keep: default_rotation_keep(),
+            compress: default_rotation_compress(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RotationInterval {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+fn default_rotation_max_size_mb() -> u64 {
+    100
+}
+
+fn default_rotation_keep() -> usize {
+    7
+}
+
+fn default_rotation_compress() -> bool {
+    true
+}
+
+/// Output of jobs carrying any of `tags` goes to `file` and/or syslog under `syslog_facility`
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogRoute {
+    pub name: String,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub file: Option<String>,
+    /// e.g. "local3", "daemon", "authpriv"
+    #[serde(default)]
+    pub syslog_facility: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageConfig {
+    /// libpq connection string of a Postgres database kept up to date with the jobs and their
+    /// history, for tools to read. Needs a daemon built with the `postgres` feature. Read at
+    /// startup only.
+    #[serde(default)]
+    pub postgres: Option<String>,
+    /// Days of history kept, in the daemon's database and in Postgres; all of it when unset
+    #[serde(default)]
+    pub history_retention_days: Option<u32>,
+    /// Days of audit log kept; all of it when set to null
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: Option<u32>,
+    /// Days execution snapshots (`capture_env`) are kept for reruns; all of them when set to null
+    #[serde(default = "default_snapshot_retention_days")]
+    pub snapshot_retention_days: Option<u32>,
+}
+
+fn default_audit_retention_days() -> Option<u32> {
+    Some(365)
+}
+
+fn default_snapshot_retention_days() -> Option<u32> {
+    Some(365)
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            postgres: None,
+            history_retention_days: None,
+            audit_retention_days: default_audit_retention_days(),
+            snapshot_retention_days: default_snapshot_retention_days(),
+        }
+    }
+}
+
+/// Listener for `lunasched-agent`s, which run the jobs that have a `target`. Agents connect
+/// over mutual TLS. Read at startup only.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgentsConfig {
+    /// Address to listen on (e.g. "0.0.0.0:7465")
+    pub bind: String,
+    /// The daemon's certificate (PEM), presented to agents
+    pub cert: String,
+    /// Its private key (PEM)
+    pub key: String,
+    /// CA (PEM) that signed the agents' certificates. An agent is accepted under a name its
+    /// certificate was issued to (a DNS subject alternative name).
+    pub ca: String,
+    /// Labels each agent may carry, by agent name; an agent's other labels are ignored
+    #[serde(default)]
+    pub labels: HashMap<String, Vec<String>>,
+}
+
+/// Leader election between daemons sharing one database: only the leader runs jobs, the
+/// others stand by to take over. Read at startup only.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HaConfig {
+    /// File holding the leader lease, on storage all the daemons share. When unset, the lease
+    /// is kept in the storage.postgres database.
+    #[serde(default)]
+    pub lease_file: Option<String>,
+    /// How long the lease lasts unrenewed; the leader renews it every third of this, and a
+    /// standby takes over at most this long after the leader goes away
+    #[serde(default = "default_lease_seconds")]
+    pub lease_seconds: u64,
+    /// This daemon's name in the lease (default: the host name); unique per daemon
+    #[serde(default)]
+    pub node: Option<String>,
+}
+
+fn default_lease_seconds() -> u64 {
+    15
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalyticsConfig {
+    /// Where to write the read-only SQLite snapshot of history; no snapshots when unset.
+    /// Read at startup only.
+    #[serde(default)]
+    pub snapshot_path: Option<String>,
+    #[serde(default = "default_snapshot_interval")]
+    pub interval_seconds: u64,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self { snapshot_path: None, interval_seconds: default_snapshot_interval() }
+    }
+}
+
+fn default_snapshot_interval() -> u64 {
+    3600
+}
+
+/// Policy for jobs carrying a tag, filling in what the job itself leaves unset
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TagDefaults {
+    /// Used by jobs without retries of their own
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+    /// Channels for the events a job has none configured for
+    #[serde(default)]
+    pub notification_config: Option<NotificationConfig>,
+    /// Limits a job doesn't set
+    #[serde(default)]
+    pub resource_limits: Option<ResourceLimits>,
+}
+
+impl TagDefaults {
+    fn fill(&self, job: &mut Job) {
+        if let Some(ref retry_policy) = self.retry_policy {
+            if job.retry_policy.max_attempts.is_none() {
+                job.retry_policy = retry_policy.clone();
+            }
+        }
+        if let Some(ref defaults) = self.notification_config {
+            let config = &mut job.notification_config;
+            if config.on_start.is_none() {
+                config.on_start = defaults.on_start.clone();
+            }
+            if config.on_success.is_none() {
+                config.on_success = defaults.on_success.clone();
+            }
+            if config.on_failure.is_none() {
+                config.on_failure = defaults.on_failure.clone();
+            }
+            if config.on_retry.is_none() {
+                config.on_retry = defaults.on_retry.clone();
+            }
+            if config.alerts.is_default() {
+                config.alerts = defaults.alerts.clone();
+            }
+            if config.template.is_none() {
+                config.template = defaults.template.clone();
+            }
+        }
+        if let Some(ref defaults) = self.resource_limits {
+            let limits = &mut job.resource_limits;
+            limits.timeout_seconds = limits.timeout_seconds.or(defaults.timeout_seconds);
+            limits.max_memory_mb = limits.max_memory_mb.or(defaults.max_memory_mb);
+            limits.cpu_quota = limits.cpu_quota.or(defaults.cpu_quota);
+        }
+    }
+}
+
+/// Policy for a concurrency group (see `Job::concurrency_group`)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConcurrencyGroup {
+    /// Skip a run that comes due while another member is running, or queue it until the group is free
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+}
+
+/// What to do on startup with a job process the previous daemon left running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanPolicy {
+    /// Let it finish, counting it as running, and record its run once it exits
+    #[default]
+    Adopt,
+    /// Terminate it and record its run as interrupted
+    Kill,
+}
+
+/// The job with the defaults of its tags merged in. When several tags set the same
+/// setting, the tag listed first on the job wins.
+pub fn apply_tag_defaults(tag_defaults: &HashMap<String, TagDefaults>, job: &Job) -> Job {
+    let mut job = job.clone();
+    for tag in job.tags.clone() {
+        if let Some(defaults) = tag_defaults.get(&tag) {
+            defaults.fill(&mut job);
+        }
+    }
+    job
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub server: ServerConfig,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub logging: LoggingConfig,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub analytics: AnalyticsConfig,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub ha: Option<HaConfig>,
+    #[serde(default)]
+    pub agents: Option<AgentsConfig>,
+    /// Jobs declared in the config file, reconciled into the scheduler on startup and SIGHUP
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub jobs: Vec<JobDefinition>,
+    /// Remove config-managed jobs that are no longer declared
+    #[serde(default)]
+    pub prune_jobs: bool,
+    /// Env var names/patterns whose values are scrubbed from logs and history for all jobs
+    /// (in addition to the built-in *_TOKEN, *_PASSWORD and *_SECRET)
+    #[serde(default)]
+    pub redact_env: Vec<String>,
+    /// Base64 ed25519 public keys whose signed job bundles may be applied
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+    /// Seconds after host boot before catch-up and @reboot runs fire; jobs may override it
+    #[serde(default)]
+    pub boot_delay: u64,
+    /// Retry, notification and resource limit defaults per tag, applied when jobs run
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tag_defaults: HashMap<String, TagDefaults>,
+    /// Policy per concurrency group; groups not listed skip runs while busy
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub concurrency_groups: HashMap<String, ConcurrencyGroup>,
+    /// Bytes of stdout and of stderr kept per execution for jobs that set no limit of their
+    /// own (default 1 MiB, 0 = no limit)
+    #[serde(default)]
+    pub max_output_bytes: Option<u64>,
+    /// Job processes still running from before a daemon restart: adopt or kill
+    #[serde(default)]
+    pub orphan_policy: OrphanPolicy,
+    /// Capabilities of local users and groups. When empty, any local user may do anything
+    /// the ownership checks allow; once rules exist, users get only what the rules grant.
+    /// Root and remote clients are not affected.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub acl: Vec<AclRule>,
+    /// Notification message for jobs (and tags) without a template of their own
+    #[serde(default)]
+    pub notification_template: Option<String>,
+    /// Container engine CLI for jobs with the Container executor: "docker", "podman" or a path.
+    /// Default: docker, else podman, whichever is installed.
+    #[serde(default)]
+    pub container_engine: Option<String>,
+}
+
+impl Config {
+    /// Load the config file (YAML, or TOML by extension). A missing file yields the defaults.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        if !std::path::Path::new(path).exists() {
+            log::info!("No config file at {}, using defaults", path);
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        if path.ends_with(".toml") {
+            Ok(toml::from_str(&contents)?)
+        } else {
+            Ok(serde_yaml::from_str(&contents)?)
+        }
+    }
+
+    /// Check what loading can't: job definitions, the notification template and log routes.
+    /// Returns the number of declared jobs.
+    pub fn validate(&self) -> anyhow::Result<usize> {
+        let jobs = common::JobFile { jobs: self.jobs.clone() }.into_jobs(CONFIG_SOURCE)
+            .map_err(|e| anyhow::anyhow!("Invalid job definitions: {}", e))?;
+        if let Some(ref template) = self.notification_template {
+            common::check_message_template(template).map_err(|e| anyhow::anyhow!("Invalid notification_template: {}", e))?;
+        }
+        for route in &self.logging.routes {
+            if route.file.is_none() && route.syslog_facility.is_none() {
+                return Err(anyhow::anyhow!("Log route {} needs a file or a syslog_facility", route.name));
+            }
+            if let Some(ref facility) = route.syslog_facility {
+                if !common::SYSLOG_FACILITIES.iter().any(|(name, _)| *name == facility.as_str()) {
+                    return Err(anyhow::anyhow!("Log route {} has unknown syslog facility {}", route.name, facility));
+                }
+            }
+        }
+        if self.storage.postgres.is_some() && !cfg!(feature = "postgres") {
+            return Err(anyhow::anyhow!("storage.postgres needs a daemon built with the postgres feature"));
+        }
+        if let Some(ref ha) = self.ha {
+            if ha.lease_file.is_none() && self.storage.postgres.is_none() {
+                return Err(anyhow::anyhow!("ha needs a lease_file, or storage.postgres to keep the lease in"));
+            }
+            if ha.lease_seconds < 3 {
+                return Err(anyhow::anyhow!("ha.lease_seconds must be at least 3"));
+            }
+        }
+        if self.server.tcp_bind.is_some() && self.server.auth_token.is_none() {
+            return Err(anyhow::anyhow!("server.tcp_bind requires server.auth_token"));
+        }
+        Ok(jobs.len())
+    }
+}
+
+/// Permission bits written in octal, as a string ("0660") or a number whose digits are
+/// octal (YAML reads an unquoted 0660 as 660)
+fn octal_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Text(String),
+        Number(u32),
+    }
+    let digits = match Option::<Mode>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Mode::Text(text)) => text,
+        Some(Mode::Number(number)) => number.to_string(),
+    };
+    match u32::from_str_radix(digits.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o777 => Ok(Some(mode)),
+        _ => Err(serde::de::Error::custom(format!("invalid socket_mode '{}', expected octal permissions like 0660", digits))),
+    }
+}
+
+/// Treat a section left empty in YAML (e.g. `jobs:` with no entries) as its default
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Mode {
+        #[serde(default, deserialize_with = "octal_mode")]
+        mode: Option<u32>,
+    }
+
+    fn mode(yaml: &str) -> Result<Option<u32>, serde_yaml::Error> {
+        serde_yaml::from_str::<Mode>(yaml).map(|m| m.mode)
+    }
+
+    #[test]
+    fn octal_mode_forms() {
+        assert_eq!(mode("mode: \"0660\"").unwrap(), Some(0o660));
+        assert_eq!(mode("mode: \"0o600\"").unwrap(), Some(0o600));
+        // Unquoted, YAML reads the digits as a decimal number
+        assert_eq!(mode("mode: 0660").unwrap(), Some(0o660));
+        assert_eq!(mode("mode: 644").unwrap(), Some(0o644));
+        assert_eq!(mode("mode: ~").unwrap(), None);
+        assert_eq!(mode("{}").unwrap(), None);
+    }
+
+    #[test]
+    fn octal_mode_rejects() {
+        for yaml in ["mode: \"0680\"", "mode: 1777", "mode: \"rw\"", "mode: -1"] {
+            assert!(mode(yaml).is_err(), "{} was accepted", yaml);
+        }
+    }
+}
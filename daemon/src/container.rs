@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use common::{Executor, Job};
+use crate::account::Account;
+use crate::resource_manager;
+use crate::sandbox;
+use crate::script;
+use crate::timeline::Timeline;
+
+// Looked for in PATH, in this order, when the config names no engine
+const ENGINES: [&str; 2] = ["docker", "podman"];
+
+// Seconds between SIGTERM and SIGKILL when stopping a container past its timeout, as for
+// local jobs
+const STOP_GRACE_SECS: u64 = 2;
+
+/// Name of an execution's container, e.g. to stop it
+pub fn name(execution_id: &str) -> String {
+    format!("lunasched-{}", execution_id)
+}
+
+/// Engine CLI to run containers with: the configured one, else docker or podman from PATH
+pub fn engine(configured: Option<&str>) -> std::io::Result<String> {
+    if let Some(engine) = configured {
+        return Ok(engine.to_string());
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    ENGINES.iter()
+        .find(|engine| std::env::split_paths(&path).any(|dir| dir.join(engine).is_file()))
+        .map(|engine| engine.to_string())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound,
+            "no container engine found; install docker or podman, or set container_engine"))
+}
+
+/// `<engine> run` for an execution of a Container job: `command` through /bin/sh in the image,
+/// attached, so the container's output is the engine's and the container goes when it ends.
+/// It runs as the job's user (uid:gid), unable to gain privileges, under the job's memory and
+/// CPU limits and its sandbox. `env` is passed by name only, so values stay off the engine's
+/// command line. A script job's `script` is mounted at `script::CONTAINER_PATH`.
+pub fn command(engine: &str, job: &Job, account: &Account, execution_id: &str, command: &str,
+               env: &HashMap<String, String>, script: Option<&Path>) -> std::io::Result<tokio::process::Command> {
+    let (image, volumes, network) = match job.executor {
+        Executor::Container { ref image, ref volumes, ref network } => (image, volumes, network),
+        Executor::Local | Executor::Systemd { .. } => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("job {} does not run in a container", job.name))),
+    };
+    let (uid, gid) = account.ids();
+
+    let mut cmd = tokio::process::Command::new(engine);
+    cmd.args(["run", "--rm", "--name", &name(execution_id)]);
+    cmd.args(["--label", &format!("lunasched.job={}", job.id.0)]);
+    cmd.args(["--user", &format!("{}:{}", uid, gid), "--security-opt", "no-new-privileges"]);
+    for volume in volumes {
+        cmd.args(["--volume", volume]);
+    }
+    if let Some(script) = script {
+        cmd.arg("--volume").arg(format!("{}:{}:ro", script.display(), script::CONTAINER_PATH));
+    }
+    let network = if job.sandbox.no_network { Some("none") } else { network.as_deref() };
+    if let Some(network) = network {
+        cmd.args(["--network", network]);
+    }
+    cmd.args(sandbox::container_options(&job.sandbox));
+    if let Some(memory_mb) = job.resource_limits.max_memory_mb {
+        // No swap beyond the limit, as with the cgroup of a local job
+        let memory = format!("{}m", memory_mb);
+        cmd.args(["--memory", &memory, "--memory-swap", &memory]);
+    }
+    if let Some(quota) = job.resource_limits.cpu_quota {
+        cmd.args(["--cpus", &quota.to_string()]);
+    }
+    if let Some(ref dir) = job.working_dir {
+        cmd.arg("--workdir").arg(dir);
+    }
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    for name in names {
+        cmd.args(["--env", name]);
+    }
+    cmd.envs(env);
+    cmd.arg(image).args(["/bin/sh", "-c", command]);
+
+    // The engine reads nothing from its own working directory
+    cmd.current_dir("/");
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    // A process group of its own, as for local jobs, so `lunasched kill` finds it
+    unsafe {
+        cmd.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(std::io::Error::from));
+    }
+    Ok(cmd)
+}
+
+/// Stop the container of an execution whose engine CLI (process group `pgid`) is still running
+/// when its timeout is up. Killing the CLI would leave the container running. Called off by
+/// `exited` (sent or dropped) when the execution ends first.
+pub async fn enforce_timeout(configured_engine: Option<String>, execution_id: String, pgid: u32, timeout_seconds: u64,
+                             timeline: Timeline, exited: tokio::sync::oneshot::Receiver<()>) {
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs(timeout_seconds)) => {}
+        _ = exited => return,
+    }
+    if !resource_manager::group_alive(pgid) {
+        return;
+    }
+    // Found as when the container was started
+    let engine = engine(configured_engine.as_deref()).unwrap_or_else(|_| ENGINES[0].to_string());
+    let container = name(&execution_id);
+    log::warn!("Container {} exceeded timeout of {}s, stopping it", container, timeout_seconds);
+    timeline.record("timed_out", Some(format!("{} stop after {}s", engine, timeout_seconds)));
+    let stopped = tokio::process::Command::new(&engine)
+        .args(["stop", "--time", &STOP_GRACE_SECS.to_string(), &container])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await;
+    let failure = match stopped {
+        Ok(output) if output.status.success() => return,
+        Ok(output) => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        Err(e) => e.to_string(),
+    };
+    // At least end the execution; the engine may still hold on to the container
+    log::warn!("Failed to stop container {}: {}; killing {}", container, failure, engine);
+    timeline.record("killed", Some("SIGKILL".to_string()));
+    resource_manager::signal_group(pgid, nix::sys::signal::Signal::SIGKILL);
+}
@@ -1,17 +1,143 @@
 use rusqlite::{params, Connection, Result};
 use common::{Job, ScheduleConfig, JobId, RetryPolicy, ResourceLimits, JobHooks};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
 
 pub struct Db {
     conn: Connection,
 }
 
-impl Db {
-    pub fn new(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        Ok(Self { conn })
+/// Job id, start time and duration of an execution
+pub type ExecutionStart = (String, DateTime<Utc>, Option<i64>);
+
+const HISTORY_COLUMNS: &str = "id, job_id, run_at, status, output, execution_id, exit_code, duration_ms, \
+    scheduled_at, started_at, stdout, stderr, resource_events, stdout_bytes, stderr_bytes, log_path, hook_result, hook_output, \
+    signal, attempt";
+
+// Statuses of executions that failed, as an SQL list
+const FAILED_STATUSES: &str = "'failed', 'Error', 'SpawnError'";
+
+/// Which history entries to read; unset fields match every entry
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearch {
+    pub job_id: Option<String>,
+    pub status: Option<String>, // "failed" covers every failed status
+    pub since: Option<DateTime<Utc>>,
+    pub grep: Option<String>, // Text in the message, stdout or stderr; case-insensitive
+}
+
+impl HistorySearch {
+    /// The search a request asks for; fails on a `since` that isn't RFC 3339
+    pub fn new(job_id: Option<JobId>, filter: common::HistoryFilter) -> std::result::Result<Self, String> {
+        let since = match filter.since.as_deref().map(DateTime::parse_from_rfc3339).transpose() {
+            Ok(since) => since.map(|t| t.with_timezone(&Utc)),
+            Err(e) => return Err(format!("Invalid time: {}", e)),
+        };
+        Ok(HistorySearch { job_id: job_id.map(|id| id.0), status: filter.status, since, grep: filter.grep })
     }
+}
+
+/// One execution of a job, as recorded in history
+#[derive(Debug, Clone)]
+pub struct ExecutionRecord {
+    pub job_id: String,
+    pub execution_id: String,
+    pub status: String,
+    pub message: Option<String>, // Set when the process could not be run or waited on, or failed its success criteria
+    pub exit_code: Option<i32>, // None when the process was killed by a signal
+    pub signal: Option<i32>, // Signal that killed the process
+    pub attempt: Option<u32>, // 1 for the first run, 2 for its first retry, and so on
+    pub duration_ms: Option<i64>,
+    pub scheduled_at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_bytes: Option<i64>, // Written by the process, including any truncated part
+    pub stderr_bytes: Option<i64>,
+    pub log_path: Option<String>, // Per-execution log file with the full output
+    pub resource_events: Option<String>, // cgroup limit events, e.g. OOM kills
+    pub hook_result: Option<String>, // How the hook run after the execution went
+    pub hook_output: Option<String>,
+}
+
+/// An execution recorded as started but not as finished
+#[derive(Debug, Clone)]
+pub struct UnfinishedExecution {
+    pub job_id: String,
+    pub execution_id: String,
+    pub scheduled_time: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub pid: Option<u32>,
+}
+
+/// Command and environment an execution ran with, replayed by `lunasched rerun`
+#[derive(Debug, Clone)]
+pub struct ExecutionSnapshot {
+    pub execution_id: String,
+    pub job_id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub params: HashMap<String, String>,
+}
+
+// How long a statement waits for another connection's lock (the CLI's, or an analytics export)
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection settings for the daemon: write-ahead logging, so readers don't block the history
+/// writer and commits don't fsync the whole database, and a busy timeout instead of SQLITE_BUSY
+pub fn tune(conn: &Connection) -> Result<()> {
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+/// Run `f` against the database on the blocking thread pool, so a slow disk holds up a
+/// blocking thread rather than the async runtime
+pub async fn query<T, F>(db: &Arc<Mutex<Db>>, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(&Db) -> T + Send + 'static,
+{
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || f(&db.lock().unwrap()))
+        .await
+        .expect("database task panicked")
+}
+
+// Same format as SQLite's CURRENT_TIMESTAMP, so history timestamps compare and sort alike
+pub fn format_timestamp(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn history_from_row(row: &rusqlite::Row) -> Result<common::HistoryEntry> {
+    Ok(common::HistoryEntry {
+        id: row.get(0)?,
+        job_id: row.get(1)?,
+        run_at: row.get(2)?,
+        status: row.get(3)?,
+        output: row.get(4)?,
+        execution_id: row.get(5)?,
+        exit_code: row.get(6)?,
+        duration_ms: row.get(7)?,
+        scheduled_at: row.get(8)?,
+        started_at: row.get(9)?,
+        stdout: row.get(10)?,
+        stderr: row.get(11)?,
+        resource_events: row.get(12)?,
+        stdout_bytes: row.get(13)?,
+        stderr_bytes: row.get(14)?,
+        log_path: row.get(15)?,
+        hook_result: row.get(16)?,
+        hook_output: row.get(17)?,
+        signal: row.get(18)?,
+        attempt: row.get(19)?,
+    })
+}
 
+impl Db {
     pub fn from_connection(conn: Connection) -> Self {
         Self { conn }
     }
@@ -21,6 +147,10 @@ impl Db {
             ScheduleConfig::Cron(s) => ("cron", s.clone()),
             ScheduleConfig::Every(s) => ("every", s.to_string()),
             ScheduleConfig::Calendar(p) => ("calendar", serde_json::to_string(p).unwrap()),
+            ScheduleConfig::Window(p) => ("window", serde_json::to_string(p).unwrap()),
+            ScheduleConfig::Heartbeat(s) => ("heartbeat", s.to_string()),
+            ScheduleConfig::Reboot => ("reboot", String::new()),
+            ScheduleConfig::Once(t) => ("once", t.to_rfc3339()),
         };
         
         let args_json = serde_json::to_string(&job.args).unwrap();
@@ -32,24 +162,42 @@ impl Db {
         let tags_json = serde_json::to_string(&job.tags).unwrap();
         let dependencies_json = serde_json::to_string(&job.dependencies).unwrap();
         let hooks_json = serde_json::to_string(&job.hooks).unwrap();
+        let success_criteria_json = serde_json::to_string(&job.success_criteria).unwrap();
         
         // Serialize Phase 2 (v1.2.0) fields
         let priority_json = serde_json::to_string(&job.priority).unwrap();
         let execution_mode_json = serde_json::to_string(&job.execution_mode).unwrap();
         let notification_config_json = serde_json::to_string(&job.notification_config).unwrap();
+        let redact_env_json = serde_json::to_string(&job.redact_env).unwrap();
+        let overflow_policy_json = serde_json::to_string(&job.overflow_policy).unwrap();
+        let catch_up_json = serde_json::to_string(&job.catch_up).unwrap();
+        let on_success_trigger_json = serde_json::to_string(&job.on_success_trigger).unwrap();
+        let on_failure_trigger_json = serde_json::to_string(&job.on_failure_trigger).unwrap();
+        let executor_json = serde_json::to_string(&job.executor).unwrap();
+        let sandbox_json = serde_json::to_string(&job.sandbox).unwrap();
 
         self.conn.execute(
             "INSERT OR REPLACE INTO jobs 
              (id, name, schedule_type, schedule_value, command, args, env, enabled, owner,
               retry_policy, resource_limits, jitter_seconds, timezone, tags, dependencies, hooks, max_concurrent,
-              priority, execution_mode, notification_config)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+              priority, execution_mode, notification_config, redact_env, fence_key, capture_env,
+              overflow_policy, catch_up, boot_delay, delete_after_run, run_as, working_dir, umask,
+              concurrency_group, success_criteria, max_output_bytes, run_immediately, on_success_trigger, on_failure_trigger,
+              disable_after_failures, expires_at, disabled_reason, target, executor, sandbox, script)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23,
+                     ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43)",
             params![
                 job.id.0, job.name, sched_type, sched_val, job.command, args_json, env_json, 
                 job.enabled, job.owner,
                 retry_policy_json, resource_limits_json, job.jitter_seconds as i64, 
                 job.timezone, tags_json, dependencies_json, hooks_json, job.max_concurrent as i64,
-                priority_json, execution_mode_json, notification_config_json
+                priority_json, execution_mode_json, notification_config_json, redact_env_json,
+                job.fence_key, job.capture_env, overflow_policy_json, catch_up_json,
+                job.boot_delay.map(|d| d as i64), job.delete_after_run, job.run_as,
+                job.working_dir.as_ref().map(|d| d.display().to_string()), job.umask,
+                job.concurrency_group, success_criteria_json, job.max_output_bytes.map(|b| b as i64),
+                job.run_immediately, on_success_trigger_json, on_failure_trigger_json,
+                job.disable_after_failures, job.expires_at.map(|t| t.to_rfc3339()), job.disabled_reason, job.target, executor_json, sandbox_json, job.script
             ],
         )?;
         Ok(())
@@ -57,6 +205,7 @@ impl Db {
 
     pub fn remove_job(&self, id: &str) -> Result<()> {
         self.conn.execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+        self.conn.execute("DELETE FROM schedule_state WHERE job_id = ?1", params![id])?;
         Ok(())
     }
 
@@ -64,7 +213,10 @@ impl Db {
         let mut stmt = self.conn.prepare(
             "SELECT id, name, schedule_type, schedule_value, command, args, env, enabled, owner,
                     retry_policy, resource_limits, jitter_seconds, timezone, tags, dependencies, hooks, max_concurrent,
-                    priority, execution_mode, notification_config
+                    priority, execution_mode, notification_config, redact_env, fence_key, capture_env,
+                    overflow_policy, catch_up, boot_delay, delete_after_run, run_as, working_dir, umask,
+                    concurrency_group, success_criteria, max_output_bytes, run_immediately,
+                    on_success_trigger, on_failure_trigger, disable_after_failures, expires_at, disabled_reason, target, executor, sandbox, script
              FROM jobs"
         )?;
         
@@ -93,6 +245,13 @@ impl Db {
                 "cron" => ScheduleConfig::Cron(sched_val),
                 "every" => ScheduleConfig::Every(sched_val.parse().unwrap_or(0)),
                 "calendar" => ScheduleConfig::Calendar(serde_json::from_str(&sched_val).unwrap()),
+                "window" => ScheduleConfig::Window(serde_json::from_str(&sched_val).unwrap()),
+                "heartbeat" => ScheduleConfig::Heartbeat(sched_val.parse().unwrap_or(0)),
+                "reboot" => ScheduleConfig::Reboot,
+                "once" => match DateTime::parse_from_rfc3339(&sched_val) {
+                    Ok(t) => ScheduleConfig::Once(t.with_timezone(&Utc)),
+                    Err(_) => ScheduleConfig::Cron(sched_val),
+                },
                 _ => ScheduleConfig::Cron(sched_val), // Fallback
             };
 
@@ -117,6 +276,34 @@ impl Db {
             let execution_mode: ExecutionMode = serde_json::from_str(&execution_mode_json).unwrap_or_default();
             let notification_config: NotificationConfig = serde_json::from_str(&notification_config_json).unwrap_or_default();
 
+            let redact_env_json: String = row.get(20).unwrap_or_else(|_| "[]".to_string());
+            let redact_env: Vec<String> = serde_json::from_str(&redact_env_json).unwrap_or_default();
+            let fence_key: Option<String> = row.get(21).unwrap_or(None);
+            let capture_env: bool = row.get(22).unwrap_or(false);
+            let overflow_policy_json: String = row.get(23).unwrap_or_else(|_| "\"Skip\"".to_string());
+            let overflow_policy: common::OverflowPolicy = serde_json::from_str(&overflow_policy_json).unwrap_or_default();
+            let catch_up_json: String = row.get(24).unwrap_or_else(|_| "\"Skip\"".to_string());
+            let catch_up: common::CatchUpPolicy = serde_json::from_str(&catch_up_json).unwrap_or_default();
+            let boot_delay: Option<i64> = row.get(25).unwrap_or(None);
+            let delete_after_run: bool = row.get(26).unwrap_or(false);
+            let run_as: Option<String> = row.get(27).unwrap_or(None);
+            let working_dir: Option<String> = row.get(28).unwrap_or(None);
+            let umask: Option<u32> = row.get(29).unwrap_or(None);
+            let concurrency_group: Option<String> = row.get(30).unwrap_or(None);
+            let success_criteria_json: String = row.get(31).unwrap_or_else(|_| "{}".to_string());
+            let success_criteria = serde_json::from_str(&success_criteria_json).unwrap_or_default();
+            let max_output_bytes: Option<i64> = row.get(32).unwrap_or(None);
+            let run_immediately: bool = row.get(33).unwrap_or(false);
+            let on_success_trigger_json: String = row.get(34).unwrap_or_else(|_| "[]".to_string());
+            let on_failure_trigger_json: String = row.get(35).unwrap_or_else(|_| "[]".to_string());
+            let disable_after_failures: Option<u32> = row.get(36).unwrap_or(None);
+            let expires_at: Option<String> = row.get(37).unwrap_or(None);
+            let disabled_reason: Option<String> = row.get(38).unwrap_or(None);
+            let target: Option<String> = row.get(39).unwrap_or(None);
+            let executor_json: String = row.get(40).unwrap_or_else(|_| "\"Local\"".to_string());
+            let sandbox_json: String = row.get(41).unwrap_or_else(|_| "{}".to_string());
+            let script: Option<String> = row.get(42).unwrap_or(None);
+
             Ok(Job {
                 id: JobId(id),
                 name,
@@ -137,6 +324,29 @@ impl Db {
                 priority,
                 execution_mode,
                 notification_config,
+                redact_env,
+                fence_key,
+                capture_env,
+                overflow_policy,
+                catch_up,
+                boot_delay: boot_delay.map(|d| d as u64),
+                delete_after_run,
+                run_as,
+                working_dir: working_dir.map(std::path::PathBuf::from),
+                umask,
+                concurrency_group,
+                success_criteria,
+                max_output_bytes: max_output_bytes.map(|b| b as u64),
+                run_immediately,
+                on_success_trigger: serde_json::from_str(&on_success_trigger_json).unwrap_or_default(),
+                on_failure_trigger: serde_json::from_str(&on_failure_trigger_json).unwrap_or_default(),
+                disable_after_failures,
+                expires_at: expires_at.and_then(|t| DateTime::parse_from_rfc3339(&t).ok()).map(|t| t.with_timezone(&Utc)),
+                disabled_reason,
+                target,
+                executor: serde_json::from_str(&executor_json).unwrap_or_default(),
+                sandbox: serde_json::from_str(&sandbox_json).unwrap_or_default(),
+                script,
             })
         })?;
 
@@ -148,6 +358,67 @@ impl Db {
         Ok(jobs)
     }
 
+    /// Run `f` in a transaction, committed if it succeeds and rolled back otherwise
+    pub fn transaction<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&Db) -> Result<()>,
+    {
+        self.conn.execute_batch("BEGIN")?;
+        match f(self) {
+            Ok(()) => self.conn.execute_batch("COMMIT"),
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+
+    /// Cheap query to confirm the database is still readable
+    pub fn check(&self) -> Result<()> {
+        self.conn.query_row("SELECT COUNT(*) FROM jobs", [], |row| row.get::<_, i64>(0))?;
+        Ok(())
+    }
+
+    /// Problems found by SQLite's integrity check; empty if there are none. Reads every page.
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let problems = rows.collect::<Result<Vec<String>>>()?;
+        Ok(problems.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    /// Remove history recorded before `before`, with the timelines of its executions and the
+    /// retry attempts of the time. Returns how many history entries went; run counters are kept.
+    pub fn prune_history(&self, before: &DateTime<Utc>) -> Result<usize> {
+        let before = format_timestamp(before);
+        self.conn.execute(
+            "DELETE FROM execution_events WHERE execution_id IN
+                 (SELECT execution_id FROM history WHERE run_at < ?1 AND execution_id IS NOT NULL)",
+            params![before],
+        )?;
+        self.conn.execute("DELETE FROM retry_attempts WHERE run_at < ?1", params![before])?;
+        self.conn.execute("DELETE FROM history WHERE run_at < ?1", params![before])
+    }
+
+    /// Executions recorded in history
+    pub fn history_count(&self) -> Result<u64> {
+        self.conn.query_row("SELECT COUNT(*) FROM history", [], |row| row.get::<_, i64>(0)).map(|n| n as u64)
+    }
+
+    /// Replace everything in the database with the contents of the one at `path`, in one
+    /// write transaction
+    pub fn restore_from(&mut self, path: &str) -> Result<()> {
+        let source = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let result = rusqlite::backup::Backup::new(&source, &mut self.conn)?.run_to_completion(-1, Duration::ZERO, None);
+        result
+    }
+
+    /// Schema version the database was last migrated to
+    pub fn schema_version(&self) -> Result<i32> {
+        let version = self.conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get::<_, Option<i32>>(0))?;
+        Ok(version.unwrap_or(0))
+    }
+
     pub fn log_history(&self, job_id: &str, status: &str, output: &str) -> Result<()> {
         self.conn.execute(
             "INSERT INTO history (job_id, status, output) VALUES (?1, ?2, ?3)",
@@ -156,40 +427,610 @@ impl Db {
         Ok(())
     }
 
+    pub fn log_execution(&self, record: &ExecutionRecord) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO history 
+             (job_id, status, output, execution_id, exit_code, duration_ms, scheduled_at, started_at, stdout, stderr,
+              resource_events, stdout_bytes, stderr_bytes, log_path, hook_result, hook_output, signal, attempt)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                record.job_id, record.status, record.message, record.execution_id, record.exit_code,
+                record.duration_ms, format_timestamp(&record.scheduled_at), format_timestamp(&record.started_at),
+                record.stdout, record.stderr, record.resource_events, record.stdout_bytes, record.stderr_bytes,
+                record.log_path, record.hook_result, record.hook_output, record.signal, record.attempt
+            ],
+        )?;
+        self.update_job_metrics(record)
+    }
+
+    // Outcomes of adopted executions are unknown and count as neither success nor failure
+    fn update_job_metrics(&self, record: &ExecutionRecord) -> Result<()> {
+        let success = record.status == "success";
+        let failure = !success && record.status != "unknown";
+        let started_at = format_timestamp(&record.started_at);
+        self.conn.execute(
+            "INSERT INTO job_metrics (job_id, total_runs, successes, failures, timed_runs, total_duration_ms,
+                 last_duration_ms, last_run_at, last_success_at, last_failure_at)
+             VALUES (?1, 1, ?2, ?3, ?4 IS NOT NULL, COALESCE(?4, 0), ?4, ?5,
+                 CASE WHEN ?2 THEN ?5 END, CASE WHEN ?3 THEN ?5 END)
+             ON CONFLICT(job_id) DO UPDATE SET
+                 total_runs = total_runs + 1,
+                 successes = successes + excluded.successes,
+                 failures = failures + excluded.failures,
+                 timed_runs = timed_runs + excluded.timed_runs,
+                 total_duration_ms = total_duration_ms + excluded.total_duration_ms,
+                 last_duration_ms = COALESCE(excluded.last_duration_ms, last_duration_ms),
+                 last_run_at = excluded.last_run_at,
+                 last_success_at = COALESCE(excluded.last_success_at, last_success_at),
+                 last_failure_at = COALESCE(excluded.last_failure_at, last_failure_at)",
+            params![record.job_id, success, failure, record.duration_ms, started_at],
+        )?;
+        Ok(())
+    }
+
+    /// Percentage of a job's runs since `since` that succeeded; None without decided runs
+    pub fn success_rate(&self, job_id: &str, since: &DateTime<Utc>) -> Result<Option<f64>> {
+        let (successes, decided): (i64, i64) = self.conn.query_row(
+            "SELECT COALESCE(SUM(status = 'success'), 0), COALESCE(SUM(status != 'unknown'), 0)
+             FROM history WHERE job_id = ?1 AND execution_id IS NOT NULL AND started_at >= ?2",
+            params![job_id, format_timestamp(since)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((decided > 0).then(|| successes as f64 * 100.0 / decided as f64))
+    }
+
+    pub fn get_job_metrics(&self, job_id: &str) -> Result<Option<common::JobStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT total_runs, successes, failures, timed_runs, total_duration_ms, last_duration_ms,
+                    last_run_at, last_success_at, last_failure_at
+             FROM job_metrics WHERE job_id = ?1"
+        )?;
+        let mut rows = stmt.query_map(params![job_id], |row| {
+            let timed_runs: i64 = row.get(3)?;
+            let total_duration_ms: i64 = row.get(4)?;
+            Ok(common::JobStats {
+                job_id: job_id.to_string(),
+                total_runs: row.get::<_, i64>(0)? as u64,
+                successes: row.get::<_, i64>(1)? as u64,
+                failures: row.get::<_, i64>(2)? as u64,
+                avg_duration_ms: (timed_runs > 0).then(|| (total_duration_ms / timed_runs) as u64),
+                last_duration_ms: row.get::<_, Option<i64>>(5)?.map(|ms| ms as u64),
+                last_run_at: row.get(6)?,
+                last_success_at: row.get(7)?,
+                last_failure_at: row.get(8)?,
+            })
+        })?;
+        rows.next().transpose()
+    }
+
     pub fn get_history(&self, job_id: &str, limit: Option<usize>) -> Result<Vec<common::HistoryEntry>> {
-        let query = match limit {
-            Some(n) => format!(
-                "SELECT id, job_id, run_at, status, output 
-                 FROM history 
-                 WHERE job_id = ?1 
-                 ORDER BY run_at DESC 
-                 LIMIT {}", n
-            ),
-            None => String::from(
-                "SELECT id, job_id, run_at, status, output 
-                 FROM history 
-                 WHERE job_id = ?1 
-                 ORDER BY run_at DESC"
-            ),
-        };
-        
+        let search = HistorySearch { job_id: Some(job_id.to_string()), ..HistorySearch::default() };
+        self.search_history(&search, limit)
+    }
+
+    /// History of one job, or of all jobs, newest first
+    pub fn search_history(&self, search: &HistorySearch, limit: Option<usize>) -> Result<Vec<common::HistoryEntry>> {
+        self.select_history(search, None, "run_at DESC", limit)
+    }
+
+    /// Up to `limit` entries recorded after entry `after_id`, oldest first; pages through a
+    /// search for an export without holding the database for all of it
+    pub fn history_page(&self, search: &HistorySearch, after_id: i64, limit: usize) -> Result<Vec<common::HistoryEntry>> {
+        self.select_history(search, Some(after_id), "id", Some(limit))
+    }
+
+    // The query holds only the conditions given, so SQLite can use the history indexes for them
+    fn select_history(&self, search: &HistorySearch, after_id: Option<i64>, order: &str,
+                      limit: Option<usize>) -> Result<Vec<common::HistoryEntry>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(ref job_id) = search.job_id {
+            values.push(job_id.clone().into());
+            conditions.push(format!("job_id = ?{}", values.len()));
+        }
+        match search.status.as_deref() {
+            Some("failed") => conditions.push(format!("status IN ({})", FAILED_STATUSES)),
+            Some(status) => {
+                values.push(status.to_string().into());
+                conditions.push(format!("status = ?{}", values.len()));
+            }
+            None => {}
+        }
+        if let Some(ref since) = search.since {
+            values.push(format_timestamp(since).into());
+            conditions.push(format!("run_at >= ?{}", values.len()));
+        }
+        if let Some(ref grep) = search.grep {
+            let escaped = grep.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            values.push(format!("%{}%", escaped).into());
+            let n = values.len();
+            conditions.push(format!(
+                "(output LIKE ?{0} ESCAPE '\\' OR stdout LIKE ?{0} ESCAPE '\\' OR stderr LIKE ?{0} ESCAPE '\\')", n));
+        }
+        if let Some(after_id) = after_id {
+            values.push(after_id.into());
+            conditions.push(format!("id > ?{}", values.len()));
+        }
+
+        let mut query = format!("SELECT {} FROM history", HISTORY_COLUMNS);
+        if !conditions.is_empty() {
+            query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+        query.push_str(&format!(" ORDER BY {}", order));
+        if let Some(n) = limit {
+            query.push_str(&format!(" LIMIT {}", n));
+        }
         let mut stmt = self.conn.prepare(&query)?;
-    
-        let history_iter = stmt.query_map(params![job_id], |row| {
-            Ok(common::HistoryEntry {
-                id: row.get(0)?,
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), history_from_row)?;
+        rows.collect()
+    }
+
+    /// Latest failed executions across all jobs, newest first
+    pub fn recent_failures(&self, limit: usize) -> Result<Vec<common::HistoryEntry>> {
+        let query = format!(
+            "SELECT {} FROM history WHERE status IN ({}) ORDER BY run_at DESC LIMIT {}",
+            HISTORY_COLUMNS, FAILED_STATUSES, limit
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map([], history_from_row)?;
+        rows.collect()
+    }
+
+    /// History entry recorded for one execution of a job
+    pub fn get_execution(&self, job_id: &str, execution_id: &str) -> Result<Option<common::HistoryEntry>> {
+        let query = format!(
+            "SELECT {} FROM history WHERE job_id = ?1 AND execution_id = ?2 ORDER BY id DESC LIMIT 1",
+            HISTORY_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let mut rows = stmt.query_map(params![job_id, execution_id], history_from_row)?;
+        rows.next().transpose()
+    }
+
+    pub fn save_snapshot(&self, snapshot: &ExecutionSnapshot) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO execution_snapshots (execution_id, job_id, command, args, env, params)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                snapshot.execution_id, snapshot.job_id, snapshot.command,
+                serde_json::to_string(&snapshot.args).unwrap(),
+                serde_json::to_string(&snapshot.env).unwrap(),
+                serde_json::to_string(&snapshot.params).unwrap()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_snapshot(&self, execution_id: &str) -> Result<Option<ExecutionSnapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT execution_id, job_id, command, args, env, params FROM execution_snapshots WHERE execution_id = ?1"
+        )?;
+        let mut rows = stmt.query_map(params![execution_id], |row| {
+            let args_json: String = row.get(3)?;
+            let env_json: String = row.get(4)?;
+            let params_json: String = row.get(5)?;
+            Ok(ExecutionSnapshot {
+                execution_id: row.get(0)?,
                 job_id: row.get(1)?,
-                run_at: row.get(2)?,
-                status: row.get(3)?,
-                output: row.get(4)?,
+                command: row.get(2)?,
+                args: serde_json::from_str(&args_json).unwrap_or_default(),
+                env: serde_json::from_str(&env_json).unwrap_or_default(),
+                params: serde_json::from_str(&params_json).unwrap_or_default(),
+            })
+        })?;
+        rows.next().transpose()
+    }
+
+    /// Record the start of an execution, so a restart can find it if it never finishes.
+    /// `fired_window` marks a scheduled window as fired, so a restart within it doesn't fire it again.
+    pub fn record_execution_start(&self, job_id: &str, execution_id: &str, scheduled_time: &DateTime<Utc>,
+                                  fired_window: bool) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO execution_windows (job_id, execution_id, scheduled_time, actual_start_time, fired_window)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![job_id, execution_id, scheduled_time.to_rfc3339(), Utc::now().to_rfc3339(), fired_window],
+        )?;
+        Ok(())
+    }
+
+    pub fn finish_execution(&self, execution_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE execution_windows SET finished_at = ?1 WHERE execution_id = ?2",
+            params![Utc::now().to_rfc3339(), execution_id],
+        )?;
+        Ok(())
+    }
+
+    /// Executions started but never finished: those a previous daemon was running when it stopped
+    pub fn unfinished_executions(&self) -> Result<Vec<UnfinishedExecution>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, execution_id, scheduled_time, actual_start_time, pid FROM execution_windows
+             WHERE finished_at IS NULL ORDER BY actual_start_time",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?, row.get::<_, Option<u32>>(4)?))
+        })?;
+
+        let mut executions = Vec::new();
+        for row in rows {
+            let (job_id, execution_id, scheduled, started, pid) = row?;
+            let (scheduled_time, started_at) = match (DateTime::parse_from_rfc3339(&scheduled), DateTime::parse_from_rfc3339(&started)) {
+                (Ok(scheduled), Ok(started)) => (scheduled.with_timezone(&Utc), started.with_timezone(&Utc)),
+                _ => continue,
+            };
+            executions.push(UnfinishedExecution { job_id, execution_id, scheduled_time, started_at, pid });
+        }
+        Ok(executions)
+    }
+
+    pub fn set_execution_window_pid(&self, execution_id: &str, pid: u32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE execution_windows SET pid = ?1 WHERE execution_id = ?2",
+            params![pid, execution_id],
+        )?;
+        Ok(())
+    }
+
+    /// Latest fired window per job
+    pub fn load_execution_windows(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        let mut stmt = self.conn.prepare("SELECT job_id, scheduled_time FROM execution_windows WHERE fired_window = 1")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut windows: HashMap<String, DateTime<Utc>> = HashMap::new();
+        for row in rows {
+            let (job_id, time) = row?;
+            if let Ok(time) = DateTime::parse_from_rfc3339(&time) {
+                let time = time.with_timezone(&Utc);
+                let latest = windows.entry(job_id).or_insert(time);
+                if time > *latest {
+                    *latest = time;
+                }
+            }
+        }
+        Ok(windows)
+    }
+
+    /// Recorded executions, newest first
+    pub fn get_execution_windows(&self, job_id: Option<&str>, limit: usize) -> Result<Vec<common::ExecutionWindow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, execution_id, scheduled_time, actual_start_time, finished_at, pid, fired_window FROM execution_windows
+             WHERE (?1 IS NULL OR job_id = ?1)
+             ORDER BY actual_start_time DESC, id DESC LIMIT ?2"
+        )?;
+        let rows = stmt.query_map(params![job_id, limit as i64], |row| {
+            Ok(common::ExecutionWindow {
+                job_id: row.get(0)?,
+                execution_id: row.get(1)?,
+                scheduled_time: row.get(2)?,
+                started_at: row.get(3)?,
+                finished_at: row.get(4)?,
+                pid: row.get(5)?,
+                fired_window: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Drop finished execution records older than `before`; only the latest window per job
+    /// matters after a restart
+    pub fn prune_execution_windows(&self, before: &DateTime<Utc>) -> Result<usize> {
+        let deleted = self.conn.execute(
+            "DELETE FROM execution_windows WHERE actual_start_time < ?1 AND finished_at IS NOT NULL",
+            params![before.to_rfc3339()],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Start time and duration of every execution started since `since`
+    pub fn execution_starts(&self, since: &DateTime<Utc>) -> Result<Vec<ExecutionStart>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, started_at, duration_ms FROM history WHERE started_at IS NOT NULL AND started_at >= ?1",
+        )?;
+        let rows = stmt.query_map(params![format_timestamp(since)], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?))
+        })?;
+        let mut starts = Vec::new();
+        for row in rows {
+            let (job_id, started_at, duration_ms) = row?;
+            if let Ok(started_at) = chrono::NaiveDateTime::parse_from_str(&started_at, "%Y-%m-%d %H:%M:%S") {
+                starts.push((job_id, started_at.and_utc(), duration_ms));
+            }
+        }
+        Ok(starts)
+    }
+
+    pub fn save_template(&self, template: &common::JobTemplate) -> Result<()> {
+        let definition = serde_json::to_string(template).unwrap();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO templates (name, owner, definition) VALUES (?1, ?2, ?3)",
+            params![template.name, template.owner, definition],
+        )?;
+        Ok(())
+    }
+
+    /// Templates of `owner`, or of everyone
+    pub fn load_templates(&self, owner: Option<&str>) -> Result<Vec<common::JobTemplate>> {
+        let mut stmt = self.conn.prepare("SELECT definition FROM templates WHERE ?1 IS NULL OR owner = ?1 ORDER BY name, owner")?;
+        let rows = stmt.query_map(params![owner], |row| row.get::<_, String>(0))?;
+        let mut templates = Vec::new();
+        for definition in rows {
+            // Definitions that no longer parse are skipped
+            if let Ok(template) = serde_json::from_str(&definition?) {
+                templates.push(template);
+            }
+        }
+        Ok(templates)
+    }
+
+    pub fn remove_template(&self, owner: &str, name: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM templates WHERE owner = ?1 AND name = ?2", params![owner, name])?;
+        Ok(())
+    }
+
+    pub fn save_workflow(&self, workflow: &common::Workflow) -> Result<()> {
+        let definition = serde_json::to_string(workflow).unwrap();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO workflows (name, owner, definition) VALUES (?1, ?2, ?3)",
+            params![workflow.name, workflow.owner, definition],
+        )?;
+        Ok(())
+    }
+
+    pub fn load_workflows(&self) -> Result<Vec<common::Workflow>> {
+        let mut stmt = self.conn.prepare("SELECT definition FROM workflows ORDER BY name")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut workflows = Vec::new();
+        for definition in rows {
+            // Definitions that no longer parse are skipped
+            if let Ok(workflow) = serde_json::from_str(&definition?) {
+                workflows.push(workflow);
+            }
+        }
+        Ok(workflows)
+    }
+
+    /// Removes the definition; past runs stay in the database
+    pub fn remove_workflow(&self, name: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM workflows WHERE name = ?1", params![name])?;
+        Ok(())
+    }
+
+    pub fn save_workflow_run(&self, run: &common::WorkflowRun) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO workflow_runs (run_id, workflow, status, started_at, finished_at, run)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                run.run_id, run.workflow, run.status(), run.started_at, run.finished_at,
+                serde_json::to_string(run).unwrap()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Latest runs of a workflow, newest first
+    pub fn workflow_runs(&self, workflow: &str, limit: usize) -> Result<Vec<common::WorkflowRun>> {
+        self.query_workflow_runs(
+            &format!("SELECT run FROM workflow_runs WHERE workflow = ?1 ORDER BY started_at DESC LIMIT {}", limit),
+            params![workflow],
+        )
+    }
+
+    /// Runs the daemon stopped in the middle of
+    pub fn unfinished_workflow_runs(&self) -> Result<Vec<common::WorkflowRun>> {
+        self.query_workflow_runs("SELECT run FROM workflow_runs WHERE finished_at IS NULL ORDER BY started_at", [])
+    }
+
+    fn query_workflow_runs(&self, query: &str, query_params: impl rusqlite::Params) -> Result<Vec<common::WorkflowRun>> {
+        let mut stmt = self.conn.prepare(query)?;
+        let rows = stmt.query_map(query_params, |row| row.get::<_, String>(0))?;
+        let mut runs = Vec::new();
+        for run in rows {
+            if let Ok(run) = serde_json::from_str(&run?) {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+
+    pub fn save_dead_letter(&self, dead_letter: &common::DeadLetter) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO dead_letters (job_id, job_name, failed_at, error, attempts, exhaustions, disabled)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![dead_letter.job_id, dead_letter.job_name, dead_letter.failed_at, dead_letter.error,
+                dead_letter.attempts, dead_letter.exhaustions, dead_letter.disabled],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_dead_letter(&self, job_id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM dead_letters WHERE job_id = ?1", params![job_id])?;
+        Ok(())
+    }
+
+    pub fn load_dead_letters(&self) -> Result<HashMap<String, common::DeadLetter>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, job_name, failed_at, error, attempts, exhaustions, disabled FROM dead_letters"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(common::DeadLetter {
+                job_id: row.get(0)?,
+                job_name: row.get(1)?,
+                failed_at: row.get(2)?,
+                error: row.get(3)?,
+                attempts: row.get(4)?,
+                exhaustions: row.get(5)?,
+                disabled: row.get(6)?,
             })
         })?;
+        let mut dead_letters = HashMap::new();
+        for dead_letter in rows {
+            let dead_letter = dead_letter?;
+            dead_letters.insert(dead_letter.job_id.clone(), dead_letter);
+        }
+        Ok(dead_letters)
+    }
+
+    pub fn record_audit(&self, entry: &common::AuditEntry) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO audit_log (at, uid, user, action, job_id, owner, summary, outcome) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![entry.at, entry.uid, entry.user, entry.action, entry.job_id, entry.owner, entry.summary, entry.outcome],
+        )?;
+        Ok(())
+    }
+
+    /// Audit entries, newest first, optionally only those about one job, about the jobs of one
+    /// owner or since a time
+    pub fn get_audit_log(&self, job_id: Option<&str>, owner: Option<&str>, since: Option<&DateTime<Utc>>, limit: usize) -> Result<Vec<common::AuditEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, uid, user, action, job_id, owner, summary, outcome FROM audit_log
+             WHERE (?1 IS NULL OR job_id = ?1) AND (?2 IS NULL OR owner = ?2) AND (?3 IS NULL OR at >= ?3)
+             ORDER BY id DESC LIMIT ?4"
+        )?;
+        let since = since.map(format_timestamp);
+        let rows = stmt.query_map(params![job_id, owner, since, limit as i64], |row| {
+            Ok(common::AuditEntry {
+                at: row.get(0)?,
+                uid: row.get(1)?,
+                user: row.get(2)?,
+                action: row.get(3)?,
+                job_id: row.get(4)?,
+                owner: row.get(5)?,
+                summary: row.get(6)?,
+                outcome: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Remove audit entries older than `before`, returning how many were removed
+    pub fn prune_audit_log(&self, before: &DateTime<Utc>) -> Result<usize> {
+        self.conn.execute("DELETE FROM audit_log WHERE at < ?1", params![format_timestamp(before)])
+    }
+
+    pub fn prune_snapshots(&self, before: &DateTime<Utc>) -> Result<usize> {
+        self.conn.execute("DELETE FROM execution_snapshots WHERE captured_at < ?1", params![format_timestamp(before)])
+    }
+
+    /// Start tracking a notification delivery, returning its log ID
+    pub fn log_notification(&self, job_id: &str, execution_id: &str, event: &str, channel: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO notification_log (job_id, execution_id, event_type, channel_type, delivered_at, status, created_at)
+             VALUES (?1, ?2, ?3, ?4, NULL, 'pending', ?5)",
+            params![job_id, execution_id, event, channel, format_timestamp(&Utc::now())],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Record a delivery attempt: status is "delivered", "retrying" or "failed"
+    pub fn update_notification(&self, id: i64, status: &str, attempts: u32, error: Option<&str>) -> Result<()> {
+        let delivered_at = (status == "delivered").then(|| format_timestamp(&Utc::now()));
+        self.conn.execute(
+            "UPDATE notification_log SET status = ?2, attempts = ?3, error = ?4, delivered_at = ?5 WHERE id = ?1",
+            params![id, status, attempts, error, delivered_at],
+        )?;
+        Ok(())
+    }
+
+    /// Mark deliveries a previous daemon was still working on as failed; returns how many
+    pub fn abandon_pending_notifications(&self) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE notification_log SET status = 'failed',
+                 error = COALESCE(error || '; ', '') || 'daemon stopped before delivery'
+             WHERE status IN ('pending', 'retrying')",
+            [],
+        )
+    }
+
+    /// Deliveries that failed for good since `since`, newest first
+    pub fn failed_notifications(&self, since: &DateTime<Utc>, limit: usize) -> Result<Vec<common::NotificationFailure>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, event_type, channel_type, created_at, attempts, error FROM notification_log
+             WHERE status = 'failed' AND created_at >= ?1 ORDER BY id DESC LIMIT ?2"
+        )?;
+        let rows = stmt.query_map(params![format_timestamp(since), limit as i64], |row| {
+            Ok(common::NotificationFailure {
+                job_id: row.get(0)?,
+                event: row.get(1)?,
+                channel: row.get(2)?,
+                created_at: row.get(3)?,
+                attempts: row.get(4)?,
+                error: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn set_secret(&self, owner: &str, name: &str, nonce: &[u8], ciphertext: &[u8]) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO secrets (owner, name, nonce, ciphertext, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![owner, name, nonce, ciphertext, format_timestamp(&Utc::now())],
+        )?;
+        Ok(())
+    }
+
+    /// Nonce and ciphertext of a secret
+    pub fn get_secret(&self, owner: &str, name: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let mut stmt = self.conn.prepare("SELECT nonce, ciphertext FROM secrets WHERE owner = ?1 AND name = ?2")?;
+        let mut rows = stmt.query_map(params![owner, name], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.next().transpose()
+    }
+
+    /// Returns whether the secret existed
+    pub fn remove_secret(&self, owner: &str, name: &str) -> Result<bool> {
+        let deleted = self.conn.execute("DELETE FROM secrets WHERE owner = ?1 AND name = ?2", params![owner, name])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn list_secrets(&self, owner: &str) -> Result<Vec<common::SecretInfo>> {
+        let mut stmt = self.conn.prepare("SELECT name, updated_at FROM secrets WHERE owner = ?1 ORDER BY name")?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(common::SecretInfo { name: row.get(0)?, updated_at: row.get(1)? })
+        })?;
+        rows.collect()
+    }
+
+    pub fn log_timeline(&self, job_id: &str, execution_id: &str, events: &[common::TimelineEvent]) -> Result<()> {
+        for event in events {
+            self.conn.execute(
+                "INSERT INTO execution_events (execution_id, job_id, event, at, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![execution_id, job_id, event.event, event.at, event.detail],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_timeline(&self, execution_id: &str) -> Result<Vec<common::TimelineEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT event, at, detail FROM execution_events WHERE execution_id = ?1 ORDER BY at, id",
+        )?;
+        let rows = stmt.query_map(params![execution_id], |row| {
+            Ok(common::TimelineEvent { event: row.get(0)?, at: row.get(1)?, detail: row.get(2)? })
+        })?;
+        rows.collect()
+    }
+
+    /// Remember the latest scheduled window fired for a job, for catch-up after downtime
+    pub fn save_last_scheduled(&self, job_id: &str, scheduled_at: &DateTime<Utc>) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO schedule_state (job_id, last_scheduled_at) VALUES (?1, ?2)",
+            params![job_id, scheduled_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn load_last_scheduled(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        let mut stmt = self.conn.prepare("SELECT job_id, last_scheduled_at FROM schedule_state")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
 
-        let mut history = Vec::new();
-        for entry in history_iter {
-            history.push(entry?);
+        let mut last_scheduled = HashMap::new();
+        for row in rows {
+            let (job_id, time) = row?;
+            if let Ok(time) = DateTime::parse_from_rfc3339(&time) {
+                last_scheduled.insert(job_id, time.with_timezone(&Utc));
+            }
         }
-        Ok(history)
+        Ok(last_scheduled)
     }
 
     pub fn log_retry_attempt(&self, job_id: &str, attempt: u32, next_retry: Option<&str>, error: &str) -> Result<()> {
@@ -0,0 +1,125 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use chrono::{DateTime, Utc};
+
+/// When each time-scheduled job is next due, earliest first. Rescheduling a job leaves its
+/// old entry in the heap; entries that no longer match `due` are dropped as they surface.
+#[derive(Debug, Default)]
+pub struct DueQueue {
+    heap: BinaryHeap<Reverse<(DateTime<Utc>, String)>>,
+    due: HashMap<String, DateTime<Utc>>,
+}
+
+impl DueQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set when the job is next due, replacing any earlier time; None takes it off the queue
+    pub fn set(&mut self, job_id: &str, at: Option<DateTime<Utc>>) {
+        match at {
+            Some(at) => {
+                if self.due.insert(job_id.to_string(), at) != Some(at) {
+                    self.heap.push(Reverse((at, job_id.to_string())));
+                }
+            }
+            None => {
+                self.due.remove(job_id);
+            }
+        }
+        // Don't let stale entries pile up when jobs are rescheduled over and over
+        if self.heap.len() > 2 * self.due.len() + 64 {
+            self.heap = self.due.iter().map(|(id, at)| Reverse((*at, id.clone()))).collect();
+        }
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<DateTime<Utc>> {
+        self.due.get(job_id).copied()
+    }
+
+    /// Earliest due time
+    pub fn peek(&mut self) -> Option<DateTime<Utc>> {
+        self.drop_stale();
+        self.heap.peek().map(|Reverse((at, _))| *at)
+    }
+
+    /// Take the jobs due by `now` off the queue, earliest first, with their due times
+    pub fn pop_due(&mut self, now: DateTime<Utc>) -> Vec<(String, DateTime<Utc>)> {
+        let mut due = Vec::new();
+        while self.peek().is_some_and(|at| at <= now) {
+            if let Some(Reverse((at, job_id))) = self.heap.pop() {
+                self.due.remove(&job_id);
+                due.push((job_id, at));
+            }
+        }
+        due
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.due.clear();
+    }
+
+    fn drop_stale(&mut self) {
+        while let Some(Reverse((at, job_id))) = self.heap.peek() {
+            if self.due.get(job_id) == Some(at) {
+                break;
+            }
+            self.heap.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn pops_earliest_first() {
+        let mut queue = DueQueue::new();
+        queue.set("c", Some(at(30)));
+        queue.set("a", Some(at(10)));
+        queue.set("b", Some(at(20)));
+        assert_eq!(queue.peek(), Some(at(10)));
+        assert_eq!(queue.pop_due(at(20)), vec![("a".to_string(), at(10)), ("b".to_string(), at(20))]);
+        assert_eq!(queue.get("a"), None);
+        assert_eq!(queue.peek(), Some(at(30)));
+        assert!(queue.pop_due(at(29)).is_empty());
+    }
+
+    #[test]
+    fn rescheduling_replaces_the_due_time() {
+        let mut queue = DueQueue::new();
+        queue.set("a", Some(at(10)));
+        queue.set("b", Some(at(20)));
+        queue.set("a", Some(at(40)));
+        assert_eq!(queue.get("a"), Some(at(40)));
+        // The stale entry for 12:10 is skipped
+        assert_eq!(queue.pop_due(at(30)), vec![("b".to_string(), at(20))]);
+        assert_eq!(queue.pop_due(at(40)), vec![("a".to_string(), at(40))]);
+    }
+
+    #[test]
+    fn unset_jobs_are_not_due() {
+        let mut queue = DueQueue::new();
+        queue.set("a", Some(at(10)));
+        queue.set("a", None);
+        assert_eq!(queue.peek(), None);
+        assert!(queue.pop_due(at(59)).is_empty());
+    }
+
+    #[test]
+    fn stale_entries_are_compacted() {
+        let mut queue = DueQueue::new();
+        for minute in 0..200 {
+            queue.set("a", Some(at(minute % 60)));
+        }
+        assert!(queue.heap.len() <= 2 * queue.due.len() + 64);
+        assert_eq!(queue.pop_due(at(59)), vec![("a".to_string(), at(199 % 60))]);
+    }
+}
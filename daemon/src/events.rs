@@ -0,0 +1,93 @@
+use chrono::{SecondsFormat, Utc};
+use common::{EventFilter, Job, JobEvent, JobEventKind, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+use crate::transport::Connection;
+
+// Events buffered per subscriber; a subscriber further behind skips the oldest
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+struct Published {
+    owner: String, // Only this owner and root see the event
+    event: JobEvent,
+}
+
+/// Fans scheduler events out to `Request::Subscribe` connections. Publishing with no
+/// subscribers is a no-op.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<Published>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, job: &Job, kind: JobEventKind, execution_id: Option<&str>, detail: Option<String>) {
+        let event = JobEvent {
+            kind,
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            job_id: job.id.0.clone(),
+            job_name: job.name.clone(),
+            execution_id: execution_id.map(str::to_string),
+            detail,
+        };
+        let _ = self.sender.send(Published { owner: job.owner.clone(), event });
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Published> {
+        self.sender.subscribe()
+    }
+}
+
+/// Serve `Request::Subscribe`: write matching events as newline-delimited `Response::Event`s
+/// until the client disconnects
+pub async fn stream(socket: &mut Box<dyn Connection>, bus: &EventBus, filters: EventFilter, requester_owner: &str) {
+    let mut receiver = bus.subscribe();
+    if write_line(socket, &Response::Ok).await.is_err() {
+        return;
+    }
+
+    // Subscribers send nothing more; reading only notices when they hang up
+    let mut probe = [0u8; 64];
+    loop {
+        let resp = tokio::select! {
+            received = receiver.recv() => match received {
+                Ok(published) => {
+                    if published.owner != requester_owner && requester_owner != "root" {
+                        continue;
+                    }
+                    if !filters.matches(&published.event) {
+                        continue;
+                    }
+                    Response::Event(published.event)
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Event subscriber fell behind; skipped {} events", skipped);
+                    Response::EventsLost(skipped)
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            read = socket.read(&mut probe) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(_) => continue,
+            },
+        };
+        if write_line(socket, &resp).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn write_line(socket: &mut Box<dyn Connection>, resp: &Response) -> std::io::Result<()> {
+    socket.write_all(&common::encode_frame(resp)?).await
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use common::{HealthCheck, HealthReport};
+use nix::unistd::{Uid, User};
+use crate::account::Account;
+use crate::db;
+use crate::migrations::SCHEMA_VERSION;
+use crate::profiler::SchedulerProfiler;
+use crate::scheduler::Scheduler;
+
+// How long `timedatectl` may take to answer
+const TIMEDATECTL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Integrity problems listed in a report; SQLite can find thousands
+const MAX_DB_PROBLEMS: usize = 5;
+
+/// Run the daemon's side of `lunasched doctor`: the database (integrity and schema version),
+/// the clock, switching to the jobs' users and the log directories
+pub async fn check(scheduler: &Arc<RwLock<Scheduler>>, profiler: &SchedulerProfiler) -> HealthReport {
+    let (db, db_path, log_dirs, run_as) = {
+        let sched = profiler.read(scheduler, "ipc");
+        let log_dirs = [&sched.paths.log_file, &sched.paths.jobs_log_file]
+            .iter()
+            .filter_map(|file| Path::new(file).parent().map(|dir| dir.display().to_string()))
+            .chain(std::iter::once(sched.paths.execution_log_dir.clone()))
+            .collect::<BTreeSet<String>>();
+        let run_as = sched.jobs.values()
+            .filter(|job| job.target.is_none())
+            .map(|job| job.run_as_user().to_string())
+            .collect::<BTreeSet<String>>();
+        (sched.db.clone(), sched.paths.db.clone(), log_dirs, run_as)
+    };
+
+    let mut checks = Vec::new();
+    match db {
+        Some(db) => {
+            let (integrity, version) = db::query(&db, |db| (db.integrity_check(), db.schema_version())).await;
+            checks.push(integrity_check(&db_path, integrity));
+            checks.push(schema_check(version));
+        }
+        None => checks.push(HealthCheck::failed("database",
+            format!("{} is not attached; history is buffered in memory", db_path),
+            format!("Check the daemon log for why it could not be opened, and that {} and its directory are writable by the daemon", db_path))),
+    }
+    checks.push(clock_check().await);
+    checks.push(user_check(&run_as));
+    for dir in &log_dirs {
+        checks.push(log_dir_check(dir));
+    }
+
+    HealthReport { checks, time: chrono::Utc::now().to_rfc3339() }
+}
+
+fn integrity_check(db_path: &str, result: rusqlite::Result<Vec<String>>) -> HealthCheck {
+    match result {
+        Ok(problems) if problems.is_empty() => HealthCheck::ok("database", format!("{} passed the integrity check", db_path)),
+        Ok(problems) => {
+            let mut detail = problems.iter().take(MAX_DB_PROBLEMS).cloned().collect::<Vec<_>>().join("; ");
+            if problems.len() > MAX_DB_PROBLEMS {
+                detail.push_str(&format!(" (and {} more)", problems.len() - MAX_DB_PROBLEMS));
+            }
+            HealthCheck::failed("database", format!("{} is corrupt: {}", db_path, detail),
+                format!("Stop the daemon, keep a copy of {0}, then rebuild it: sqlite3 {0} .recover | sqlite3 {0}.recovered \
+                         and move {0}.recovered into place", db_path))
+        }
+        Err(e) => HealthCheck::failed("database", format!("Integrity check of {} failed: {}", db_path, e),
+            format!("Check that {} is readable by the daemon and not locked by another process", db_path)),
+    }
+}
+
+fn schema_check(version: rusqlite::Result<i32>) -> HealthCheck {
+    match version {
+        Ok(version) if version == SCHEMA_VERSION => HealthCheck::ok("schema", format!("Version {}", version)),
+        Ok(version) if version > SCHEMA_VERSION => HealthCheck::failed("schema",
+            format!("Version {} is newer than this daemon's ({})", version, SCHEMA_VERSION),
+            "The database was written by a newer lunasched; upgrade the daemon".to_string()),
+        Ok(version) => HealthCheck::failed("schema",
+            format!("Version {} is older than this daemon's ({}); migrations did not complete", version, SCHEMA_VERSION),
+            "Restart the daemon and look for migration errors in its log".to_string()),
+        Err(e) => HealthCheck::failed("schema", format!("Could not read the schema version: {}", e),
+            "Restart the daemon so it runs its migrations".to_string()),
+    }
+}
+
+// Whether the system clock is kept in sync, as systemd sees it (covers timesyncd, chrony and ntpd)
+async fn clock_check() -> HealthCheck {
+    let output = tokio::process::Command::new("timedatectl")
+        .args(["show", "--property=NTPSynchronized", "--value"])
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(TIMEDATECTL_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => match String::from_utf8_lossy(&output.stdout).trim() {
+            "yes" => HealthCheck::ok("clock", "Synchronized with NTP".to_string()),
+            _ => HealthCheck::warning("clock", "Not synchronized with NTP; schedules follow the clock as it drifts".to_string(),
+                "Enable time synchronization: sudo timedatectl set-ntp true".to_string()),
+        },
+        Ok(Ok(output)) => HealthCheck::warning("clock",
+            format!("timedatectl failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+            "Check that the host's clock is synchronized (chronyc tracking, ntpq -p)".to_string()),
+        Ok(Err(e)) => HealthCheck::warning("clock", format!("Could not run timedatectl: {}", e),
+            "Check that the host's clock is synchronized (chronyc tracking, ntpq -p)".to_string()),
+        Err(_) => HealthCheck::warning("clock", "timedatectl did not answer".to_string(),
+            "Check that the host's clock is synchronized (chronyc tracking, ntpq -p)".to_string()),
+    }
+}
+
+// The daemon runs jobs as their users itself (no sudo); that needs root, and the users must exist
+fn user_check(run_as: &BTreeSet<String>) -> HealthCheck {
+    let uid = Uid::effective();
+    let daemon_user = User::from_uid(uid).ok().flatten().map(|user| user.name).unwrap_or_else(|| uid.to_string());
+    let unknown: Vec<&str> = run_as.iter().filter(|user| Account::lookup(user).is_err()).map(String::as_str).collect();
+    if !unknown.is_empty() {
+        return HealthCheck::failed("users", format!("Jobs run as unknown users: {}", unknown.join(", ")),
+            "Create the accounts, or change the jobs' --user".to_string());
+    }
+    if uid.is_root() {
+        return HealthCheck::ok("users", "Running as root; jobs can run as any user".to_string());
+    }
+    let others: Vec<&str> = run_as.iter().filter(|user| **user != daemon_user).map(String::as_str).collect();
+    if others.is_empty() {
+        HealthCheck::ok("users", format!("Running as {}, as are all jobs", daemon_user))
+    } else {
+        HealthCheck::failed("users", format!("Running as {}, which can't run jobs as {}", daemon_user, others.join(", ")),
+            "Run the system daemon as root (sudo systemctl start lunasched), or add those jobs to their users' own daemons".to_string())
+    }
+}
+
+// Writable for the daemon, found by writing a file. A missing directory is created, as it
+// would be for the first execution log.
+fn log_dir_check(dir: &str) -> HealthCheck {
+    let name = format!("log dir {}", dir);
+    let probe = Path::new(dir).join(format!(".lunasched-doctor-{}", std::process::id()));
+    match std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&probe, b"")) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            HealthCheck::ok(&name, "Writable".to_string())
+        }
+        Err(e) => HealthCheck::failed(&name, format!("Not writable by the daemon: {}", e),
+            format!("Create it and give it to the daemon's user: sudo install -d -m 0755 {}", dir)),
+    }
+}
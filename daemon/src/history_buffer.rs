@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use crate::db::{Db, ExecutionRecord};
+use crate::storage::Replica;
+use common::TimelineEvent;
+
+// Records held while the database is unavailable; beyond this the oldest are dropped
+const MAX_BUFFERED: usize = 10_000;
+
+// Records written per transaction
+const MAX_BATCH: usize = 500;
+
+// How long the writer waits before trying again after a failed batch
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A history write waiting for the database
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum PendingWrite {
+    Execution(ExecutionRecord),
+    Event { job_id: String, status: String, message: String }, // Heartbeat missed/recovered
+    RetryAttempt { job_id: String, attempt: u32, next_retry: Option<String>, error: String },
+    Timeline { job_id: String, execution_id: String, events: Vec<TimelineEvent> },
+}
+
+impl PendingWrite {
+    fn apply(&self, db: &Db) -> rusqlite::Result<()> {
+        match self {
+            PendingWrite::Execution(record) => db.log_execution(record),
+            PendingWrite::Event { job_id, status, message } => db.log_history(job_id, status, message),
+            PendingWrite::RetryAttempt { job_id, attempt, next_retry, error } => {
+                db.log_retry_attempt(job_id, *attempt, next_retry.as_deref(), error)
+            }
+            PendingWrite::Timeline { job_id, execution_id, events } => db.log_timeline(job_id, execution_id, events),
+        }
+    }
+}
+
+/// Queue of history writes, applied by a writer thread of its own in batches of up to
+/// MAX_BATCH per transaction, so neither the scheduler tick nor the async runtime waits on the
+/// disk. While the database is unavailable records are held, in order, until it is back.
+/// Every outage and every dropped record is logged at error level.
+///
+/// No lock is taken while another is held; the database is locked by the writer alone, with
+/// the queue unlocked.
+#[derive(Default)]
+pub struct HistoryBuffer {
+    pending: Mutex<Queue>,
+    work: Condvar,    // Writes queued or the database attached
+    settled: Condvar, // A batch was written or failed
+    attempted: Notify, // Same, for async waiters
+    dropped: AtomicU64,
+    alerted: AtomicBool, // Outage already reported
+    replica: Mutex<Option<Replica>>, // Also given every record, as it is queued
+}
+
+#[derive(Default)]
+struct Queue {
+    writes: VecDeque<(u64, PendingWrite)>,
+    next_seq: u64,
+    attempted: u64, // Writes up to this sequence number were written or failed to be
+    db: Option<Arc<Mutex<Db>>>, // Where they go
+}
+
+impl HistoryBuffer {
+    /// Create the buffer and start its writer thread
+    pub fn start(db: Option<Arc<Mutex<Db>>>) -> Arc<Self> {
+        let buffer = Arc::new(Self::default());
+        buffer.pending.lock().unwrap().db = db;
+        let writer = buffer.clone();
+        std::thread::Builder::new()
+            .name("history-writer".to_string())
+            .spawn(move || writer.run())
+            .expect("failed to start the history writer thread");
+        buffer
+    }
+
+    /// Write to this database from now on, starting with anything buffered
+    pub fn attach(&self, db: Arc<Mutex<Db>>) {
+        self.pending.lock().unwrap().db = Some(db);
+        self.retry();
+    }
+
+    /// Hand every record queued from now on to `replica` as well
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub fn replicate_to(&self, replica: Replica) {
+        *self.replica.lock().unwrap() = Some(replica);
+    }
+
+    /// Try buffered records again
+    pub fn retry(&self) {
+        self.work.notify_one();
+    }
+
+    /// Queue a record; it is written in the background
+    pub fn write(&self, write: PendingWrite) {
+        self.enqueue(write);
+    }
+
+    /// Queue a record and wait until the writer has tried it, for readers that need to see it
+    /// (notifications look up the execution they are about). Returns at once without a database.
+    pub async fn persist(&self, write: PendingWrite) {
+        let seq = self.enqueue(write);
+        loop {
+            let attempted = self.attempted.notified();
+            let done = {
+                let queue = self.pending.lock().unwrap();
+                queue.db.is_none() || queue.attempted >= seq
+            };
+            if done {
+                return;
+            }
+            attempted.await;
+        }
+    }
+
+    /// Wait up to `timeout` for the queue to be written, at shutdown. Returns whether it was.
+    pub fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.pending.lock().unwrap();
+        let last = queue.next_seq;
+        while queue.attempted < last && !queue.writes.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            queue = self.settled.wait_timeout(queue, left).unwrap().0;
+        }
+        queue.writes.is_empty()
+    }
+
+    fn enqueue(&self, write: PendingWrite) -> u64 {
+        if let Some(ref replica) = *self.replica.lock().unwrap() {
+            replica.recorded(&write);
+        }
+        let mut queue = self.pending.lock().unwrap();
+        if queue.writes.len() >= MAX_BUFFERED {
+            queue.writes.pop_front();
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            log::error!("HISTORY LOST: buffer full, dropped the oldest record ({} dropped so far)", dropped);
+        }
+        queue.next_seq += 1;
+        let seq = queue.next_seq;
+        queue.writes.push_back((seq, write));
+        let detached = queue.db.is_none();
+        drop(queue);
+        if detached {
+            self.alert("no database attached");
+        }
+        self.work.notify_one();
+        seq
+    }
+
+    fn run(&self) {
+        loop {
+            // Wait for writes, and for a database to write them to
+            let (batch, db): (Vec<(u64, PendingWrite)>, Arc<Mutex<Db>>) = {
+                let mut queue = self.pending.lock().unwrap();
+                loop {
+                    match queue.db {
+                        Some(ref db) if !queue.writes.is_empty() => break (queue.writes.iter().take(MAX_BATCH).cloned().collect(), db.clone()),
+                        _ => queue = self.work.wait(queue).unwrap(),
+                    }
+                }
+            };
+            let last = batch.last().map_or(0, |(seq, _)| *seq);
+            let result = db.lock().unwrap().transaction(|db| batch.iter().try_for_each(|(_, write)| write.apply(db)));
+
+            let mut queue = self.pending.lock().unwrap();
+            queue.attempted = queue.attempted.max(last);
+            match result {
+                Ok(()) => {
+                    // Drop what was written; records dropped for space meanwhile are already gone
+                    while queue.writes.front().is_some_and(|(seq, _)| *seq <= last) {
+                        queue.writes.pop_front();
+                    }
+                    if self.alerted.swap(false, Ordering::Relaxed) {
+                        log::info!("History is written to the database again ({} records waiting)", queue.writes.len());
+                    }
+                }
+                Err(e) => {
+                    drop(queue);
+                    self.alert(&e.to_string());
+                    self.settled.notify_all();
+                    self.attempted.notify_waiters();
+                    // Retried on the next write or persistence check, or after a while
+                    let queue = self.pending.lock().unwrap();
+                    let _ = self.work.wait_timeout(queue, RETRY_INTERVAL).unwrap();
+                    continue;
+                }
+            }
+            drop(queue);
+            self.settled.notify_all();
+            self.attempted.notify_waiters();
+        }
+    }
+
+    fn alert(&self, reason: &str) {
+        if !self.alerted.swap(true, Ordering::Relaxed) {
+            log::error!("HISTORY NOT PERSISTED: {}. Buffering up to {} records in memory; \
+                they are lost if the daemon stops before the database is back", reason, MAX_BUFFERED);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().writes.is_empty()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Prometheus text for persistence health
+    pub fn export(&self, db_attached: bool) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP lunasched_db_attached Whether the history database is attached");
+        let _ = writeln!(out, "# TYPE lunasched_db_attached gauge");
+        let _ = writeln!(out, "lunasched_db_attached {}", db_attached as u8);
+        let _ = writeln!(out, "# HELP lunasched_history_buffered History records waiting for the database");
+        let _ = writeln!(out, "# TYPE lunasched_history_buffered gauge");
+        let _ = writeln!(out, "lunasched_history_buffered {}", self.len());
+        let _ = writeln!(out, "# HELP lunasched_history_dropped_total History records lost because the buffer was full");
+        let _ = writeln!(out, "# TYPE lunasched_history_dropped_total counter");
+        let _ = writeln!(out, "lunasched_history_dropped_total {}", self.dropped());
+        out
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use common::{redact, Job};
+use nix::sys::signal::Signal;
+use crate::account::Account;
+use crate::resource_manager;
+
+/// How long a hook may run when its job sets no `hooks.timeout_seconds`
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 60;
+
+// Kept of a hook's output (the end of it)
+const MAX_HOOK_OUTPUT: usize = 16 * 1024;
+
+/// How a hook went, for history
+#[derive(Debug, Clone)]
+pub struct HookOutcome {
+    pub result: String, // e.g. "on_success: exit code 0 in 35ms", "on_failure: timed out after 60s"
+    pub output: String, // Stdout, then stderr
+}
+
+/// Run a hook of `job` for `event` ("on_success", "on_failure" or "on_retry") and wait for it.
+/// The hook runs like the job: through /bin/sh as the job's user, in its working directory,
+/// with its environment (`env`, secrets resolved) and sandbox, and in a process group of its
+/// own, which is killed when the hook's timeout is up. Secret values are redacted from the output.
+pub async fn run(job: &Job, event: &str, command: &str, env: &HashMap<String, String>, secrets: &[String]) -> HookOutcome {
+    let not_run = |e: String| HookOutcome { result: format!("{}: not run: {}", event, e), output: String::new() };
+    let account = match Account::lookup(job.run_as_user()) {
+        Ok(account) => account,
+        Err(e) => return not_run(e.to_string()),
+    };
+    let timeout = job.hooks.timeout_seconds.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS);
+
+    let mut cmd = tokio::process::Command::new("/bin/sh");
+    cmd.arg("-c").arg(command);
+    cmd.env_clear();
+    cmd.envs(account.login_env());
+    cmd.envs(env);
+    cmd.env("LUNASCHED_HOOK", event);
+    cmd.current_dir(job.working_dir.clone().unwrap_or_else(|| PathBuf::from("/tmp")));
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    unsafe {
+        cmd.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(std::io::Error::from));
+    }
+    crate::sandbox::apply(&job.sandbox, &mut cmd);
+    account.switch_to(&mut cmd);
+
+    log::info!("Running {} hook of job {} as {}", event, job.name, job.run_as_user());
+    let started = Instant::now();
+    let child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => return not_run(e.to_string()),
+    };
+    let pgid = child.id().unwrap_or_default();
+    let outcome = match tokio::time::timeout(Duration::from_secs(timeout), child.wait_with_output()).await {
+        Ok(Ok(output)) => {
+            let status = match output.status.code() {
+                Some(code) => format!("exit code {}", code),
+                None => "killed by signal".to_string(),
+            };
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            HookOutcome {
+                result: format!("{}: {} in {}ms", event, status, started.elapsed().as_millis()),
+                output: tail(&redact::redact(&text, secrets)),
+            }
+        }
+        Ok(Err(e)) => HookOutcome { result: format!("{}: failed to wait: {}", event, e), output: String::new() },
+        Err(_) => {
+            resource_manager::signal_group(pgid, Signal::SIGKILL);
+            HookOutcome { result: format!("{}: timed out after {}s", event, timeout), output: String::new() }
+        }
+    };
+    log::info!("Hook of job {} finished: {}", job.name, outcome.result);
+    outcome
+}
+
+fn tail(text: &str) -> String {
+    if text.len() <= MAX_HOOK_OUTPUT {
+        return text.to_string();
+    }
+    let mut start = text.len() - MAX_HOOK_OUTPUT;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
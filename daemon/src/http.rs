@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use common::{HistoryFilter, JobId, Request, Response};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use nix::unistd::User;
+use tokio::net::{TcpListener, TcpStream};
+use crate::config::ServerConfig;
+use crate::scheduler::Scheduler;
+
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: body.into() }
+    }
+
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self { status, content_type: "application/json", body: value.to_string() }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.into() }))
+    }
+}
+
+/// Who JSON API clients are: the holders of the bearer token, acting as `server.api_user`
+pub struct ApiAccess {
+    token: String,
+    user: String,
+    uid: u32,
+}
+
+impl ApiAccess {
+    /// The API's access from the server config: None when the API is off (no `api_token`).
+    /// It needs an `api_user`, which may only be root with `api_allow_root`.
+    pub fn from_config(server: &ServerConfig) -> anyhow::Result<Option<Self>> {
+        let token = match server.api_token {
+            Some(ref token) if !token.is_empty() => token.clone(),
+            _ => return Ok(None),
+        };
+        let user = server.api_user.clone()
+            .ok_or_else(|| anyhow::anyhow!("server.api_user must be set to enable the JSON API"))?;
+        let uid = match User::from_name(&user)? {
+            Some(account) => account.uid.as_raw(),
+            None => return Err(anyhow::anyhow!("server.api_user {} is not a user on this host", user)),
+        };
+        if uid == 0 && !server.api_allow_root {
+            return Err(anyhow::anyhow!("server.api_user is root, which whoever holds the token would be; \
+                set api_allow_root to allow it"));
+        }
+        Ok(Some(Self { token, user, uid }))
+    }
+}
+
+/// Bind the optional HTTP listener and serve requests in the background
+pub async fn serve(bind: &str, scheduler: Arc<RwLock<Scheduler>>, api: Option<ApiAccess>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(bind).await?;
+    log::info!("HTTP listener bound to {}", bind);
+    if let Some(ref api) = api {
+        log::info!("JSON API enabled on {}, acting as {}", bind, api.user);
+    }
+    let api = api.map(Arc::new);
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, addr) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log::error!("HTTP accept error: {}", e);
+                    continue;
+                }
+            };
+
+            let scheduler = scheduler.clone();
+            let api = api.clone();
+            tokio::spawn(async move {
+                let read = tokio::time::timeout(std::time::Duration::from_secs(10), read_request(&mut socket)).await;
+                let response = match read {
+                    Ok(Ok(req)) => {
+                        log::debug!("HTTP {} {} from {}", req.method, req.path, addr);
+                        route(&scheduler, req, api.as_deref()).await
+                    }
+                    Ok(Err(e)) => HttpResponse::text(400, format!("Bad request: {}\n", e)),
+                    Err(_) => HttpResponse::text(408, "Request timeout\n"),
+                };
+                if let Err(e) = write_response(&mut socket, &response).await {
+                    log::debug!("Failed to write HTTP response to {}: {}", addr, e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn route(scheduler: &Arc<RwLock<Scheduler>>, req: HttpRequest, access: Option<&ApiAccess>) -> HttpResponse {
+    if req.path == "/jobs" || req.path.starts_with("/jobs/") {
+        return match access {
+            Some(access) => api(scheduler, req, access).await,
+            None => HttpResponse::text(404, "Not found\n"),
+        };
+    }
+    if let Some(id) = req.path.strip_prefix("/ping/") {
+        return ping(scheduler, &req, id);
+    }
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/metrics") => {
+            let (metrics, profiler, history, db_attached, queue_depth) = {
+                let sched = scheduler.read().unwrap();
+                (sched.metrics.clone(), sched.profiler.clone(), sched.history.clone(), sched.db.is_some(), sched.running_jobs.len())
+            };
+            HttpResponse {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                body: metrics.export(queue_depth) + &profiler.export() + &history.export(db_attached),
+            }
+        }
+        (_, "/metrics") => HttpResponse::text(405, "Method not allowed\n"),
+        _ => HttpResponse::text(404, "Not found\n"),
+    }
+}
+
+/// Ping a heartbeat job from anywhere the listener is reachable. The job's ping token
+/// (`lunasched ping JOB --show-token`) comes as a bearer token or `?token=`, and only
+/// authorizes pings of that job.
+fn ping(scheduler: &Arc<RwLock<Scheduler>>, req: &HttpRequest, id: &str) -> HttpResponse {
+    if req.method != "POST" {
+        return HttpResponse::text(405, "Method not allowed\n");
+    }
+    let presented = req.authorization.as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| query_param(&req.query, "token"))
+        .map(str::trim);
+    let mut sched = scheduler.write().unwrap();
+    let store = match sched.secrets {
+        Some(ref store) => store.clone(),
+        None => return HttpResponse::error(404, "Pings are unavailable: the daemon has no secrets key"),
+    };
+    if !presented.is_some_and(|token| store.check_ping_token(id, token)) {
+        log::warn!("Unauthorized ping of job {}", id);
+        return HttpResponse::error(401, "Missing or invalid ping token");
+    }
+    if let Some(ref leadership) = sched.leadership {
+        if !leadership.is_leader() {
+            return HttpResponse::error(503, leadership.standby_error());
+        }
+    }
+    match sched.record_heartbeat(id) {
+        Ok(()) => HttpResponse::json(200, &serde_json::json!({ "status": "ok" })),
+        Err(e) if e == "Job not found" => HttpResponse::error(404, e),
+        Err(e) => HttpResponse::error(400, e),
+    }
+}
+
+/// Serve the JSON API: translate the route into an IPC request and answer it through the
+/// same access control and dispatch as the daemon socket, as the API user
+async fn api(scheduler: &Arc<RwLock<Scheduler>>, req: HttpRequest, access: &ApiAccess) -> HttpResponse {
+    let presented = req.authorization.as_deref().and_then(|value| value.strip_prefix("Bearer "));
+    if !presented.is_some_and(|presented| crate::transport::token_matches(&access.token, presented.trim())) {
+        log::warn!("Unauthorized HTTP API request: {} {}", req.method, req.path);
+        return HttpResponse::error(401, "Missing or invalid bearer token");
+    }
+    let mut request = match api_request(&req) {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+
+    let profiler = scheduler.read().unwrap().profiler.clone();
+    let owner = crate::owner_for(access.uid);
+    let actor = crate::audit::Actor { uid: Some(access.uid), name: "api".to_string() };
+    crate::resolve_job_names(&mut request, scheduler, &profiler, owner);
+    let identity = (access.uid != 0).then(|| crate::acl::Identity::lookup(access.uid));
+    if let Some(ref who) = identity {
+        let denied = {
+            let sched = profiler.read(scheduler, "ipc");
+            crate::acl::authorize(&sched.acl, who, &request, &sched.jobs).err()
+        };
+        if let Some(reason) = denied {
+            log::warn!("{}", reason);
+            let action = crate::audit::describe(&request, &profiler.read(scheduler, "ipc").jobs);
+            if let Some(action) = action {
+                crate::audit::record(scheduler, &profiler, &actor, action, format!("denied: {}", reason));
+            }
+            return HttpResponse::error(403, reason);
+        }
+    }
+    crate::log_request(&request, scheduler, &profiler);
+    let action = crate::audit::describe(&request, &profiler.read(scheduler, "ipc").jobs);
+    let mut response = crate::dispatch(request, owner, scheduler, &profiler).await;
+    if let Some(action) = action {
+        crate::audit::record(scheduler, &profiler, &actor, action, crate::audit::outcome(&response));
+    }
+    if let Some(ref who) = identity {
+        response = crate::acl::filter_response(&profiler.read(scheduler, "ipc").acl, who, response);
+    }
+    match response {
+        Response::Ok => HttpResponse::json(200, &serde_json::json!({ "status": "ok" })),
+        Response::JobAdded(id) => HttpResponse::json(200, &serde_json::json!({ "status": "ok", "id": id.0 })),
+        Response::JobEnsured { id, outcome, hash } => {
+            HttpResponse::json(200, &serde_json::json!({ "status": outcome, "id": id.0, "hash": hash }))
+        }
+        Response::JobDetail(None) => HttpResponse::error(404, "Job not found"),
+        Response::Error(e) => {
+            let status = if e.starts_with("Permission denied") {
+                403
+            } else if e.to_lowercase().contains("not found") {
+                404
+            } else {
+                400
+            };
+            HttpResponse::error(status, e)
+        }
+        other => match serde_json::to_value(other) {
+            // Drop the variant name around the payload, as `lunasched --output json` does
+            Ok(serde_json::Value::Object(map)) if map.len() == 1 => {
+                HttpResponse::json(200, &map.into_iter().next().map(|(_, v)| v).unwrap_or_default())
+            }
+            Ok(value) => HttpResponse::json(200, &value),
+            Err(e) => HttpResponse::error(500, e.to_string()),
+        },
+    }
+}
+
+fn api_request(req: &HttpRequest) -> Result<Request, HttpResponse> {
+    let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+    match (req.method.as_str(), segments.as_slice()) {
+        ("GET", ["jobs"]) => Ok(Request::ListJobs),
+        ("POST", ["jobs"]) => serde_json::from_slice(&req.body)
+            .map(Request::AddJob)
+            .map_err(|e| HttpResponse::error(400, format!("Invalid job: {}", e))),
+        // Convergent: answers whether the job was created, updated or already as given
+        ("PUT", ["jobs"]) => serde_json::from_slice(&req.body)
+            .map(|job| Request::EnsureJob { job, if_absent: query_param(&req.query, "if_absent") == Some("true") })
+            .map_err(|e| HttpResponse::error(400, format!("Invalid job: {}", e))),
+        ("GET", ["jobs", id]) => Ok(Request::GetJob(JobId(id.to_string()))),
+        ("DELETE", ["jobs", id]) => Ok(Request::RemoveJob(JobId(id.to_string()))),
+        // An optional JSON object body carries run parameters
+        ("POST", ["jobs", id, "start"]) if req.body.iter().all(u8::is_ascii_whitespace) => {
+            Ok(Request::StartJob(JobId(id.to_string())))
+        }
+        ("POST", ["jobs", id, "start"]) => serde_json::from_slice::<HashMap<String, String>>(&req.body)
+            .map(|params| Request::StartJobWithParams { job_id: JobId(id.to_string()), params })
+            .map_err(|e| HttpResponse::error(400, format!("Invalid run parameters: {}", e))),
+        ("GET", ["jobs", id, "history"]) => {
+            let limit = match query_param(&req.query, "limit") {
+                Some(limit) => Some(limit.parse().map_err(|_| HttpResponse::error(400, "limit must be a number"))?),
+                None => None,
+            };
+            // Values aren't percent-decoded; give times in UTC (2026-01-31T00:00:00Z)
+            let filter = HistoryFilter {
+                status: query_param(&req.query, "status").map(str::to_string),
+                since: query_param(&req.query, "since").map(str::to_string),
+                grep: None,
+            };
+            Ok(Request::GetHistory { job_id: Some(JobId(id.to_string())), limit, filter })
+        }
+        (_, ["jobs"]) | (_, ["jobs", _]) | (_, ["jobs", _, "start"]) | (_, ["jobs", _, "history"]) => {
+            Err(HttpResponse::error(405, "Method not allowed"))
+        }
+        _ => Err(HttpResponse::error(404, "Not found")),
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| match pair.split_once('=') {
+        Some((key, value)) if key == name => Some(value),
+        _ => None,
+    })
+}
+
+async fn read_request(socket: &mut TcpStream) -> anyhow::Result<HttpRequest> {
+    let mut buf = Vec::new();
+    let mut temp_buf = [0u8; 4096];
+
+    let header_end = loop {
+        let n = socket.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("connection closed"));
+        }
+        buf.extend_from_slice(&temp_buf[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEADER_BYTES {
+            return Err(anyhow::anyhow!("headers too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().ok_or_else(|| anyhow::anyhow!("missing method"))?.to_string();
+    let target = request_line.next().ok_or_else(|| anyhow::anyhow!("missing path"))?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().map_err(|_| anyhow::anyhow!("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(anyhow::anyhow!("body too large"));
+    }
+
+    // Whatever followed the headers in the last read is the start of the body
+    let mut body = buf.split_off(header_end + 4);
+    while body.len() < content_length {
+        let n = socket.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("connection closed"));
+        }
+        body.extend_from_slice(&temp_buf[..n]);
+    }
+    body.truncate(content_length);
+
+    Ok(HttpRequest { method, path: path.to_string(), query: query.to_string(), authorization, body })
+}
+
+async fn write_response(socket: &mut TcpStream, response: &HttpResponse) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, reason, response.content_type, response.body.len()
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(response.body.as_bytes()).await?;
+    socket.shutdown().await
+}
@@ -0,0 +1,56 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+
+/// Exclusive lock on the pidfile, held for the life of the daemon so that a second daemon
+/// can't start against the same database and socket. The kernel drops the lock when the
+/// process exits, however it exits, so a crashed daemon never leaves a stale lock behind.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock and write our PID into the file. If another live daemon holds it, fail
+    /// with its PID, or with `force` warn and start without the lock.
+    pub fn acquire(path: &Path, dir_mode: u32, force: bool) -> anyhow::Result<Option<Self>> {
+        if let Some(parent) = path.parent() {
+            // Also the socket directory: reachable by all users, or private to a user instance
+            std::fs::DirBuilder::new().recursive(true).mode(dir_mode).create(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o644).open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open lock file {}: {}", path.display(), e))?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = match holder.trim() {
+                    "" => "unknown PID".to_string(),
+                    pid => format!("PID {}", pid),
+                };
+                if force {
+                    log::warn!("Another lunasched daemon ({}) holds {}; starting anyway because of --force",
+                        holder, path.display());
+                    return Ok(None);
+                }
+                return Err(anyhow::anyhow!(
+                    "Another lunasched daemon is already running ({}, lock held on {}). \
+                     Stop it first, or pass --force to start anyway.",
+                    holder, path.display()));
+            }
+            Err(e) => return Err(anyhow::anyhow!("Failed to lock {}: {}", path.display(), e)),
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Some(Self { _file: file }))
+    }
+}
@@ -0,0 +1,315 @@
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use common::{HaStatus, Request};
+use nix::fcntl::{flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use crate::config::OrphanPolicy;
+use crate::scheduler::Scheduler;
+
+/// The leadership lease as stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseRecord {
+    pub holder: String, // Node name
+    pub renewed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The lease after an attempt to take it, and the lease of another node it replaced, if any
+pub type Acquired = (LeaseRecord, Option<LeaseRecord>);
+
+pub type LeaseFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// A lease at most one daemon holds at a time. Its holder renews it well before it expires;
+/// once it expires, any daemon may take it.
+pub trait Lease: Send + Sync {
+    /// Where the lease is kept, for logs and status
+    fn describe(&self) -> String;
+    /// Take or renew the lease for `node`, for `ttl`, unless another node holds it unexpired
+    fn try_acquire(&self, node: &str, ttl: Duration) -> LeaseFuture<'_, Acquired>;
+    /// Give up the lease if `node` holds it, so a standby can take over without waiting for it to expire
+    fn release(&self, node: &str) -> LeaseFuture<'_, ()>;
+}
+
+/// A lease kept as JSON in a file on storage all the daemons share (NFS, CephFS, ...). The file
+/// is read and written under an exclusive `flock`, held only for that; expiry is judged by the
+/// daemons' clocks, which must be kept in sync.
+pub struct FileLease {
+    path: String,
+}
+
+impl FileLease {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string() }
+    }
+
+    // Read the lease, let `update` change it, and write back what it returns, under the lock
+    fn update<T: Send + 'static>(&self, update: impl FnOnce(Option<LeaseRecord>) -> (Option<LeaseRecord>, T) + Send + 'static)
+        -> LeaseFuture<'_, T> {
+        let path = self.path.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || -> anyhow::Result<T> {
+                let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o644).open(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path, e))?;
+                flock(file.as_raw_fd(), FlockArg::LockExclusive).map_err(|e| anyhow::anyhow!("Failed to lock {}: {}", path, e))?;
+                let mut contents = String::new();
+                file.read_to_string(&mut contents)?;
+                // An empty or unreadable file holds no lease
+                let current = serde_json::from_str(&contents).ok();
+                let (new, result) = update(current);
+                if let Some(new) = new {
+                    file.set_len(0)?;
+                    file.seek(SeekFrom::Start(0))?;
+                    file.write_all(serde_json::to_string(&new)?.as_bytes())?;
+                    file.sync_all()?;
+                }
+                Ok(result)
+            }).await?
+        })
+    }
+}
+
+impl Lease for FileLease {
+    fn describe(&self) -> String {
+        format!("file {}", self.path)
+    }
+
+    fn try_acquire(&self, node: &str, ttl: Duration) -> LeaseFuture<'_, Acquired> {
+        let node = node.to_string();
+        self.update(move |current| {
+            let now = Utc::now();
+            match current {
+                Some(lease) if lease.holder != node && lease.expires_at > now => (None, (lease, None)),
+                previous => {
+                    let lease = LeaseRecord { holder: node.clone(), renewed_at: now, expires_at: now + ttl };
+                    let taken_over = previous.filter(|previous| previous.holder != node);
+                    (Some(lease.clone()), (lease, taken_over))
+                }
+            }
+        })
+    }
+
+    fn release(&self, node: &str) -> LeaseFuture<'_, ()> {
+        let node = node.to_string();
+        self.update(move |current| match current {
+            Some(lease) if lease.holder == node => (Some(LeaseRecord { expires_at: Utc::now(), ..lease }), ()),
+            _ => (None, ()),
+        })
+    }
+}
+
+/// A lease kept in a row of the `storage.postgres` database, taken in a transaction that locks
+/// the row. Expiry is judged by the database's clock.
+#[cfg(feature = "postgres")]
+pub struct PostgresLease {
+    client: tokio::sync::Mutex<tokio_postgres::Client>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresLease {
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, tokio_postgres::NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::error!("Postgres lease connection lost: {}", e);
+            }
+        });
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS leader_lease (
+                 id INTEGER PRIMARY KEY,
+                 holder TEXT NOT NULL,
+                 renewed_at TIMESTAMPTZ NOT NULL,
+                 expires_at TIMESTAMPTZ NOT NULL
+             )",
+        ).await?;
+        Ok(Self { client: tokio::sync::Mutex::new(client) })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Lease for PostgresLease {
+    fn describe(&self) -> String {
+        "postgres table leader_lease".to_string()
+    }
+
+    fn try_acquire(&self, node: &str, ttl: Duration) -> LeaseFuture<'_, Acquired> {
+        let node = node.to_string();
+        Box::pin(async move {
+            let mut client = self.client.lock().await;
+            let tx = client.transaction().await?;
+            let current = tx.query_opt(
+                "SELECT holder, renewed_at, expires_at, expires_at > now() FROM leader_lease WHERE id = 1 FOR UPDATE", &[],
+            ).await?;
+            let previous = current.as_ref().map(|row| LeaseRecord { holder: row.get(0), renewed_at: row.get(1), expires_at: row.get(2) });
+            if let (Some(row), Some(lease)) = (&current, &previous) {
+                if lease.holder != node && row.get::<_, bool>(3) {
+                    tx.commit().await?;
+                    return Ok((lease.clone(), None));
+                }
+            }
+            let seconds = ttl.num_seconds() as f64;
+            let row = tx.query_one(
+                "INSERT INTO leader_lease (id, holder, renewed_at, expires_at)
+                 VALUES (1, $1, now(), now() + make_interval(secs => $2))
+                 ON CONFLICT (id) DO UPDATE SET holder = excluded.holder, renewed_at = excluded.renewed_at,
+                     expires_at = excluded.expires_at
+                 RETURNING renewed_at, expires_at",
+                &[&node, &seconds],
+            ).await?;
+            tx.commit().await?;
+            let lease = LeaseRecord { holder: node.clone(), renewed_at: row.get(0), expires_at: row.get(1) };
+            Ok((lease, previous.filter(|previous| previous.holder != node)))
+        })
+    }
+
+    fn release(&self, node: &str) -> LeaseFuture<'_, ()> {
+        let node = node.to_string();
+        Box::pin(async move {
+            self.client.lock().await
+                .execute("UPDATE leader_lease SET expires_at = now() WHERE id = 1 AND holder = $1", &[&node]).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Whether this daemon leads, as the election task last found. Only the leader runs jobs;
+/// a standby keeps its jobs loaded and answers reads, ready to take over.
+#[derive(Debug)]
+pub struct Leadership {
+    pub node: String,
+    pub lease_source: String,
+    leader: AtomicBool,
+    lease: Mutex<Option<LeaseRecord>>, // As last seen; None when it could not be read
+}
+
+impl Leadership {
+    pub fn new(node: String, lease_source: String) -> Self {
+        Self { node, lease_source, leader: AtomicBool::new(false), lease: Mutex::new(None) }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> HaStatus {
+        let lease = self.lease.lock().unwrap().clone();
+        HaStatus {
+            node: self.node.clone(),
+            leader: self.is_leader(),
+            lease_holder: lease.as_ref().map(|lease| lease.holder.clone()),
+            lease_expires_at: lease.map(|lease| lease.expires_at.to_rfc3339()),
+            lease_source: self.lease_source.clone(),
+        }
+    }
+
+    /// Error for requests a standby refuses
+    pub fn standby_error(&self) -> String {
+        match *self.lease.lock().unwrap() {
+            Some(ref lease) if lease.holder != self.node => {
+                format!("This daemon ({}) is on standby; send the request to the leader, {}", self.node, lease.holder)
+            }
+            _ => format!("This daemon ({}) is on standby and no leader is known yet; try again shortly", self.node),
+        }
+    }
+}
+
+/// Requests that run jobs or change them, which only the leader serves: a standby's changes
+/// would not reach the leader's scheduler
+pub fn needs_leader(request: &Request) -> bool {
+    matches!(request,
+        Request::AddJob(_)
+        | Request::EnsureJob { .. }
+        | Request::RemoveJob(_)
+        | Request::RenameJob { .. }
+        | Request::CloneJob { .. }
+        | Request::StartJob(_)
+        | Request::StartJobWithParams { .. }
+        | Request::RetryJob(_)
+        | Request::Rerun { .. }
+        | Request::RunOnce { .. }
+        | Request::Ping(_)
+        | Request::ImportJobs { .. }
+        | Request::ApplyBundle { .. }
+        | Request::ApplyJobs { .. }
+        | Request::AddTemplate(_)
+        | Request::RemoveTemplate(_)
+        | Request::AddWorkflow(_)
+        | Request::RemoveWorkflow(_)
+        | Request::RunWorkflow(_)
+        | Request::RestoreDatabase { .. })
+}
+
+/// Take part in the election: try for the lease every third of its lifetime, lead while it is
+/// ours and stand by otherwise. A leader that can't renew in time steps down at once, before
+/// the lease it holds can expire and be taken by another daemon.
+pub fn spawn(lease: Arc<dyn Lease>, leadership: Arc<Leadership>, ttl: Duration, scheduler: Arc<RwLock<Scheduler>>,
+             orphan_policy: OrphanPolicy) {
+    let interval = (ttl / 3).to_std().unwrap_or(std::time::Duration::from_secs(1));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let attempt = tokio::time::timeout(interval, lease.try_acquire(&leadership.node, ttl)).await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("no answer within {}s", interval.as_secs())));
+            let was_leader = leadership.is_leader();
+            match attempt {
+                Ok((current, taken_over)) => {
+                    let ours = current.holder == leadership.node;
+                    *leadership.lease.lock().unwrap() = Some(current.clone());
+                    if ours && !was_leader {
+                        promote(&scheduler, &leadership, taken_over, orphan_policy);
+                    } else if !ours && was_leader {
+                        log::error!("Lost the leader lease to {}; standing by", current.holder);
+                        leadership.leader.store(false, Ordering::SeqCst);
+                    } else if !ours {
+                        log::debug!("Standing by; {} leads until {}", current.holder, current.expires_at);
+                    }
+                }
+                Err(e) => {
+                    *leadership.lease.lock().unwrap() = None;
+                    if was_leader {
+                        log::error!("Failed to renew the leader lease ({}): {}; standing by", lease.describe(), e);
+                        leadership.leader.store(false, Ordering::SeqCst);
+                    } else {
+                        log::warn!("Failed to read the leader lease ({}): {}", lease.describe(), e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Take over: pick up the jobs and runs the previous leader left in the database, settle the
+// executions it left unfinished, catch up on what was due while nobody led, and run @reboot
+// jobs if this host booted since it last led
+fn promote(scheduler: &Arc<RwLock<Scheduler>>, leadership: &Leadership, taken_over: Option<LeaseRecord>, orphan_policy: OrphanPolicy) {
+    match taken_over {
+        Some(ref previous) => {
+            log::warn!("Taking over as leader from {} (lease last renewed {})", previous.holder, previous.renewed_at);
+            scheduler.read().unwrap().abandon_executions(&previous.holder);
+        }
+        None => {
+            // This daemon led last (before a restart); its processes may still be running here
+            log::warn!("Leading now ({})", leadership.lease_source);
+            Scheduler::recover_executions(scheduler.clone(), orphan_policy);
+        }
+    }
+    let mut sched = scheduler.write().unwrap();
+    sched.reload_from_db();
+    sched.catch_up_after_failover();
+    sched.run_reboot_jobs();
+    leadership.leader.store(true, Ordering::SeqCst);
+}
+
+/// Name of this daemon in the lease: the host name
+pub fn default_node() -> String {
+    nix::unistd::gethostname().ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_else(|| format!("pid-{}", std::process::id()))
+}
@@ -0,0 +1,130 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use crate::config::{LogRotation, RotationInterval};
+
+/// Log file that moves itself aside to `<path>.<timestamp>` once it reaches the size limit or a
+/// new interval begins, then gzips the old file and keeps only the newest `keep` of them.
+/// Rotation happens on flush, so a record is never split across files.
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: LogRotation,
+    file: File,
+    size: u64,
+    period: Option<String>, // Interval the current file belongs to
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, policy: LogRotation) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let period = policy.interval.map(period_key);
+        Ok(Self { path: path.to_path_buf(), policy, file, size, period })
+    }
+
+    fn due(&self) -> bool {
+        let max_bytes = self.policy.max_size_mb * 1024 * 1024;
+        let over_size = max_bytes > 0 && self.size >= max_bytes;
+        over_size || self.policy.interval.map(period_key) != self.period
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let stamp = Local::now().format("%Y%m%d-%H%M%S");
+        let mut rotated = PathBuf::from(format!("{}.{}", self.path.display(), stamp));
+        let mut n = 1;
+        while rotated.exists() || gz_path(&rotated).exists() {
+            n += 1;
+            rotated = PathBuf::from(format!("{}.{}-{}", self.path.display(), stamp, n));
+        }
+        std::fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.period = self.policy.interval.map(period_key);
+
+        // Compressing can take a while; don't hold up logging. This runs inside the logger,
+        // so problems go to stderr rather than the log.
+        let (compress, keep, path) = (self.policy.compress, self.policy.keep, self.path.clone());
+        std::thread::spawn(move || {
+            if compress {
+                if let Err(e) = compress_file(&rotated) {
+                    eprintln!("Failed to compress rotated log {}: {}", rotated.display(), e);
+                }
+            }
+            prune_rotated(&path, keep);
+        });
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.due() {
+            if let Err(e) = self.rotate() {
+                eprintln!("Failed to rotate log {}: {}", self.path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gzip `path` into `<path>.gz` and remove the original, returning the new path
+pub fn compress_file(path: &Path) -> io::Result<PathBuf> {
+    let target = gz_path(path);
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(path)?;
+    Ok(target)
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.gz", path.display()))
+}
+
+// Remove the oldest rotated copies of `path` (`<name>.<timestamp>[.gz]`) beyond the newest `keep`
+fn prune_rotated(path: &Path, keep: usize) {
+    if keep == 0 {
+        return;
+    }
+    let (dir, name) = match (path.parent(), path.file_name().and_then(|n| n.to_str())) {
+        (Some(dir), Some(name)) => (dir, format!("{}.", name)),
+        _ => return,
+    };
+    let mut rotated: Vec<(std::time::SystemTime, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str().is_some_and(|n| n.starts_with(&name)))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect(),
+        Err(_) => return,
+    };
+    if rotated.len() <= keep {
+        return;
+    }
+    rotated.sort();
+    for (_, old) in &rotated[..rotated.len() - keep] {
+        if let Err(e) = std::fs::remove_file(old) {
+            eprintln!("Failed to remove rotated log {}: {}", old.display(), e);
+        }
+    }
+}
+
+fn period_key(interval: RotationInterval) -> String {
+    let now = Local::now();
+    match interval {
+        RotationInterval::Hourly => now.format("%Y%m%d%H").to_string(),
+        RotationInterval::Daily => now.format("%Y%m%d").to_string(),
+        RotationInterval::Weekly => now.format("%G%V").to_string(),
+    }
+}
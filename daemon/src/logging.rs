@@ -0,0 +1,153 @@
+use crate::config::{LogFormat, LogRotation, LogRoute, LoggingConfig};
+use crate::log_rotation::RotatingFile;
+use std::os::unix::net::UnixDatagram;
+use std::sync::OnceLock;
+
+/// Log target of job output that no route claims; it goes to the jobs log
+pub const JOB_OUTPUT_TARGET: &str = "job_output";
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+// Routes the logger was built with. Fixed at startup, since the logger can only be installed once.
+static ROUTES: OnceLock<Vec<LogRoute>> = OnceLock::new();
+
+/// Log target for the output of a job with these tags: the first route sharing a tag, or the jobs log
+pub fn job_output_target(tags: &[String]) -> String {
+    let routes = ROUTES.get().map(|r| r.as_slice()).unwrap_or(&[]);
+    match routes.iter().find(|route| route.tags.iter().any(|t| tags.contains(t))) {
+        Some(route) => route_target(route),
+        None => JOB_OUTPUT_TARGET.to_string(),
+    }
+}
+
+fn route_target(route: &LogRoute) -> String {
+    format!("{}::{}", JOB_OUTPUT_TARGET, route.name)
+}
+
+pub fn setup_logging(config: &LoggingConfig, paths: &common::Paths, level: log::LevelFilter) -> anyhow::Result<()> {
+    let log_file = paths.log_file.as_str();
+    let jobs_log_file = paths.jobs_log_file.as_str();
+
+    let base_config = match config.format {
+        LogFormat::Text => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][{}][{}] {}",
+                chrono::Local::now().format("%Y-%m-%d][%H:%M:%S"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        }),
+        LogFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!("{}", json_record(message, record)))
+        }),
+    }
+    .level(level);
+
+    // Main log file: Filter OUT job output (routed or not)
+    let main_log = fern::Dispatch::new()
+        .filter(|metadata| !metadata.target().starts_with(JOB_OUTPUT_TARGET))
+        .chain(std::io::stdout())
+        .chain(rotating_file(log_file, &config.rotation)?);
+
+    // Jobs log file: Filter IN job output no route claimed
+    let jobs_log = fern::Dispatch::new()
+        .filter(|metadata| metadata.target() == JOB_OUTPUT_TARGET)
+        .chain(rotating_file(jobs_log_file, &config.rotation)?);
+
+    let mut dispatch = base_config.chain(main_log).chain(jobs_log);
+    for route in &config.routes {
+        dispatch = dispatch.chain(route_dispatch(route, &config.rotation)?);
+    }
+    dispatch.apply()?;
+
+    let _ = ROUTES.set(config.routes.clone());
+    Ok(())
+}
+
+/// One JSON line per record. Key-values attached to the record (`job_id`, `execution_id`)
+/// become fields of their own, so log shippers don't have to parse them out of the message.
+fn json_record(message: &std::fmt::Arguments, record: &log::Record) -> String {
+    let mut fields = serde_json::Map::new();
+    fields.insert("timestamp".into(), chrono::Local::now().to_rfc3339().into());
+    fields.insert("level".into(), record.level().as_str().into());
+    fields.insert("target".into(), record.target().into());
+    fields.insert("message".into(), message.to_string().into());
+    let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+    serde_json::Value::Object(fields).to_string()
+}
+
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.insert(key.to_string(), value.to_string().into());
+        Ok(())
+    }
+}
+
+fn rotating_file(path: &str, rotation: &LogRotation) -> anyhow::Result<fern::Output> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent).map_err(|e| anyhow::anyhow!("Failed to create log directory {}: {}", parent.display(), e))?;
+    }
+    let file = RotatingFile::open(std::path::Path::new(path), rotation.clone())
+        .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", path, e))?;
+    Ok(fern::Output::from(Box::new(file) as Box<dyn std::io::Write + Send>))
+}
+
+fn route_dispatch(route: &LogRoute, rotation: &LogRotation) -> anyhow::Result<fern::Dispatch> {
+    if route.file.is_none() && route.syslog_facility.is_none() {
+        return Err(anyhow::anyhow!("Log route {} needs a file or a syslog_facility", route.name));
+    }
+
+    let target = route_target(route);
+    let mut dispatch = fern::Dispatch::new().filter(move |metadata| metadata.target() == target);
+    if let Some(ref file) = route.file {
+        dispatch = dispatch.chain(rotating_file(file, rotation)?);
+    }
+    if let Some(ref facility) = route.syslog_facility {
+        dispatch = dispatch.chain(syslog_output(facility)?);
+    }
+    Ok(dispatch)
+}
+
+/// Send records to the local syslog daemon, one datagram per line
+fn syslog_output(facility: &str) -> anyhow::Result<fern::Output> {
+    let code = facility_code(facility)
+        .ok_or_else(|| anyhow::anyhow!("Unknown syslog facility: {}", facility))?;
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(SYSLOG_SOCKET)
+        .map_err(|e| anyhow::anyhow!("Failed to connect to syslog at {}: {}", SYSLOG_SOCKET, e))?;
+    let pid = std::process::id();
+
+    Ok(fern::Output::call(move |record| {
+        let severity = match record.level() {
+            log::Level::Error => 3,
+            log::Level::Warn => 4,
+            log::Level::Info => 6,
+            log::Level::Debug | log::Level::Trace => 7,
+        };
+        let priority = code * 8 + severity;
+        for line in record.args().to_string().lines() {
+            let _ = socket.send(format!("<{}>lunasched[{}]: {}", priority, pid, line).as_bytes());
+        }
+    }))
+}
+
+fn facility_code(facility: &str) -> Option<u32> {
+    let code = match facility {
+        "user" => 1,
+        "daemon" => 3,
+        "auth" => 4,
+        "authpriv" => 10,
+        "cron" => 9,
+        _ => {
+            let n: u32 = facility.strip_prefix("local")?.parse().ok()?;
+            if n > 7 {
+                return None;
+            }
+            16 + n
+        }
+    };
+    Some(code)
+}
@@ -1,36 +1,199 @@
 mod scheduler;
+mod due_queue;
 mod db;
 mod migrations;
+mod config;
+mod notifier;
+mod metrics;
+mod http;
+mod profiler;
+mod transport;
+mod output;
+mod resource_manager;
+mod logging;
+mod log_rotation;
+mod history_buffer;
+mod analytics;
+mod timeline;
+mod account;
+mod rebalance;
+mod secrets;
+mod events;
+mod instance_lock;
+mod acl;
+mod audit;
+mod hooks;
+mod health;
+mod backup;
+mod storage;
+mod leader;
+mod agents;
+mod container;
+mod systemd_run;
+mod sandbox;
+mod script;
+mod rate_limit;
+#[cfg(feature = "postgres")]
+mod pg_storage;
 
 use tokio::net::UnixListener;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use common::{Request, Response};
-use std::sync::{Arc, Mutex};
-use scheduler::Scheduler;
+use common::{encode_frame, DaemonStatus, FrameReader, JobRuntime, ListedJob, NextRun, PendingRetry, Request, Response, RunningExecution};
+use std::sync::{Arc, Mutex, RwLock};
+use scheduler::{RunParams, Scheduler};
+use profiler::SchedulerProfiler;
+use rate_limit::{Client, ConnectionSlot, RateLimiter};
+use transport::{Connection, Peer, TcpTransport, Transport, UnixTransport};
 use db::Db;
+use clap::Parser;
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+// How often persistence is retried while the database is missing or history is buffered
+const DB_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+// How long shutdown waits for buffered history to reach the database
+const SHUTDOWN_HISTORY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Failed executions reported by `GetStatus`
+const RECENT_FAILURES: usize = 10;
+
+// Most fire times `PreviewSchedule` returns
+const MAX_PREVIEW_RUNS: usize = 1000;
+
+// History entries per chunk of an export
+const HISTORY_EXPORT_PAGE: usize = 500;
+
+// Time a TCP client has to authenticate
+const AUTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Paths are taken from, in order: these flags (or their environment variables), the config
+/// file (server.socket_path), and the system or user defaults
+#[derive(Parser)]
+#[command(name = "lunasched-daemon", author, version, about, long_about = None)]
+struct Args {
+    /// Config file
+    #[arg(long, env = "LUNASCHED_CONFIG_PATH")]
+    config: Option<String>,
+    /// SQLite database
+    #[arg(long, env = "LUNASCHED_DB")]
+    db: Option<String>,
+    /// Unix socket for local clients; its instance lock is kept next to it (<socket>.pid)
+    #[arg(long, env = "LUNASCHED_SOCKET")]
+    socket: Option<String>,
+    /// Daemon log file
+    #[arg(long, env = "LUNASCHED_LOG")]
+    log_file: Option<String>,
+    /// Least severe messages logged: error, warn, info, debug or trace
+    #[arg(long, env = "LUNASCHED_LOG_LEVEL", default_value_t = log::LevelFilter::Info)]
+    log_level: log::LevelFilter,
+    /// Stay attached to the terminal (the default; what systemd expects)
+    #[arg(long, overrides_with = "daemonize")]
+    foreground: bool,
+    /// Detach from the terminal and run in the background
+    #[arg(long, overrides_with = "foreground")]
+    daemonize: bool,
+    /// Check the config file and exit: 0 if it is valid, 1 with the problem otherwise
+    #[arg(long)]
+    validate_config: bool,
+    /// Start even if another daemon holds the instance lock
+    #[arg(long)]
+    force: bool,
+    /// Use per-user paths even as root
+    #[arg(long, conflicts_with = "system")]
+    user: bool,
+    /// Use the system paths even as another user
+    #[arg(long)]
+    system: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // Root runs the system daemon; anyone else a private instance (--system/--user override)
+    let user_mode = !args.system && (args.user || !nix::unistd::geteuid().is_root());
+    let mut paths = common::Paths::for_mode(user_mode);
+    if let Some(ref config) = args.config {
+        paths.config = config.clone();
+    }
+    if let Some(ref log_file) = args.log_file {
+        paths.log_file = log_file.clone();
+    }
+
+    if args.validate_config {
+        match config::Config::load(&paths.config).and_then(|config| config.validate()) {
+            Ok(jobs) => {
+                println!("{}: OK ({} jobs)", paths.config, jobs);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}: {}", paths.config, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Fork before the runtime starts any threads
+    if args.daemonize && !args.foreground {
+        nix::unistd::daemon(true, false).map_err(|e| anyhow::anyhow!("Failed to daemonize: {}", e))?;
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args, user_mode, paths))
+}
+
+async fn run(args: Args, user_mode: bool, mut paths: common::Paths) -> anyhow::Result<()> {
     // Set up panic handler BEFORE anything else
-    std::panic::set_hook(Box::new(|panic_info| {
+    let log_file = paths.log_file.clone();
+    std::panic::set_hook(Box::new(move |panic_info| {
         let location = panic_info.location()
             .map(|l| format!(" at {}:{}", l.file(), l.line()))
             .unwrap_or_else(|| String::from(""));
         let payload = panic_info.payload()
-            .downcast_ref::<&str>()
-            .map(|s| *s)
+            .downcast_ref::<&str>().copied()
             .or_else(|| panic_info.payload().downcast_ref::<String>().map(|s| s.as_str()))
             .unwrap_or("<no message>");
         
         log::error!("PANIC{}: {}", location, payload);
         eprintln!("FATAL: Daemon panicked{}: {}", location, payload);
-        eprintln!("Check logs at: {}", common::DEFAULT_LOG_FILE);
+        eprintln!("Check logs at: {}", log_file);
     }));
     
-    setup_logging()?;
+    // Log routes and the socket come from the config file, so read it once before logging is up
+    let config_path = paths.config.clone();
+    let startup_config = config::Config::load(&config_path).unwrap_or_default();
+    if let Some(socket) = args.socket.clone().or_else(|| startup_config.server.socket_path.clone()) {
+        paths.lock_file = std::path::Path::new(&socket).with_extension("pid").display().to_string();
+        paths.socket = socket;
+    }
+    if let Some(ref db) = args.db {
+        paths.db = db.clone();
+    }
+    logging::setup_logging(&startup_config.logging, &paths, args.log_level)?;
     log::info!("Starting lunasched-daemon v{}...", env!("CARGO_PKG_VERSION"));
+    if user_mode {
+        log::info!("Running as a user instance (socket {}, database {})", paths.socket, paths.db);
+    }
 
-    let db_path = common::DEFAULT_DB_PATH;
+    // A user instance's socket and lock go in a directory only its user can enter, so no one
+    // else can plant a socket there for the CLI to talk to
+    if user_mode {
+        for file in [&paths.lock_file, &paths.socket] {
+            if let Some(dir) = std::path::Path::new(file).parent() {
+                common::paths::ensure_private_dir(dir).inspect_err(|e| log::error!("{}", e))?;
+            }
+        }
+    }
+
+    // One daemon per database and socket; held until the process exits
+    let force = args.force;
+    let run_dir_mode = if user_mode { 0o700 } else { 0o755 };
+    let _instance_lock = instance_lock::InstanceLock::acquire(std::path::Path::new(&paths.lock_file), run_dir_mode, force)
+        .map_err(|e| {
+            log::error!("{}", e);
+            e
+        })?;
+
+    let db_path = paths.db.as_str();
     
     // Ensure parent directories exist
     if let Some(parent) = std::path::Path::new(db_path).parent() {
@@ -47,6 +210,9 @@ async fn main() -> anyhow::Result<()> {
     let db = match rusqlite::Connection::open(db_path) {
         Ok(conn) => {
             log::info!("Database opened at {}", db_path);
+            if let Err(e) = db::tune(&conn) {
+                log::warn!("Failed to tune database connection: {}", e);
+            }
             let mut migrator = migrations::Migrator::new(conn);
             if let Err(e) = migrator.run_migrations() {
                 log::error!("Failed to run database migrations: {}", e);
@@ -57,13 +223,69 @@ async fn main() -> anyhow::Result<()> {
         },
         Err(e) => {
             log::error!("Failed to open database at {}: {}", db_path, e);
-            log::warn!("Continuing without database - jobs will not persist");
+            log::error!("Continuing without database - history is buffered in memory and the database retried every {}s",
+                DB_RETRY_INTERVAL.as_secs());
             None
         }
     };
 
-    let scheduler = Arc::new(Mutex::new(Scheduler::new(db)));
-    let socket_path = common::DEFAULT_SOCKET_PATH;
+    let scheduler = Arc::new(RwLock::new(Scheduler::new(db)));
+    scheduler.write().unwrap().paths = paths.clone();
+
+    // Key for secret:// env values, generated on first start
+    match secrets::SecretStore::open(&paths.secrets_key) {
+        Ok(store) => scheduler.write().unwrap().secrets = Some(Arc::new(store)),
+        Err(e) => log::warn!("Secrets are unavailable ({}); jobs with secret:// env values will fail to start", e),
+    }
+
+    // Reconcile jobs declared in the config file
+    let config = reload_config(&scheduler, &config_path).unwrap_or_default();
+    start_storage(&scheduler, &config.storage).await;
+
+    // Notifications the previous daemon was still retrying went down with it
+    if let Some(ref db) = scheduler.read().unwrap().db {
+        match db.lock().unwrap().abandon_pending_notifications() {
+            Ok(0) => {}
+            Ok(n) => log::warn!("{} notification(s) were still being delivered when the daemon stopped; marked failed", n),
+            Err(e) => log::warn!("Failed to check for undelivered notifications: {}", e),
+        }
+    }
+
+    // With leader election, a daemon does all of this once it leads
+    let lease = start_election(&scheduler, &config).await?;
+    if lease.is_none() {
+        // Executions the previous daemon was running when it stopped
+        Scheduler::recover_executions(scheduler.clone(), config.orphan_policy);
+
+        // Runs missed while the daemon was down, for jobs with a catch-up policy, and @reboot jobs
+        let mut sched = scheduler.write().unwrap();
+        sched.catch_up_missed_runs();
+        sched.run_reboot_jobs();
+    }
+
+    // Agents running jobs with a target
+    if let Some(ref agents_config) = config.agents {
+        let (registry, leadership) = {
+            let sched = scheduler.read().unwrap();
+            (sched.agents.clone(), sched.leadership.clone())
+        };
+        match agents::serve(agents_config, registry, leadership).await {
+            Ok(()) => log::info!("Accepting agents on {}", agents_config.bind),
+            Err(e) => log::error!("Failed to start the agent listener on {}: {}", agents_config.bind, e),
+        }
+    }
+
+    // Optional HTTP listener (/metrics, and the JSON API if it has a token)
+    if let Some(ref bind) = config.server.http_bind {
+        let api = http::ApiAccess::from_config(&config.server).unwrap_or_else(|e| {
+            log::error!("JSON API disabled: {}", e);
+            None
+        });
+        if let Err(e) = http::serve(bind, scheduler.clone(), api).await {
+            log::error!("Failed to start HTTP listener on {}: {}", bind, e);
+        }
+    }
+    let socket_path = paths.socket.as_str();
 
     // Ensure parent directory exists (critical for /var/run/lunasched after reboot)
     if let Some(parent) = std::path::Path::new(socket_path).parent() {
@@ -74,16 +296,17 @@ async fn main() -> anyhow::Result<()> {
                 return Err(anyhow::anyhow!("Failed to create socket directory: {}", e));
             }
             
-            // Set directory permissions to allow all users to access
+            // Set directory permissions to allow all users to access (only its owner, for a user instance)
             use std::os::unix::fs::PermissionsExt;
             let mut perms = std::fs::metadata(parent)?.permissions();
-            perms.set_mode(0o755);
+            perms.set_mode(run_dir_mode);
             std::fs::set_permissions(parent, perms)?;
-            log::info!("Socket directory created with permissions 0755");
+            log::info!("Socket directory created with permissions {:04o}", run_dir_mode);
         }
     }
 
-    // Remove stale socket file if it exists
+    // Remove stale socket file if it exists. Without --force we hold the instance lock, so no
+    // other daemon is serving it.
     if std::path::Path::new(socket_path).exists() {
         log::info!("Removing stale socket file: {}", socket_path);
         std::fs::remove_file(socket_path)?;
@@ -104,223 +327,118 @@ async fn main() -> anyhow::Result<()> {
     
     println!("Listening on {}", socket_path);
     
-    // Set socket permissions to allow all users to connect
+    // Set socket permissions to allow all users to connect (only its owner, for a user instance),
+    // unless the config restricts it
     use std::os::unix::fs::PermissionsExt;
+    let mode = startup_config.server.socket_mode.unwrap_or(if user_mode { 0o600 } else { 0o666 });
     let mut perms = std::fs::metadata(socket_path)?.permissions();
-    perms.set_mode(0o666);
+    perms.set_mode(mode);
     std::fs::set_permissions(socket_path, perms)?;
-    log::info!("Socket permissions set to 0666");
+    log::info!("Socket permissions set to {:04o}", mode);
+    if let Some(ref group) = startup_config.server.socket_group {
+        let gid = match nix::unistd::Group::from_name(group) {
+            Ok(Some(group)) => group.gid,
+            Ok(None) => return Err(anyhow::anyhow!("Socket group {} does not exist", group)),
+            Err(e) => return Err(anyhow::anyhow!("Failed to look up socket group {}: {}", group, e)),
+        };
+        nix::unistd::chown(socket_path, None, Some(gid))
+            .map_err(|e| anyhow::anyhow!("Failed to give socket {} to group {}: {}", socket_path, group, e))?;
+        log::info!("Socket group set to {}", group);
+    }
 
     // Spawn scheduler tick loop
     let tick_scheduler = scheduler.clone();
+    let profiler = scheduler.read().unwrap().profiler.clone();
+    let tick_profiler = profiler.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
+        let max_sleep = std::time::Duration::from_secs(common::TICK_INTERVAL_SECS);
         loop {
-            interval.tick().await;
-            let mut sched = tick_scheduler.lock().unwrap();
-            let jobs = sched.tick();
-            
-            drop(sched);
+            // Sleep on the monotonic clock until the next job is due, but no longer than a tick
+            // so retries, queued runs and clock jumps are still looked at. A slow tick delays
+            // the next one instead of bunching ticks up behind it.
+            let wake_at = tick_profiler.write(&tick_scheduler, "tick").next_wake();
+            let until_due = wake_at.map_or(max_sleep, |at| (at - chrono::Utc::now()).to_std().unwrap_or_default());
+            tokio::time::sleep(until_due.min(max_sleep)).await;
+            // A standby keeps its schedule, but runs nothing
+            let standby = {
+                let sched = tick_profiler.read(&tick_scheduler, "tick");
+                sched.leadership.as_ref().is_some_and(|leadership| !leadership.is_leader())
+            };
+            if standby {
+                tokio::time::sleep(max_sleep).await;
+                continue;
+            }
+            let jobs = tick_profiler.write(&tick_scheduler, "tick").tick();
 
-            for job in jobs {
+            for run in jobs {
                 let s = tick_scheduler.clone();
                 // Don't hold lock while executing jobs!
                 tokio::spawn(async move {
                     // Execute job without holding lock
-                    Scheduler::execute_job(s.clone(), &job);
+                    Scheduler::execute_job(s.clone(), &run.job, run.params, run.execution_id);
                 });
             }
         }
     });
 
+    // Serve IPC on the Unix socket and, if configured, on TCP for remote clients
+    let mut transports: Vec<Arc<dyn Transport>> = Vec::new();
+    transports.push(Arc::new(UnixTransport::new(listener)));
+    if let Some(ref bind) = config.server.tcp_bind {
+        let token = config.server.auth_token.clone().unwrap_or_default();
+        match TcpTransport::bind(bind, token, config.server.tcp_user.as_deref()).await {
+            Ok(tcp) => {
+                log::info!("TCP listener bound to {}", bind);
+                transports.push(Arc::new(tcp));
+            }
+            Err(e) => log::error!("Failed to start TCP listener on {}: {}", bind, e),
+        }
+    }
+    let limiter = rate_limit::RateLimiter::new(config.server.rate_limit.clone());
+    for transport in transports {
+        spawn_accept_loop(transport, scheduler.clone(), profiler.clone(), limiter.clone());
+    }
+
     // Set up signal handling for graceful shutdown
     let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
     let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+    let mut sighup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
     
     log::info!("Daemon initialization complete, ready to accept connections");
 
-    // Main accept loop with graceful shutdown
-    loop {
-        tokio::select! {
-            // Handle incoming connections
-            accept_result = listener.accept() => {
-                match accept_result {
-                    Ok((mut socket, addr)) => {
-                        log::info!("New connection accepted from {:?}", addr);
-                        let scheduler = scheduler.clone();
+    // Periodic analytics snapshot for BI tooling
+    if let Some(snapshot_path) = config.analytics.snapshot_path.clone() {
+        let interval_secs = config.analytics.interval_seconds.max(60);
+        log::info!("Writing analytics snapshots to {} every {}s", snapshot_path, interval_secs);
+        let db_path = paths.db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                let (db_path, path) = (db_path.clone(), snapshot_path.clone());
+                match tokio::task::spawn_blocking(move || analytics::export_snapshot(&db_path, &path)).await {
+                    Ok(Ok(executions)) => log::info!("Analytics snapshot written to {} ({} executions)", snapshot_path, executions),
+                    Ok(Err(e)) => log::error!("Failed to write analytics snapshot to {}: {}", snapshot_path, e),
+                    Err(e) => log::error!("Analytics snapshot task failed: {}", e),
+                }
+            }
+        });
+    }
 
-                        tokio::spawn(async move {
-                            let peer_uid = match socket.peer_cred() {
-                                Ok(cred) => cred.uid(),
-                                Err(e) => {
-                                    log::error!("Failed to get peer credentials: {}", e);
-                                    return;
-                                }
-                            };
-
-                            // Read complete message with proper buffering
-                            let mut complete_buf = Vec::new();
-                            let mut temp_buf = vec![0; 8192];
-                            
-                            loop {
-                                let n = match socket.read(&mut temp_buf).await {
-                                    Ok(0) => {
-                                        if complete_buf.is_empty() {
-                                            return;  // Connection closed
-                                        }
-                                        break;  // EOF, process what we have
-                                    }
-                                    Ok(n) => n,
-                                    Err(e) => {
-                                        log::error!("failed to read from socket; err = {:?}", e);
-                                        return;
-                                    }
-                                };
-                                
-                                complete_buf.extend_from_slice(&temp_buf[0..n]);
-                                
-                                // Try to parse - if successful, we have a complete message
-                                if let Ok(req) = serde_json::from_slice::<Request>(&complete_buf) {
-                                    // Process the request
-                                    let mut request = req;
-                                    let requester_owner = if peer_uid == 0 { "root" } else { "lunasched" };
-
-                                    // Override owner for AddJob
-                                    if let Request::AddJob(ref mut job) = request {
-                                        job.owner = requester_owner.to_string();
-                                    }
-
-                                    log::info!("Received request: {:?}", request);
-                                    
-                                    let resp = match request {
-                                        Request::AddJob(job) => {
-                                            let response = {
-                                                let mut sched = scheduler.lock().unwrap();
-                                                // Check if job exists and verify ownership
-                                                if let Some(existing) = sched.jobs.get(&job.id.0) {
-                                                    if existing.owner != requester_owner && requester_owner != "root" {
-                                                        Response::Error(format!("Permission denied: Cannot overwrite job owned by {}", existing.owner))
-                                                    } else {
-                                                        sched.add_job(job);
-                                                        Response::Ok
-                                                    }
-                                                } else {
-                                                    sched.add_job(job);
-                                                    Response::Ok
-                                                }
-                                            };
-                                            response
-                                        },
-                                        Request::ListJobs => {
-                                            let jobs = {
-                                                let sched = scheduler.lock().unwrap();
-                                                sched.jobs.values().cloned().collect()
-                                            };
-                                            Response::JobList(jobs)
-                                        },
-                                        Request::StartJob(job_id) => {
-                                            let response = {
-                                                let sched = scheduler.lock().unwrap();
-                                                if let Some(job) = sched.jobs.get(&job_id.0) {
-                                                     if job.owner != requester_owner && requester_owner != "root" {
-                                                         Response::Error(format!("Permission denied: Cannot start job owned by {}", job.owner))
-                                                     } else if sched.running_jobs.contains_key(&job_id.0) {
-                                                         Response::Error("Job is already running".to_string())
-                                                     } else {
-                                                         let job_clone = job.clone();
-                                                         
-                                                         // Create execution context for manual start
-                                                         let execution_id = uuid::Uuid::new_v4().to_string();
-                                                         let now = chrono::Utc::now();
-                                                         sched.running_jobs.insert(
-                                                             job_id.0.clone(),
-                                                             scheduler::JobExecutionContext {
-                                                                 execution_id: execution_id.clone(),
-                                                                 scheduled_time: now,
-                                                                 start_time: now,
-                                                                 pid: None,
-                                                             },
-                                                         );
-                                                         
-                                                         log::info!("Manually starting job: {} (execution_id: {})", job_clone.name, execution_id);
-                                                         
-                                                         let s = scheduler.clone();
-                                                         drop(sched);  // Drop lock before executing job
-                                                         Scheduler::execute_job(s, &job_clone);
-                                                         Response::Ok
-                                                     }
-                                                } else {
-                                                    Response::Error("Job not found".to_string())
-                                                }
-                                            };
-                                            response
-                                        },
-                                        Request::RemoveJob(id) => {
-                                            let response = {
-                                                let mut sched = scheduler.lock().unwrap();
-                                                if let Some(job) = sched.jobs.get(&id.0) {
-                                                    if job.owner != requester_owner && requester_owner != "root" {
-                                                        Response::Error(format!("Permission denied: Cannot remove job owned by {}", job.owner))
-                                                    } else {
-                                                        sched.remove_job(&id.0);
-                                                        Response::Ok
-                                                    }
-                                                } else {
-                                                    Response::Error("Job not found".to_string())
-                                                }
-                                            };
-                                            response
-                                        },
-                                        Request::GetJob(id) => {
-                                            let job_opt = {
-                                                let sched = scheduler.lock().unwrap();
-                                                sched.jobs.get(&id.0).cloned()
-                                            };
-                                            Response::JobDetail(job_opt)
-                                        },
-                                        Request::GetHistory { job_id, limit } => {
-                                            let sched = scheduler.lock().unwrap();
-                                            if let Some(ref db) = sched.db {
-                                                match db.lock().unwrap().get_history(&job_id.0, limit) {
-                                                    Ok(history) => Response::HistoryList(history),
-                                                    Err(e) => Response::Error(format!("DB Error: {}", e)),
-                                                }
-                                            } else {
-                                                Response::Error("No database configured".to_string())
-                                            }
-                                        },
-                                    };
-                                    
-                                    log::debug!("About to serialize response: {:?}", resp);
-                                    let resp_bytes = serde_json::to_vec(&resp).unwrap();
-                                    log::debug!("Response serialized, {} bytes", resp_bytes.len());
-
-                                    if let Err(e) = socket.write_all(&resp_bytes).await {
-                                        log::error!("failed to write to socket; err = {:?}", e);
-                                        return;
-                                    }
-                                    
-                                    // Clear buffer for next request
-                                    complete_buf.clear();
-                                    continue;
-                                }
-                                
-                                // If buffer grows too large, something is wrong
-                                if complete_buf.len() > 1024 * 1024 {  // 1MB limit
-                                    log::error!("Request too large: {} bytes", complete_buf.len());
-                                    return;
-                                }
-                            }
+    // Reattach the database / flush buffered history while persistence is degraded
+    let persist_scheduler = scheduler.clone();
+    let persist_db_path = paths.db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DB_RETRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            retry_persistence(&persist_scheduler, &persist_db_path);
+        }
+    });
 
-                        });
-                    }
-                    Err(e) => {
-                        log::error!("Accept error: {}", e);
-                        // Continue on accept errors instead of crashing
-                        continue;
-                    }
-                }
-            },
+    // Main loop: connections are served by the transport tasks; wait for signals
+    loop {
+        tokio::select! {
             // Handle SIGTERM
             _ = sigterm.recv() => {
                 log::info!("Received SIGTERM, initiating graceful shutdown...");
@@ -331,10 +449,28 @@ async fn main() -> anyhow::Result<()> {
                 log::info!("Received SIGINT, initiating graceful shutdown...");
                 break;
             },
+            // Handle SIGHUP (reload config)
+            _ = sighup.recv() => {
+                log::info!("Received SIGHUP, reloading config from {}", config_path);
+                reload_config(&scheduler, &config_path);
+            },
         }
     }
     
     // Cleanup
+    if let Some(lease) = lease {
+        // Let a standby take over now rather than when the lease expires
+        let node = scheduler.read().unwrap().leadership.as_ref().map(|leadership| leadership.node.clone()).unwrap_or_default();
+        if let Err(e) = lease.release(&node).await {
+            log::warn!("Failed to release the leader lease ({}): {}", lease.describe(), e);
+        }
+    }
+    retry_persistence(&scheduler, db_path);
+    let history = scheduler.read().unwrap().history.clone();
+    if !history.drain(SHUTDOWN_HISTORY_TIMEOUT) {
+        let lost = history.len();
+        log::error!("HISTORY LOST: {} buffered records could not be written to the database before shutdown", lost);
+    }
     log::info!("Graceful shutdown complete");
     if let Err(e) = std::fs::remove_file(socket_path) {
         log::warn!("Failed to remove socket file: {}", e);
@@ -343,37 +479,1376 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn setup_logging() -> anyhow::Result<()> {
-    let log_file = std::env::var("LUNASCHED_LOG").unwrap_or_else(|_| common::DEFAULT_LOG_FILE.to_string());
-    let jobs_log_file = common::DEFAULT_JOBS_LOG_FILE;
-
-    let base_config = fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{}][{}][{}] {}",
-                chrono::Local::now().format("%Y-%m-%d][%H:%M:%S"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info);
-
-    // Main log file: Filter OUT job_output
-    let main_log = fern::Dispatch::new()
-        .filter(|metadata| metadata.target() != "job_output")
-        .chain(std::io::stdout())
-        .chain(fern::log_file(log_file)?);
-
-    // Jobs log file: Filter IN job_output
-    let jobs_log = fern::Dispatch::new()
-        .filter(|metadata| metadata.target() == "job_output")
-        .chain(fern::log_file(jobs_log_file)?);
-
-    base_config
-        .chain(main_log)
-        .chain(jobs_log)
-        .apply()?;
-        
+/// Accept connections on a transport and serve each on its own task. Connections over the
+/// rate limits are answered with the error and closed.
+fn spawn_accept_loop(transport: Arc<dyn Transport>, scheduler: Arc<RwLock<Scheduler>>, profiler: Arc<SchedulerProfiler>, limiter: Arc<RateLimiter>) {
+    tokio::spawn(async move {
+        loop {
+            match transport.accept().await {
+                Ok((mut socket, peer)) => match limiter.admit(peer.remote.map_or(Client::Uid(peer.uid), Client::Address)) {
+                    Ok(slot) => {
+                        log::info!("New {} connection accepted from {}", transport.name(), peer.addr);
+                        let (scheduler, profiler, limiter) = (scheduler.clone(), profiler.clone(), limiter.clone());
+                        tokio::spawn(handle_connection(socket, peer, scheduler, profiler, limiter, slot));
+                    }
+                    Err(reason) => {
+                        // Debug only: a flood would otherwise flood the log too
+                        log::debug!("Refused {} connection from {}: {}", transport.name(), peer.addr, reason);
+                        tokio::spawn(async move {
+                            let _ = tokio::time::timeout(
+                                std::time::Duration::from_secs(1),
+                                send_response(&mut socket, &Response::Error(reason)),
+                            ).await;
+                        });
+                    }
+                },
+                Err(e) => {
+                    log::error!("Accept error on {} transport: {}", transport.name(), e);
+                    // Continue on accept errors instead of crashing
+                    continue;
+                }
+            }
+        }
+    });
+}
+
+/// Serve requests from one client until it disconnects. The connection counts against `slot`
+/// while it is open.
+async fn handle_connection(mut socket: Box<dyn Connection>, peer: Peer, scheduler: Arc<RwLock<Scheduler>>,
+                           profiler: Arc<SchedulerProfiler>, limiter: Arc<RateLimiter>, mut slot: ConnectionSlot) {
+    let mut authenticated = peer.required_token.is_none();
+    // Root is not subject to the ACL; TCP clients act as server.tcp_user, which is never root
+    let identity = (peer.uid != 0).then(|| acl::Identity::lookup(peer.uid));
+    let actor = match (&identity, &peer.required_token) {
+        (_, Some(_)) => audit::Actor { uid: Some(peer.uid), name: "remote".to_string() },
+        (Some(who), None) => audit::Actor { uid: Some(peer.uid), name: who.name.clone() },
+        (None, None) => audit::Actor { uid: Some(0), name: "root".to_string() },
+    };
+
+    // Requests are served in order as they complete; a client may send several on one connection
+    let mut frames = FrameReader::default();
+    let mut temp_buf = vec![0; 8192];
+    let deadline = tokio::time::Instant::now() + AUTH_TIMEOUT;
+
+    loop {
+        // A TCP client has a while to authenticate, so connections that send nothing don't
+        // hold their slot; after that it may idle
+        let read = socket.read(&mut temp_buf);
+        let read = if authenticated {
+            read.await
+        } else {
+            match tokio::time::timeout_at(deadline, read).await {
+                Ok(read) => read,
+                Err(_) => {
+                    log::debug!("Closing connection from {}: not authenticated within {}s", peer.addr, AUTH_TIMEOUT.as_secs());
+                    return;
+                }
+            }
+        };
+        let n = match read {
+            Ok(0) => return,  // Connection closed
+            Ok(n) => n,
+            Err(e) => {
+                log::error!("failed to read from socket; err = {:?}", e);
+                return;
+            }
+        };
+
+        frames.push(&temp_buf[0..n]);
+
+        loop {
+            let mut request = match frames.next::<Request>() {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Malformed request from {}: {}", peer.addr, e);
+                    let _ = send_response(&mut socket, &Response::Error(format!("Malformed request: {}", e))).await;
+                    return;
+                }
+            };
+            // Over the rate limit, a request is answered with the error; the connection stays open
+            if let Err(reason) = limiter.request(slot.client()) {
+                if send_response(&mut socket, &Response::Error(reason)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            let requester_owner = owner_for(peer.uid);
+
+            // Remote peers must authenticate before anything else; the token is never logged
+            if let Request::Authenticate(ref token) = request {
+                let resp = match peer.required_token {
+                    Some(ref expected) if !transport::token_matches(expected, token) => {
+                        log::warn!("Authentication failed for {}", peer.addr);
+                        Response::Error("Authentication failed".to_string())
+                    }
+                    // From now on the client counts as the user it acts as
+                    _ => match slot.authenticated(peer.uid) {
+                        Ok(()) => {
+                            authenticated = true;
+                            Response::Ok
+                        }
+                        Err(reason) => Response::Error(reason),
+                    },
+                };
+                if send_response(&mut socket, &resp).await.is_err() || !authenticated {
+                    return;
+                }
+                continue;
+            }
+            if !authenticated {
+                log::warn!("Unauthenticated request from {}", peer.addr);
+                let _ = send_response(&mut socket, &Response::Error("Authentication required".to_string())).await;
+                return;
+            }
+
+            resolve_job_names(&mut request, &scheduler, &profiler, requester_owner);
+            if let Some(ref who) = identity {
+                let denied = {
+                    let sched = profiler.read(&scheduler, "ipc");
+                    acl::authorize(&sched.acl, who, &request, &sched.jobs).err()
+                };
+                if let Some(reason) = denied {
+                    log::warn!("{}", reason);
+                    let action = audit::describe(&request, &profiler.read(&scheduler, "ipc").jobs);
+                    if let Some(action) = action {
+                        audit::record(&scheduler, &profiler, &actor, action, format!("denied: {}", reason));
+                    }
+                    if send_response(&mut socket, &Response::Error(reason)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            }
+
+            log_request(&request, &scheduler, &profiler);
+
+            // Output is streamed as a sequence of responses rather than a single one
+            if let Request::GetJobOutput { ref job_id, ref execution_id, follow } = request {
+                let found = {
+                    let sched = profiler.read(&scheduler, "ipc");
+                    match sched.jobs.get(&job_id.0).or_else(|| sched.transient_jobs.get(&job_id.0)) {
+                        None => Err("Job not found".to_string()),
+                        Some(job) if job.owner != requester_owner && requester_owner != "root" => {
+                            Err(format!("Permission denied: Cannot read output of job owned by {}", job.owner))
+                        }
+                        Some(_) => Ok((output::find_live(&sched.live_outputs, &job_id.0, execution_id.as_deref()), sched.db.clone())),
+                    }
+                };
+                let lookup = match found {
+                    Err(e) => Err(e),
+                    Ok((live, db)) => {
+                        // Without a running execution, fall back to the recorded output
+                        let last_output = match (&live, db) {
+                            (None, Some(db)) => recorded_output(&db, &job_id.0, execution_id.as_deref()).await,
+                            _ => None,
+                        };
+                        Ok((live, last_output))
+                    }
+                };
+                match lookup {
+                    Ok((live, last_output)) => output::stream(&mut socket, live, last_output, follow).await,
+                    Err(e) => {
+                        if send_response(&mut socket, &Response::Error(e)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if let Request::ExportHistory { job_id, filter } = request {
+                let db = profiler.read(&scheduler, "ipc").db.clone();
+                let resp = match (db, db::HistorySearch::new(job_id, filter)) {
+                    (None, _) => Response::Error("No database configured".to_string()),
+                    (_, Err(e)) => Response::Error(e),
+                    (Some(db), Ok(search)) => match stream_history(&mut socket, &db, search).await {
+                        Ok(resp) => resp,
+                        Err(_) => return,
+                    },
+                };
+                if send_response(&mut socket, &resp).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            // Subscriptions keep the connection until the client hangs up
+            if let Request::Subscribe { filters } = request {
+                let events = profiler.read(&scheduler, "ipc").events.clone();
+                events::stream(&mut socket, &events, filters, requester_owner).await;
+                return;
+            }
+
+            let follow_run = matches!(request, Request::RunOnce { follow: true, .. });
+            let action = audit::describe(&request, &profiler.read(&scheduler, "ipc").jobs);
+            let mut resp = dispatch(request, requester_owner, &scheduler, &profiler).await;
+            if let Some(action) = action {
+                audit::record(&scheduler, &profiler, &actor, action, audit::outcome(&resp));
+            }
+            if let Some(ref who) = identity {
+                resp = acl::filter_response(&profiler.read(&scheduler, "ipc").acl, who, resp);
+            }
+
+            if let Response::SecretValue(_) = resp {
+                log::debug!("About to send response: SecretValue(..)");
+            } else {
+                log::debug!("About to send response: {:?}", resp);
+            }
+            if let Err(e) = send_response(&mut socket, &resp).await {
+                log::error!("failed to write to socket; err = {:?}", e);
+                return;
+            }
+
+            // A followed transient run goes on with its output, as for GetJobOutput
+            if let (true, Response::ExecutionStarted { job_id, execution_id }) = (follow_run, &resp) {
+                let (live, db) = {
+                    let sched = profiler.read(&scheduler, "ipc");
+                    (output::find_live(&sched.live_outputs, job_id, Some(execution_id.as_str())), sched.db.clone())
+                };
+                // Finished already: its record is written before its live output goes
+                let last_output = match (&live, db) {
+                    (None, Some(db)) => recorded_output(&db, job_id, Some(execution_id.as_str())).await,
+                    _ => None,
+                };
+                output::stream(&mut socket, live, last_output, true).await;
+            }
+
+        }
+
+        // If buffer grows too large, something is wrong
+        if frames.buffered() > 1024 * 1024 {  // 1MB limit
+            log::error!("Request too large: {} bytes", frames.buffered());
+            return;
+        }
+    }
+}
+
+/// Owner a client with `uid` adds jobs as, and is checked against for ownership
+fn owner_for(uid: u32) -> &'static str {
+    if uid == 0 { "root" } else { "lunasched" }
+}
+
+/// Log an incoming request, keeping secrets carried in its payload out of the log
+fn log_request(request: &Request, scheduler: &Arc<RwLock<Scheduler>>, profiler: &SchedulerProfiler) {
+    if let Request::AddJob(ref job) | Request::EnsureJob { ref job, .. } | Request::RunOnce { ref job, .. } = *request {
+        // Job env may carry secrets; keep their values out of the log
+        let mut patterns = profiler.read(scheduler, "ipc").redact_patterns.clone();
+        patterns.extend(job.redact_env.iter().cloned());
+        let secrets = common::redact::secret_values(job.env.iter(), &patterns);
+        log::info!("Received request: {}", common::redact::redact(&format!("{:?}", request), &secrets));
+    } else if let Request::ApplyBundle { ref bundle, prune } = *request {
+        // Bundle contents may carry job env secrets
+        log::info!("Received request: ApplyBundle {{ source: {:?}, signer: {}, prune: {} }}",
+            bundle.source, bundle.public_key, prune);
+    } else if let Request::AddTemplate(ref template) = *request {
+        // Template env may carry secrets
+        log::info!("Received request: AddTemplate {{ name: {:?} }}", template.name);
+    } else if let Request::SetSecret { ref name, .. } = *request {
+        log::info!("Received request: SetSecret {{ name: {:?} }}", name);
+    } else if let Request::ApplyJobs { ref source, ref jobs, prune } = *request {
+        // Applied jobs carry env values; log only their names
+        let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+        log::info!("Received request: ApplyJobs {{ source: {:?}, jobs: {:?}, prune: {} }}", source, names, prune);
+    } else if let Request::ImportJobs { ref jobs, on_conflict } = *request {
+        // Imported jobs carry env values; log only their IDs
+        let ids: Vec<&str> = jobs.iter().map(|job| job.id.0.as_str()).collect();
+        log::info!("Received request: ImportJobs {{ jobs: {:?}, on_conflict: {:?} }}", ids, on_conflict);
+    } else {
+        log::info!("Received request: {:?}", request);
+    }
+}
+
+/// Replace job names in a request with the IDs of the jobs they name, so that everything after
+/// (access control, audit, dispatch) deals in IDs. References that name no job are left alone.
+/// A job added without an ID gets the one it will be saved under.
+fn resolve_job_names(request: &mut Request, scheduler: &Arc<RwLock<Scheduler>>, profiler: &SchedulerProfiler, owner: &str) {
+    let job_id = match request {
+        Request::AddJob(job) | Request::EnsureJob { job, .. } => {
+            job.owner = owner.to_string();
+            profiler.read(scheduler, "ipc").assign_ids(job);
+            return;
+        }
+        Request::RemoveJob(id)
+        | Request::GetJob(id)
+        | Request::StartJob(id)
+        | Request::Ping(id)
+        | Request::GetPingToken(id)
+        | Request::RetryJob(id)
+        | Request::GetMetrics(id)
+        | Request::StartJobWithParams { job_id: id, .. }
+        | Request::GetHistory { job_id: Some(id), .. }
+        | Request::ExportHistory { job_id: Some(id), .. }
+        | Request::GetJobOutput { job_id: id, .. }
+        | Request::TestNotification { job_id: id, .. }
+        | Request::GetExecution { job_id: id, .. }
+        | Request::GetAuditLog { job_id: Some(id), .. }
+        | Request::GetExecutionWindows { job_id: Some(id), .. }
+        | Request::RenameJob { job_id: id, .. }
+        | Request::CloneJob { job_id: id, .. } => id,
+        _ => return,
+    };
+    if let Some(id) = profiler.read(scheduler, "ipc").job_id_for(&job_id.0, owner) {
+        *job_id = id;
+    }
+}
+
+/// Output and status of a finished execution (the job's last one without `execution_id`),
+/// read back from its log file while it exists
+async fn recorded_output(db: &Arc<Mutex<Db>>, job_id: &str, execution_id: Option<&str>) -> Option<(String, String)> {
+    let (job_id, execution_id) = (job_id.to_string(), execution_id.map(str::to_string));
+    let entry = db::query(db, move |db| match execution_id {
+        None => db.get_history(&job_id, Some(1)).ok().and_then(|h| h.into_iter().next()),
+        Some(execution_id) => db.get_execution(&job_id, &execution_id).ok().flatten(),
+    }).await?;
+    let output = entry.log_path.as_deref()
+        .and_then(|path| output::read_log_tail(std::path::Path::new(path), output::MAX_LIVE_OUTPUT as u64).ok())
+        .unwrap_or_else(|| entry.combined_output());
+    Some((output, entry.status))
+}
+
+/// Send the history a search finds as HistoryChunks, a page at a time so neither a response nor
+/// a database read has to hold all of it. Returns the response that ends the stream: HistoryEnd,
+/// or the error that cut it short.
+async fn stream_history(socket: &mut Box<dyn Connection>, db: &Arc<Mutex<Db>>, search: db::HistorySearch) -> std::io::Result<Response> {
+    let (mut after_id, mut count) = (0, 0u64);
+    loop {
+        let page_search = search.clone();
+        let page = match db::query(db, move |db| db.history_page(&page_search, after_id, HISTORY_EXPORT_PAGE)).await {
+            Ok(page) => page,
+            Err(e) => return Ok(Response::Error(format!("DB Error: {}", e))),
+        };
+        let last_page = page.len() < HISTORY_EXPORT_PAGE;
+        if let Some(last) = page.last() {
+            after_id = last.id;
+            count += page.len() as u64;
+            send_response(socket, &Response::HistoryChunk(page)).await?;
+        }
+        if last_page {
+            return Ok(Response::HistoryEnd { count });
+        }
+    }
+}
+
+/// Answer one request on behalf of `requester_owner`. Shared by the socket transports and the
+/// HTTP API; streamed requests (GetJobOutput, Subscribe, ExportHistory) are served by the socket
+/// loop itself.
+async fn dispatch(request: Request, requester_owner: &str, scheduler: &Arc<RwLock<Scheduler>>, profiler: &SchedulerProfiler) -> Response {
+    if leader::needs_leader(&request) {
+        if let Some(ref leadership) = profiler.read(scheduler, "ipc").leadership {
+            if !leadership.is_leader() {
+                return Response::Error(leadership.standby_error());
+            }
+        }
+    }
+    match request {
+        Request::Authenticate(_) => Response::Ok, // Handled by the transport
+        Request::GetJobOutput { .. } | Request::Subscribe { .. } | Request::ExportHistory { .. } => {
+            Response::Error("This request is only served on the daemon socket".to_string())
+        },
+        Request::AddJob(mut job) => {
+            job.owner = requester_owner.to_string();
+            let mut sched = profiler.write(scheduler, "ipc");
+            sched.assign_ids(&mut job);
+            save_job(&mut sched, job, requester_owner)
+        },
+        Request::EnsureJob { mut job, if_absent } => {
+            job.owner = requester_owner.to_string();
+            let mut sched = profiler.write(scheduler, "ipc");
+            sched.assign_ids(&mut job);
+            ensure_job(&mut sched, job, if_absent, requester_owner)
+        },
+        Request::RenameJob { job_id, name } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            match sched.jobs.get(&job_id.0).cloned() {
+                Some(mut job) => {
+                    job.name = name;
+                    save_job(&mut sched, job, requester_owner)
+                }
+                None => Response::Error("Job not found".to_string()),
+            }
+        },
+        Request::CloneJob { job_id, name, schedule, enabled } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            match sched.jobs.get(&job_id.0).cloned() {
+                Some(job) if job.owner != requester_owner && requester_owner != "root" => {
+                    Response::Error(format!("Permission denied: Cannot clone job owned by {}", job.owner))
+                }
+                Some(mut job) => {
+                    // The copy is the requester's own, and no file declares it
+                    job.id = scheduler::new_job_id();
+                    job.name = name;
+                    job.owner = requester_owner.to_string();
+                    job.enabled = enabled;
+                    job.tags.retain(|tag| !tag.starts_with(common::declarative::SOURCE_TAG_PREFIX));
+                    if let Some(schedule) = schedule {
+                        job.schedule = schedule;
+                    }
+                    save_job(&mut sched, job, requester_owner)
+                }
+                None => Response::Error("Job not found".to_string()),
+            }
+        },
+        Request::RunOnce { mut job, .. } => {
+            // Not a job of the scheduler's: its own ID, one run, no retries
+            job.id = common::JobId(format!("run-{}", &uuid::Uuid::new_v4().simple().to_string()[..12]));
+            job.owner = requester_owner.to_string();
+            job.schedule = common::ScheduleConfig::Once(chrono::Utc::now());
+            job.enabled = false;
+            job.retry_policy.max_attempts = Some(0);
+            if let Err(e) = account::check_run_as(&job).and_then(|_| job.success_criteria.validate()).and_then(|_| job.check_executor()) {
+                return Response::Error(e);
+            }
+            let execution_id = profiler.write(scheduler, "ipc").begin_transient(job.clone());
+            log::info!("Starting transient job {} for {}: {} (execution_id: {})", job.id.0, requester_owner, job.name, execution_id);
+            Scheduler::execute_job(scheduler.clone(), &job, RunParams::new(), execution_id.clone());
+            Response::ExecutionStarted { job_id: job.id.0, execution_id }
+        },
+        Request::ListJobs => {
+            let now = chrono::Utc::now();
+            let sched = profiler.read(scheduler, "ipc");
+            let mut running: std::collections::HashMap<String, Vec<RunningExecution>> = std::collections::HashMap::new();
+            for ctx in sched.running_jobs.iter() {
+                running.entry(ctx.job_id.clone()).or_default().push(running_execution(&ctx, now));
+            }
+            let jobs = sched.jobs.values()
+                .map(|job| {
+                    let retry = sched.retry_state.values().find(|state| state.job_id == job.id.0 && state.next_attempt_at.is_some());
+                    let queued = sched.queued_runs.get(&job.id.0).map_or(0, |runs| runs.len());
+                    let status = if let Some(mut executions) = running.remove(&job.id.0) {
+                        executions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+                        JobRuntime::Running { executions }
+                    } else if queued > 0 {
+                        JobRuntime::Queued { runs: queued }
+                    } else if let Some(retry) = retry {
+                        JobRuntime::PendingRetry { attempt: retry.attempt, next_attempt_at: retry.next_attempt_at.map(|t| t.to_rfc3339()) }
+                    } else {
+                        JobRuntime::Idle { next_run: sched.next_run(job, now).map(|t| t.to_rfc3339()) }
+                    };
+                    ListedJob { job: sched.effective_job(job), status }
+                })
+                .collect();
+            Response::JobListing(jobs)
+        },
+        Request::StartJob(job_id) => {
+            start_job(scheduler, profiler, &job_id.0, RunParams::new(), requester_owner, None)
+        },
+        Request::StartJobWithParams { job_id, params } => {
+            start_job(scheduler, profiler, &job_id.0, params, requester_owner, None)
+        },
+        Request::Rerun { execution_id } => {
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            let snapshot = match db {
+                Some(db) => {
+                    let id = execution_id.clone();
+                    db::query(&db, move |db| db.get_snapshot(&id)).await.map_err(|e| format!("DB Error: {}", e))
+                }
+                None => Err("No database configured".to_string()),
+            };
+            match snapshot {
+                Ok(Some(snapshot)) => {
+                    start_job(scheduler, profiler, &snapshot.job_id, snapshot.params.clone(), requester_owner, Some(&snapshot))
+                }
+                Ok(None) => Response::Error(format!(
+                    "No snapshot for execution {}; only jobs with capture_env enabled can be rerun", execution_id)),
+                Err(e) => Response::Error(e),
+            }
+        },
+        Request::RemoveJob(id) => {
+            let response = {
+                let mut sched = profiler.write(scheduler, "ipc");
+                if let Some(job) = sched.jobs.get(&id.0) {
+                    if job.owner != requester_owner && requester_owner != "root" {
+                        Response::Error(format!("Permission denied: Cannot remove job owned by {}", job.owner))
+                    } else {
+                        sched.remove_job(&id.0);
+                        Response::Ok
+                    }
+                } else {
+                    Response::Error("Job not found".to_string())
+                }
+            };
+            response
+        },
+        Request::Ping(id) => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            let denied_owner = sched.jobs.get(&id.0)
+                .filter(|job| job.owner != requester_owner && requester_owner != "root")
+                .map(|job| job.owner.clone());
+            if let Some(owner) = denied_owner {
+                Response::Error(format!("Permission denied: Cannot ping job owned by {}", owner))
+            } else {
+                match sched.record_heartbeat(&id.0) {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error(e),
+                }
+            }
+        },
+        Request::GetPingToken(id) => {
+            let sched = profiler.read(scheduler, "ipc");
+            match (sched.jobs.get(&id.0), &sched.secrets) {
+                (None, _) => Response::Error("Job not found".to_string()),
+                (Some(job), _) if job.owner != requester_owner && requester_owner != "root" => {
+                    Response::Error(format!("Permission denied: Cannot ping job owned by {}", job.owner))
+                }
+                (Some(job), _) if !matches!(job.schedule, common::ScheduleConfig::Heartbeat(_)) => {
+                    Response::Error(format!("Job {} is not a heartbeat job", job.name))
+                }
+                (Some(_), None) => Response::Error(format!("Ping tokens are unavailable: no secrets key at {}", sched.paths.secrets_key)),
+                (Some(job), Some(store)) => Response::SecretValue(store.ping_token(&job.id.0)),
+            }
+        },
+        Request::ApplyBundle { bundle, prune } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            let desired = check_source(&bundle.source).map_err(anyhow::Error::msg)
+                .and_then(|_| bundle.verify_trusted(&sched.trusted_keys))
+                .and_then(|_| bundle.job_file())
+                .and_then(|file| file.into_jobs(&bundle.source));
+            match desired {
+                Err(e) => {
+                    log::warn!("Rejected bundle {} signed by {}: {}", bundle.source, bundle.public_key, e);
+                    Response::Error(format!("Bundle rejected: {}", e))
+                }
+                Ok(mut desired) => {
+                    if let Some((id, owner)) = foreign_applied_job(&sched, &bundle.source, &desired, prune, requester_owner) {
+                        Response::Error(format!("Permission denied: Bundle would modify job {} owned by {}", id, owner))
+                    } else {
+                        for job in &mut desired {
+                            job.owner = requester_owner.to_string();
+                        }
+                        match desired.iter().map(account::check_run_as).find_map(Result::err) {
+                            Some(e) => Response::Error(format!("Bundle rejected: {}", e)),
+                            None => {
+                                log::info!("Applying bundle {} signed by {}", bundle.source, bundle.public_key);
+                                match sched.reconcile(desired, &bundle.source, prune) {
+                                    Ok(report) => Response::Applied(report),
+                                    Err(e) => Response::Error(e),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        Request::ApplyJobs { source, mut jobs, prune } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            let source_tag = common::declarative::source_tag(&source);
+            for job in &mut jobs {
+                // Jobs are matched to existing ones by name; an ID sent along is never trusted
+                job.id = common::JobId::default();
+                job.owner = requester_owner.to_string();
+                if !job.tags.contains(&source_tag) {
+                    job.tags.push(source_tag.clone());
+                }
+            }
+            if let Err(e) = check_source(&source) {
+                Response::Error(e)
+            } else if let Some((id, owner)) = foreign_applied_job(&sched, &source, &jobs, prune, requester_owner) {
+                Response::Error(format!("Permission denied: Applying {} would modify job {} owned by {}", source, id, owner))
+            } else {
+                // Every job is checked before any is saved
+                match jobs.iter().map(check_job).find_map(Result::err) {
+                    Some(e) => Response::Error(format!("{} not applied: {}", source, e)),
+                    None => match sched.reconcile(jobs, &source, prune) {
+                        Ok(report) => Response::Applied(report),
+                        Err(e) => Response::Error(e),
+                    },
+                }
+            }
+        },
+        Request::ExportJobs { tag } => {
+            let sched = profiler.read(scheduler, "ipc");
+            // Exports carry env values, so non-root users only get their own jobs
+            let mut jobs: Vec<_> = sched.jobs.values()
+                .filter(|job| job.owner == requester_owner || requester_owner == "root")
+                .filter(|job| tag.as_ref().is_none_or(|tag| job.tags.contains(tag)))
+                .cloned()
+                .collect();
+            jobs.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+            Response::JobList(jobs)
+        },
+        Request::ImportJobs { mut jobs, on_conflict } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            let denied_job = match on_conflict {
+                common::ConflictPolicy::Overwrite => sched.jobs.values()
+                    .filter(|job| job.owner != requester_owner && requester_owner != "root")
+                    .find(|job| jobs.iter().any(|j| j.id == job.id))
+                    .map(|job| (job.id.0.clone(), job.owner.clone())),
+                _ => None,
+            };
+            if let Some((id, owner)) = denied_job {
+                Response::Error(format!("Permission denied: Cannot overwrite job {} owned by {}", id, owner))
+            } else {
+                // Root keeps the owners recorded in the export; anyone else imports as themselves
+                if requester_owner != "root" {
+                    for job in &mut jobs {
+                        job.owner = requester_owner.to_string();
+                    }
+                }
+                // Every job is checked as AddJob would before any is imported
+                let checked = jobs.iter()
+                    .try_for_each(|job| check_job(job).and_then(|_| sched.check_triggers(job)).map_err(|e| format!("{} not imported: {}", job.id, e)));
+                match checked {
+                    Err(e) => Response::Error(e),
+                    Ok(()) => Response::Imported(sched.import_jobs(jobs, on_conflict)),
+                }
+            }
+        },
+        Request::GetJob(id) => {
+            let job_opt = {
+                let sched = profiler.read(scheduler, "ipc");
+                sched.jobs.get(&id.0).map(|job| sched.effective_job(job))
+            };
+            Response::JobDetail(job_opt)
+        },
+        Request::ResolveJob(reference) => {
+            let sched = profiler.read(scheduler, "ipc");
+            match sched.resolve_job(&reference, requester_owner) {
+                Ok(job) => Response::JobDetail(Some(job.clone())),
+                Err(e) => Response::Error(e),
+            }
+        },
+        Request::GetStatus { verbose } => {
+            let top = if verbose { profiler::TOP_OFFENDERS } else { 0 };
+            let now = chrono::Utc::now();
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            let (db_error, recent_failures, notification_failures) = match db {
+                Some(db) => db::query(&db, move |db| (
+                    db.check().err().map(|e| e.to_string()),
+                    db.recent_failures(RECENT_FAILURES).unwrap_or_default(),
+                    db.failed_notifications(&(now - chrono::Duration::hours(24)), RECENT_FAILURES).unwrap_or_default(),
+                )).await,
+                None => (None, Vec::new(), Vec::new()),
+            };
+
+            let sched = profiler.read(scheduler, "ipc");
+            let mut running: Vec<RunningExecution> = sched.running_jobs.iter()
+                .map(|ctx| running_execution(&ctx, now))
+                .collect();
+            running.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+            let mut next_runs: Vec<NextRun> = sched.jobs.values()
+                .map(|job| NextRun {
+                    job_id: job.id.0.clone(),
+                    job_name: job.name.clone(),
+                    next_run: sched.next_run(job, now).map(|t| t.to_rfc3339()),
+                })
+                .collect();
+            next_runs.sort_by(|a, b| (a.next_run.is_none(), &a.next_run).cmp(&(b.next_run.is_none(), &b.next_run)));
+            let mut pending_retries: Vec<PendingRetry> = sched.retry_state.values()
+                .filter(|state| state.next_attempt_at.is_some())
+                .map(|state| PendingRetry {
+                    job_id: state.job_id.clone(),
+                    attempt: state.attempt,
+                    next_attempt_at: state.next_attempt_at.map(|t| t.to_rfc3339()),
+                })
+                .collect();
+            pending_retries.sort_by(|a, b| a.next_attempt_at.cmp(&b.next_attempt_at));
+            Response::Status(DaemonStatus {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_seconds: (chrono::Utc::now() - sched.started_at).num_seconds().max(0) as u64,
+                job_count: sched.jobs.len(),
+                running_count: sched.running_jobs.len(),
+                db_attached: sched.db.is_some(),
+                history_buffered: sched.history.len(),
+                history_dropped: sched.history.dropped(),
+                db_path: Some(sched.paths.db.clone()),
+                db_error,
+                retry_backlog: pending_retries.len(),
+                queued_runs: sched.queued_runs.values().map(|q| q.len()).sum(),
+                running,
+                next_runs,
+                pending_retries,
+                recent_failures,
+                notification_failures,
+                profile: sched.profiler.snapshot(top),
+                ha: sched.leadership.as_ref().map(|leadership| leadership.status()),
+            })
+        },
+        Request::HealthCheck => Response::Health(health::check(scheduler, profiler).await),
+        Request::ListAgents => Response::AgentList(profiler.read(scheduler, "ipc").agents.list()),
+        Request::BackupDatabase { path } => {
+            let (db, db_path) = {
+                let sched = profiler.read(scheduler, "ipc");
+                (sched.db.clone(), sched.paths.db.clone())
+            };
+            if db.is_none() {
+                return Response::Error("No database configured".to_string());
+            }
+            // Reads the database through a connection of its own, so the daemon's isn't held
+            match tokio::task::spawn_blocking(move || backup::backup(&db_path, &path)).await {
+                Ok(Ok(report)) => Response::Backup(report),
+                Ok(Err(e)) => Response::Error(format!("Backup failed: {}", e)),
+                Err(e) => Response::Error(format!("Backup failed: {}", e)),
+            }
+        },
+        Request::RestoreDatabase { path } => {
+            let (db, db_path) = {
+                let sched = profiler.read(scheduler, "ipc");
+                (sched.db.clone(), sched.paths.db.clone())
+            };
+            let db = match db {
+                Some(db) => db,
+                None => return Response::Error("No database configured".to_string()),
+            };
+            let restored = tokio::task::spawn_blocking(move || backup::restore(&db, &db_path, &path)).await;
+            match restored {
+                Ok(Ok(report)) => {
+                    profiler.write(scheduler, "ipc").reload_from_db();
+                    log::warn!("Database restored from {}: {} jobs, {} history entries", report.path, report.jobs, report.history_entries);
+                    Response::Backup(report)
+                }
+                Ok(Err(e)) => Response::Error(format!("Restore failed: {}", e)),
+                Err(e) => Response::Error(format!("Restore failed: {}", e)),
+            }
+        },
+        Request::GetHistory { job_id, limit, filter } => {
+            let search = match db::HistorySearch::new(job_id, filter) {
+                Ok(search) => search,
+                Err(e) => return Response::Error(e),
+            };
+            // Database reads happen after the scheduler lock is released, on the blocking pool,
+            // so they hold up neither ticks nor other connections
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            if let Some(db) = db {
+                match db::query(&db, move |db| db.search_history(&search, limit)).await {
+                    Ok(history) => Response::HistoryList(history),
+                    Err(e) => Response::Error(format!("DB Error: {}", e)),
+                }
+            } else {
+                Response::Error("No database configured".to_string())
+            }
+        },
+        Request::GetMetrics(job_id) => {
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            if let Some(db) = db {
+                let id = job_id.0.clone();
+                let metrics = db::query(&db, move |db| db.get_job_metrics(&id)).await;
+                match metrics {
+                    Ok(Some(stats)) => Response::JobStats(stats),
+                    Ok(None) if profiler.read(scheduler, "ipc").jobs.contains_key(&job_id.0) => Response::JobStats(common::JobStats {
+                        job_id: job_id.0,
+                        ..Default::default()
+                    }),
+                    Ok(None) => Response::Error("Job not found".to_string()),
+                    Err(e) => Response::Error(format!("DB Error: {}", e)),
+                }
+            } else {
+                Response::Error("No database configured".to_string())
+            }
+        },
+        Request::GetExecution { job_id, execution_id } => {
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            if let Some(db) = db {
+                let detail = db::query(&db, move |db| db.get_execution(&job_id.0, &execution_id)
+                    .and_then(|entry| Ok((entry, db.get_timeline(&execution_id)?)))).await;
+                match detail {
+                    Ok((entry, timeline)) => Response::ExecutionDetail { entry, timeline },
+                    Err(e) => Response::Error(format!("DB Error: {}", e)),
+                }
+            } else {
+                Response::Error("No database configured".to_string())
+            }
+        },
+        Request::SuggestRebalance { days, max_per_slot } => {
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            if let Some(db) = db {
+                let since = chrono::Utc::now() - chrono::Duration::days(days as i64);
+                let starts = db::query(&db, move |db| db.execution_starts(&since)).await;
+                match starts {
+                    Ok(starts) => {
+                        let sched = profiler.read(scheduler, "ipc");
+                        Response::RebalancePlan(rebalance::suggest(&sched.jobs, &starts, max_per_slot))
+                    }
+                    Err(e) => Response::Error(format!("DB Error: {}", e)),
+                }
+            } else {
+                Response::Error("No database configured".to_string())
+            }
+        },
+        Request::AddTemplate(_) | Request::ListTemplates | Request::GetTemplate(_) | Request::RemoveTemplate(_) => {
+            let db = profiler.read(scheduler, "ipc").db.clone();
+            match db {
+                None => Response::Error("No database configured".to_string()),
+                Some(db) => {
+                    let db = db.lock().unwrap();
+                    // Each user has templates of their own (their env may hold credentials);
+                    // root sees everyone's, and its own first
+                    let owner = (requester_owner != "root").then_some(requester_owner);
+                    let result = db.load_templates(owner).and_then(|templates| {
+                        let find = |name: &str| templates.iter().filter(|t| t.name == name)
+                            .min_by_key(|t| t.owner != requester_owner)
+                            .cloned();
+                        Ok(match request {
+                            Request::AddTemplate(mut template) => {
+                                template.owner = requester_owner.to_string();
+                                db.save_template(&template)?;
+                                Response::Ok
+                            },
+                            Request::GetTemplate(name) => Response::TemplateDetail(find(&name)),
+                            Request::RemoveTemplate(name) => match find(&name) {
+                                None => Response::Error(format!("Template {} not found", name)),
+                                Some(existing) => {
+                                    db.remove_template(&existing.owner, &name)?;
+                                    Response::Ok
+                                }
+                            },
+                            _ => Response::TemplateList(templates.clone()),
+                        })
+                    });
+                    result.unwrap_or_else(|e| Response::Error(format!("DB Error: {}", e)))
+                }
+            }
+        },
+        Request::AddWorkflow(_) | Request::ListWorkflows | Request::RemoveWorkflow(_) | Request::RunWorkflow(_)
+        | Request::GetWorkflowRuns { .. } => {
+            let mut sched = profiler.write(scheduler, "ipc");
+            let db = match sched.db {
+                Some(ref db) => db.clone(),
+                None => return Response::Error("No database configured".to_string()),
+            };
+            // The database lock is taken per statement: starting a run saves it too
+            let workflows = match db.lock().unwrap().load_workflows() {
+                Ok(workflows) => workflows,
+                Err(e) => return Response::Error(format!("DB Error: {}", e)),
+            };
+            let find = |name: &str| workflows.iter().find(|w| w.name == name).cloned();
+            let permitted = |owner: &str| owner == requester_owner || requester_owner == "root";
+            let result = match request {
+                Request::AddWorkflow(mut workflow) => {
+                    workflow.owner = requester_owner.to_string();
+                    // Steps may name their jobs; they are kept by ID
+                    for step in &mut workflow.steps {
+                        for job in std::iter::once(&mut step.job).chain(step.after.iter_mut()) {
+                            if let Some(id) = sched.job_id_for(job, requester_owner) {
+                                *job = id.0;
+                            }
+                        }
+                    }
+                    let invalid_step = workflow.steps.iter().find_map(|step| match sched.jobs.get(&step.job) {
+                        None => Some(format!("Job {} not found", step.job)),
+                        Some(job) if !permitted(&job.owner) => {
+                            Some(format!("Permission denied: Job {} is owned by {}", job.id, job.owner))
+                        }
+                        Some(job) if matches!(job.schedule, common::ScheduleConfig::Heartbeat(_)) => {
+                            Some(format!("Heartbeat job {} can't be a workflow step", job.id))
+                        }
+                        Some(_) => None,
+                    });
+                    match (workflow.validate(), find(&workflow.name), invalid_step) {
+                        (Err(e), _, _) => Ok(Response::Error(e.to_string())),
+                        (_, Some(existing), _) if !permitted(&existing.owner) => {
+                            Ok(Response::Error(format!("Permission denied: Cannot overwrite workflow owned by {}", existing.owner)))
+                        }
+                        (_, _, Some(problem)) => Ok(Response::Error(problem)),
+                        _ => db.lock().unwrap().save_workflow(&workflow).map(|_| Response::Ok),
+                    }
+                }
+                Request::RemoveWorkflow(name) | Request::RunWorkflow(name) | Request::GetWorkflowRuns { name, .. }
+                    if find(&name).is_some_and(|w| !permitted(&w.owner)) =>
+                {
+                    Ok(Response::Error(format!("Permission denied: Workflow {} belongs to another user", name)))
+                }
+                Request::RemoveWorkflow(name) | Request::RunWorkflow(name) | Request::GetWorkflowRuns { name, .. }
+                    if find(&name).is_none() =>
+                {
+                    Ok(Response::Error(format!("Workflow {} not found", name)))
+                }
+                Request::RemoveWorkflow(name) => db.lock().unwrap().remove_workflow(&name).map(|_| Response::Ok),
+                Request::RunWorkflow(name) => Ok(match sched.start_workflow(find(&name).unwrap()) {
+                    Ok(run_id) => Response::WorkflowStarted { run_id },
+                    Err(e) => Response::Error(e),
+                }),
+                Request::GetWorkflowRuns { name, limit } => db.lock().unwrap().workflow_runs(&name, limit).map(Response::WorkflowRuns),
+                _ => Ok(Response::WorkflowList(workflows.clone())),
+            };
+            result.unwrap_or_else(|e| Response::Error(format!("DB Error: {}", e)))
+        },
+        Request::GetSecret { .. } if requester_owner != "root" => {
+            // Every non-root client is the same owner, so values are only handed to root;
+            // jobs get theirs when they are spawned
+            Response::Error("Permission denied: Only root may read secret values".to_string())
+        },
+        Request::SetSecret { .. } | Request::GetSecret { .. } | Request::RemoveSecret { .. } | Request::ListSecrets => {
+            let (db, secrets) = {
+                let sched = profiler.read(scheduler, "ipc");
+                (sched.db.clone(), sched.secrets.clone())
+            };
+            match (&db, &secrets) {
+                (None, _) => Response::Error("No database configured".to_string()),
+                (_, None) => Response::Error(format!("Secrets are unavailable: no key at {}",
+                    profiler.read(scheduler, "ipc").paths.secrets_key)),
+                (Some(db), Some(store)) => {
+                    let db = db.lock().unwrap();
+                    let result = match request {
+                        Request::SetSecret { name, value } => store.set(&db, requester_owner, &name, &value).map(|_| Response::Ok),
+                        Request::GetSecret { name } => store.get(&db, requester_owner, &name).map(|value| match value {
+                            Some(value) => Response::SecretValue(value),
+                            None => Response::Error(format!("Secret {} not found", name)),
+                        }),
+                        Request::RemoveSecret { name } => db.remove_secret(requester_owner, &name).map_err(Into::into).map(|removed| {
+                            if removed { Response::Ok } else { Response::Error(format!("Secret {} not found", name)) }
+                        }),
+                        _ => db.list_secrets(requester_owner).map_err(Into::into).map(Response::SecretList),
+                    };
+                    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+                }
+            }
+        },
+        Request::TestNotification { job_id, channel, preview } => {
+            let (lookup, db) = {
+                let sched = profiler.read(scheduler, "ipc");
+                let lookup = match sched.jobs.get(&job_id.0) {
+                    None => Err("Job not found".to_string()),
+                    Some(job) if job.owner != requester_owner && requester_owner != "root" => {
+                        Err(format!("Permission denied: Cannot test notifications of job owned by {}", job.owner))
+                    }
+                    Some(job) => Ok(sched.effective_job(job)),
+                };
+                (lookup, sched.db.clone())
+            };
+            match lookup {
+                Err(e) => Response::Error(e),
+                Ok(job) => {
+                    let configured = notifier::configured_channels(&job).len();
+                    match channel {
+                        _ if configured == 0 => Response::Error(format!("Job {} has no notification channels configured", job.id)),
+                        Some(index) if index >= configured => {
+                            Response::Error(format!("Channel {} does not exist; job {} has {} channel(s)", index, job.id, configured))
+                        }
+                        _ => Response::NotificationTestResults(notifier::test_channels(&job, channel, preview, db.as_ref()).await),
+                    }
+                }
+            }
+        },
+        Request::ListDeadLetters => {
+            let sched = profiler.read(scheduler, "ipc");
+            let mut dead_letters: Vec<common::DeadLetter> = sched.dead_letters.values()
+                .filter(|d| requester_owner == "root"
+                    || sched.jobs.get(&d.job_id).is_some_and(|job| job.owner == requester_owner))
+                .cloned()
+                .collect();
+            dead_letters.sort_by(|a, b| b.failed_at.cmp(&a.failed_at));
+            Response::DeadLetterList(dead_letters)
+        },
+        Request::RetryJob(job_id) => {
+            {
+                let mut sched = profiler.write(scheduler, "ipc");
+                let job = match sched.jobs.get(&job_id.0) {
+                    Some(job) => job.clone(),
+                    None => return Response::Error("Job not found".to_string()),
+                };
+                if job.owner != requester_owner && requester_owner != "root" {
+                    return Response::Error(format!("Permission denied: Cannot retry job owned by {}", job.owner));
+                }
+                let dead_letter = match sched.clear_dead_letter(&job_id.0) {
+                    Some(dead_letter) => dead_letter,
+                    None => return Response::Error(format!("Job {} is not in the failed state", job_id)),
+                };
+                log::info!("Re-arming job {} after {} consecutive failed runs", job.name, dead_letter.exhaustions);
+                sched.failure_streaks.remove(&job_id.0);
+                if (dead_letter.disabled || job.disabled_reason.is_some()) && !job.enabled {
+                    let mut job = job;
+                    job.enabled = true;
+                    job.disabled_reason = None;
+                    sched.add_job(job);
+                }
+            }
+            start_job(scheduler, profiler, &job_id.0, RunParams::new(), requester_owner, None)
+        },
+        Request::PreviewSchedule { schedule, timezone, count } => {
+            let parsed = match common::parse_schedule(&schedule) {
+                Ok(parsed) => parsed,
+                Err(e) => return Response::Error(format!("Invalid schedule '{}': {}", schedule, e)),
+            };
+            let runs = scheduler::upcoming_runs(&parsed, timezone.as_deref(), chrono::Utc::now(),
+                count.min(MAX_PREVIEW_RUNS));
+            match (runs, timezone.and_then(|tz| tz.parse::<chrono_tz::Tz>().ok())) {
+                (Ok(runs), Some(tz)) => Response::ScheduleTimes(runs.iter().map(|t| t.with_timezone(&tz).to_rfc3339()).collect()),
+                (Ok(runs), None) => Response::ScheduleTimes(runs.iter().map(|t| t.with_timezone(&chrono::Local).to_rfc3339()).collect()),
+                (Err(e), _) => Response::Error(e),
+            }
+        },
+        Request::GetExecutionWindows { job_id, limit } => {
+            let db = match profiler.read(scheduler, "ipc").db.clone() {
+                Some(db) => db,
+                None => return Response::Error("No database configured".to_string()),
+            };
+            match db::query(&db, move |db| db.get_execution_windows(job_id.as_ref().map(|id| id.0.as_str()), limit)).await {
+                Ok(windows) => Response::ExecutionWindows(windows),
+                Err(e) => Response::Error(format!("DB Error: {}", e)),
+            }
+        },
+        Request::GetAuditLog { job_id, since, limit } => {
+            let since = match since.as_deref().map(chrono::DateTime::parse_from_rfc3339).transpose() {
+                Ok(since) => since.map(|t| t.with_timezone(&chrono::Utc)),
+                Err(e) => return Response::Error(format!("Invalid time: {}", e)),
+            };
+            let db = match profiler.read(scheduler, "ipc").db.clone() {
+                Some(db) => db,
+                None => return Response::Error("No database configured".to_string()),
+            };
+            // Others only see what was done to their own jobs
+            let owner = (requester_owner != "root").then(|| requester_owner.to_string());
+            let entries = db::query(&db, move |db| {
+                db.get_audit_log(job_id.as_ref().map(|id| id.0.as_str()), owner.as_deref(), since.as_ref(), limit)
+            }).await;
+            match entries {
+                Ok(entries) => Response::AuditLog(entries),
+                Err(e) => Response::Error(format!("Failed to read audit log: {}", e)),
+            }
+        },
+    }
+}
+
+async fn send_response(socket: &mut Box<dyn Connection>, resp: &Response) -> std::io::Result<()> {
+    let resp_bytes = encode_frame(resp)?;
+    log::debug!("Response serialized, {} bytes", resp_bytes.len());
+    socket.write_all(&resp_bytes).await
+}
+
+/// Refuse the declarative source reserved for the config file's jobs, which only the daemon
+/// itself applies
+fn check_source(source: &str) -> Result<(), String> {
+    if source == config::CONFIG_SOURCE {
+        return Err(format!("Source {} is reserved for jobs declared in the daemon's config file", source));
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Check a job's settings on their own: its source tags, schedule, user, success criteria,
+/// notifications and executor
+fn check_job(job: &common::Job) -> Result<(), String> {
+    job.tags.iter()
+        .filter_map(|tag| tag.strip_prefix(common::declarative::SOURCE_TAG_PREFIX))
+        .try_for_each(check_source)
+        .and_then(|_| job.schedule.validate())
+        .and_then(|_| account::check_run_as(job))
+        .and_then(|_| job.success_criteria.validate())
+        .and_then(|_| job.notification_config.validate())
+        .and_then(|_| job.check_executor())
+}
+
+/// A job of someone else's that applying `desired` from `source` would change: one applied from
+/// the source before that is declared again, or with `prune` any of those. Returns its ID and owner.
+fn foreign_applied_job(sched: &Scheduler, source: &str, desired: &[common::Job], prune: bool, requester_owner: &str) -> Option<(String, String)> {
+    let source_tag = common::declarative::source_tag(source);
+    sched.jobs.values()
+        .filter(|job| job.owner != requester_owner && requester_owner != "root")
+        .filter(|job| job.tags.contains(&source_tag))
+        .find(|job| prune || desired.iter().any(|d| d.name == job.name))
+        .map(|job| (job.id.0.clone(), job.owner.clone()))
+}
+
+/// Validate a job and add it, or replace the job with its ID if the requester may; answered
+/// with JobAdded
+fn save_job(sched: &mut Scheduler, job: common::Job, requester_owner: &str) -> Response {
+    if let Err(e) = check_job(&job)
+        .and_then(|_| sched.check_name(&job))
+        .and_then(|_| sched.check_triggers(&job)) {
+        return Response::Error(e);
+    }
+    // Check if job exists and verify ownership
+    if let Some(existing) = sched.jobs.get(&job.id.0) {
+        if existing.owner != requester_owner && requester_owner != "root" {
+            return Response::Error(format!("Permission denied: Cannot overwrite job owned by {}", existing.owner));
+        }
+    }
+    let id = job.id.clone();
+    sched.add_job(job);
+    Response::JobAdded(id)
+}
+
+/// Save a job as save_job does, unless that would change nothing: the job of its ID (or name)
+/// is identical by content hash, or with `if_absent` there is one at all
+fn ensure_job(sched: &mut Scheduler, job: common::Job, if_absent: bool, requester_owner: &str) -> Response {
+    let hash = job.content_hash();
+    let outcome = match sched.jobs.get(&job.id.0) {
+        None => common::SaveOutcome::Created,
+        Some(existing) if existing.owner != requester_owner && requester_owner != "root" => {
+            return Response::Error(format!("Permission denied: Cannot overwrite job owned by {}", existing.owner));
+        }
+        Some(existing) if if_absent || existing.content_hash() == hash => {
+            return Response::JobEnsured { id: existing.id.clone(), outcome: common::SaveOutcome::Unchanged, hash: existing.content_hash() };
+        }
+        Some(_) => common::SaveOutcome::Updated,
+    };
+    match save_job(sched, job, requester_owner) {
+        Response::JobAdded(id) => Response::JobEnsured { id, outcome, hash },
+        other => other,
+    }
+}
+
+fn running_execution(ctx: &scheduler::JobExecutionContext, now: chrono::DateTime<chrono::Utc>) -> RunningExecution {
+    RunningExecution {
+        execution_id: ctx.execution_id.clone(),
+        job_id: ctx.job_id.clone(),
+        pid: ctx.pid,
+        started_at: ctx.start_time.to_rfc3339(),
+        duration_seconds: (now - ctx.start_time).num_seconds().max(0) as u64,
+    }
+}
+
+/// Start a job manually, refusing if a run with the same run key is in progress.
+/// With `replay`, the command and environment come from a captured execution instead.
+fn start_job(
+    scheduler: &Arc<RwLock<Scheduler>>,
+    profiler: &SchedulerProfiler,
+    job_id: &str,
+    params: RunParams,
+    requester_owner: &str,
+    replay: Option<&db::ExecutionSnapshot>,
+) -> Response {
+    let mut sched = profiler.write(scheduler, "ipc");
+    if let Some(job) = sched.jobs.get(job_id).cloned() {
+        let run_key = job.run_key(&params);
+        if job.owner != requester_owner && requester_owner != "root" {
+            Response::Error(format!("Permission denied: Cannot start job owned by {}", job.owner))
+        } else if matches!(job.schedule, common::ScheduleConfig::Heartbeat(_)) {
+            Response::Error("Heartbeat jobs have no command to start; use ping".to_string())
+        } else if !sched.has_capacity(&job, &run_key) {
+            let parallel = job.execution_mode == common::ExecutionMode::Parallel;
+            if parallel && job.overflow_policy == common::OverflowPolicy::Queue && replay.is_none() {
+                if sched.enqueue_run(&job, params) {
+                    Response::Ok
+                } else {
+                    Response::Error(format!("Run queue of job {} is full", job.name))
+                }
+            } else if run_key != job.id.0 && sched.is_running(&run_key) {
+                Response::Error(format!("A run with fence key {} is already running", run_key))
+            } else if parallel {
+                Response::Error(format!("Job is at its max_concurrent limit ({})", job.max_concurrent))
+            } else {
+                Response::Error("Job is already running".to_string())
+            }
+        } else if let Some(holder) = sched.group_holder(&job) {
+            let group = job.concurrency_group.clone().unwrap_or_default();
+            if sched.group_policy(&job) == common::OverflowPolicy::Queue && replay.is_none() {
+                if sched.enqueue_run(&job, params) {
+                    Response::Ok
+                } else {
+                    Response::Error(format!("Run queue of job {} is full", job.name))
+                }
+            } else {
+                Response::Error(format!("Concurrency group {} is busy: job {} is running", group, holder))
+            }
+        } else {
+            let mut job_clone = job.clone();
+            if let Some(snapshot) = replay {
+                job_clone.command = snapshot.command.clone();
+                job_clone.args = snapshot.args.clone();
+                job_clone.env = snapshot.env.clone();
+            }
+
+            // Create execution context for manual start
+            let execution_id = sched.begin_execution(&job, run_key, chrono::Utc::now(), false);
+
+            match replay {
+                Some(snapshot) => log::info!("Rerunning execution {} of job {} (execution_id: {})",
+                    snapshot.execution_id, job_clone.name, execution_id),
+                None => log::info!("Manually starting job: {} (execution_id: {})", job_clone.name, execution_id),
+            }
+
+            let s = scheduler.clone();
+            drop(sched);  // Drop lock before executing job
+            Scheduler::execute_job(s, &job_clone, params, execution_id);
+            Response::Ok
+        }
+    } else {
+        Response::Error("Job not found".to_string())
+    }
+}
+
+/// Open the database and bring its schema up to date
+fn open_db(db_path: &str) -> anyhow::Result<Db> {
+    let conn = rusqlite::Connection::open(db_path)?;
+    if let Err(e) = db::tune(&conn) {
+        log::warn!("Failed to tune database connection: {}", e);
+    }
+    let mut migrator = migrations::Migrator::new(conn);
+    migrator.run_migrations()?;
+    Ok(Db::from_connection(migrator.into_connection()))
+}
+
+/// Start replicating the job store to Postgres, if configured, and pruning history and the
+/// audit log past their retention periods
+async fn start_storage(scheduler: &Arc<RwLock<Scheduler>>, config: &config::StorageConfig) {
+    let mut stores: Vec<Arc<dyn storage::Storage>> = Vec::new();
+    let (db, profiler) = {
+        let sched = scheduler.read().unwrap();
+        (sched.db.clone(), sched.profiler.clone())
+    };
+    if let Some(ref db) = db {
+        stores.push(Arc::new(storage::SqliteStorage::new(db.clone())));
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(ref url) = config.postgres {
+        match pg_storage::PgStorage::connect(url).await {
+            Ok(store) => {
+                let store: Arc<dyn storage::Storage> = Arc::new(store);
+                let replica = storage::Replica::start(store.clone());
+                let mut sched = scheduler.write().unwrap();
+                replica.sync(sched.jobs.values().cloned().collect());
+                sched.history.replicate_to(replica.clone());
+                sched.replica = Some(replica);
+                stores.push(store);
+                log::info!("Replicating jobs and history to Postgres");
+            }
+            Err(e) => log::error!("Failed to connect to Postgres; jobs and history are not replicated: {}", e),
+        }
+    }
+    let retention = storage::Retention {
+        history_days: config.history_retention_days,
+        audit_days: config.audit_retention_days,
+        snapshot_days: config.snapshot_retention_days,
+    };
+    storage::spawn_retention(stores, db, profiler, retention);
+}
+
+/// Take part in leader election, if configured, standing by until the lease is won. Returns
+/// the lease, to be released on shutdown.
+async fn start_election(scheduler: &Arc<RwLock<Scheduler>>, config: &config::Config) -> anyhow::Result<Option<Arc<dyn leader::Lease>>> {
+    let ha = match config.ha {
+        Some(ref ha) => ha,
+        None => return Ok(None),
+    };
+    let lease: Arc<dyn leader::Lease> = match ha.lease_file {
+        Some(ref path) => Arc::new(leader::FileLease::new(path)),
+        #[cfg(feature = "postgres")]
+        None => {
+            let url = config.storage.postgres.as_deref().unwrap_or_default();
+            Arc::new(leader::PostgresLease::connect(url).await
+                .map_err(|e| anyhow::anyhow!("Failed to set up the leader lease in Postgres: {}", e))?)
+        }
+        #[cfg(not(feature = "postgres"))]
+        None => return Err(anyhow::anyhow!("ha.lease_file is not set")), // Refused by Config::validate
+    };
+    let node = ha.node.clone().unwrap_or_else(leader::default_node);
+    log::info!("Standing by as {} until elected leader ({})", node, lease.describe());
+    let leadership = Arc::new(leader::Leadership::new(node, lease.describe()));
+    scheduler.write().unwrap().leadership = Some(leadership.clone());
+    leader::spawn(lease.clone(), leadership, chrono::Duration::seconds(ha.lease_seconds as i64), scheduler.clone(), config.orphan_policy);
+    Ok(Some(lease))
+}
+
+/// Attach the database if the daemon runs without one, then have any buffered history retried
+fn retry_persistence(scheduler: &Arc<RwLock<Scheduler>>, db_path: &str) {
+    let (attached, history) = {
+        let sched = scheduler.read().unwrap();
+        (sched.db.is_some(), sched.history.clone())
+    };
+    if !attached {
+        match open_db(db_path) {
+            Ok(db) => scheduler.write().unwrap().attach_db(Arc::new(Mutex::new(db))),
+            Err(e) => {
+                log::warn!("Database at {} still unavailable ({} history records buffered): {}", db_path, history.len(), e);
+                return;
+            }
+        }
+    }
+    if !history.is_empty() {
+        history.retry();
+    }
+}
+
+/// Load the config file and reconcile its declared jobs with the scheduler
+fn reload_config(scheduler: &Arc<RwLock<Scheduler>>, config_path: &str) -> Option<config::Config> {
+    let config = match config::Config::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Failed to load config {}: {}", config_path, e);
+            return None;
+        }
+    };
+
+    {
+        let mut sched = scheduler.write().unwrap();
+        sched.redact_patterns = config.redact_env.clone();
+        sched.acl = config.acl.clone();
+        sched.trusted_keys = config.trusted_keys.clone();
+        sched.boot_delay = config.boot_delay;
+        sched.tag_defaults = config.tag_defaults.clone();
+        sched.concurrency_groups = config.concurrency_groups.clone();
+        sched.max_output_bytes = config.max_output_bytes;
+        sched.execution_logs_kept = config.logging.execution_logs_kept;
+        sched.compress_execution_logs = config.logging.rotation.compress;
+        sched.container_engine = config.container_engine.clone();
+        sched.notification_template = match config.notification_template {
+            Some(ref template) => match common::check_message_template(template) {
+                Ok(()) => Some(template.clone()),
+                Err(e) => {
+                    log::error!("Ignoring notification_template in {}: {}", config_path, e);
+                    None
+                }
+            },
+            None => None,
+        };
+    }
+
+    let file = common::JobFile { jobs: config.jobs.clone() };
+    let desired = match file.into_jobs(config::CONFIG_SOURCE) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log::error!("Invalid job definitions in {}: {} (skipping reconciliation)", config_path, e);
+            return Some(config);
+        }
+    };
+
+    // Config-declared jobs belong to root, like the config file itself
+    let desired: Vec<_> = desired.into_iter()
+        .map(|mut job| {
+            job.owner = "root".to_string();
+            job
+        })
+        .collect();
+
+    let report = match scheduler.write().unwrap().reconcile(desired, config::CONFIG_SOURCE, config.prune_jobs) {
+        Ok(report) => report,
+        Err(e) => {
+            log::error!("Config jobs not reconciled: {}", e);
+            return Some(config);
+        }
+    };
+    log::info!("Config jobs reconciled: {} created, {} updated, {} removed",
+        report.created.len(), report.updated.len(), report.deleted.len());
+    for id in &report.created {
+        log::info!("Config job created: {}", id);
+    }
+    for id in &report.updated {
+        log::info!("Config job updated: {}", id);
+    }
+    for id in &report.deleted {
+        log::info!("Config job removed: {}", id);
+    }
+    Some(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensuring_a_tagged_job_again_changes_nothing() {
+        let mut sched = Scheduler::new(None);
+        let defaults: config::TagDefaults = serde_json::from_value(serde_json::json!({
+            "resource_limits": { "timeout_seconds": 3600 },
+        })).unwrap();
+        sched.tag_defaults = std::collections::HashMap::from([("production".to_string(), defaults)]);
+        let definition: common::JobDefinition = serde_json::from_value(serde_json::json!({
+            "name": "backup", "schedule": "every 1h", "command": "true", "tags": ["production"],
+        })).unwrap();
+        let mut job = definition.into_job("test").unwrap();
+        job.id = common::JobId("backup".to_string());
+        job.owner = "alice".to_string();
+
+        let first = ensure_job(&mut sched, job.clone(), false, "alice");
+        assert!(matches!(first, Response::JobEnsured { outcome: common::SaveOutcome::Created, .. }), "{:?}", first);
+        let second = ensure_job(&mut sched, job.clone(), false, "alice");
+        match second {
+            Response::JobEnsured { outcome, hash, .. } => {
+                assert_eq!(outcome, common::SaveOutcome::Unchanged);
+                assert_eq!(hash, job.content_hash());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Recent durations kept per job for percentile calculation
+const DURATION_SAMPLES: usize = 1000;
+
+#[derive(Debug, Default)]
+struct JobMetrics {
+    executions: u64,
+    successes: u64,
+    failures: u64,
+    duration_sum_ms: u64,
+    duration_count: u64,
+    recent_durations: VecDeque<u64>,
+}
+
+// Name, help text and value of a per-job counter
+type Counter = (&'static str, &'static str, fn(&JobMetrics) -> u64);
+
+impl JobMetrics {
+    fn record_duration(&mut self, duration_ms: u64) {
+        self.duration_sum_ms += duration_ms;
+        self.duration_count += 1;
+        if self.recent_durations.len() == DURATION_SAMPLES {
+            self.recent_durations.pop_front();
+        }
+        self.recent_durations.push_back(duration_ms);
+    }
+}
+
+/// In-memory execution counters exported in Prometheus text format
+pub struct MetricsCollector {
+    jobs: Mutex<HashMap<String, JobMetrics>>,
+    ticks: AtomicU64,
+}
+
+impl Default for MetricsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsCollector {
+    pub fn new() -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            ticks: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_execution(&self, job_id: &str) {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.entry(job_id.to_string()).or_default().executions += 1;
+    }
+
+    pub fn record_success(&self, job_id: &str, duration_ms: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let m = jobs.entry(job_id.to_string()).or_default();
+        m.successes += 1;
+        m.record_duration(duration_ms);
+    }
+
+    pub fn record_failure(&self, job_id: &str, duration_ms: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let m = jobs.entry(job_id.to_string()).or_default();
+        m.failures += 1;
+        m.record_duration(duration_ms);
+    }
+
+    pub fn record_tick(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn export(&self, queue_depth: usize) -> String {
+        let jobs = self.jobs.lock().unwrap();
+        let mut ids: Vec<&String> = jobs.keys().collect();
+        ids.sort();
+
+        let mut out = String::new();
+        let counters: [Counter; 3] = [
+            ("lunasched_job_executions_total", "Total job executions", |m| m.executions),
+            ("lunasched_job_successes_total", "Successful job executions", |m| m.successes),
+            ("lunasched_job_failures_total", "Failed job executions", |m| m.failures),
+        ];
+        for (name, help, value) in counters.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for id in &ids {
+                let _ = writeln!(out, "{}{{job_id=\"{}\"}} {}", name, escape_label(id), value(&jobs[*id]));
+            }
+        }
+
+        let _ = writeln!(out, "# HELP lunasched_job_duration_ms Job execution duration in milliseconds");
+        let _ = writeln!(out, "# TYPE lunasched_job_duration_ms summary");
+        for id in &ids {
+            let m = &jobs[*id];
+            let label = escape_label(id);
+            let mut sorted: Vec<u64> = m.recent_durations.iter().copied().collect();
+            sorted.sort_unstable();
+            if !sorted.is_empty() {
+                for q in [0.5, 0.95, 0.99] {
+                    let _ = writeln!(out, "lunasched_job_duration_ms{{job_id=\"{}\",quantile=\"{}\"}} {}",
+                        label, q, percentile(&sorted, q));
+                }
+            }
+            let _ = writeln!(out, "lunasched_job_duration_ms_sum{{job_id=\"{}\"}} {}", label, m.duration_sum_ms);
+            let _ = writeln!(out, "lunasched_job_duration_ms_count{{job_id=\"{}\"}} {}", label, m.duration_count);
+        }
+
+        let _ = writeln!(out, "# HELP lunasched_scheduler_ticks_total Scheduler ticks since start");
+        let _ = writeln!(out, "# TYPE lunasched_scheduler_ticks_total counter");
+        let _ = writeln!(out, "lunasched_scheduler_ticks_total {}", self.ticks.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP lunasched_queue_depth Executions currently running");
+        let _ = writeln!(out, "# TYPE lunasched_queue_depth gauge");
+        let _ = writeln!(out, "lunasched_queue_depth {}", queue_depth);
+
+        out
+    }
+}
+
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    let idx = ((sorted.len() as f64 - 1.0) * q).round() as usize;
+    sorted[idx.min(sorted.len() - 1)]
+}
+
+pub(crate) fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
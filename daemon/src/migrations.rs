@@ -1,5 +1,5 @@
 use rusqlite::{params, Connection, Result};
-const SCHEMA_VERSION: i32 = 3;
+pub const SCHEMA_VERSION: i32 = 41;
 
 pub struct Migrator {
     conn: Connection,
@@ -40,14 +40,6 @@ impl Migrator {
         Ok(version.unwrap_or(0))
     }
 
-    fn set_version(&self, version: i32) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO schema_version (version) VALUES (?1)",
-            params![version],
-        )?;
-        Ok(())
-    }
-
     fn migrate_from(&mut self, from_version: i32) -> Result<()> {
         let tx = self.conn.transaction()?;
 
@@ -57,6 +49,44 @@ impl Migrator {
                 1 => Self::migrate_to_v1_impl(&tx)?,
                 2 => Self::migrate_to_v2_impl(&tx)?,
                 3 => Self::migrate_to_v3_impl(&tx)?,
+                4 => Self::migrate_to_v4_impl(&tx)?,
+                5 => Self::migrate_to_v5_impl(&tx)?,
+                6 => Self::migrate_to_v6_impl(&tx)?,
+                7 => Self::migrate_to_v7_impl(&tx)?,
+                8 => Self::migrate_to_v8_impl(&tx)?,
+                9 => Self::migrate_to_v9_impl(&tx)?,
+                10 => Self::migrate_to_v10_impl(&tx)?,
+                11 => Self::migrate_to_v11_impl(&tx)?,
+                12 => Self::migrate_to_v12_impl(&tx)?,
+                13 => Self::migrate_to_v13_impl(&tx)?,
+                14 => Self::migrate_to_v14_impl(&tx)?,
+                15 => Self::migrate_to_v15_impl(&tx)?,
+                16 => Self::migrate_to_v16_impl(&tx)?,
+                17 => Self::migrate_to_v17_impl(&tx)?,
+                18 => Self::migrate_to_v18_impl(&tx)?,
+                19 => Self::migrate_to_v19_impl(&tx)?,
+                20 => Self::migrate_to_v20_impl(&tx)?,
+                21 => Self::migrate_to_v21_impl(&tx)?,
+                22 => Self::migrate_to_v22_impl(&tx)?,
+                23 => Self::migrate_to_v23_impl(&tx)?,
+                24 => Self::migrate_to_v24_impl(&tx)?,
+                25 => Self::migrate_to_v25_impl(&tx)?,
+                26 => Self::migrate_to_v26_impl(&tx)?,
+                27 => Self::migrate_to_v27_impl(&tx)?,
+                28 => Self::migrate_to_v28_impl(&tx)?,
+                29 => Self::migrate_to_v29_impl(&tx)?,
+                30 => Self::migrate_to_v30_impl(&tx)?,
+                31 => Self::migrate_to_v31_impl(&tx)?,
+                32 => Self::migrate_to_v32_impl(&tx)?,
+                33 => Self::migrate_to_v33_impl(&tx)?,
+                34 => Self::migrate_to_v34_impl(&tx)?,
+                35 => Self::migrate_to_v35_impl(&tx)?,
+                36 => Self::migrate_to_v36_impl(&tx)?,
+                37 => Self::migrate_to_v37_impl(&tx)?,
+                38 => Self::migrate_to_v38_impl(&tx)?,
+                39 => Self::migrate_to_v39_impl(&tx)?,
+                40 => Self::migrate_to_v40_impl(&tx)?,
+                41 => Self::migrate_to_v41_impl(&tx)?,
                 _ => return Err(rusqlite::Error::InvalidQuery),
             }
             
@@ -217,6 +247,422 @@ impl Migrator {
         Ok(())
     }
 
+    fn migrate_to_v4_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job secret redaction patterns
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN redact_env TEXT DEFAULT '[]'", []);
+        Ok(())
+    }
+
+    fn migrate_to_v5_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job concurrency fence key template
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN fence_key TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v6_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-execution history details, with stdout and stderr kept apart
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN execution_id TEXT", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN exit_code INTEGER", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN duration_ms INTEGER", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN scheduled_at DATETIME", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN started_at DATETIME", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN stdout TEXT", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN stderr TEXT", []);
+
+        tx.execute(
+            "CREATE INDEX IF NOT EXISTS idx_history_execution_id ON history(execution_id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v7_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // OOM kills / CPU throttling seen by an execution's cgroup
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN resource_events TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v8_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Command/environment snapshots for `lunasched rerun`. Kept when the job is removed,
+        // so no foreign key.
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN capture_env BOOLEAN DEFAULT 0", []);
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS execution_snapshots (
+                execution_id TEXT PRIMARY KEY,
+                job_id TEXT NOT NULL,
+                command TEXT NOT NULL,
+                args TEXT NOT NULL,
+                env TEXT NOT NULL,
+                params TEXT NOT NULL,
+                captured_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v9_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // What to do with Parallel runs over max_concurrent
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN overflow_policy TEXT DEFAULT '\"Skip\"'", []);
+        Ok(())
+    }
+
+    fn migrate_to_v10_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Missed-run catch-up: per-job policy and the last window fired
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN catch_up TEXT DEFAULT '\"Skip\"'", []);
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS schedule_state (
+                job_id TEXT PRIMARY KEY,
+                last_scheduled_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v11_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job settling period after host boot
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN boot_delay INTEGER", []);
+        Ok(())
+    }
+
+    fn migrate_to_v12_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // One-shot jobs may remove themselves after running
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN delete_after_run INTEGER DEFAULT 0", []);
+        Ok(())
+    }
+
+    fn migrate_to_v13_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Lifecycle timeline per execution
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS execution_events (
+                id INTEGER PRIMARY KEY,
+                execution_id TEXT NOT NULL,
+                job_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                at TEXT NOT NULL,
+                detail TEXT
+            )",
+            [],
+        )?;
+        tx.execute(
+            "CREATE INDEX IF NOT EXISTS idx_execution_events_execution_id ON execution_events(execution_id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v14_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // User a job runs as, when not its owner
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN run_as TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v15_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job working directory and umask
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN working_dir TEXT", []);
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN umask INTEGER", []);
+        Ok(())
+    }
+
+    fn migrate_to_v16_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Encrypted secrets, one namespace per owner
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS secrets (
+                owner TEXT NOT NULL,
+                name TEXT NOT NULL,
+                nonce BLOB NOT NULL,
+                ciphertext BLOB NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (owner, name)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v17_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Job templates, stored as JSON
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS templates (
+                name TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                definition TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v18_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Workflow definitions (JSON) and their runs, with step progress as JSON
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS workflows (
+                name TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                definition TEXT NOT NULL
+            )",
+            [],
+        )?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS workflow_runs (
+                run_id TEXT PRIMARY KEY,
+                workflow TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                finished_at TEXT,
+                run TEXT NOT NULL
+            )",
+            [],
+        )?;
+        tx.execute(
+            "CREATE INDEX IF NOT EXISTS idx_workflow_runs_workflow ON workflow_runs(workflow, started_at)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v19_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Concurrency group shared with unrelated jobs
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN concurrency_group TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v20_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Jobs whose last run failed with no retries left
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS dead_letters (
+                job_id TEXT PRIMARY KEY,
+                job_name TEXT NOT NULL,
+                failed_at TEXT NOT NULL,
+                error TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                exhaustions INTEGER NOT NULL,
+                disabled BOOLEAN NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v21_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Success criteria beyond exit code 0 (JSON)
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN success_criteria TEXT DEFAULT '{}'", []);
+        Ok(())
+    }
+
+    fn migrate_to_v22_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job output limit, and how much output executions wrote before truncation
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN max_output_bytes INTEGER", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN stdout_bytes INTEGER", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN stderr_bytes INTEGER", []);
+        Ok(())
+    }
+
+    fn migrate_to_v23_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Path of the per-execution log file holding the full output
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN log_path TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v24_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Every execution start is recorded, not only fired schedule windows, and marked
+        // finished once over, so a restart can find the executions it interrupted
+        let _ = tx.execute("ALTER TABLE execution_windows ADD COLUMN finished_at DATETIME", []);
+        let _ = tx.execute("ALTER TABLE execution_windows ADD COLUMN fired_window BOOLEAN NOT NULL DEFAULT 1", []);
+        // Earlier rows were never marked; don't mistake them for interrupted executions
+        tx.execute("UPDATE execution_windows SET finished_at = actual_start_time WHERE finished_at IS NULL", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v25_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Requests that changed something, with who sent them
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                at DATETIME NOT NULL,
+                uid INTEGER,
+                user TEXT NOT NULL,
+                action TEXT NOT NULL,
+                job_id TEXT,
+                summary TEXT NOT NULL,
+                outcome TEXT NOT NULL
+            )",
+            [],
+        )?;
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_audit_log_job ON audit_log(job_id, at)", [])?;
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_audit_log_at ON audit_log(at)", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v26_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Per-job run counters, updated with each recorded execution
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS job_metrics (
+                job_id TEXT PRIMARY KEY,
+                total_runs INTEGER NOT NULL DEFAULT 0,
+                successes INTEGER NOT NULL DEFAULT 0,
+                failures INTEGER NOT NULL DEFAULT 0,
+                timed_runs INTEGER NOT NULL DEFAULT 0,
+                total_duration_ms INTEGER NOT NULL DEFAULT 0,
+                last_duration_ms INTEGER,
+                last_run_at DATETIME,
+                last_success_at DATETIME,
+                last_failure_at DATETIME
+            )",
+            [],
+        )?;
+        // Start from the executions already in history
+        tx.execute(
+            "INSERT OR IGNORE INTO job_metrics (job_id, total_runs, successes, failures, timed_runs,
+                 total_duration_ms, last_run_at, last_success_at, last_failure_at)
+             SELECT job_id, COUNT(*),
+                 SUM(status = 'success'),
+                 SUM(status NOT IN ('success', 'unknown')),
+                 COUNT(duration_ms),
+                 COALESCE(SUM(duration_ms), 0),
+                 MAX(started_at),
+                 MAX(CASE WHEN status = 'success' THEN started_at END),
+                 MAX(CASE WHEN status NOT IN ('success', 'unknown') THEN started_at END)
+             FROM history WHERE execution_id IS NOT NULL GROUP BY job_id",
+            [],
+        )?;
+        tx.execute(
+            "UPDATE job_metrics SET last_duration_ms = (
+                 SELECT duration_ms FROM history
+                 WHERE history.job_id = job_metrics.job_id AND execution_id IS NOT NULL
+                 ORDER BY started_at DESC LIMIT 1
+             )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn migrate_to_v27_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Notification deliveries are retried; delivered_at is now only set once delivered
+        let _ = tx.execute("ALTER TABLE notification_log ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0", []);
+        let _ = tx.execute("ALTER TABLE notification_log ADD COLUMN created_at DATETIME", []);
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_notification_log_status ON notification_log(status, created_at)", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v28_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // New interval jobs wait one interval unless run_immediately is set
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN run_immediately INTEGER DEFAULT 0", []);
+        Ok(())
+    }
+
+    fn migrate_to_v29_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Jobs started when a run succeeds or fails for good
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN on_success_trigger TEXT DEFAULT '[]'", []);
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN on_failure_trigger TEXT DEFAULT '[]'", []);
+        Ok(())
+    }
+
+    fn migrate_to_v30_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Outcome and output of the hook run after an execution
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN hook_result TEXT", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN hook_output TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v31_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Job IDs no longer derive from names, which are unique per owner instead. Existing jobs
+        // keep their IDs; a name shared by one owner's jobs (only possible through import) is
+        // kept by the first, the others are named after their IDs.
+        tx.execute(
+            "UPDATE jobs SET name = id WHERE EXISTS (
+                 SELECT 1 FROM jobs AS other
+                 WHERE other.owner = jobs.owner AND other.name = jobs.name AND other.id < jobs.id
+             )",
+            [],
+        )?;
+        tx.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_jobs_owner_name ON jobs(owner, name)", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v32_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Jobs the daemon disables after failing executions or once expired, and why it did
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN disable_after_failures INTEGER", []);
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN expires_at TEXT", []);
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN disabled_reason TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v33_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // History is searched by job, status and time, newest first
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_history_job_run_at ON history(job_id, run_at)", [])?;
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_history_status_run_at ON history(status, run_at)", [])?;
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_history_run_at ON history(run_at)", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v34_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Agent (name or label) a job runs on
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN target TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v35_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // What runs the job's command (JSON); NULL reads as Local
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN executor TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v36_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Restrictions on the job's processes (JSON); NULL reads as none
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN sandbox TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v37_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Body of the script a job runs instead of a command line
+        let _ = tx.execute("ALTER TABLE jobs ADD COLUMN script TEXT", []);
+        Ok(())
+    }
+
+    fn migrate_to_v38_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Signal that killed an execution, and which attempt of its run it was
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN signal INTEGER", []);
+        let _ = tx.execute("ALTER TABLE history ADD COLUMN attempt INTEGER", []);
+        Ok(())
+    }
+
+    fn migrate_to_v39_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Owner of the job an audit entry is about, so entries are filtered by it in SQL and
+        // outlive the job; earlier entries get the owner of their job if it still exists
+        let _ = tx.execute("ALTER TABLE audit_log ADD COLUMN owner TEXT", []);
+        tx.execute("UPDATE audit_log SET owner = (SELECT owner FROM jobs WHERE jobs.id = audit_log.job_id) WHERE owner IS NULL", [])?;
+        tx.execute("CREATE INDEX IF NOT EXISTS idx_audit_log_owner ON audit_log(owner, id)", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v40_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // Template names are per owner
+        tx.execute(
+            "CREATE TABLE templates_by_owner (
+                name TEXT NOT NULL,
+                owner TEXT NOT NULL,
+                definition TEXT NOT NULL,
+                PRIMARY KEY (owner, name)
+            )",
+            [],
+        )?;
+        tx.execute("INSERT INTO templates_by_owner (name, owner, definition) SELECT name, owner, definition FROM templates", [])?;
+        tx.execute("DROP TABLE templates", [])?;
+        tx.execute("ALTER TABLE templates_by_owner RENAME TO templates", [])?;
+        Ok(())
+    }
+
+    fn migrate_to_v41_impl(tx: &rusqlite::Transaction) -> Result<()> {
+        // max_attempts is optional: 0 used to mean unset, and now opts out of tag defaults
+        tx.execute(
+            "UPDATE jobs SET retry_policy = replace(retry_policy, '{\"max_attempts\":0,', '{\"max_attempts\":null,')",
+            [],
+        )?;
+        Ok(())
+    }
+
     pub fn into_connection(self) -> Connection {
         self.conn
     }
@@ -0,0 +1,415 @@
+use std::sync::{Arc, Mutex};
+use common::{HistoryEntry, Job, NotificationChannel, NotificationTestResult};
+use serde::Serialize;
+use crate::db::Db;
+
+// Attempts per delivery, and the wait between them: doubling from the initial backoff, capped
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF_SECS: u64 = 2;
+const MAX_BACKOFF_SECS: u64 = 60;
+// Upper bound on one attempt, so a black-holed endpoint can't hold a delivery forever
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Read by syslog daemons and journald alike
+const SYSLOG_SOCKET: &str = "/dev/log";
+// Longest message the Telegram Bot API accepts, in characters
+const TELEGRAM_MAX_LEN: usize = 4096;
+// How much of the end of a run's output {{output_tail}} shows
+const OUTPUT_TAIL_LINES: usize = 10;
+const OUTPUT_TAIL_CHARS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum NotificationEvent {
+    Start,
+    Success,
+    Failure,
+    Retry, // A failed run will be attempted again
+    Disabled, // The daemon stopped scheduling the job; sent to on_failure channels
+    Test,  // `lunasched notify test`
+}
+
+impl std::fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationEvent::Start => write!(f, "start"),
+            NotificationEvent::Success => write!(f, "success"),
+            NotificationEvent::Failure => write!(f, "failure"),
+            NotificationEvent::Retry => write!(f, "retry"),
+            NotificationEvent::Disabled => write!(f, "disabled"),
+            NotificationEvent::Test => write!(f, "test"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub job_id: String,
+    pub job_name: String,
+    pub event: NotificationEvent,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    // Details of the execution, once it has finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tail: Option<String>,
+    #[serde(skip)]
+    template: Option<String>,
+}
+
+impl Notification {
+    pub fn new(job: &Job, event: NotificationEvent, message: impl Into<String>) -> Self {
+        Self {
+            job_id: job.id.0.clone(),
+            job_name: job.name.clone(),
+            event,
+            message: message.into(),
+            execution_id: None,
+            status: None,
+            exit_code: None,
+            duration_ms: None,
+            output_tail: None,
+            template: job.notification_config.template.clone(),
+        }
+    }
+
+    /// Fill in the details of a finished execution from its history entry
+    pub fn with_execution(mut self, entry: &HistoryEntry) -> Self {
+        self.execution_id = entry.execution_id.clone();
+        self.status = Some(entry.status.clone());
+        self.exit_code = entry.exit_code;
+        self.duration_ms = entry.duration_ms;
+        let output = [entry.stdout.as_deref(), entry.stderr.as_deref()]
+            .into_iter()
+            .flatten()
+            .flat_map(str::lines)
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        let tail = output[output.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n");
+        self.output_tail = Some(truncate_start(tail, OUTPUT_TAIL_CHARS)).filter(|tail| !tail.is_empty());
+        self
+    }
+
+    /// The message as most channels send it: the job's template rendered, or the built-in format
+    fn summary(&self) -> String {
+        match self.template {
+            Some(ref template) => self.render(template),
+            None => format!("[lunasched] Job '{}' {}: {}", self.job_name, self.event, self.message),
+        }
+    }
+
+    /// The message without the built-in prefix, for channels that show the job in a title
+    fn body(&self) -> String {
+        match self.template {
+            Some(ref template) => self.render(template),
+            None => self.message.clone(),
+        }
+    }
+
+    /// The message `channel` sends
+    fn text_for(&self, channel: &NotificationChannel) -> String {
+        match channel {
+            NotificationChannel::Ntfy { .. } => self.body(),
+            _ => self.summary(),
+        }
+    }
+
+    fn render(&self, template: &str) -> String {
+        common::render_message(template, |name| {
+            let value = match name {
+                "job.id" => self.job_id.clone(),
+                "job.name" => self.job_name.clone(),
+                "event" => self.event.to_string(),
+                "status" => self.status.clone().unwrap_or_else(|| self.event.to_string()),
+                "message" => self.message.clone(),
+                "duration" => self.duration_ms.map_or("-".to_string(), format_duration_ms),
+                "exit_code" => self.exit_code.map_or("-".to_string(), |code| code.to_string()),
+                "output_tail" => self.output_tail.clone().unwrap_or_default(),
+                "execution_id" => self.execution_id.clone().unwrap_or_default(),
+                _ => return None,
+            };
+            Some(value)
+        })
+    }
+}
+
+// Human-readable run time, e.g. "850ms", "42s" or "1h 3m 20s"
+fn format_duration_ms(ms: i64) -> String {
+    if ms < 1000 {
+        return format!("{}ms", ms);
+    }
+    let secs = ms / 1000;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+/// Channels configured on a job for the given event
+pub fn channels_for(job: &Job, event: NotificationEvent) -> Vec<NotificationChannel> {
+    let config = &job.notification_config;
+    let channels = match event {
+        NotificationEvent::Start => &config.on_start,
+        NotificationEvent::Success => &config.on_success,
+        NotificationEvent::Failure | NotificationEvent::Disabled => &config.on_failure,
+        NotificationEvent::Retry => &config.on_retry,
+        NotificationEvent::Test => return configured_channels(job).into_iter().map(|(_, c)| c).collect(),
+    };
+    channels.clone().unwrap_or_default()
+}
+
+/// Every channel configured on a job with the event it is attached to, in the order
+/// `notify test --channel` numbers them
+pub fn configured_channels(job: &Job) -> Vec<(NotificationEvent, NotificationChannel)> {
+    [NotificationEvent::Start, NotificationEvent::Success, NotificationEvent::Failure, NotificationEvent::Retry]
+        .into_iter()
+        .flat_map(|event| channels_for(job, event).into_iter().map(move |c| (event, c)))
+        .collect()
+}
+
+/// Send a notification to every channel configured for its event, in the background. Each
+/// delivery is retried with backoff on transient errors and tracked in the notification log.
+pub fn dispatch(job: &Job, event: NotificationEvent, message: impl Into<String>, execution_id: Option<&str>, db: Option<&Arc<Mutex<Db>>>) {
+    let channels = channels_for(job, event);
+    if channels.is_empty() {
+        return;
+    }
+
+    let mut notification = Notification::new(job, event, message);
+    notification.execution_id = execution_id.map(str::to_string);
+    // Finished executions are in the history by the time their notifications go out
+    if let (Some(execution_id), Some(db)) = (execution_id, db) {
+        match db.lock().unwrap().get_execution(&job.id.0, execution_id) {
+            Ok(Some(entry)) => notification = notification.with_execution(&entry),
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to look up execution {} for its notification: {}", execution_id, e),
+        }
+    }
+    for channel in channels {
+        tokio::spawn(deliver(channel, notification.clone(), db.cloned()));
+    }
+}
+
+// One delivery, attempted until it succeeds, fails for good, or runs out of attempts
+async fn deliver(channel: NotificationChannel, notification: Notification, db: Option<Arc<Mutex<Db>>>) {
+    let channel_type = channel.kind();
+    let log_id = db.as_ref().and_then(|db| {
+        let execution_id = notification.execution_id.as_deref().unwrap_or_default();
+        db.lock().unwrap()
+            .log_notification(&notification.job_id, execution_id, &notification.event.to_string(), channel_type)
+            .map_err(|e| log::warn!("Failed to record notification for job {}: {}", notification.job_name, e))
+            .ok()
+    });
+
+    let mut backoff = INITIAL_BACKOFF_SECS;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = match tokio::time::timeout(SEND_TIMEOUT, send(&channel, &notification)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("Timed out after {}s", SEND_TIMEOUT.as_secs())),
+        };
+        let retry = matches!(result, Err(ref e) if attempt < MAX_ATTEMPTS && is_transient(e));
+        let (status, error) = match result {
+            Ok(()) => ("delivered", None),
+            Err(e) => (if retry { "retrying" } else { "failed" }, Some(e.to_string())),
+        };
+        if let (Some(db), Some(id)) = (&db, log_id) {
+            if let Err(e) = db.lock().unwrap().update_notification(id, status, attempt, error.as_deref()) {
+                log::warn!("Failed to record notification attempt for job {}: {}", notification.job_name, e);
+            }
+        }
+        let error = match error {
+            None => return,
+            Some(error) => error,
+        };
+        if !retry {
+            log::warn!("Failed to deliver {} notification for job {} via {} after {} attempt(s): {}",
+                notification.event, notification.job_name, channel_type, attempt, error);
+            return;
+        }
+        log::info!("Delivering {} notification for job {} via {} failed (attempt {}/{}), retrying in {}s: {}",
+            notification.event, notification.job_name, channel_type, attempt, MAX_ATTEMPTS, backoff, error);
+        tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+    }
+}
+
+// HTTP errors are worth retrying on 5xx, 408 and 429 (and when no response came back at all);
+// other 4xx mean the request itself is wrong. Email errors are always retried.
+fn is_transient(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
+        Some(status) => status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429,
+        None => true,
+    }
+}
+
+// Upper bound on a single test delivery, so a black-holed SMTP server can't hang the CLI
+const TEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Send a test message through every configured channel (or only the one at `index`),
+/// reporting how each delivery went. Templates are filled in from the job's latest execution.
+/// With `preview`, each channel's message is returned instead of sent.
+pub async fn test_channels(job: &Job, index: Option<usize>, preview: bool, db: Option<&Arc<Mutex<Db>>>) -> Vec<NotificationTestResult> {
+    let mut notification = Notification::new(job, NotificationEvent::Test, "Test notification sent by `lunasched notify test`");
+    let latest = db.and_then(|db| db.lock().unwrap().get_history(&job.id.0, Some(1)).ok())
+        .and_then(|history| history.into_iter().find(|entry| entry.execution_id.is_some()));
+    if let Some(entry) = latest {
+        notification = notification.with_execution(&entry);
+    }
+
+    let mut results = Vec::new();
+    for (i, (event, channel)) in configured_channels(job).into_iter().enumerate() {
+        if matches!(index, Some(index) if index != i) {
+            continue;
+        }
+        let (error, message) = if preview {
+            (None, Some(notification.text_for(&channel)))
+        } else {
+            let error = match tokio::time::timeout(TEST_TIMEOUT, send(&channel, &notification)).await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(_) => Some(format!("Timed out after {}s", TEST_TIMEOUT.as_secs())),
+            };
+            (error, None)
+        };
+        results.push(NotificationTestResult {
+            index: i,
+            event: event.to_string(),
+            channel: channel.kind().to_string(),
+            target: channel.target(),
+            error,
+            message,
+        });
+    }
+    results
+}
+
+/// Deliver a single notification through one channel
+pub async fn send(channel: &NotificationChannel, notification: &Notification) -> anyhow::Result<()> {
+    match channel {
+        NotificationChannel::Email { to, subject } => {
+            let to = to.clone();
+            let subject = subject.clone().unwrap_or_else(|| {
+                format!("[lunasched] {} {}", notification.job_name, notification.event)
+            });
+            let body = notification.summary();
+            tokio::task::spawn_blocking(move || send_email(&to, &subject, &body)).await?
+        }
+        NotificationChannel::Webhook { url, headers } => {
+            let mut payload = serde_json::to_value(notification)?;
+            payload["text"] = notification.summary().into();
+            let client = reqwest::Client::new();
+            let mut req = client.post(url).json(&payload);
+            if let Some(headers) = headers {
+                for (k, v) in headers {
+                    req = req.header(k.as_str(), v.as_str());
+                }
+            }
+            req.send().await?.error_for_status()?;
+            Ok(())
+        }
+        NotificationChannel::Discord { webhook_url } => {
+            let payload = serde_json::json!({ "content": notification.summary() });
+            reqwest::Client::new().post(webhook_url).json(&payload).send().await?.error_for_status()?;
+            Ok(())
+        }
+        NotificationChannel::Slack { webhook_url } => {
+            let payload = serde_json::json!({ "text": notification.summary() });
+            reqwest::Client::new().post(webhook_url).json(&payload).send().await?.error_for_status()?;
+            Ok(())
+        }
+        NotificationChannel::Telegram { bot_token, chat_id } => {
+            let url = format!("https://api.telegram.org/bot{}/sendMessage", bot_token);
+            let payload = serde_json::json!({ "chat_id": chat_id, "text": truncate(notification.summary(), TELEGRAM_MAX_LEN) });
+            // The token is part of the URL, which reqwest errors would otherwise carry into logs
+            reqwest::Client::new().post(url).json(&payload).send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.without_url())?;
+            Ok(())
+        }
+        NotificationChannel::Ntfy { topic, server, token, priority } => {
+            let url = format!("{}/{}", server.as_deref().unwrap_or(NotificationChannel::NTFY_SERVER).trim_end_matches('/'), topic);
+            let default_priority = if notification.event == NotificationEvent::Failure { 4 } else { 3 };
+            let mut req = reqwest::Client::new()
+                .post(url)
+                .header("Title", format!("lunasched: {} {}", notification.job_name, notification.event))
+                .header("Priority", priority.unwrap_or(default_priority).to_string())
+                .body(notification.body());
+            if notification.event == NotificationEvent::Failure {
+                req = req.header("Tags", "warning");
+            }
+            if let Some(token) = token {
+                req = req.bearer_auth(token);
+            }
+            req.send().await?.error_for_status()?;
+            Ok(())
+        }
+        NotificationChannel::Syslog { facility } => {
+            let facility = facility.as_deref().unwrap_or(NotificationChannel::SYSLOG_FACILITY);
+            let code = common::SYSLOG_FACILITIES.iter()
+                .find(|(name, _)| *name == facility)
+                .map(|(_, code)| *code)
+                .ok_or_else(|| anyhow::anyhow!("Unknown syslog facility '{}'", facility))?;
+            // err for failures, warning for retries and disabled jobs, notice otherwise
+            let severity = match notification.event {
+                NotificationEvent::Failure => 3,
+                NotificationEvent::Retry | NotificationEvent::Disabled => 4,
+                _ => 5,
+            };
+            let line = format!("<{}>lunasched[{}]: {}", code * 8 + severity, std::process::id(), notification.summary());
+            let socket = std::os::unix::net::UnixDatagram::unbound()?;
+            socket.send_to(line.as_bytes(), SYSLOG_SOCKET)
+                .map_err(|e| anyhow::anyhow!("Failed to write to {}: {}", SYSLOG_SOCKET, e))?;
+            Ok(())
+        }
+    }
+}
+
+// Cut `text` to at most `max` characters
+fn truncate(mut text: String, max: usize) -> String {
+    if let Some((idx, _)) = text.char_indices().nth(max) {
+        text.truncate(idx);
+    }
+    text
+}
+
+// Keep the last `max` characters of `text`
+fn truncate_start(text: String, max: usize) -> String {
+    match text.char_indices().rev().nth(max) {
+        Some((idx, c)) => text[idx + c.len_utf8()..].to_string(),
+        None => text,
+    }
+}
+
+/// Send an email using the SMTP settings from the LUNASCHED_SMTP_* environment variables
+fn send_email(to: &str, subject: &str, body: &str) -> anyhow::Result<()> {
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{Message, SmtpTransport, Transport};
+
+    let server = std::env::var("LUNASCHED_SMTP_SERVER")
+        .map_err(|_| anyhow::anyhow!("LUNASCHED_SMTP_SERVER is not set"))?;
+    let from = std::env::var("LUNASCHED_EMAIL_FROM")
+        .map_err(|_| anyhow::anyhow!("LUNASCHED_EMAIL_FROM is not set"))?;
+
+    let email = Message::builder()
+        .from(from.parse()?)
+        .to(to.parse()?)
+        .subject(subject)
+        .body(body.to_string())?;
+
+    let mut builder = SmtpTransport::relay(&server)?;
+    if let Ok(port) = std::env::var("LUNASCHED_SMTP_PORT") {
+        builder = builder.port(port.parse()?);
+    }
+    if let (Ok(user), Ok(pass)) = (std::env::var("LUNASCHED_SMTP_USERNAME"), std::env::var("LUNASCHED_SMTP_PASSWORD")) {
+        builder = builder.credentials(Credentials::new(user, pass));
+    }
+
+    builder.build().send(&email)?;
+    Ok(())
+}
@@ -0,0 +1,365 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use common::Response;
+use dashmap::DashMap;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use crate::transport::Connection;
+
+// Oldest live output is dropped beyond this; the execution log keeps the full text
+pub const MAX_LIVE_OUTPUT: usize = 1024 * 1024;
+
+/// Bytes of stdout and of stderr kept in history per execution when neither the job nor the
+/// config sets `max_output_bytes`
+pub const DEFAULT_MAX_OUTPUT_BYTES: u64 = 1024 * 1024;
+
+/// Execution logs kept per job when the config doesn't say
+pub const DEFAULT_EXECUTION_LOGS_KEPT: usize = 50;
+
+// Longest line read at once; longer ones are passed on in pieces of this size
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+
+// How often followers re-check a running execution even without new output
+const FOLLOW_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct OutputState {
+    text: String,
+    dropped: usize, // Bytes trimmed from the front of `text`
+    status: Option<String>, // Set once the execution finished
+}
+
+/// Output of a running execution, readable while the job is still producing it
+#[derive(Debug)]
+pub struct LiveOutput {
+    pub job_id: String,
+    pub execution_id: String,
+    pub started_at: DateTime<Utc>,
+    first_output_at: OnceLock<DateTime<Utc>>,
+    state: Mutex<OutputState>,
+    notify: tokio::sync::Notify,
+}
+
+impl LiveOutput {
+    pub fn new(job_id: &str, execution_id: &str) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            execution_id: execution_id.to_string(),
+            started_at: Utc::now(),
+            first_output_at: OnceLock::new(),
+            state: Mutex::new(OutputState::default()),
+            notify: tokio::sync::Notify::new(),
+        }
+    }
+
+    pub fn append(&self, text: &str) {
+        if !text.is_empty() {
+            let _ = self.first_output_at.set(Utc::now());
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            state.text.push_str(text);
+            if state.text.len() > MAX_LIVE_OUTPUT {
+                let mut cut = state.text.len() - MAX_LIVE_OUTPUT / 2;
+                while !state.text.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                state.text.drain(..cut);
+                state.dropped += cut;
+            }
+        }
+        self.notify.notify_waiters();
+    }
+
+    /// When the execution first wrote to stdout or stderr
+    pub fn first_output_at(&self) -> Option<DateTime<Utc>> {
+        self.first_output_at.get().cloned()
+    }
+
+    pub fn finish(&self, status: &str) {
+        self.state.lock().unwrap().status = Some(status.to_string());
+        self.notify.notify_waiters();
+    }
+
+    /// Text after absolute byte `offset`, the new offset, and the final status if finished
+    fn read_from(&self, offset: usize) -> (String, usize, Option<String>) {
+        let state = self.state.lock().unwrap();
+        let start = offset.saturating_sub(state.dropped).min(state.text.len());
+        (state.text[start..].to_string(), state.dropped + state.text.len(), state.status.clone())
+    }
+}
+
+/// Live outputs of running executions, keyed by execution ID
+pub type LiveOutputs = Arc<DashMap<String, Arc<LiveOutput>>>;
+
+/// Log file of one execution, `<dir>/<job_id>/<execution_id>.log`, receiving its redacted
+/// stdout and stderr as they are written. Writes go through tokio's blocking pool, so a slow
+/// disk holds up the readers, not the runtime's workers.
+#[derive(Debug)]
+pub struct ExecutionLog {
+    pub path: PathBuf,
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl ExecutionLog {
+    /// Create the log of a new execution, first removing the job's oldest logs so that no
+    /// more than `keep` remain with the new one (0 = keep all)
+    pub fn create(dir: &Path, job_id: &str, execution_id: &str, keep: usize) -> std::io::Result<Self> {
+        let path = execution_log_path(dir, job_id, execution_id);
+        let job_dir = path.parent().unwrap_or(dir);
+        std::fs::create_dir_all(job_dir)?;
+        if keep > 0 {
+            prune_logs(job_dir, keep - 1);
+        }
+        let file = std::fs::OpenOptions::new().create(true).append(true).mode(0o640).open(&path)?;
+        Ok(Self { path, file: tokio::sync::Mutex::new(tokio::fs::File::from_std(file)) })
+    }
+
+    async fn write(&self, text: &str) {
+        if let Err(e) = self.file.lock().await.write_all(text.as_bytes()).await {
+            log::debug!("Failed to write {}: {}", self.path.display(), e);
+        }
+    }
+
+    // Wait for the writes so far to reach the file
+    async fn flush(&self) {
+        if let Err(e) = self.file.lock().await.flush().await {
+            log::debug!("Failed to write {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Where the log of an execution is written (before it is gzipped)
+pub fn execution_log_path(dir: &Path, job_id: &str, execution_id: &str) -> PathBuf {
+    dir.join(file_name(job_id)).join(format!("{}.log", file_name(execution_id)))
+}
+
+// Job and execution IDs as single path components
+fn file_name(id: &str) -> String {
+    id.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' }).collect::<String>()
+        .trim_start_matches('.').to_string()
+}
+
+// Remove the oldest execution logs (*.log, *.log.gz) in `dir` beyond the newest `keep`
+fn prune_logs(dir: &Path, keep: usize) {
+    let mut logs: Vec<(std::time::SystemTime, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str().is_some_and(|n| n.ends_with(".log") || n.ends_with(".log.gz")))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect(),
+        Err(_) => return,
+    };
+    if logs.len() <= keep {
+        return;
+    }
+    logs.sort();
+    for (_, path) in &logs[..logs.len() - keep] {
+        if let Err(e) = std::fs::remove_file(path) {
+            log::warn!("Failed to remove old execution log {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// The last `max` bytes of a log file (gzipped if it ends in .gz), starting at a line
+/// boundary if cut
+pub fn read_log_tail(path: &Path, max: u64) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut bytes = Vec::new();
+    let start = if path.extension().is_some_and(|ext| ext == "gz") {
+        // No seeking in a gzip stream: decompress it all, holding on to the tail
+        let mut decoder = flate2::read::GzDecoder::new(file);
+        let mut chunk = vec![0; 64 * 1024];
+        let mut dropped = 0u64;
+        loop {
+            let n = decoder.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..n]);
+            if bytes.len() as u64 > max.saturating_mul(2) {
+                let cut = bytes.len() - max as usize;
+                bytes.drain(..cut);
+                dropped += cut as u64;
+            }
+        }
+        let cut = (bytes.len() as u64).saturating_sub(max) as usize;
+        bytes.drain(..cut);
+        dropped + cut as u64
+    } else {
+        let start = file.metadata()?.len().saturating_sub(max);
+        file.seek(SeekFrom::Start(start))?;
+        file.read_to_end(&mut bytes)?;
+        start
+    };
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    if start == 0 {
+        return Ok(text);
+    }
+    let from = text.find('\n').map_or(0, |i| i + 1);
+    Ok(format!("[{} earlier bytes in {}]\n{}", start + from as u64, path.display(), &text[from..]))
+}
+
+/// What was kept of one child pipe
+#[derive(Debug, Default)]
+pub struct CapturedOutput {
+    pub text: String, // Raw text; its last `limit` bytes after a marker if it was cut
+    pub total_bytes: u64, // Everything the child wrote, kept or not
+}
+
+/// Read a child pipe line by line, feeding redacted lines to the live buffer and the
+/// execution log. Resolves to the raw text once the pipe closes, keeping its last `limit`
+/// bytes (0 = no limit); the rest is still read, so the child never blocks on a full pipe.
+pub fn spawn_reader<R>(
+    pipe: Option<R>,
+    live: Arc<LiveOutput>,
+    log_file: Option<Arc<ExecutionLog>>,
+    secrets: Arc<Vec<String>>,
+    limit: u64,
+) -> tokio::task::JoinHandle<CapturedOutput>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut captured = CapturedOutput::default();
+        let mut reader = match pipe {
+            Some(pipe) => BufReader::new(pipe),
+            None => return captured,
+        };
+        let limit = if limit == 0 { usize::MAX } else { limit as usize };
+        let mut line = Vec::new();
+        let mut truncated = false;
+        loop {
+            line.clear();
+            match (&mut reader).take(MAX_LINE_BYTES).read_until(b'\n', &mut line).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    let text = String::from_utf8_lossy(&line);
+                    let redacted = common::redact::redact(&text, &secrets);
+                    live.append(&redacted);
+                    if let Some(ref log_file) = log_file {
+                        log_file.write(&redacted).await;
+                    }
+                    captured.total_bytes += n as u64;
+                    captured.text.push_str(&text);
+                    // Trim in batches rather than on every line
+                    if captured.text.len() > limit.saturating_mul(2) {
+                        trim_front(&mut captured.text, limit);
+                        truncated = true;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Failed to read output of execution {}: {}", live.execution_id, e);
+                    break;
+                }
+            }
+        }
+        if let Some(ref log_file) = log_file {
+            log_file.flush().await;
+        }
+        if captured.text.len() > limit {
+            trim_front(&mut captured.text, limit);
+            truncated = true;
+        }
+        if truncated {
+            let omitted = captured.total_bytes.saturating_sub(captured.text.len() as u64);
+            // The log may be compressed afterwards, so it isn't named here
+            let full = if log_file.is_some() { "; full output in the execution log" } else { "" };
+            captured.text.insert_str(0, &format!("[output truncated: first {} of {} bytes omitted{}]\n",
+                omitted, captured.total_bytes, full));
+        }
+        captured
+    })
+}
+
+// Drop the front of `text` so that at most `keep` bytes remain
+fn trim_front(text: &mut String, keep: usize) {
+    let mut cut = text.len() - keep;
+    while !text.is_char_boundary(cut) {
+        cut += 1;
+    }
+    text.drain(..cut);
+}
+
+/// Serve `Request::GetJobOutput`: stream the output of the selected running execution
+/// (or the last recorded one) as `OutputChunk`s followed by `OutputEnd`
+pub async fn stream(socket: &mut Box<dyn Connection>, live: Option<Arc<LiveOutput>>, last_output: Option<(String, String)>, follow: bool) {
+    let live = match live {
+        Some(live) => live,
+        None => {
+            let (output, status) = match last_output {
+                Some(entry) => entry,
+                None => {
+                    let _ = crate::send_response(socket, &Response::Error("No running execution or recorded output found".to_string())).await;
+                    return;
+                }
+            };
+            if crate::send_response(socket, &Response::OutputChunk(output)).await.is_ok() {
+                let _ = crate::send_response(socket, &Response::OutputEnd { status: Some(status) }).await;
+            }
+            return;
+        }
+    };
+
+    let mut offset = 0;
+    loop {
+        let notified = live.notify.notified();
+        let (text, next_offset, status) = live.read_from(offset);
+        offset = next_offset;
+        if !text.is_empty() && crate::send_response(socket, &Response::OutputChunk(text)).await.is_err() {
+            return;
+        }
+        if status.is_some() || !follow {
+            let _ = crate::send_response(socket, &Response::OutputEnd { status }).await;
+            return;
+        }
+        let _ = tokio::time::timeout(FOLLOW_POLL, notified).await;
+    }
+}
+
+/// Pick the live execution to show: the given one, or the job's most recently started
+pub fn find_live(outputs: &LiveOutputs, job_id: &str, execution_id: Option<&str>) -> Option<Arc<LiveOutput>> {
+    match execution_id {
+        Some(id) => outputs.get(id).map(|o| o.value().clone()).filter(|o| o.job_id == job_id),
+        None => outputs.iter()
+            .filter(|o| o.job_id == job_id)
+            .max_by_key(|o| o.started_at)
+            .map(|o| o.value().clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reader_fills_the_execution_log() {
+        let dir = std::env::temp_dir().join(format!("lunasched-output-{}", std::process::id()));
+        let log_file = Arc::new(ExecutionLog::create(&dir, "job", "run", 0).unwrap());
+        let live = Arc::new(LiveOutput::new("job", "run"));
+        let secrets = Arc::new(vec!["hunter2".to_string()]);
+        let pipe = std::io::Cursor::new(b"one\npassword hunter2\nlast".to_vec());
+
+        let captured = spawn_reader(Some(pipe), live.clone(), Some(log_file.clone()), secrets, 0).await.unwrap();
+        assert_eq!(captured.text, "one\npassword hunter2\nlast");
+        assert_eq!(captured.total_bytes, 25);
+        // Complete once the reader is done, redacted like the live output
+        let written = std::fs::read_to_string(&log_file.path).unwrap();
+        assert_eq!(written, format!("one\npassword {}\nlast", common::redact::REDACTED));
+        assert_eq!(live.read_from(0).0, written);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reader_keeps_the_tail() {
+        let live = Arc::new(LiveOutput::new("job", "run"));
+        let text: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let pipe = std::io::Cursor::new(text.clone().into_bytes());
+        let captured = spawn_reader(Some(pipe), live, None, Arc::new(Vec::new()), 16).await.unwrap();
+        assert_eq!(captured.total_bytes, text.len() as u64);
+        assert!(captured.text.starts_with("[output truncated: first "), "{}", captured.text);
+        assert!(captured.text.ends_with("line 98\nline 99\n"), "{}", captured.text);
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use common::Job;
+use tokio_postgres::{Client, NoTls};
+use crate::db::ExecutionRecord;
+use crate::storage::{Storage, StorageFuture};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        enabled BOOLEAN NOT NULL,
+        definition JSONB NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id BIGSERIAL PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        status TEXT NOT NULL,
+        output TEXT,
+        execution_id TEXT,
+        exit_code INTEGER,
+        duration_ms BIGINT,
+        scheduled_at TIMESTAMPTZ,
+        started_at TIMESTAMPTZ,
+        stdout TEXT,
+        stderr TEXT,
+        resource_events TEXT,
+        stdout_bytes BIGINT,
+        stderr_bytes BIGINT,
+        log_path TEXT,
+        hook_result TEXT,
+        hook_output TEXT,
+        signal INTEGER,
+        attempt INTEGER
+    );
+    -- Columns added since the table was first created
+    ALTER TABLE history ADD COLUMN IF NOT EXISTS signal INTEGER;
+    ALTER TABLE history ADD COLUMN IF NOT EXISTS attempt INTEGER;
+    CREATE INDEX IF NOT EXISTS idx_history_job_run_at ON history (job_id, run_at);
+    CREATE INDEX IF NOT EXISTS idx_history_run_at ON history (run_at);
+    -- The counters of `lunasched stats`, computed from history. Adopted executions, of unknown
+    -- outcome, count as neither success nor failure.
+    CREATE OR REPLACE VIEW job_metrics AS
+        SELECT job_id,
+               COUNT(*) AS total_runs,
+               COUNT(*) FILTER (WHERE status = 'success') AS successes,
+               COUNT(*) FILTER (WHERE status NOT IN ('success', 'unknown')) AS failures,
+               AVG(duration_ms)::BIGINT AS avg_duration_ms,
+               MAX(started_at) AS last_run_at,
+               MAX(started_at) FILTER (WHERE status = 'success') AS last_success_at,
+               MAX(started_at) FILTER (WHERE status NOT IN ('success', 'unknown')) AS last_failure_at
+        FROM history WHERE execution_id IS NOT NULL
+        GROUP BY job_id;
+";
+
+/// Jobs and history in Postgres, for deployments that already run it and want tools to read
+/// the job store there. Jobs are kept whole as JSON in `jobs.definition` (the same fields as
+/// `lunasched export --format json`); history has the columns of the daemon's own, and the
+/// `job_metrics` view computes the run counters from it.
+pub struct PgStorage {
+    client: Client,
+}
+
+impl PgStorage {
+    /// Connect with a libpq connection string ("host=db user=lunasched dbname=lunasched") and
+    /// create the tables if needed
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::error!("Postgres connection lost: {}", e);
+            }
+        });
+        client.batch_execute(SCHEMA).await?;
+        Ok(Self { client })
+    }
+}
+
+impl Storage for PgStorage {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn add_job(&self, job: Job) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            let definition = serde_json::to_value(&job)?;
+            self.client.execute(
+                "INSERT INTO jobs (id, owner, name, enabled, definition, updated_at) VALUES ($1, $2, $3, $4, $5, now())
+                 ON CONFLICT (id) DO UPDATE SET owner = excluded.owner, name = excluded.name, enabled = excluded.enabled,
+                     definition = excluded.definition, updated_at = now()",
+                &[&job.id.0, &job.owner, &job.name, &job.enabled, &definition],
+            ).await?;
+            Ok(())
+        })
+    }
+
+    fn remove_job(&self, job_id: String) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            self.client.execute("DELETE FROM jobs WHERE id = $1", &[&job_id]).await?;
+            Ok(())
+        })
+    }
+
+    fn load_jobs(&self) -> StorageFuture<'_, HashMap<String, Job>> {
+        Box::pin(async move {
+            let rows = self.client.query("SELECT id, definition FROM jobs", &[]).await?;
+            rows.into_iter()
+                .map(|row| -> anyhow::Result<(String, Job)> {
+                    let job: Job = serde_json::from_value(row.get(1))?;
+                    Ok((row.get(0), job))
+                })
+                .collect()
+        })
+    }
+
+    fn log_history(&self, job_id: String, status: String, message: String) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            self.client.execute("INSERT INTO history (job_id, status, output) VALUES ($1, $2, $3)",
+                &[&job_id, &status, &message]).await?;
+            Ok(())
+        })
+    }
+
+    fn log_execution(&self, record: ExecutionRecord) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            self.client.execute(
+                "INSERT INTO history
+                 (job_id, status, output, execution_id, exit_code, duration_ms, scheduled_at, started_at, stdout, stderr,
+                  resource_events, stdout_bytes, stderr_bytes, log_path, hook_result, hook_output,
+                  signal, attempt)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+                &[
+                    &record.job_id, &record.status, &record.message, &record.execution_id, &record.exit_code,
+                    &record.duration_ms, &record.scheduled_at, &record.started_at, &record.stdout, &record.stderr,
+                    &record.resource_events, &record.stdout_bytes, &record.stderr_bytes, &record.log_path,
+                    &record.hook_result, &record.hook_output, &record.signal, &record.attempt.map(|attempt| attempt as i32),
+                ],
+            ).await?;
+            Ok(())
+        })
+    }
+
+    fn prune_history(&self, before: DateTime<Utc>) -> StorageFuture<'_, usize> {
+        Box::pin(async move {
+            let pruned = self.client.execute("DELETE FROM history WHERE run_at < $1", &[&before]).await?;
+            Ok(pruned as usize)
+        })
+    }
+}
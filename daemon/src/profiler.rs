@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use common::{JobEvalStats, LockWaitStats, SchedulerProfile};
+use crate::metrics::escape_label;
+
+/// Ticks slower than this are logged with their most expensive jobs
+pub const SLOW_TICK: Duration = Duration::from_millis(250);
+
+/// Number of jobs reported by `status --verbose` and /metrics
+pub const TOP_OFFENDERS: usize = 10;
+
+/// Statistics of a job not evaluated for this long (removed, disabled or run once) are dropped
+pub const JOB_STATS_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Stats {
+    count: u64,
+    total: Duration,
+    max: Duration,
+    last: Option<Instant>,
+}
+
+impl Stats {
+    fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        self.last = Some(Instant::now());
+    }
+
+    fn avg_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total.as_secs_f64() * 1000.0 / self.count as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProfileState {
+    ticks: Stats,
+    last_tick: Duration,
+    slow_ticks: u64,
+    lock_waits: HashMap<&'static str, Stats>,
+    jobs: HashMap<String, Stats>,
+}
+
+/// Tick duration, per-job schedule evaluation cost and scheduler lock wait statistics
+#[derive(Debug, Default)]
+pub struct SchedulerProfiler {
+    state: Mutex<ProfileState>,
+}
+
+impl SchedulerProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_tick(&self, elapsed: Duration, evaluations: &[(String, Duration)]) {
+        let mut state = self.state.lock().unwrap();
+        state.ticks.record(elapsed);
+        state.last_tick = elapsed;
+
+        for (job_id, cost) in evaluations {
+            match state.jobs.get_mut(job_id) {
+                Some(stats) => stats.record(*cost),
+                None => {
+                    let mut stats = Stats::default();
+                    stats.record(*cost);
+                    state.jobs.insert(job_id.clone(), stats);
+                }
+            }
+        }
+
+        if elapsed > SLOW_TICK {
+            state.slow_ticks += 1;
+            let mut costly: Vec<&(String, Duration)> = evaluations.iter().collect();
+            costly.sort_by_key(|(_, cost)| std::cmp::Reverse(*cost));
+            let top: Vec<String> = costly.iter()
+                .take(3)
+                .map(|(id, cost)| format!("{} ({:.2}ms)", id, cost.as_secs_f64() * 1000.0))
+                .collect();
+            log::warn!("Slow scheduler tick: {:.1}ms for {} due jobs; most expensive: {}",
+                elapsed.as_secs_f64() * 1000.0, evaluations.len(), top.join(", "));
+        }
+    }
+
+    /// Drop the statistics of jobs not evaluated within `retention`, returning how many
+    pub fn prune_jobs(&self, retention: Duration) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let before = state.jobs.len();
+        state.jobs.retain(|_, stats| stats.last.is_some_and(|last| last.elapsed() < retention));
+        before - state.jobs.len()
+    }
+
+    pub fn record_lock_wait(&self, site: &'static str, waited: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.lock_waits.entry(site).or_default().record(waited);
+    }
+
+    /// Acquire `lock` for reading, recording how long `site` waited for it
+    pub fn read<'a, T>(&self, lock: &'a RwLock<T>, site: &'static str) -> RwLockReadGuard<'a, T> {
+        let start = std::time::Instant::now();
+        let guard = lock.read().unwrap();
+        self.record_lock_wait(site, start.elapsed());
+        guard
+    }
+
+    /// Acquire `lock` for writing, recording how long `site` waited for it
+    pub fn write<'a, T>(&self, lock: &'a RwLock<T>, site: &'static str) -> RwLockWriteGuard<'a, T> {
+        let start = std::time::Instant::now();
+        let guard = lock.write().unwrap();
+        self.record_lock_wait(site, start.elapsed());
+        guard
+    }
+
+    /// Current statistics; lock waits and the `top` most expensive jobs are only filled in when `top > 0`
+    pub fn snapshot(&self, top: usize) -> SchedulerProfile {
+        let state = self.state.lock().unwrap();
+
+        let mut lock_waits = Vec::new();
+        let mut top_jobs = Vec::new();
+        if top > 0 {
+            lock_waits = state.lock_waits.iter()
+                .map(|(site, stats)| LockWaitStats {
+                    site: site.to_string(),
+                    count: stats.count,
+                    avg_ms: stats.avg_ms(),
+                    max_ms: stats.max.as_secs_f64() * 1000.0,
+                })
+                .collect();
+            lock_waits.sort_by(|a, b| a.site.cmp(&b.site));
+
+            let mut jobs: Vec<(&String, &Stats)> = state.jobs.iter().collect();
+            jobs.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+            top_jobs = jobs.into_iter()
+                .take(top)
+                .map(|(id, stats)| JobEvalStats {
+                    job_id: id.clone(),
+                    evaluations: stats.count,
+                    avg_us: stats.avg_ms() * 1000.0,
+                    max_us: stats.max.as_secs_f64() * 1_000_000.0,
+                    total_ms: stats.total.as_secs_f64() * 1000.0,
+                })
+                .collect();
+        }
+
+        SchedulerProfile {
+            ticks: state.ticks.count,
+            last_tick_ms: state.last_tick.as_secs_f64() * 1000.0,
+            avg_tick_ms: state.ticks.avg_ms(),
+            max_tick_ms: state.ticks.max.as_secs_f64() * 1000.0,
+            slow_ticks: state.slow_ticks,
+            lock_waits,
+            top_jobs,
+        }
+    }
+
+    /// Render profiling data in the Prometheus text exposition format
+    pub fn export(&self) -> String {
+        let profile = self.snapshot(TOP_OFFENDERS);
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP lunasched_tick_duration_ms Duration of the last scheduler tick");
+        let _ = writeln!(out, "# TYPE lunasched_tick_duration_ms gauge");
+        let _ = writeln!(out, "lunasched_tick_duration_ms {:.3}", profile.last_tick_ms);
+        let _ = writeln!(out, "# HELP lunasched_tick_duration_max_ms Slowest scheduler tick since start");
+        let _ = writeln!(out, "# TYPE lunasched_tick_duration_max_ms gauge");
+        let _ = writeln!(out, "lunasched_tick_duration_max_ms {:.3}", profile.max_tick_ms);
+        let _ = writeln!(out, "# HELP lunasched_slow_ticks_total Scheduler ticks slower than {}ms", SLOW_TICK.as_millis());
+        let _ = writeln!(out, "# TYPE lunasched_slow_ticks_total counter");
+        let _ = writeln!(out, "lunasched_slow_ticks_total {}", profile.slow_ticks);
+
+        let _ = writeln!(out, "# HELP lunasched_lock_wait_max_ms Longest wait for the scheduler lock");
+        let _ = writeln!(out, "# TYPE lunasched_lock_wait_max_ms gauge");
+        for wait in &profile.lock_waits {
+            let _ = writeln!(out, "lunasched_lock_wait_max_ms{{site=\"{}\"}} {:.3}", wait.site, wait.max_ms);
+        }
+        let _ = writeln!(out, "# HELP lunasched_lock_wait_avg_ms Average wait for the scheduler lock");
+        let _ = writeln!(out, "# TYPE lunasched_lock_wait_avg_ms gauge");
+        for wait in &profile.lock_waits {
+            let _ = writeln!(out, "lunasched_lock_wait_avg_ms{{site=\"{}\"}} {:.3}", wait.site, wait.avg_ms);
+        }
+
+        let _ = writeln!(out, "# HELP lunasched_job_eval_cost_us Average schedule evaluation cost of the most expensive jobs");
+        let _ = writeln!(out, "# TYPE lunasched_job_eval_cost_us gauge");
+        for job in &profile.top_jobs {
+            let _ = writeln!(out, "lunasched_job_eval_cost_us{{job_id=\"{}\"}} {:.3}",
+                escape_label(&job.job_id), job.avg_us);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_drops_jobs_not_evaluated() {
+        let profiler = SchedulerProfiler::new();
+        profiler.record_tick(Duration::from_millis(1), &[("a".to_string(), Duration::from_micros(5))]);
+        assert_eq!(profiler.prune_jobs(JOB_STATS_RETENTION), 0);
+        assert_eq!(profiler.snapshot(TOP_OFFENDERS).top_jobs.len(), 1);
+        assert_eq!(profiler.prune_jobs(Duration::ZERO), 1);
+        let profile = profiler.snapshot(TOP_OFFENDERS);
+        assert!(profile.top_jobs.is_empty());
+        // Tick statistics stay
+        assert_eq!(profile.ticks, 1);
+    }
+}
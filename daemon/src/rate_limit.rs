@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::config::RateLimits;
+
+// Clients tracked before idle ones are forgotten
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// Enforces `server.rate_limit` on IPC clients: connections open at once (in all and per
+/// client), and the rates at which a client opens connections and sends requests
+pub struct RateLimiter {
+    limits: RateLimits,
+    state: Mutex<State>,
+}
+
+/// Who connections and requests are counted against: a uid, or the address of a TCP client
+/// that hasn't authenticated yet, so it can't use up the limits of the user it would act as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Client {
+    Uid(u32),
+    Address(IpAddr),
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Client::Uid(uid) => write!(f, "uid {}", uid),
+            Client::Address(addr) => write!(f, "address {}", addr),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    connections: usize,
+    clients: HashMap<Client, ClientState>,
+}
+
+struct ClientState {
+    connections: usize,
+    connects: Bucket,
+    requests: Bucket,
+    limited: bool, // Refused a request since the last one let through; logged once
+}
+
+/// An admitted connection, counted until dropped
+pub struct ConnectionSlot {
+    limiter: Arc<RateLimiter>,
+    client: Client,
+}
+
+impl ConnectionSlot {
+    pub fn client(&self) -> Client {
+        self.client
+    }
+
+    /// Count the connection against `uid` from now on, once its client authenticated as it
+    pub fn authenticated(&mut self, uid: u32) -> Result<(), String> {
+        let to = Client::Uid(uid);
+        if to == self.client {
+            return Ok(());
+        }
+        let limits = &self.limiter.limits;
+        let mut guard = self.limiter.state.lock().unwrap();
+        let state = &mut *guard;
+        state.admit_client(limits, to)?;
+        if let Some(from) = state.clients.get_mut(&self.client) {
+            from.connections = from.connections.saturating_sub(1);
+        }
+        self.client = to;
+        Ok(())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.connections = state.connections.saturating_sub(1);
+        if let Some(client) = state.clients.get_mut(&self.client) {
+            client.connections = client.connections.saturating_sub(1);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Arc<Self> {
+        Arc::new(Self { limits, state: Mutex::new(State::default()) })
+    }
+
+    /// Count a new connection from `client`, or the error to refuse it with
+    pub fn admit(self: &Arc<Self>, client: Client) -> Result<ConnectionSlot, String> {
+        let limits = &self.limits;
+        let mut state = self.state.lock().unwrap();
+        if limits.max_connections > 0 && state.connections >= limits.max_connections {
+            return Err(format!("rate limited: the daemon has its maximum of {} connections open", limits.max_connections));
+        }
+        state.admit_client(limits, client)?;
+        state.connections += 1;
+        Ok(ConnectionSlot { limiter: self.clone(), client })
+    }
+
+    /// Whether `client` may send another request now; otherwise the error to answer it with
+    pub fn request(&self, client: Client) -> Result<(), String> {
+        let limits = &self.limits;
+        let mut state = self.state.lock().unwrap();
+        let tracked = state.clients.entry(client).or_insert_with(|| ClientState::new(limits));
+        if tracked.requests.take(limits.requests_per_second, limits.burst) {
+            tracked.limited = false;
+            return Ok(());
+        }
+        if !tracked.limited {
+            log::warn!("Rate limiting {}: more than {} requests a second", client, limits.requests_per_second);
+            tracked.limited = true;
+        }
+        Err(format!("rate limited: more than {} requests a second; slow down", limits.requests_per_second))
+    }
+}
+
+impl State {
+    // Count one more connection of `client`, within its limits
+    fn admit_client(&mut self, limits: &RateLimits, client: Client) -> Result<(), String> {
+        if self.clients.len() >= MAX_TRACKED_CLIENTS && !self.clients.contains_key(&client) {
+            self.clients.retain(|_, tracked| tracked.connections > 0 || !tracked.idle(limits));
+        }
+        let tracked = self.clients.entry(client).or_insert_with(|| ClientState::new(limits));
+        if limits.max_connections_per_uid > 0 && tracked.connections >= limits.max_connections_per_uid {
+            return Err(format!("rate limited: {} has its maximum of {} connections open", client, limits.max_connections_per_uid));
+        }
+        if !tracked.connects.take(limits.connections_per_second, limits.burst) {
+            return Err(format!("rate limited: {} opens more than {} connections a second", client, limits.connections_per_second));
+        }
+        tracked.connections += 1;
+        Ok(())
+    }
+}
+
+impl ClientState {
+    fn new(limits: &RateLimits) -> Self {
+        Self {
+            connections: 0,
+            connects: Bucket::full(limits.burst),
+            requests: Bucket::full(limits.burst),
+            limited: false,
+        }
+    }
+
+    // Both buckets would be full again, so forgetting the uid changes nothing
+    fn idle(&self, limits: &RateLimits) -> bool {
+        self.connects.refilled(limits.connections_per_second, limits.burst)
+            && self.requests.refilled(limits.requests_per_second, limits.burst)
+    }
+}
+
+// Token bucket: refills at a rate per second up to the burst size; each connection or
+// request takes one token
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(burst: u32) -> Self {
+        Self { tokens: burst.max(1) as f64, updated: Instant::now() }
+    }
+
+    fn take(&mut self, rate: u32, burst: u32) -> bool {
+        if rate == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * rate as f64;
+        self.tokens = (self.tokens + refill).min(burst.max(1) as f64);
+        self.updated = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn refilled(&self, rate: u32, burst: u32) -> bool {
+        rate == 0 || self.tokens + self.updated.elapsed().as_secs_f64() * rate as f64 >= burst.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits(max_connections: usize, per_uid: usize, connections_per_second: u32, requests_per_second: u32, burst: u32) -> RateLimits {
+        RateLimits { max_connections, max_connections_per_uid: per_uid, connections_per_second, requests_per_second, burst }
+    }
+
+    #[test]
+    fn bucket_refills_at_its_rate() {
+        let mut bucket = Bucket::full(2);
+        assert!(bucket.take(10, 2));
+        assert!(bucket.take(10, 2));
+        assert!(!bucket.take(10, 2));
+        bucket.updated -= Duration::from_millis(150);
+        assert!(bucket.take(10, 2));
+        // Never more than the burst
+        bucket.updated -= Duration::from_secs(1);
+        assert!(bucket.refilled(10, 2));
+        assert!(bucket.take(10, 2) && bucket.take(10, 2));
+        assert!(!bucket.take(10, 2));
+        // A rate of 0 is no limit
+        assert!(bucket.take(0, 2));
+    }
+
+    #[test]
+    fn connections_per_client_and_in_all() {
+        let limiter = RateLimiter::new(limits(3, 2, 0, 0, 1));
+        let a1 = limiter.admit(Client::Uid(1)).unwrap();
+        let _a2 = limiter.admit(Client::Uid(1)).unwrap();
+        assert!(limiter.admit(Client::Uid(1)).is_err());
+        let _b1 = limiter.admit(Client::Uid(2)).unwrap();
+        assert!(limiter.admit(Client::Uid(3)).is_err());
+        drop(a1);
+        assert!(limiter.admit(Client::Uid(1)).is_ok());
+    }
+
+    #[test]
+    fn connection_rate() {
+        let limiter = RateLimiter::new(limits(0, 0, 1, 0, 2));
+        let _slots: Vec<_> = (0..2).map(|_| limiter.admit(Client::Uid(1)).unwrap()).collect();
+        assert!(limiter.admit(Client::Uid(1)).is_err());
+        assert!(limiter.admit(Client::Uid(2)).is_ok());
+    }
+
+    #[test]
+    fn request_rate() {
+        let limiter = RateLimiter::new(limits(0, 0, 0, 1, 3));
+        for _ in 0..3 {
+            assert!(limiter.request(Client::Uid(1)).is_ok());
+        }
+        assert!(limiter.request(Client::Uid(1)).is_err());
+        assert!(limiter.request(Client::Uid(2)).is_ok());
+    }
+
+    #[test]
+    fn authenticating_moves_the_connection() {
+        let address = Client::Address("192.0.2.1".parse().unwrap());
+        let limiter = RateLimiter::new(limits(0, 1, 0, 0, 1));
+        let mut slot = limiter.admit(address).unwrap();
+        // The address has its one connection until the client authenticates
+        assert!(limiter.admit(address).is_err());
+        slot.authenticated(1000).unwrap();
+        assert_eq!(slot.client(), Client::Uid(1000));
+        // The uid now has its one connection
+        let mut second = limiter.admit(address).unwrap();
+        assert!(second.authenticated(1000).is_err());
+        assert_eq!(second.client(), address);
+        drop(slot);
+        assert!(second.authenticated(1000).is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Local, Timelike, Utc};
+use common::{Job, JobPriority, RebalanceSuggestion, ScheduleConfig};
+use std::collections::{HashMap, HashSet};
+
+const MINUTES_PER_DAY: usize = 24 * 60;
+
+// Jitter suggested for crowded jobs whose schedule has no single start minute to move
+const SUGGESTED_JITTER_SECONDS: u64 = 600;
+
+/// When a job regularly starts (minutes of the local day) and how many minutes a run takes
+struct Profile {
+    slots: Vec<usize>,
+    duration_minutes: usize,
+}
+
+/// Minutes of the day, each holding how many jobs typically run then
+struct Load([u32; MINUTES_PER_DAY]);
+
+impl Load {
+    fn add(&mut self, profile: &Profile, shift: isize, delta: i32) {
+        for &slot in &profile.slots {
+            for offset in 0..profile.duration_minutes {
+                let minute = wrap(slot as isize + shift + offset as isize);
+                self.0[minute] = (self.0[minute] as i32 + delta).max(0) as u32;
+            }
+        }
+    }
+
+    // Busiest minute a job would see if its starts were shifted by `shift` minutes
+    fn peak(&self, profile: &Profile, shift: isize) -> u32 {
+        profile.slots.iter()
+            .flat_map(|&slot| (0..profile.duration_minutes).map(move |offset| wrap(slot as isize + shift + offset as isize)))
+            .map(|minute| self.0[minute])
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn wrap(minute: isize) -> usize {
+    minute.rem_euclid(MINUTES_PER_DAY as isize) as usize
+}
+
+fn format_minute(minute: usize) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Find minutes where more than `max_per_slot` jobs regularly start together, judging by
+/// their recorded start times and durations, and suggest spreading them: Cron jobs with a
+/// fixed minute move to the least loaded minute of the same hour, others get jitter.
+/// Lower priority and shorter jobs move first.
+pub fn suggest(
+    jobs: &HashMap<String, Job>,
+    starts: &[(String, DateTime<Utc>, Option<i64>)],
+    max_per_slot: usize,
+) -> Vec<RebalanceSuggestion> {
+    let mut profiles: HashMap<&str, Profile> = HashMap::new();
+    let mut counts: HashMap<&str, HashMap<usize, u32>> = HashMap::new();
+    let mut durations: HashMap<&str, (i64, i64)> = HashMap::new();
+    for (job_id, started_at, duration_ms) in starts {
+        let local = started_at.with_timezone(&Local);
+        let minute = local.hour() as usize * 60 + local.minute() as usize;
+        *counts.entry(job_id.as_str()).or_default().entry(minute).or_default() += 1;
+        if let Some(ms) = duration_ms {
+            let total = durations.entry(job_id.as_str()).or_default();
+            total.0 += ms;
+            total.1 += 1;
+        }
+    }
+    for (job_id, minutes) in counts {
+        if !jobs.get(job_id).is_some_and(|job| job.enabled) {
+            continue;
+        }
+        // Regular start times: at least half as common as the job's most common one
+        let busiest = minutes.values().copied().max().unwrap_or(0);
+        let mut slots: Vec<usize> = minutes.into_iter()
+            .filter(|(_, count)| count * 2 >= busiest)
+            .map(|(minute, _)| minute)
+            .collect();
+        slots.sort_unstable();
+        let duration_minutes = match durations.get(job_id) {
+            Some(&(total, runs)) if runs > 0 => ((total / runs) as f64 / 60_000.0).ceil() as usize,
+            _ => 1,
+        };
+        profiles.insert(job_id, Profile { slots, duration_minutes: duration_minutes.clamp(1, MINUTES_PER_DAY) });
+    }
+
+    let mut load = Load([0; MINUTES_PER_DAY]);
+    let mut starting: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (job_id, profile) in &profiles {
+        load.add(profile, 0, 1);
+        for &slot in &profile.slots {
+            starting.entry(slot).or_default().push(*job_id);
+        }
+    }
+
+    let mut crowded: Vec<(usize, usize)> = starting.iter()
+        .filter(|(_, ids)| ids.len() > max_per_slot)
+        .map(|(&slot, ids)| (slot, ids.len()))
+        .collect();
+    crowded.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut moved: HashSet<&str> = HashSet::new();
+    let mut suggestions = Vec::new();
+    for (slot, slot_jobs) in crowded {
+        let mut candidates: Vec<&str> = starting[&slot].iter()
+            .copied()
+            .filter(|id| !moved.contains(id))
+            .collect();
+        let excess = candidates.len().saturating_sub(max_per_slot);
+        if excess == 0 {
+            continue;
+        }
+        candidates.sort_by_key(|id| {
+            let job = &jobs[*id];
+            (cron_minute(job) != Some(slot % 60), priority_rank(&job.priority), profiles[id].duration_minutes, *id)
+        });
+
+        for job_id in candidates.into_iter().take(excess) {
+            let job = &jobs[job_id];
+            let profile = &profiles[job_id];
+            let mut suggestion = RebalanceSuggestion {
+                job_id: job.id.clone(),
+                job_name: job.name.clone(),
+                slot: format_minute(slot),
+                slot_jobs,
+                schedule: None,
+                target: None,
+                jitter_seconds: None,
+            };
+
+            load.add(profile, 0, -1);
+            let shift = match (cron_minute(job), &job.schedule) {
+                (Some(current), ScheduleConfig::Cron(expression)) if current == slot % 60 => {
+                    // Least loaded minute of the same hour, the nearest one on a tie
+                    let hour_start = slot - slot % 60;
+                    let best = (0..60usize)
+                        .filter(|&m| m != current)
+                        .min_by_key(|&m| (load.peak(profile, m as isize - current as isize), m.abs_diff(current)))
+                        .unwrap_or(current);
+                    suggestion.schedule = Some(with_minute(expression, best));
+                    suggestion.target = Some(format_minute(hour_start + best));
+                    best as isize - current as isize
+                }
+                _ if job.jitter_seconds < SUGGESTED_JITTER_SECONDS => {
+                    suggestion.jitter_seconds = Some(SUGGESTED_JITTER_SECONDS);
+                    0
+                }
+                // Already jittered as much as we would suggest
+                _ => {
+                    load.add(profile, 0, 1);
+                    continue;
+                }
+            };
+            load.add(profile, shift, 1);
+            moved.insert(job_id);
+            suggestions.push(suggestion);
+        }
+    }
+    suggestions
+}
+
+// The minute a Cron job fires at, when its minute field is a single number
+fn cron_minute(job: &Job) -> Option<usize> {
+    match job.schedule {
+        // Fields: second minute hour day-of-month month day-of-week [year]
+        ScheduleConfig::Cron(ref expression) => expression.split_whitespace().nth(1)?.parse().ok().filter(|m| *m < 60),
+        _ => None,
+    }
+}
+
+fn with_minute(expression: &str, minute: usize) -> String {
+    let mut fields: Vec<String> = expression.split_whitespace().map(str::to_string).collect();
+    fields[1] = minute.to_string();
+    fields.join(" ")
+}
+
+fn priority_rank(priority: &JobPriority) -> u8 {
+    match priority {
+        JobPriority::Low => 0,
+        JobPriority::Normal => 1,
+        JobPriority::High => 2,
+        JobPriority::Critical => 3,
+    }
+}
@@ -0,0 +1,270 @@
+use common::ResourceLimits;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use nix::sys::signal::{kill, Signal};
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use crate::timeline::Timeline;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// Parent of the per-execution cgroups, directly below the root
+const PARENT_GROUP: &str = "lunasched";
+// cpu.max period; the quota is cpu_quota times this
+const CPU_PERIOD_US: u64 = 100_000;
+// How often the memory watchdog samples an execution's process tree
+const MEMORY_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Transient cgroup v2 group enforcing one execution's memory and CPU limits
+#[derive(Debug)]
+pub struct ExecutionCgroup {
+    path: PathBuf,
+}
+
+/// Limit events seen by an execution's cgroup while it ran
+#[derive(Debug, Default)]
+pub struct ResourceEvents {
+    pub oom_kills: u64,
+    pub throttled_periods: u64,
+    pub throttled_usec: u64,
+}
+
+impl ResourceEvents {
+    /// Short description for history, or None if no limit was hit
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.oom_kills > 0 {
+            parts.push(format!("oom_kill={}", self.oom_kills));
+        }
+        if self.throttled_periods > 0 {
+            parts.push(format!("cpu_throttled={} ({}ms)", self.throttled_periods, self.throttled_usec / 1000));
+        }
+        if parts.is_empty() { None } else { Some(parts.join(", ")) }
+    }
+}
+
+impl ExecutionCgroup {
+    /// Create a cgroup for the execution with its limits applied. Ok(None) if the job has
+    /// no memory or CPU limits; an error if they can't be enforced (not root, no cgroup v2).
+    pub fn create(execution_id: &str, limits: &ResourceLimits) -> anyhow::Result<Option<Self>> {
+        if limits.max_memory_mb.is_none() && limits.cpu_quota.is_none() {
+            return Ok(None);
+        }
+        if !nix::unistd::Uid::effective().is_root() {
+            return Err(anyhow::anyhow!("cgroup limits require the daemon to run as root"));
+        }
+        let root = Path::new(CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return Err(anyhow::anyhow!("cgroup v2 is not mounted at {}", CGROUP_ROOT));
+        }
+
+        // Controllers must be enabled on every level above the execution's group
+        let parent = root.join(PARENT_GROUP);
+        std::fs::create_dir_all(&parent)?;
+        enable_controllers(root)?;
+        enable_controllers(&parent)?;
+
+        let cgroup = Self { path: parent.join(execution_id) };
+        std::fs::create_dir(&cgroup.path)?;
+        if let Err(e) = cgroup.apply(limits) {
+            cgroup.remove();
+            return Err(e);
+        }
+        Ok(Some(cgroup))
+    }
+
+    fn apply(&self, limits: &ResourceLimits) -> anyhow::Result<()> {
+        if let Some(mb) = limits.max_memory_mb {
+            std::fs::write(self.path.join("memory.max"), (mb * 1024 * 1024).to_string())?;
+            // Without this the limit only pushes the job into swap
+            let _ = std::fs::write(self.path.join("memory.swap.max"), "0");
+        }
+        if let Some(quota) = limits.cpu_quota {
+            if quota <= 0.0 {
+                return Err(anyhow::anyhow!("cpu_quota must be greater than 0, got {}", quota));
+            }
+            let quota_us = ((quota as f64) * CPU_PERIOD_US as f64).round().max(1000.0) as u64;
+            std::fs::write(self.path.join("cpu.max"), format!("{} {}", quota_us, CPU_PERIOD_US))?;
+        }
+        Ok(())
+    }
+
+    /// The group's `cgroup.procs`, opened for the child to move itself in before exec
+    pub fn procs_file(&self) -> std::io::Result<File> {
+        std::fs::OpenOptions::new().write(true).open(self.path.join("cgroup.procs"))
+    }
+
+    pub fn events(&self) -> ResourceEvents {
+        let memory_events = read_keyed(&self.path.join("memory.events"));
+        let cpu_stat = read_keyed(&self.path.join("cpu.stat"));
+        let get = |stats: &[(String, u64)], key: &str| {
+            stats.iter().find(|(k, _)| k == key).map(|(_, v)| *v).unwrap_or(0)
+        };
+        ResourceEvents {
+            oom_kills: get(&memory_events, "oom_kill"),
+            throttled_periods: get(&cpu_stat, "nr_throttled"),
+            throttled_usec: get(&cpu_stat, "throttled_usec"),
+        }
+    }
+
+    /// Delete the group. Fails (and is logged) if processes the job left behind still live in it.
+    pub fn remove(self) {
+        if let Err(e) = std::fs::remove_dir(&self.path) {
+            log::warn!("Failed to remove cgroup {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn enable_controllers(group: &Path) -> anyhow::Result<()> {
+    let control = group.join("cgroup.subtree_control");
+    let enabled = std::fs::read_to_string(&control)?;
+    for controller in ["memory", "cpu"] {
+        if !enabled.split_whitespace().any(|c| c == controller) {
+            std::fs::write(&control, format!("+{}", controller))
+                .map_err(|e| anyhow::anyhow!("Failed to enable {} controller in {}: {}", controller, group.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+// Parse a flat keyed cgroup file ("key value" per line); missing files read as empty
+fn read_keyed(path: &Path) -> Vec<(String, u64)> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Enforces max_memory_mb where no cgroup does: samples the resident memory of the execution's
+/// process tree and kills the whole tree once it goes over the limit
+pub struct MemoryWatchdog {
+    state: Arc<WatchState>,
+    limit_bytes: u64,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    peak_bytes: AtomicU64,
+    killed: AtomicBool,
+}
+
+/// What the watchdog saw of a finished execution
+#[derive(Debug)]
+pub struct MemoryReport {
+    pub peak_bytes: u64,
+    pub limit_bytes: u64,
+    pub killed: bool,
+}
+
+impl MemoryReport {
+    /// Short description for history, like `ResourceEvents::summary`
+    pub fn summary(&self) -> String {
+        let peak = self.peak_bytes / (1024 * 1024);
+        if self.killed {
+            format!("memory_kill peak={}MB limit={}MB", peak, self.limit_bytes / (1024 * 1024))
+        } else {
+            format!("memory_peak={}MB", peak)
+        }
+    }
+
+    /// Why the run failed, if the watchdog killed it
+    pub fn failure(&self) -> Option<String> {
+        self.killed.then(|| format!("killed: memory limit ({} MB peak, limit {} MB)",
+            self.peak_bytes / (1024 * 1024), self.limit_bytes / (1024 * 1024)))
+    }
+}
+
+impl MemoryWatchdog {
+    /// Watch the execution whose process (and process group) is `pid`
+    pub fn spawn(pid: u32, limit_mb: u64, timeline: Timeline) -> Self {
+        let state = Arc::new(WatchState::default());
+        let limit_bytes = limit_mb * 1024 * 1024;
+        let watched = state.clone();
+        let task = tokio::spawn(async move {
+            let root = Pid::from_u32(pid);
+            let mut system = System::new();
+            loop {
+                system.refresh_processes_specifics(ProcessRefreshKind::new().with_memory());
+                let tree = process_tree(&system, root);
+                if tree.is_empty() {
+                    return;
+                }
+                let rss: u64 = tree.iter().filter_map(|pid| system.process(*pid)).map(|p| p.memory()).sum();
+                watched.peak_bytes.fetch_max(rss, Ordering::Relaxed);
+                if rss > limit_bytes {
+                    log::warn!("Process {} is using {} MB, over its {} MB limit; killing it", pid, rss / (1024 * 1024), limit_mb);
+                    // Recorded before the kill, so the flag is set when the exit is handled
+                    watched.killed.store(true, Ordering::Relaxed);
+                    timeline.record("memory_limit", Some(format!("{} MB over {} MB, SIGKILL", rss / (1024 * 1024), limit_mb)));
+                    // The group also holds descendants already orphaned out of the tree
+                    signal_group(pid, Signal::SIGKILL);
+                    for pid in tree {
+                        let _ = kill(nix::unistd::Pid::from_raw(pid.as_u32() as i32), Signal::SIGKILL);
+                    }
+                    return;
+                }
+                tokio::time::sleep(MEMORY_SAMPLE_INTERVAL).await;
+            }
+        });
+        Self { state, limit_bytes, task }
+    }
+
+    /// Stop watching (the execution has exited) and report what was seen
+    pub fn finish(self) -> MemoryReport {
+        self.task.abort();
+        MemoryReport {
+            peak_bytes: self.state.peak_bytes.load(Ordering::Relaxed),
+            limit_bytes: self.limit_bytes,
+            killed: self.state.killed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// `root` and all of its descendants that are still alive; empty once `root` has exited
+fn process_tree(system: &System, root: Pid) -> Vec<Pid> {
+    if system.process(root).is_none() {
+        return Vec::new();
+    }
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for (pid, process) in system.processes() {
+        if let Some(parent) = process.parent() {
+            children.entry(parent).or_default().push(*pid);
+        }
+    }
+    let mut tree = vec![root];
+    let mut next = 0;
+    while next < tree.len() {
+        if let Some(kids) = children.get(&tree[next]) {
+            tree.extend(kids);
+        }
+        next += 1;
+    }
+    tree
+}
+
+/// The process group led by `pid`, if it leads one. Executions run in a session of their own,
+/// so their group ID is their PID; processes started before that was the case lead none.
+pub fn execution_group(pid: u32) -> Option<u32> {
+    let process = nix::unistd::Pid::from_raw(pid as i32);
+    match nix::unistd::getpgid(Some(process)) {
+        Ok(pgid) if pgid == process => Some(pid),
+        _ => None,
+    }
+}
+
+/// Send `signal` to every process of the group `pgid`, which reaches the children of a job's
+/// shell even once the shell itself is gone
+pub fn signal_group(pgid: u32, signal: Signal) {
+    let _ = kill(nix::unistd::Pid::from_raw(-(pgid as i32)), signal);
+}
+
+/// Whether any process of the group `pgid` is still alive
+pub fn group_alive(pgid: u32) -> bool {
+    kill(nix::unistd::Pid::from_raw(-(pgid as i32)), None).is_ok()
+}
@@ -0,0 +1,98 @@
+use common::Sandbox;
+use nix::errno::Errno;
+use nix::libc;
+use nix::mount::{mount, MsFlags};
+use nix::sched::{unshare, CloneFlags};
+
+// Capabilities dropped from the bounding set are numbered from 0 up to at most this; prctl
+// refuses the first number past the kernel's last, which ends the loop there
+const MAX_CAPABILITY: libc::c_ulong = 63;
+
+/// Apply a job's sandbox to its process between fork and exec. Register after joining the
+/// cgroup and before the user switch: namespaces, mounts and the bounding set need the
+/// daemon's privileges, so the sandbox needs the system daemon (root).
+pub fn apply(sandbox: &Sandbox, cmd: &mut tokio::process::Command) {
+    if sandbox.is_empty() {
+        return;
+    }
+    let sandbox = sandbox.clone();
+    // Only syscalls on values prepared before the fork (paths short enough for nix to copy
+    // onto the stack), which keeps this fork-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            let mut namespaces = CloneFlags::empty();
+            if sandbox.no_network {
+                namespaces |= CloneFlags::CLONE_NEWNET;
+            }
+            if sandbox.private_tmp || sandbox.read_only_root {
+                namespaces |= CloneFlags::CLONE_NEWNS;
+            }
+            if !namespaces.is_empty() {
+                unshare(namespaces)?;
+            }
+            if namespaces.contains(CloneFlags::CLONE_NEWNS) {
+                // Keep the mounts below from propagating back to the host
+                mount(None::<&str>, "/", None::<&str>, MsFlags::MS_REC | MsFlags::MS_PRIVATE, None::<&str>)?;
+            }
+            if sandbox.read_only_root {
+                mount(None::<&str>, "/", None::<&str>, MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY, None::<&str>)?;
+            }
+            if sandbox.private_tmp {
+                mount(Some("tmpfs"), "/tmp", Some("tmpfs"), MsFlags::MS_NOSUID | MsFlags::MS_NODEV, Some("mode=1777"))?;
+            }
+            if sandbox.drop_capabilities {
+                // What the command can still gain when exec'd, root or not; the daemon keeps its
+                // own capabilities until the user switch
+                for capability in 0..=MAX_CAPABILITY {
+                    if libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) != 0 {
+                        if capability == 0 {
+                            return Err(Errno::last().into());
+                        }
+                        break;
+                    }
+                }
+            }
+            if sandbox.no_new_privileges && libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(Errno::last().into());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// The sandbox as systemd unit properties, for jobs run by `systemd-run`
+pub fn unit_properties(sandbox: &Sandbox) -> Vec<String> {
+    let mut properties = Vec::new();
+    if sandbox.no_new_privileges {
+        properties.push("NoNewPrivileges=yes".to_string());
+    }
+    if sandbox.drop_capabilities {
+        properties.push("CapabilityBoundingSet=".to_string());
+    }
+    if sandbox.private_tmp {
+        properties.push("PrivateTmp=yes".to_string());
+    }
+    if sandbox.read_only_root {
+        properties.push("ReadOnlyPaths=/".to_string());
+    }
+    if sandbox.no_network {
+        properties.push("PrivateNetwork=yes".to_string());
+    }
+    properties
+}
+
+/// The sandbox as `docker run` / `podman run` options
+pub fn container_options(sandbox: &Sandbox) -> Vec<&'static str> {
+    let mut options = Vec::new();
+    // Containers never gain privileges (see `container::command`)
+    if sandbox.drop_capabilities {
+        options.extend(["--cap-drop", "ALL"]);
+    }
+    if sandbox.private_tmp {
+        options.extend(["--tmpfs", "/tmp"]);
+    }
+    if sandbox.read_only_root {
+        options.push("--read-only");
+    }
+    options
+}
@@ -1,10 +1,35 @@
-use common::{Job, ScheduleConfig};
+use common::{ApplyReport, CalendarParams, ConflictPolicy, ImportReport, JobId, CatchUpPolicy, ExecutionMode, Executor, Job, JobEventKind, OverflowPolicy, ScheduleConfig, PlanAction};
+use common::{AlertPolicy, DeadLetter, StepState, Workflow, WorkflowRun};
+use common::agent::RemoteRun;
+use common::redact;
 use cron::Schedule;
+use std::os::unix::process::ExitStatusExt;
 use std::str::FromStr;
-use chrono::{Utc, DateTime, Duration, Timelike};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use crate::db::Db;
+use chrono::{Utc, DateTime, Duration, TimeZone};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::acl::AclRule;
+use crate::agents::{AgentRegistry, RemoteProcess};
+use crate::config::{self, ConcurrencyGroup, OrphanPolicy, TagDefaults};
+use crate::container;
+use crate::sandbox;
+use crate::script::{self, ScriptFile};
+use crate::systemd_run;
+use crate::db::{Db, ExecutionRecord, ExecutionSnapshot, UnfinishedExecution};
+use crate::due_queue::DueQueue;
+use crate::events::EventBus;
+use crate::history_buffer::{HistoryBuffer, PendingWrite};
+use crate::hooks;
+use crate::leader::Leadership;
+use crate::metrics::MetricsCollector;
+use crate::output::{self, LiveOutput, LiveOutputs};
+use crate::profiler::SchedulerProfiler;
+use crate::resource_manager::{self, ExecutionCgroup, MemoryWatchdog};
+use crate::timeline::Timeline;
+use crate::account::Account;
+use crate::secrets::SecretStore;
+use crate::storage::Replica;
+use crate::notifier::{self, NotificationEvent};
 use dashmap::DashMap;
 use uuid::Uuid;
 use sysinfo::{System, ProcessRefreshKind};
@@ -22,42 +47,69 @@ fn calculate_backoff_delay(
         BackoffStrategy::Fixed => initial_delay,
         BackoffStrategy::Linear => initial_delay * (attempt as u64 + 1),
         BackoffStrategy::Exponential => {
-            let base_delay = initial_delay * 2_u64.pow(attempt);
-            base_delay
+            
+            initial_delay * 2_u64.pow(attempt)
         },
     };
     
     delay.min(max_delay)
 }
 
-/// Monitor and enforce timeout for a process
+/// Move a retry delay randomly by up to `percent` of it either way, staying within `max_delay`
+fn apply_backoff_jitter(delay: u64, percent: Option<u32>, max_delay: u64) -> u64 {
+    use rand::Rng;
+    let spread = match percent {
+        Some(percent) if percent > 0 && delay > 0 => delay * percent.min(100) as u64 / 100,
+        _ => return delay,
+    };
+    rand::thread_rng().gen_range(delay - spread..=delay + spread).min(max_delay)
+}
+
+// "24h", "7d", "90m"
+fn format_window(secs: u64) -> String {
+    match secs {
+        s if s >= 86400 && s % 86400 == 0 => format!("{}d", s / 86400),
+        s if s >= 3600 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s => format!("{}m", s / 60),
+    }
+}
+
+/// Monitor and enforce timeout for an execution, given its process group (led by its shell).
+/// Called off by `exited` (sent or dropped) when the execution ends first, before its process
+/// group ID can be reused.
 async fn enforce_timeout(
-    pid: u32,
+    pgid: u32,
     timeout_seconds: u64,
+    timeline: Timeline,
+    exited: tokio::sync::oneshot::Receiver<()>,
 ) -> Result<(), &'static str> {
     let duration = std::time::Duration::from_secs(timeout_seconds);
     
-    tokio::time::sleep(duration).await;
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = exited => return Ok(()),
+    }
     
     // Check if process is still running
     let mut system = System::new();
     system.refresh_processes_specifics(ProcessRefreshKind::everything());
     
-    if system.process(sysinfo::Pid::from_u32(pid)).is_some() {
-        // Process still running, kill it
-        log::warn!("Process {} exceeded timeout of {}s, terminating", pid, timeout_seconds);
+    if system.process(sysinfo::Pid::from_u32(pgid)).is_some() {
+        // Process still running, kill it along with everything it started
+        log::warn!("Process {} exceeded timeout of {}s, terminating its process group", pgid, timeout_seconds);
         
-        use nix::sys::signal::{kill, Signal};
-        use nix::unistd::Pid;
-        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+        use nix::sys::signal::Signal;
+        // Recorded before signalling, so the events are in place before the exit is handled
+        timeline.record("timed_out", Some(format!("SIGTERM after {}s", timeout_seconds)));
+        resource_manager::signal_group(pgid, Signal::SIGTERM);
         
         // Give it a moment to clean up
         tokio::time::sleep(std::time::Duration::from_secs(2)).await;
         
-        // Force kill if still alive
-        system.refresh_processes_specifics(ProcessRefreshKind::everything());
-        if system.process(sysinfo::Pid::from_u32(pid)).is_some() {
-            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        // Force kill if the shell or anything it started is still alive
+        if resource_manager::group_alive(pgid) {
+            timeline.record("killed", Some("SIGKILL".to_string()));
+            resource_manager::signal_group(pgid, Signal::SIGKILL);
         }
         
         return Err("Process timeout exceeded");
@@ -66,25 +118,395 @@ async fn enforce_timeout(
     Ok(())
 }
 
+// Queued runs kept per job for the Queue overflow policy; further runs are dropped
+const MAX_QUEUED_RUNS: usize = 100;
+
+// Fired windows older than this are pruned from the database at startup
+const EXECUTION_WINDOW_RETENTION_DAYS: i64 = 7;
+
+/// Runs the daemon stopped in the middle of, to continue. Steps that were running lost their
+/// executions and count as failed; steps that don't depend on them still run.
+fn resume_workflow_runs(db: &Db) -> HashMap<String, (Workflow, WorkflowRun)> {
+    let workflows = db.load_workflows().unwrap_or_default();
+    let mut resumed = HashMap::new();
+    for mut run in db.unfinished_workflow_runs().unwrap_or_default() {
+        let workflow = match workflows.iter().find(|w| w.name == run.workflow) {
+            Some(workflow) => workflow.clone(),
+            None => {
+                log::warn!("Abandoning run {} of workflow {}: the workflow no longer exists", run.run_id, run.workflow);
+                run.finished_at = Some(Utc::now().to_rfc3339());
+                let _ = db.save_workflow_run(&run);
+                continue;
+            }
+        };
+        for step in run.steps.iter_mut().filter(|s| s.state == StepState::Running) {
+            step.state = StepState::Failed;
+            step.finished_at = Some(Utc::now().to_rfc3339());
+        }
+        workflow.skip_blocked(&mut run);
+        log::info!("Resuming run {} of workflow {}", run.run_id, run.workflow);
+        resumed.insert(run.run_id.clone(), (workflow, run));
+    }
+    resumed
+}
+
+/// Time since the host booted, from /proc/uptime
+fn host_uptime() -> Option<Duration> {
+    let contents = std::fs::read_to_string("/proc/uptime").ok()?;
+    let seconds: f64 = contents.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::milliseconds((seconds * 1000.0) as i64))
+}
+
+/// Run parameter carrying the missed window a catch-up run stands in for (RFC 3339)
+pub const CATCH_UP_PARAM: &str = "LUNASCHED_CATCH_UP_FOR";
+
+/// Run parameter listing the jobs whose runs led to a triggered run, comma-separated, first first
+pub const TRIGGER_PARAM: &str = "LUNASCHED_TRIGGERED_BY";
+
+// Wall clock drift from the monotonic clock between two ticks that counts as a clock jump
+const CLOCK_JUMP_SECS: i64 = 2;
+
+/// Refuse a job without a name, or named like another of its owner's jobs in `jobs`
+fn check_name_among(jobs: &HashMap<String, Job>, job: &Job) -> Result<(), String> {
+    if job.name.trim().is_empty() {
+        return Err("Job name can't be empty".to_string());
+    }
+    match jobs.values().find(|other| other.owner == job.owner && other.name == job.name) {
+        Some(other) if other.id != job.id => {
+            Err(format!("{} already has a job named {} ({})", job.owner, job.name, other.id))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Refuse triggers of other owners' jobs, and triggers that would lead back to `job`, in
+/// `jobs` with `job` added
+fn check_triggers_among(jobs: &HashMap<String, Job>, job: &Job) -> Result<(), String> {
+    for target in job.on_success_trigger.iter().chain(&job.on_failure_trigger) {
+        if let Some(other) = jobs.get(&target.0) {
+            if other.owner != job.owner && job.owner != "root" {
+                return Err(format!("Permission denied: Cannot trigger job {} owned by {}", target.0, other.owner));
+            }
+        }
+    }
+    // Walk the trigger graph as it would be with `job` added
+    let lookup = |id: &str| if id == job.id.0 { Some(job) } else { jobs.get(id) };
+    let mut seen = HashSet::new();
+    let mut paths = vec![vec![job.id.0.as_str()]];
+    while let Some(path) = paths.pop() {
+        let last = match lookup(path[path.len() - 1]) {
+            Some(last) => last,
+            None => continue,
+        };
+        for next in last.on_success_trigger.iter().chain(&last.on_failure_trigger) {
+            if next.0 == job.id.0 {
+                return Err(format!("Trigger loop: {} -> {}", path.join(" -> "), next.0));
+            }
+            if seen.insert(next.0.as_str()) {
+                let mut longer = path.clone();
+                longer.push(next.0.as_str());
+                paths.push(longer);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A new job ID. Jobs are added without one and given it by the daemon; it never changes,
+/// so history and triggers survive renaming the job.
+pub fn new_job_id() -> JobId {
+    JobId(Uuid::new_v4().to_string())
+}
+
+// Jobs suggested for a job reference that matches none
+const MAX_SUGGESTIONS: usize = 3;
+
+// Levenshtein distance, by characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Latest fired window per job from the database, pruning old records on the way
+fn load_execution_windows(db: &Db) -> HashMap<String, DateTime<Utc>> {
+    let cutoff = Utc::now() - Duration::days(EXECUTION_WINDOW_RETENTION_DAYS);
+    if let Err(e) = db.prune_execution_windows(&cutoff) {
+        log::warn!("Failed to prune execution windows: {}", e);
+    }
+    match db.load_execution_windows() {
+        Ok(windows) => windows,
+        Err(e) => {
+            log::error!("Failed to load execution windows, duplicate prevention starts empty: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+// When each Every job last fired, so a restart doesn't make them all fire at once
+fn load_last_runs(db: &Db, jobs: &HashMap<String, Job>) -> HashMap<String, DateTime<Utc>> {
+    match db.load_last_scheduled() {
+        Ok(last_scheduled) => last_scheduled.into_iter()
+            .filter(|(id, _)| matches!(jobs.get(id).map(|job| &job.schedule), Some(ScheduleConfig::Every(_))))
+            .collect(),
+        Err(e) => {
+            log::error!("Failed to load schedule state, interval jobs restart their intervals: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Windows of a Cron or Calendar schedule strictly between `since` and `now`, oldest first.
+/// Only the most recent MAX_QUEUED_RUNS are kept.
+fn missed_windows(job: &Job, since: DateTime<Utc>, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut windows = VecDeque::new();
+    let mut push = |window: DateTime<Utc>| {
+        if windows.len() == MAX_QUEUED_RUNS {
+            windows.pop_front();
+        }
+        windows.push_back(window);
+    };
+
+    match &job.schedule {
+        ScheduleConfig::Cron(expression) => {
+            if let Ok(schedule) = Schedule::from_str(expression) {
+                schedule.after(&since).take_while(|t| *t < now).for_each(&mut push);
+            }
+        }
+        ScheduleConfig::Calendar(params) => {
+            job_calendar_windows(job, params, since, now).into_iter().for_each(&mut push);
+        }
+        ScheduleConfig::Window(params) => {
+            job_calendar_windows(job, &params.calendar(), since, now).into_iter().for_each(&mut push);
+        }
+        // A past-due Once job runs at the next tick anyway
+        ScheduleConfig::Every(_) | ScheduleConfig::Heartbeat(_) | ScheduleConfig::Reboot | ScheduleConfig::Once(_) => {}
+    }
+    windows.into()
+}
+
+// Calendar windows in the job's timezone, or local time if it has none
+fn job_calendar_windows(job: &Job, params: &CalendarParams, since: DateTime<Utc>, until: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    timezone_calendar_windows(job.timezone.as_deref(), params, since, until)
+}
+
+// First Calendar window after `since`. Looks up to four years ahead (the 29th in Feb), in
+// widening steps so a daily schedule doesn't list years of windows.
+fn next_calendar_window(job: &Job, params: &CalendarParams, since: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let horizon = since + Duration::days(4 * 366);
+    let mut from = since;
+    let mut span = Duration::days(2);
+    while from < horizon {
+        let until = (from + span).min(horizon);
+        if let Some(window) = job_calendar_windows(job, params, from, until).into_iter().next() {
+            return Some(window);
+        }
+        from = until - Duration::seconds(1);
+        span = span * 4;
+    }
+    None
+}
+
+fn timezone_calendar_windows(timezone: Option<&str>, params: &CalendarParams, since: DateTime<Utc>, until: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    match timezone.and_then(|tz| tz.parse::<chrono_tz::Tz>().ok()) {
+        Some(tz) => calendar_windows(&tz, params, since, until),
+        None => calendar_windows(&chrono::Local, params, since, until),
+    }
+}
+
+fn calendar_windows<Tz: TimeZone>(tz: &Tz, params: &CalendarParams, since: DateTime<Utc>, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut windows = Vec::new();
+    let mut date = since.with_timezone(tz).date_naive();
+    let last = now.with_timezone(tz).date_naive();
+    while date <= last {
+        if params.matches_date(date) {
+            let mut day_windows: Vec<DateTime<Utc>> = params.times()
+                // Skip times that don't exist on this day (DST gap)
+                .filter_map(|(h, m, s)| date.and_hms_opt(h, m, s))
+                .filter_map(|t| tz.from_local_datetime(&t).earliest())
+                .map(|t| t.with_timezone(&Utc))
+                .filter(|window| *window > since && *window < now)
+                .collect();
+            day_windows.sort();
+            day_windows.dedup();
+            windows.extend(day_windows);
+        }
+        date = match date.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    windows
+}
+
+// How far ahead `upcoming_runs` looks for calendar windows
+const PREVIEW_HORIZON_DAYS: i64 = 10 * 366;
+
+/// The next `count` times a job with this schedule would fire after `from`, worked out the way
+/// the scheduler does (a new Every job runs at once). Cron expressions are evaluated in UTC,
+/// calendar schedules in `timezone`, or local time without one.
+pub fn upcoming_runs(schedule: &ScheduleConfig, timezone: Option<&str>, from: DateTime<Utc>, count: usize)
+    -> Result<Vec<DateTime<Utc>>, String> {
+    if let Some(tz) = timezone {
+        tz.parse::<chrono_tz::Tz>().map_err(|_| format!("Unknown timezone: {}", tz))?;
+    }
+    match schedule {
+        ScheduleConfig::Cron(expression) => {
+            let schedule = Schedule::from_str(expression)
+                .map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))?;
+            Ok(schedule.after(&(from - Duration::seconds(1))).take(count).collect())
+        }
+        ScheduleConfig::Every(seconds) => {
+            Ok((0..count as i64).map(|i| from + Duration::seconds(i * *seconds as i64)).collect())
+        }
+        ScheduleConfig::Window(params) => upcoming_runs(&ScheduleConfig::Calendar(params.calendar()), timezone, from, count),
+        ScheduleConfig::Calendar(params) => {
+            let mut runs = Vec::new();
+            let mut since = from - Duration::seconds(1);
+            let horizon = from + Duration::days(PREVIEW_HORIZON_DAYS);
+            while runs.len() < count && since < horizon {
+                let until = (since + Duration::days(366)).min(horizon);
+                runs.extend(timezone_calendar_windows(timezone, params, since, until));
+                since = until - Duration::seconds(1);
+            }
+            runs.truncate(count);
+            Ok(runs)
+        }
+        ScheduleConfig::Once(at) => Ok(vec![(*at).max(from)].into_iter().take(count).collect()),
+        ScheduleConfig::Heartbeat(_) => Err("Heartbeat schedules never fire; they expect a ping".to_string()),
+        ScheduleConfig::Reboot => Err("@reboot jobs run once per host boot, when the daemon starts".to_string()),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct JobExecutionContext {
     pub execution_id: String,
+    pub job_id: String,
+    pub run_key: String, // See Job::run_key
     pub scheduled_time: DateTime<Utc>,
     pub start_time: DateTime<Utc>,
     pub pid: Option<u32>,
+    pub pgid: Option<u32>, // Process group to signal, so the whole tree is stopped
 }
 
 pub struct Scheduler {
     pub jobs: HashMap<String, Job>,
     pub last_runs: HashMap<String, DateTime<Utc>>,
     pub last_execution_windows: HashMap<String, DateTime<Utc>>, // Track scheduled window to prevent duplicates
-    pub running_jobs: Arc<DashMap<String, JobExecutionContext>>, // Enhanced with execution context
+    pub due: DueQueue, // When each Cron, Every, Calendar and Once job next fires
+    pub waiting: HashMap<String, DateTime<Utc>>, // Due Sequential jobs waiting for their running execution, by job ID
+    pub last_tick: Option<(std::time::Instant, DateTime<Utc>)>, // Monotonic and wall clock of the last tick
+    pub running_jobs: Arc<DashMap<String, JobExecutionContext>>, // Keyed by execution ID
+    pub transient_jobs: HashMap<String, Job>, // Jobs of `lunasched run` while their run lasts, by job ID
+    removed_while_detached: HashSet<String>, // Jobs to delete from the database once it is back
     pub db: Option<Arc<Mutex<Db>>>,
+    pub history: Arc<HistoryBuffer>, // History writes waiting for the database
+    pub paths: common::Paths, // System or user instance files
     pub retry_state: HashMap<String, RetryState>,
+    pub queued_runs: HashMap<String, VecDeque<RunParams>>, // Parallel jobs over max_concurrent, by job ID
+    pub held_runs: Vec<HeldRun>, // Startup runs waiting for the host to settle after boot
+    pub boot_delay: u64, // Default settling period in seconds, from config
+    pub tag_defaults: HashMap<String, TagDefaults>, // Per-tag policy from config
+    pub concurrency_groups: HashMap<String, ConcurrencyGroup>, // Per-group policy from config
+    pub max_output_bytes: Option<u64>, // Default per-stream output limit from config
+    pub execution_logs_kept: usize, // Execution log files kept per job (0 = all), from config
+    pub compress_execution_logs: bool, // Gzip execution logs once the execution finishes
+    pub last_heartbeats: HashMap<String, DateTime<Utc>>, // Last ping per heartbeat job
+    pub missed_heartbeats: HashSet<String>, // Heartbeat jobs currently alerting
+    pub low_success_rate: HashSet<String>, // Jobs alerting for a success rate below their threshold
+    pub redact_patterns: Vec<String>, // Global secret env patterns from config
+    pub acl: Vec<AclRule>, // Capabilities of local users, from config
+    pub notification_template: Option<String>, // Default notification message, from config
+    pub container_engine: Option<String>, // Engine CLI for Container jobs, from config; found in PATH when unset
+    pub trusted_keys: Vec<String>, // Public keys accepted for signed job bundles
+    pub secrets: Option<Arc<SecretStore>>, // Decrypts secret:// env values; None without a key
+    pub metrics: Arc<MetricsCollector>,
+    pub live_outputs: LiveOutputs, // Output of running executions, for `lunasched logs`
+    pub events: Arc<EventBus>, // Job lifecycle events, for `lunasched watch`
+    pub workflow_runs: HashMap<String, (Workflow, WorkflowRun)>, // Unfinished workflow runs by run ID
+    pub dead_letters: HashMap<String, DeadLetter>, // Jobs whose last run failed for good, by job ID
+    pub failure_streaks: HashMap<String, u32>, // Executions in a row that failed, retries included, by job ID
+    pub replica: Option<Replica>, // Another store kept up to date with jobs and history (storage.postgres)
+    pub leadership: Option<Arc<Leadership>>, // Set with `ha`: jobs run only while this daemon leads
+    pub agents: Arc<AgentRegistry>, // Connected agents, which run the jobs that have a target
+    pub profiler: Arc<SchedulerProfiler>,
+    pub started_at: DateTime<Utc>,
+}
+
+// A started execution: a child of the daemon, or a process an agent runs on its host
+enum Process {
+    Local(tokio::process::Child),
+    Remote(RemoteProcess),
 }
 
+// Stdout or stderr of an execution
+type Pipe = Box<dyn tokio::io::AsyncRead + Unpin + Send>;
+
+impl Process {
+    // PID of a local child; an agent's process is out of the daemon's reach
+    fn local_pid(&self) -> Option<u32> {
+        match self {
+            Process::Local(child) => child.id(),
+            Process::Remote(_) => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Process::Local(child) => format!("pid {}", child.id().unwrap_or_default()),
+            Process::Remote(remote) => format!("on agent {}", remote.agent),
+        }
+    }
+
+    fn take_output(&mut self) -> (Option<Pipe>, Option<Pipe>) {
+        match self {
+            Process::Local(child) => (child.stdout.take().map(|pipe| Box::new(pipe) as Pipe),
+                child.stderr.take().map(|pipe| Box::new(pipe) as Pipe)),
+            Process::Remote(remote) => (remote.stdout.take().map(|pipe| Box::new(pipe) as Pipe),
+                remote.stderr.take().map(|pipe| Box::new(pipe) as Pipe)),
+        }
+    }
+
+    async fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        match self {
+            Process::Local(child) => child.wait().await,
+            Process::Remote(remote) => remote.wait().await,
+        }
+    }
+}
+
+/// Run parameters (`lunasched start --param`), exposed to the command as env vars
+pub type RunParams = HashMap<String, String>;
+
+/// A run picked by `tick` and registered in `running_jobs`, ready for `execute_job`
+pub struct PendingRun {
+    pub job: Job,
+    pub params: RunParams,
+    pub execution_id: String,
+}
+
+/// A catch-up or @reboot run held back until `not_before`, when the host has been up for its boot delay
+#[derive(Debug, Clone)]
+pub struct HeldRun {
+    pub job_id: String,
+    pub params: RunParams,
+    pub not_before: DateTime<Utc>,
+}
+
+/// Retry bookkeeping, keyed by run key (see `Job::run_key`)
 #[derive(Debug, Clone)]
 pub struct RetryState {
+    pub job_id: String,
+    pub params: RunParams,
     pub attempt: u32,
     pub next_attempt_at: Option<DateTime<Utc>>,
 }
@@ -92,322 +514,1375 @@ pub struct RetryState {
 impl Scheduler {
     pub fn new(db: Option<Arc<Mutex<Db>>>) -> Self {
         let mut jobs = HashMap::new();
+        let mut last_runs = HashMap::new();
+        let mut last_execution_windows = HashMap::new();
+        let mut workflow_runs = HashMap::new();
+        let mut dead_letters = HashMap::new();
         if let Some(ref db) = db {
-            if let Ok(loaded_jobs) = db.lock().unwrap().load_jobs() {
+            let db = db.lock().unwrap();
+            if let Ok(loaded_jobs) = db.load_jobs() {
                 jobs = loaded_jobs;
             }
+            last_runs = load_last_runs(&db, &jobs);
+            last_execution_windows = load_execution_windows(&db);
+            workflow_runs = resume_workflow_runs(&db);
+            dead_letters = db.load_dead_letters().unwrap_or_default();
         }
         
-        Self {
+        let history = HistoryBuffer::start(db.clone());
+        let mut scheduler = Self {
             jobs,
-            last_runs: HashMap::new(),
-            last_execution_windows: HashMap::new(),
+            last_runs,
+            last_execution_windows,
+            due: DueQueue::new(),
+            waiting: HashMap::new(),
+            last_tick: None,
             running_jobs: Arc::new(DashMap::new()),
+            transient_jobs: HashMap::new(),
+            removed_while_detached: HashSet::new(),
             db,
+            history,
+            paths: common::Paths::system(),
             retry_state: HashMap::new(),
+            queued_runs: HashMap::new(),
+            held_runs: Vec::new(),
+            boot_delay: 0,
+            tag_defaults: HashMap::new(),
+            concurrency_groups: HashMap::new(),
+            max_output_bytes: None,
+            execution_logs_kept: output::DEFAULT_EXECUTION_LOGS_KEPT,
+            compress_execution_logs: true,
+            last_heartbeats: HashMap::new(),
+            missed_heartbeats: HashSet::new(),
+            low_success_rate: HashSet::new(),
+            redact_patterns: Vec::new(),
+            acl: Vec::new(),
+            notification_template: None,
+            container_engine: None,
+            trusted_keys: Vec::new(),
+            secrets: None,
+            metrics: Arc::new(MetricsCollector::new()),
+            live_outputs: Arc::new(DashMap::new()),
+            events: Arc::new(EventBus::new()),
+            workflow_runs,
+            dead_letters,
+            failure_streaks: HashMap::new(),
+            replica: None,
+            leadership: None,
+            agents: Arc::new(AgentRegistry::default()),
+            profiler: Arc::new(SchedulerProfiler::new()),
+            started_at: Utc::now(),
+        };
+        scheduler.reschedule_all(Utc::now());
+        scheduler
+    }
+
+    /// Attach a database that was unavailable at startup. Jobs added in the meantime are
+    /// saved to it; jobs only it knows about are loaded.
+    pub fn attach_db(&mut self, db: Arc<Mutex<Db>>) {
+        {
+            let db = db.lock().unwrap();
+            // Jobs removed meanwhile stay removed
+            for id in self.removed_while_detached.drain() {
+                let _ = db.remove_job(&id);
+            }
+            if let Ok(stored) = db.load_jobs() {
+                for (id, job) in stored {
+                    self.jobs.entry(id).or_insert(job);
+                }
+            }
+            for job in self.jobs.values() {
+                let _ = db.add_job(job);
+            }
+            for (job_id, last_run) in load_last_runs(&db, &self.jobs) {
+                self.last_runs.entry(job_id).or_insert(last_run);
+            }
+            for (job_id, window) in load_execution_windows(&db) {
+                let latest = self.last_execution_windows.entry(job_id).or_insert(window);
+                if window > *latest {
+                    *latest = window;
+                }
+            }
+        }
+        log::warn!("Database attached; jobs and history are persisted again");
+        self.history.attach(db.clone());
+        self.db = Some(db);
+        self.reschedule_all(Utc::now());
+    }
+
+    /// Replace the jobs, and what is known of their runs, with the contents of the attached
+    /// database after it was restored from a backup, or when taking over as leader. Running executions go on and are recorded
+    /// in it; retries and queued runs of jobs the backup doesn't have are dropped.
+    pub fn reload_from_db(&mut self) {
+        let db = match self.db.clone() {
+            Some(db) => db,
+            None => return,
+        };
+        {
+            let db = db.lock().unwrap();
+            match db.load_jobs() {
+                Ok(jobs) => self.jobs = jobs,
+                Err(e) => log::error!("Failed to reload jobs from the database: {}", e),
+            }
+            self.last_runs = load_last_runs(&db, &self.jobs);
+            self.last_execution_windows = load_execution_windows(&db);
+            self.dead_letters = db.load_dead_letters().unwrap_or_default();
+        }
+        self.retry_state.retain(|_, retry| self.jobs.contains_key(&retry.job_id));
+        self.queued_runs.retain(|job_id, _| self.jobs.contains_key(job_id));
+        self.held_runs.retain(|run| self.jobs.contains_key(&run.job_id));
+        self.waiting.retain(|job_id, _| self.jobs.contains_key(job_id));
+        self.failure_streaks.clear();
+        if let Some(ref replica) = self.replica {
+            replica.sync(self.jobs.values().cloned().collect());
+        }
+        self.reschedule_all(Utc::now());
+    }
+
+    pub fn add_job(&mut self, job: Job) {
+        if let Some(ref db) = self.db {
+            let _ = db.lock().unwrap().add_job(&job);
+        }
+        self.put_job(job);
+    }
+
+    // Everything of add_job but the database write
+    fn put_job(&mut self, job: Job) {
+        if let Some(ref replica) = self.replica {
+            replica.job_saved(&job);
+        }
+        let change = if self.jobs.contains_key(&job.id.0) { "updated" } else { "added" };
+        self.events.publish(&job, JobEventKind::ScheduleChanged, None, Some(change.to_string()));
+        let id = job.id.0.clone();
+        self.jobs.insert(id.clone(), job);
+        self.reschedule(&id, Utc::now());
+    }
+
+    pub fn remove_job(&mut self, id: &str) -> bool {
+        if let Some(ref db) = self.db {
+            let _ = db.lock().unwrap().remove_job(id);
+        }
+        self.forget_job(id)
+    }
+
+    // Everything of remove_job but the database write
+    fn forget_job(&mut self, id: &str) -> bool {
+        if self.db.is_none() {
+            self.removed_while_detached.insert(id.to_string());
+        }
+        if let Some(ref replica) = self.replica {
+            replica.job_removed(id);
+        }
+        self.last_runs.remove(id);
+        self.due.set(id, None);
+        self.waiting.remove(id);
+        self.last_heartbeats.remove(id);
+        self.missed_heartbeats.remove(id);
+        self.queued_runs.remove(id);
+        self.clear_dead_letter(id);
+        match self.jobs.remove(id) {
+            Some(job) => {
+                self.events.publish(&job, JobEventKind::ScheduleChanged, None, Some("removed".to_string()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record that a run of the job failed with no retries left. After `disable_after`
+    /// consecutive such runs the job is disabled.
+    pub fn dead_letter(&mut self, job_id: &str, attempts: u32, error: String, disable_after: Option<u32>) {
+        let job = match self.jobs.get(job_id) {
+            Some(job) => job.clone(),
+            None => return,
+        };
+        let exhaustions = self.dead_letters.get(job_id).map_or(0, |d| d.exhaustions) + 1;
+        let disable = job.enabled && disable_after.is_some_and(|limit| exhaustions >= limit);
+        let dead_letter = DeadLetter {
+            job_id: job_id.to_string(),
+            job_name: job.name.clone(),
+            failed_at: Utc::now().to_rfc3339(),
+            error,
+            attempts,
+            exhaustions,
+            disabled: disable || self.dead_letters.get(job_id).is_some_and(|d| d.disabled),
+        };
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.lock().unwrap().save_dead_letter(&dead_letter) {
+                log::warn!("Failed to record failed state of job {}: {}", job.name, e);
+            }
+        }
+        self.dead_letters.insert(job_id.to_string(), dead_letter);
+        if disable {
+            self.disable_job(job_id, format!("{} consecutive runs failed with no retries left", exhaustions));
+        }
+    }
+
+    /// Stop scheduling a job, with the reason shown by `lunasched get`: its pending retries
+    /// are dropped, and watchers and the job's on_failure channels are told
+    pub fn disable_job(&mut self, job_id: &str, reason: String) {
+        let mut job = match self.jobs.get(job_id) {
+            Some(job) => job.clone(),
+            None => return,
+        };
+        log::error!("Disabling job {}: {}", job.name, reason);
+        self.retry_state.retain(|_, state| state.job_id != job_id);
+        job.enabled = false;
+        job.disabled_reason = Some(reason.clone());
+        self.add_job(job.clone());
+        self.events.publish(&job, JobEventKind::Disabled, None, Some(reason.clone()));
+        notifier::dispatch(&self.effective_job(&job), NotificationEvent::Disabled, format!("disabled: {}", reason), None, self.db.as_ref());
+    }
+
+    // Count a finished execution towards the job's failure streak, disabling the job at its
+    // disable_after_failures
+    fn count_failure(&mut self, job_id: &str, status: &str) {
+        match status {
+            "success" => {
+                self.failure_streaks.remove(job_id);
+            }
+            "unknown" => {} // Adopted after a restart; the outcome is not known
+            _ => {
+                let failures = self.failure_streaks.entry(job_id.to_string()).or_insert(0);
+                *failures += 1;
+                let failures = *failures;
+                let limit = self.jobs.get(job_id).filter(|job| job.enabled).and_then(|job| job.disable_after_failures);
+                if limit.is_some_and(|limit| failures >= limit) {
+                    self.disable_job(job_id, format!("{} executions in a row failed", failures));
+                }
+            }
+        }
+    }
+
+    // Disable jobs whose expires_at has passed
+    fn expire_jobs(&mut self, now: DateTime<Utc>) {
+        let expired: Vec<(String, DateTime<Utc>)> = self.jobs.values()
+            .filter(|job| job.enabled)
+            .filter_map(|job| job.expires_at.filter(|at| *at <= now).map(|at| (job.id.0.clone(), at)))
+            .collect();
+        for (job_id, at) in expired {
+            self.disable_job(&job_id, format!("expired at {}", at.to_rfc3339()));
+        }
+    }
+
+    /// Clear the job's failed state, returning it if there was one
+    pub fn clear_dead_letter(&mut self, job_id: &str) -> Option<DeadLetter> {
+        let dead_letter = self.dead_letters.remove(job_id)?;
+        if let Some(ref db) = self.db {
+            let _ = db.lock().unwrap().remove_dead_letter(job_id);
+        }
+        Some(dead_letter)
+    }
+
+    /// Notify the job's channels about a finished run, as its alert policy allows. `failure`
+    /// is the error of a run that failed for good (after `dead_letter`); `ended_streak` the
+    /// failed runs a success put an end to.
+    pub fn notify_run(&mut self, job_id: &str, execution_id: &str, failure: Option<&str>, ended_streak: u32) {
+        let job = match self.jobs.get(job_id) {
+            Some(job) => self.effective_job(job),
+            None => return,
+        };
+        let policy = job.notification_config.alerts.clone();
+        let mut notifications = Vec::new();
+
+        if let Some(threshold) = policy.min_success_rate {
+            let window = policy.success_rate_window.unwrap_or(AlertPolicy::DEFAULT_SUCCESS_RATE_WINDOW);
+            let since = Utc::now() - Duration::seconds(window as i64);
+            let rate = match self.db {
+                Some(ref db) => db.lock().unwrap().success_rate(job_id, &since).unwrap_or_else(|e| {
+                    log::warn!("Failed to compute success rate of job {}: {}", job.name, e);
+                    None
+                }),
+                None => None,
+            };
+            match rate {
+                Some(rate) if rate < threshold && self.low_success_rate.insert(job_id.to_string()) => {
+                    notifications.push((NotificationEvent::Failure, format!(
+                        "success rate over the last {} fell to {:.0}% (threshold {:.0}%)", format_window(window), rate, threshold)));
+                }
+                Some(rate) if rate >= threshold && self.low_success_rate.remove(job_id) => {
+                    notifications.push((NotificationEvent::Success, format!(
+                        "success rate over the last {} is back to {:.0}%", format_window(window), rate)));
+                }
+                _ => {}
+            }
+        }
+
+        match failure {
+            Some(error) => {
+                let streak = self.dead_letters.get(job_id).map_or(1, |d| d.exhaustions);
+                match policy.after_failures {
+                    Some(limit) if streak == limit => notifications.push((NotificationEvent::Failure,
+                        format!("{} consecutive runs failed; last error: {}", streak, error))),
+                    None if policy.min_success_rate.is_none() => {
+                        notifications.push((NotificationEvent::Failure, error.to_string()));
+                    }
+                    _ => {}
+                }
+            }
+            None => match policy.after_failures {
+                Some(limit) if ended_streak >= limit => notifications.push((NotificationEvent::Success,
+                    format!("succeeded again after {} failed runs", ended_streak))),
+                _ if notifications.is_empty() => {
+                    notifications.push((NotificationEvent::Success, "finished successfully".to_string()));
+                }
+                _ => {}
+            },
+        }
+
+        for (event, message) in notifications {
+            notifier::dispatch(&job, event, message, Some(execution_id), self.db.as_ref());
+        }
+    }
+
+    /// The job as it runs: its own settings plus the config defaults of its tags (and the
+    /// global notification template)
+    pub fn effective_job(&self, job: &Job) -> Job {
+        let mut job = config::apply_tag_defaults(&self.tag_defaults, job);
+        if job.notification_config.template.is_none() {
+            job.notification_config.template = self.notification_template.clone();
+        }
+        job
+    }
+
+    /// Whether a run with the given run key is in progress
+    pub fn is_running(&self, run_key: &str) -> bool {
+        self.running_jobs.iter().any(|ctx| ctx.run_key == run_key)
+    }
+
+    /// Whether another execution of `job` may start now. Sequential jobs run once per run key;
+    /// Parallel jobs up to `max_concurrent` at a time (0 = unlimited), still once per key if fenced.
+    pub fn has_capacity(&self, job: &Job, run_key: &str) -> bool {
+        if job.execution_mode != ExecutionMode::Parallel {
+            return !self.is_running(run_key);
+        }
+        if job.fence_key.is_some() && self.is_running(run_key) {
+            return false;
+        }
+        let running = self.running_jobs.iter().filter(|ctx| ctx.job_id == job.id.0).count();
+        job.max_concurrent == 0 || running < job.max_concurrent as usize
+    }
+
+    /// A running job of `job`'s concurrency group (possibly `job` itself), if any. Groups are
+    /// per owner, so no user can hold up another's jobs by joining their group.
+    pub fn group_holder(&self, job: &Job) -> Option<String> {
+        let group = job.concurrency_group.as_ref()?;
+        self.running_jobs.iter()
+            .find(|ctx| self.jobs.get(&ctx.job_id)
+                .is_some_and(|other| other.concurrency_group.as_ref() == Some(group) && other.owner == job.owner))
+            .map(|ctx| ctx.job_id.clone())
+    }
+
+    /// What happens to a run of `job` that comes due while its concurrency group is busy
+    pub fn group_policy(&self, job: &Job) -> OverflowPolicy {
+        job.concurrency_group.as_ref()
+            .and_then(|group| self.concurrency_groups.get(group))
+            .map(|group| group.overflow_policy.clone())
+            .unwrap_or_default()
+    }
+
+    /// `has_capacity`, and no other member of the job's concurrency group running
+    pub fn can_start(&self, job: &Job, run_key: &str) -> bool {
+        self.has_capacity(job, run_key) && self.group_holder(job).is_none()
+    }
+
+    /// Register a new execution in `running_jobs` and record its start, returning its execution ID.
+    /// `fired_window` is set for scheduled runs, whose window then counts as fired across restarts.
+    pub fn begin_execution(&self, job: &Job, run_key: String, scheduled_time: DateTime<Utc>, fired_window: bool) -> String {
+        let execution_id = Uuid::new_v4().to_string();
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.lock().unwrap().record_execution_start(&job.id.0, &execution_id, &scheduled_time, fired_window) {
+                log::warn!("Failed to record start of job {}: {}", job.name, e);
+            }
+        }
+        self.running_jobs.insert(
+            execution_id.clone(),
+            JobExecutionContext {
+                execution_id: execution_id.clone(),
+                job_id: job.id.0.clone(),
+                run_key,
+                scheduled_time,
+                start_time: Utc::now(),
+                pid: None,
+                pgid: None,
+            },
+        );
+        execution_id
+    }
+
+    /// Start the run of a transient job (`lunasched run`). The job is never added or scheduled
+    /// and is forgotten once the run is over; its history stays.
+    pub fn begin_transient(&mut self, job: Job) -> String {
+        let execution_id = self.begin_execution(&job, job.id.0.clone(), Utc::now(), false);
+        self.transient_jobs.insert(job.id.0.clone(), job);
+        execution_id
+    }
+
+    /// Queue a run to start once the job has capacity (Queue overflow policy, catch-up and
+    /// startup runs); false if the queue is full
+    pub fn enqueue_run(&mut self, job: &Job, params: RunParams) -> bool {
+        let queue = self.queued_runs.entry(job.id.0.clone()).or_default();
+        if queue.len() >= MAX_QUEUED_RUNS {
+            log::warn!("Dropping run of job {}: {} runs already queued", job.name, queue.len());
+            return false;
+        }
+        queue.push_back(params);
+        log::info!("Queued run of job {} ({} waiting)", job.name, queue.len());
+        true
+    }
+
+    /// The job `owner` has named `name`; names are unique per owner
+    pub fn job_named(&self, owner: &str, name: &str) -> Option<&Job> {
+        self.jobs.values().find(|job| job.owner == owner && job.name == name)
+    }
+
+    /// The ID of the job `reference` names exactly: its ID, or the name of one of `owner`'s jobs,
+    /// or of the only job anyone has by that name
+    pub fn job_id_for(&self, reference: &str, owner: &str) -> Option<JobId> {
+        if let Some(job) = self.jobs.get(reference) {
+            return Some(job.id.clone());
+        }
+        if let Some(job) = self.job_named(owner, reference) {
+            return Some(job.id.clone());
+        }
+        let mut named = self.jobs.values().filter(|job| job.name == reference);
+        match (named.next(), named.next()) {
+            (Some(job), None) => Some(job.id.clone()),
+            _ => None,
+        }
+    }
+
+    /// Give a job sent without an ID the ID of its owner's job of the same name, which it
+    /// replaces, or a new one; and keep the jobs it triggers by ID, whichever form they were given in
+    pub fn assign_ids(&self, job: &mut Job) {
+        if job.id.0.is_empty() {
+            job.id = self.job_named(&job.owner, &job.name).map(|existing| existing.id.clone()).unwrap_or_else(new_job_id);
+        }
+        for target in job.on_success_trigger.iter_mut().chain(job.on_failure_trigger.iter_mut()) {
+            if let Some(id) = self.job_id_for(&target.0, &job.owner) {
+                *target = id;
+            }
+        }
+    }
+
+    /// Refuse a job named like another of its owner's jobs
+    pub fn check_name(&self, job: &Job) -> Result<(), String> {
+        check_name_among(&self.jobs, job)
+    }
+
+    /// Find a job by ID or name: an exact match first (by name, `owner`'s job before anyone
+    /// else's), else the only job whose ID or name starts with `reference`. Otherwise the error
+    /// lists the candidates, or the jobs named most like it.
+    pub fn resolve_job(&self, reference: &str, owner: &str) -> Result<&Job, String> {
+        if let Some(job) = self.jobs.get(reference).or_else(|| self.job_named(owner, reference)) {
+            return Ok(job);
+        }
+        let mut named: Vec<&Job> = self.jobs.values().filter(|job| job.name == reference).collect();
+        if named.is_empty() {
+            named = self.jobs.values()
+                .filter(|job| job.id.0.starts_with(reference) || job.name.starts_with(reference))
+                .collect();
+        }
+        named.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        let describe = |jobs: &[&Job]| jobs.iter()
+            .map(|job| if job.name == job.id.0 { job.id.0.clone() } else { format!("{} ({})", job.name, job.id) })
+            .collect::<Vec<_>>()
+            .join(", ");
+        match named.as_slice() {
+            [job] => Ok(*job),
+            [] => {
+                // Typos: names and IDs within a few edits
+                let max_distance = (reference.chars().count() / 3).max(2);
+                let mut near: Vec<(usize, &Job)> = self.jobs.values()
+                    .map(|job| (edit_distance(reference, &job.name).min(edit_distance(reference, &job.id.0)), job))
+                    .filter(|(distance, _)| *distance <= max_distance)
+                    .collect();
+                near.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.id.0.cmp(&b.1.id.0)));
+                let near: Vec<&Job> = near.into_iter().take(MAX_SUGGESTIONS).map(|(_, job)| job).collect();
+                if near.is_empty() {
+                    Err(format!("Job not found: {}", reference))
+                } else {
+                    Err(format!("Job not found: {}. Did you mean {}?", reference, describe(&near)))
+                }
+            }
+            jobs => Err(format!("'{}' matches {} jobs: {}", reference, jobs.len(), describe(jobs))),
+        }
+    }
+
+    /// Refuse triggers of jobs owned by someone else, and triggers that lead back to `job`
+    pub fn check_triggers(&self, job: &Job) -> Result<(), String> {
+        check_triggers_among(&self.jobs, job)
+    }
+
+    /// Queue runs of the jobs a finished run of `job` triggers. Each triggered run carries the
+    /// chain of jobs that led to it (`TRIGGER_PARAM`), and a job already in the chain isn't
+    /// triggered again, so a loop stops after one round. Disabled jobs can be triggered.
+    pub fn trigger_jobs(&mut self, job: &Job, params: &RunParams, success: bool) {
+        let targets = if success { &job.on_success_trigger } else { &job.on_failure_trigger };
+        if targets.is_empty() {
+            return;
+        }
+        let mut chain: Vec<&str> = params.get(TRIGGER_PARAM)
+            .map(|chain| chain.split(',').collect())
+            .unwrap_or_default();
+        chain.push(&job.id.0);
+        for target in targets {
+            let next = match self.jobs.get(&target.0) {
+                Some(next) => next.clone(),
+                None => {
+                    log::warn!("Job {} triggers job {}, which doesn't exist", job.name, target.0);
+                    continue;
+                }
+            };
+            if chain.contains(&target.0.as_str()) {
+                log::warn!("Not triggering job {} again: trigger loop {} -> {}", next.name, chain.join(" -> "), target.0);
+            } else if next.owner != job.owner && job.owner != "root" {
+                log::warn!("Not triggering job {} from job {}: it is owned by {}", next.name, job.name, next.owner);
+            } else if matches!(next.schedule, ScheduleConfig::Heartbeat(_)) {
+                log::warn!("Not triggering job {}: heartbeat jobs have no command", next.name);
+            } else {
+                let mut run_params = RunParams::new();
+                run_params.insert(TRIGGER_PARAM.to_string(), chain.join(","));
+                log::info!("Job {} triggers job {}", job.name, next.name);
+                self.enqueue_run(&next, run_params);
+            }
+        }
+    }
+
+    /// When `job` is next due; None if it is disabled or never runs on its own
+    pub fn next_run(&self, job: &Job, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !job.enabled {
+            return None;
+        }
+        self.due.get(&job.id.0)
+            .or_else(|| self.waiting.get(&job.id.0).copied())
+            .map(|at| at.max(now))
+    }
+
+    /// Earliest time a job is due, for the tick loop to sleep until
+    pub fn next_wake(&mut self) -> Option<DateTime<Utc>> {
+        self.due.peek()
+    }
+
+    // When the job fires next after its last run. Windows that passed without a run (the daemon
+    // was busy, or the clock jumped) are not run late; catch-up is a separate policy.
+    fn due_time(&self, job: &Job, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !job.enabled {
+            return None;
+        }
+        let since = |last: Option<&DateTime<Utc>>| last.map_or(now - Duration::seconds(1), |last| (*last).max(now - Duration::seconds(1)));
+        match &job.schedule {
+            ScheduleConfig::Cron(expression) => {
+                Schedule::from_str(expression).ok()?.after(&since(self.last_runs.get(&job.id.0))).next()
+            }
+            ScheduleConfig::Every(seconds) => {
+                let interval = Duration::seconds(*seconds as i64);
+                match self.last_runs.get(&job.id.0) {
+                    Some(last) => Some(*last + interval),
+                    None if job.run_immediately => Some(now),
+                    None => Some(now + interval),
+                }
+            }
+            ScheduleConfig::Calendar(params) => {
+                next_calendar_window(job, params, since(self.last_execution_windows.get(&job.id.0)))
+            }
+            ScheduleConfig::Window(params) => {
+                next_calendar_window(job, &params.calendar(), since(self.last_execution_windows.get(&job.id.0)))
+            }
+            // Disabled once it fires, so it can't fire twice
+            ScheduleConfig::Once(at) => Some(*at),
+            // Passive jobs never run on their own; see check_heartbeats. @reboot jobs are
+            // started by run_reboot_jobs.
+            ScheduleConfig::Heartbeat(_) | ScheduleConfig::Reboot => None,
+        }
+    }
+
+    /// Recompute when a job is next due, after it is added, changed or has fired
+    fn reschedule(&mut self, job_id: &str, now: DateTime<Utc>) {
+        let job = match self.jobs.get(job_id) {
+            Some(job) => job,
+            None => {
+                self.due.set(job_id, None);
+                return;
+            }
+        };
+        // The first interval of a new job counts from now
+        if matches!(job.schedule, ScheduleConfig::Every(_)) && !job.run_immediately && !self.last_runs.contains_key(job_id) {
+            if let Some(ref db) = self.db {
+                if let Err(e) = db.lock().unwrap().save_last_scheduled(job_id, &now) {
+                    log::warn!("Failed to save schedule state for job {}: {}", job.name, e);
+                }
+            }
+            self.last_runs.insert(job_id.to_string(), now);
+        }
+        let at = self.due_time(job, now);
+        self.due.set(job_id, at);
+    }
+
+    fn reschedule_all(&mut self, now: DateTime<Utc>) {
+        self.due.clear();
+        let job_ids: Vec<String> = self.jobs.keys().cloned().collect();
+        for job_id in job_ids {
+            self.reschedule(&job_id, now);
+        }
+    }
+
+    /// Notice the wall clock stepping (NTP, a manual change, or a suspend, which the monotonic
+    /// clock doesn't count) by comparing both clocks since the last tick, and plan around it
+    fn check_clock(&mut self, now: DateTime<Utc>) {
+        let mono = std::time::Instant::now();
+        let (last_mono, last_wall) = match self.last_tick.replace((mono, now)) {
+            Some(last) => last,
+            None => return,
+        };
+        let skew = (now - last_wall) - Duration::from_std(mono - last_mono).unwrap_or_else(|_| Duration::zero());
+        if skew.num_seconds().abs() < CLOCK_JUMP_SECS {
+            return;
+        }
+        if skew > Duration::zero() {
+            log::warn!("System clock jumped forward by {}s; windows skipped over follow each job's catch-up policy",
+                skew.num_seconds());
+        } else {
+            log::warn!("System clock jumped back by {}s; windows already run are not run again", -skew.num_seconds());
+        }
+
+        // Intervals measure elapsed time, so the last runs of Every jobs move with the clock
+        let every_jobs: Vec<String> = self.jobs.values()
+            .filter(|job| matches!(job.schedule, ScheduleConfig::Every(_)))
+            .map(|job| job.id.0.clone())
+            .collect();
+        for job_id in every_jobs {
+            if let Some(last_run) = self.last_runs.get_mut(&job_id) {
+                *last_run += skew;
+                if let Some(ref db) = self.db {
+                    let _ = db.lock().unwrap().save_last_scheduled(&job_id, last_run);
+                }
+            }
+        }
+        if skew > Duration::zero() {
+            self.catch_up(|_| Some(last_wall), now, "while the clock jumped");
+        }
+        self.reschedule_all(now);
+    }
+
+    /// Queue runs for Cron and Calendar windows missed while the daemon was down, per each
+    /// job's catch-up policy. Call once at startup, before the first tick.
+    pub fn catch_up_missed_runs(&mut self) {
+        self.catch_up_from_db("while the daemon was down");
+    }
+
+    /// Queue runs for the windows missed since the previous leader last fired each job, per the
+    /// jobs' catch-up policies. Call when this daemon becomes the leader (`ha`).
+    pub fn catch_up_after_failover(&mut self) {
+        self.catch_up_from_db("while no daemon was leading");
+    }
+
+    // Catch up from the last scheduled window of each job, as recorded in the database
+    fn catch_up_from_db(&mut self, when: &str) {
+        let last_scheduled = match self.db {
+            Some(ref db) => match db.lock().unwrap().load_last_scheduled() {
+                Ok(last_scheduled) => last_scheduled,
+                Err(e) => {
+                    log::error!("Failed to load schedule state, skipping catch-up: {}", e);
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        self.catch_up(|job| last_scheduled.get(&job.id.0).copied(), Utc::now(), when);
+    }
+
+    // Queue runs for the Cron and Calendar windows between `since` (None: never fired, nothing
+    // was missed) and `now`, per each job's catch-up policy
+    fn catch_up(&mut self, since: impl Fn(&Job) -> Option<DateTime<Utc>>, now: DateTime<Utc>, when: &str) {
+        let mut catch_ups = Vec::new();
+        for job in self.jobs.values() {
+            if !job.enabled || job.catch_up == CatchUpPolicy::Skip {
+                continue;
+            }
+            let since = match since(job) {
+                Some(since) => since,
+                None => continue,
+            };
+            let mut windows = missed_windows(job, since, now);
+            if windows.is_empty() {
+                continue;
+            }
+            log::warn!("Job {} missed {} scheduled run(s) since {} {}; catch-up policy {:?}",
+                job.name, windows.len(), since.to_rfc3339(), when, job.catch_up);
+            if job.catch_up == CatchUpPolicy::RunOnce {
+                windows.drain(..windows.len() - 1);
+            }
+            catch_ups.push((job.clone(), windows));
+        }
+
+        for (job, windows) in catch_ups {
+            for window in windows {
+                let mut params = RunParams::new();
+                params.insert(CATCH_UP_PARAM.to_string(), window.to_rfc3339());
+                if !self.start_after_boot_delay(&job, params) {
+                    break;
+                }
+            }
+            // The missed windows are handled; don't catch them up again on the next restart
+            if let Some(ref db) = self.db {
+                let _ = db.lock().unwrap().save_last_scheduled(&job.id.0, &now);
+            }
+        }
+    }
+
+    /// Start @reboot jobs, once per host boot. Call at startup after catch_up_missed_runs.
+    pub fn run_reboot_jobs(&mut self) {
+        // The marker lives next to the socket on /run (or $XDG_RUNTIME_DIR), a tmpfs cleared at boot
+        let marker = std::path::PathBuf::from(&self.paths.reboot_marker);
+        if marker.exists() {
+            return; // Daemon restarted without a reboot
+        }
+        let jobs: Vec<Job> = self.jobs.values()
+            .filter(|job| job.enabled && matches!(job.schedule, ScheduleConfig::Reboot))
+            .cloned()
+            .collect();
+        for job in jobs {
+            log::info!("Starting @reboot job {}", job.name);
+            self.start_after_boot_delay(&job, RunParams::new());
+        }
+        if let Some(parent) = marker.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = std::fs::write(&marker, b"") {
+            log::warn!("Failed to create {}; @reboot jobs will run again on the next daemon start: {}", marker.display(), e);
+        }
+    }
+
+    /// Queue a startup run, holding it until the host has been up for the job's boot delay
+    /// (or the configured default). False if the run was dropped.
+    fn start_after_boot_delay(&mut self, job: &Job, params: RunParams) -> bool {
+        let delay = Duration::seconds(job.boot_delay.unwrap_or(self.boot_delay) as i64);
+        match host_uptime() {
+            Some(uptime) if uptime < delay => {
+                let not_before = Utc::now() + (delay - uptime);
+                log::info!("Holding run of job {} until {} ({}s after boot)", job.name, not_before.to_rfc3339(), delay.num_seconds());
+                self.held_runs.push(HeldRun { job_id: job.id.0.clone(), params, not_before });
+                true
+            }
+            _ => self.enqueue_run(job, params),
+        }
+    }
+
+    /// Add exported jobs, resolving ID clashes with existing jobs per `on_conflict`
+    pub fn import_jobs(&mut self, jobs: Vec<Job>, on_conflict: ConflictPolicy) -> ImportReport {
+        let mut report = ImportReport::default();
+        for mut job in jobs {
+            if job.id.0.is_empty() {
+                job.id = new_job_id();
+            }
+            let id = job.id.0.clone();
+            // A job clashes by ID, or by name with another of its owner's jobs
+            let named = self.job_named(&job.owner, &job.name).map(|other| other.id.clone()).filter(|other| other.0 != id);
+            if !self.jobs.contains_key(&id) && named.is_none() {
+                self.add_job(job);
+                report.created.push(id);
+                continue;
+            }
+            match on_conflict {
+                ConflictPolicy::Skip => report.skipped.push(id),
+                ConflictPolicy::Overwrite => {
+                    // Replaces the job of the same name, keeping its ID (and history)
+                    if let Some(other) = named {
+                        job.id = other;
+                    }
+                    report.overwritten.push(job.id.0.clone());
+                    self.add_job(job);
+                }
+                ConflictPolicy::Rename => {
+                    if self.jobs.contains_key(&id) {
+                        job.id = new_job_id();
+                    }
+                    if named.is_some() {
+                        let name = job.name.clone();
+                        job.name = (2..).map(|n| format!("{}-{}", name, n))
+                            .find(|candidate| self.job_named(&job.owner, candidate).is_none())
+                            .unwrap();
+                    }
+                    report.renamed.push((id, job.id.0.clone()));
+                    self.add_job(job);
+                }
+            }
+        }
+        report
+    }
+
+    /// Bring jobs from a declarative source in line with `desired`.
+    /// Only jobs tagged with the source are updated, and with `prune`, removed when no longer
+    /// declared. A declared job named like another job of its owner's is an error.
+    /// The changes are stored in one transaction: should that fail, none of them is made.
+    pub fn reconcile(&mut self, desired: Vec<Job>, source: &str, prune: bool) -> Result<ApplyReport, String> {
+        let current: Vec<Job> = self.jobs.values().cloned().collect();
+        let mut report = ApplyReport::default();
+        let (mut saved, mut deleted) = (Vec::new(), Vec::new());
+
+        for action in common::declarative::plan(&current, desired, source, prune) {
+            match action {
+                PlanAction::Create(mut job) => {
+                    if let Some(other) = self.job_named(&job.owner, &job.name) {
+                        return Err(format!("{} not applied: {} already has a job named {} ({}) that {} doesn't manage",
+                            source, job.owner, job.name, other.id, source));
+                    }
+                    self.assign_ids(&mut job);
+                    report.created.push(job.id.0.clone());
+                    saved.push(job);
+                }
+                PlanAction::Update { mut job, .. } => {
+                    self.assign_ids(&mut job);
+                    report.updated.push(job.id.0.clone());
+                    saved.push(job);
+                }
+                PlanAction::Delete(id) => {
+                    report.deleted.push(id.0.clone());
+                    deleted.push(id.0);
+                }
+            }
+        }
+
+        // Names and triggers are checked as add checks them, against the jobs as they will be
+        let mut staged = self.jobs.clone();
+        for id in &deleted {
+            staged.remove(id);
+        }
+        for job in &saved {
+            staged.insert(job.id.0.clone(), job.clone());
+        }
+        for job in &saved {
+            check_name_among(&staged, job)
+                .and_then(|_| check_triggers_among(&staged, job))
+                .map_err(|e| format!("{} not applied: {}", source, e))?;
+        }
+
+        if let Some(ref db) = self.db {
+            db.lock().unwrap().transaction(|db| {
+                saved.iter().try_for_each(|job| db.add_job(job))?;
+                deleted.iter().try_for_each(|id| db.remove_job(id))
+            }).map_err(|e| format!("Failed to store the jobs of {}: {}", source, e))?;
+        }
+        for job in saved {
+            self.put_job(job);
+        }
+        for id in deleted {
+            self.forget_job(&id);
+        }
+        Ok(report)
+    }
+
+    /// Record an inbound ping for a heartbeat job
+    pub fn record_heartbeat(&mut self, id: &str) -> Result<(), String> {
+        let job = match self.jobs.get(id) {
+            Some(job) => job,
+            None => return Err("Job not found".to_string()),
+        };
+        if !matches!(job.schedule, ScheduleConfig::Heartbeat(_)) {
+            return Err(format!("Job {} is not a heartbeat job", job.name));
+        }
+
+        self.last_heartbeats.insert(id.to_string(), Utc::now());
+        if self.missed_heartbeats.remove(id) {
+            log::info!("Heartbeat job {} recovered", job.name);
+            self.history.write(PendingWrite::Event {
+                job_id: id.to_string(),
+                status: "recovered".to_string(),
+                message: "Heartbeat received again".to_string(),
+            });
+            notifier::dispatch(&self.effective_job(job), NotificationEvent::Success, "heartbeat recovered", None, self.db.as_ref());
+        } else {
+            log::debug!("Heartbeat received for job {}", job.name);
+        }
+        Ok(())
+    }
+
+    /// Raise a failure event for heartbeat jobs whose ping is overdue (once per outage)
+    fn check_heartbeats(&mut self, now: DateTime<Utc>) {
+        for job in self.jobs.values() {
+            let interval = match job.schedule {
+                ScheduleConfig::Heartbeat(seconds) if job.enabled => seconds,
+                _ => continue,
+            };
+
+            // Without a ping yet, start the clock when the job is first seen
+            let last = *self.last_heartbeats.entry(job.id.0.clone()).or_insert(now);
+            let silent_for = (now - last).num_seconds();
+            if silent_for <= interval as i64 || self.missed_heartbeats.contains(&job.id.0) {
+                continue;
+            }
+
+            let message = format!("No heartbeat received for {}s (expected every {}s)", silent_for, interval);
+            log::error!("Heartbeat job {} missed: {}", job.name, message);
+            self.missed_heartbeats.insert(job.id.0.clone());
+
+            self.history.write(PendingWrite::Event {
+                job_id: job.id.0.clone(),
+                status: "missed".to_string(),
+                message: message.clone(),
+            });
+            if let Some(on_failure) = job.hooks.on_failure.clone() {
+                let (job, db, store) = (job.clone(), self.db.clone(), self.secrets.clone());
+                tokio::spawn(async move {
+                    let (env, secrets) = crate::secrets::resolve_env(&job.env, &job.owner, db.as_deref(), store.as_deref())
+                        .unwrap_or_default();
+                    hooks::run(&job, "on_failure", &on_failure, &env, &secrets).await;
+                });
+            }
+            notifier::dispatch(&self.effective_job(job), NotificationEvent::Failure, message, None, self.db.as_ref());
+        }
+    }
+
+    pub fn tick(&mut self) -> Vec<PendingRun> {
+        let tick_start = std::time::Instant::now();
+        let mut jobs_to_run = Vec::new();
+        let mut evaluations = Vec::new();
+        let now = Utc::now();
+
+        self.check_clock(now);
+        self.metrics.record_tick();
+        self.check_heartbeats(now);
+        self.expire_jobs(now);
+        
+        // Check for scheduled retries
+        let retry_jobs: Vec<(String, String, RunParams)> = self.retry_state.iter()
+            .filter_map(|(run_key, state)| {
+                if let Some(next_attempt) = state.next_attempt_at {
+                    if next_attempt <= now {
+                        Some((run_key.clone(), state.job_id.clone(), state.params.clone()))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            })
+            .collect();
+        
+        let mut retried = Vec::new();
+        for (run_key, job_id, params) in retry_jobs {
+            if let Some(job) = self.jobs.get(&job_id) {
+                if self.can_start(job, &run_key) {
+                    log::info!("Retrying job: {} (attempt {})", job.name, 
+                        self.retry_state.get(&run_key).map(|s| s.attempt + 1).unwrap_or(1));
+                    
+                    // Due once; a failure of this attempt schedules the next one
+                    if let Some(state) = self.retry_state.get_mut(&run_key) {
+                        state.next_attempt_at = None;
+                    }
+                    let execution_id = self.begin_execution(job, run_key, Utc::now(), false);
+                    retried.push((job_id.clone(), execution_id.clone()));
+                    jobs_to_run.push(PendingRun { job: job.clone(), params, execution_id });
+                }
+            }
+        }
+        for (job_id, execution_id) in retried {
+            self.track_workflow_retry(&job_id, &execution_id);
+        }
+
+        // Release startup runs once the host has settled after boot
+        if self.held_runs.iter().any(|run| run.not_before <= now) {
+            let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held_runs)
+                .into_iter()
+                .partition(|run| run.not_before <= now);
+            self.held_runs = held;
+            for run in due {
+                if let Some(job) = self.jobs.get(&run.job_id).cloned() {
+                    self.enqueue_run(&job, run.params);
+                }
+            }
+        }
+
+        // Start queued runs as executions of their jobs finish
+        let queued_job_ids: Vec<String> = self.queued_runs.keys().cloned().collect();
+        for job_id in queued_job_ids {
+            let job = match self.jobs.get(&job_id) {
+                Some(job) => job.clone(),
+                None => {
+                    self.queued_runs.remove(&job_id);
+                    continue;
+                }
+            };
+            while let Some(params) = self.queued_runs.get(&job_id).and_then(|q| q.front()) {
+                let run_key = job.run_key(params);
+                if !self.can_start(&job, &run_key) {
+                    break;
+                }
+                let params = self.queued_runs.get_mut(&job_id).and_then(|q| q.pop_front()).unwrap_or_default();
+                log::info!("Starting queued run of job {}", job.name);
+                let execution_id = self.begin_execution(&job, run_key, now, false);
+                jobs_to_run.push(PendingRun { job: job.clone(), params, execution_id });
+            }
+            if self.queued_runs.get(&job_id).is_some_and(|q| q.is_empty()) {
+                self.queued_runs.remove(&job_id);
+            }
+        }
+
+        jobs_to_run.extend(self.advance_workflows(now));
+        
+        // Jobs that came due, and Sequential jobs still waiting for their last run to finish
+        let mut due: Vec<(String, DateTime<Utc>)> = self.waiting.drain().collect();
+        due.extend(self.due.pop_due(now));
+        due.sort_by_key(|(_, at)| *at);
+
+        let no_params = RunParams::new();
+        let mut overflowed = Vec::new();
+        let mut fired_once = Vec::new();
+        for (job_id, due_at) in due {
+            let job = match self.jobs.get(&job_id) {
+                Some(job) if job.enabled => job.clone(),
+                _ => continue,
+            };
+
+            // Concurrency check. A busy Sequential job waits; a Parallel job at its
+            // max_concurrent limit still fires so its overflow policy applies.
+            let run_key = job.run_key(&no_params);
+            let at_capacity = !self.has_capacity(&job, &run_key);
+            if at_capacity && job.execution_mode != ExecutionMode::Parallel {
+                self.waiting.insert(job_id, due_at);
+                continue;
+            }
+
+            let mut next_run_time = due_at;
+            if let ScheduleConfig::Every(seconds) = job.schedule {
+                // Lag check: if we are behind by more than 10 intervals, reset to now
+                let interval = Duration::seconds(seconds as i64);
+                if (now - due_at) > (interval * 10) {
+                    log::warn!("Job {} is lagging significantly. Resetting schedule.", job.name);
+                    next_run_time = now;
+                }
+            }
+
+            // Remember the window for catch-up after downtime, and interval runs so a
+            // restart doesn't start the interval over
+            if matches!(job.schedule, ScheduleConfig::Cron(_) | ScheduleConfig::Calendar(_) | ScheduleConfig::Window(_) | ScheduleConfig::Every(_)) {
+                if let Some(ref db) = self.db {
+                    if let Err(e) = db.lock().unwrap().save_last_scheduled(&job.id.0, &next_run_time) {
+                        log::warn!("Failed to save schedule state for job {}: {}", job.name, e);
+                    }
+                }
+            }
+
+            // Apply jitter if configured
+            if job.jitter_seconds > 0 {
+                use rand::Rng;
+                let jitter_ms = rand::thread_rng().gen_range(0..job.jitter_seconds * 1000);
+                next_run_time += Duration::milliseconds(jitter_ms as i64);
+                log::debug!("Applied jitter of {}ms to job {}", jitter_ms, job.name);
+            }
+
+            self.last_runs.insert(job.id.0.clone(), next_run_time);
+            self.last_execution_windows.insert(job.id.0.clone(), next_run_time);
+            let eval_start = std::time::Instant::now();
+            self.reschedule(&job_id, now);
+            evaluations.push((job_id, eval_start.elapsed()));
+
+            let group_holder = if at_capacity { None } else { self.group_holder(&job) };
+            let skipped = (at_capacity && job.overflow_policy == OverflowPolicy::Skip)
+                || (group_holder.is_some() && self.group_policy(&job) == OverflowPolicy::Skip);
+            if matches!(job.schedule, ScheduleConfig::Once(_)) && !skipped {
+                fired_once.push(job.id.0.clone());
+            }
+
+            if at_capacity {
+                match job.overflow_policy {
+                    OverflowPolicy::Skip => log::warn!("Skipping run of job {}: {} executions already running",
+                        job.name, job.max_concurrent),
+                    OverflowPolicy::Queue => overflowed.push(job.clone()),
+                }
+                continue;
+            }
+            if let Some(holder) = group_holder {
+                match self.group_policy(&job) {
+                    OverflowPolicy::Skip => log::warn!("Skipping run of job {}: job {} of concurrency group {} is running",
+                        job.name, holder, job.concurrency_group.as_deref().unwrap_or_default()),
+                    OverflowPolicy::Queue => overflowed.push(job.clone()),
+                }
+                continue;
+            }
+
+            // Create execution context
+            let execution_id = self.begin_execution(&job, run_key, next_run_time, true);
+            log::info!("Scheduling job: {} (execution_id: {})", job.name, execution_id);
+            jobs_to_run.push(PendingRun { job, params: RunParams::new(), execution_id });
+        }
+        self.profiler.record_tick(tick_start.elapsed(), &evaluations);
+        for job in overflowed {
+            self.enqueue_run(&job, RunParams::new());
+        }
+        for job_id in fired_once {
+            if let Some(mut job) = self.jobs.get(&job_id).cloned() {
+                log::info!("One-shot job {} fired; disabling it", job.name);
+                job.enabled = false;
+                self.add_job(job);
+            }
+        }
+        jobs_to_run
+    }
+
+    pub fn finish_job(&mut self, execution_id: &str, status: &str) {
+        let ctx = match self.running_jobs.remove(execution_id) {
+            Some((_, ctx)) => ctx,
+            None => return,
+        };
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.lock().unwrap().finish_execution(execution_id) {
+                log::warn!("Failed to record end of execution {}: {}", execution_id, e);
+            }
         }
-    }
+        self.finish_workflow_step(&ctx, status == "success");
+        self.transient_jobs.remove(&ctx.job_id);
+        self.count_failure(&ctx.job_id, status);
 
-    pub fn add_job(&mut self, job: Job) {
-        if let Some(ref db) = self.db {
-            let _ = db.lock().unwrap().add_job(&job);
+        // A one-shot job marked delete_after_run goes once its run is over, retries included
+        let done_once = self.jobs.get(&ctx.job_id).is_some_and(|job| {
+            job.delete_after_run && !job.enabled && matches!(job.schedule, ScheduleConfig::Once(_))
+        });
+        if done_once && !self.retry_state.contains_key(&ctx.run_key) && !self.is_running(&ctx.run_key) {
+            log::info!("Removing one-shot job {} after its run", ctx.job_id);
+            self.remove_job(&ctx.job_id);
         }
-        self.jobs.insert(job.id.0.clone(), job);
     }
 
-    pub fn remove_job(&mut self, id: &str) -> bool {
-        if let Some(ref db) = self.db {
-            let _ = db.lock().unwrap().remove_job(id);
+    /// Start a run of `workflow`; its first steps start on the next tick. A workflow runs
+    /// once at a time.
+    pub fn start_workflow(&mut self, workflow: Workflow) -> Result<String, String> {
+        if let Some((_, run)) = self.workflow_runs.values().find(|(w, _)| w.name == workflow.name) {
+            return Err(format!("Workflow {} is already running (run {})", workflow.name, run.run_id));
         }
-        self.jobs.remove(id).is_some()
+        let run = workflow.new_run(Uuid::new_v4().to_string(), Utc::now().to_rfc3339());
+        let run_id = run.run_id.clone();
+        log::info!("Starting workflow {} (run {})", workflow.name, run_id);
+        self.save_workflow_run(&run);
+        self.workflow_runs.insert(run_id.clone(), (workflow, run));
+        Ok(run_id)
     }
 
-    pub fn tick(&mut self) -> Vec<Job> {
-        let mut jobs_to_run = Vec::new();
-        let now = Utc::now();
-        
-        // Check for scheduled retries
-        let retry_jobs: Vec<String> = self.retry_state.iter()
-            .filter_map(|(job_id, state)| {
-                if let Some(next_attempt) = state.next_attempt_at {
-                    if next_attempt <= now {
-                        Some(job_id.clone())
-                    } else {
-                        None
+    /// Start workflow steps whose prerequisites succeeded and close runs with nothing left to do.
+    /// Steps run whether or not their jobs are enabled; a busy job delays its step to a later tick.
+    fn advance_workflows(&mut self, now: DateTime<Utc>) -> Vec<PendingRun> {
+        let mut to_run = Vec::new();
+        let run_ids: Vec<String> = self.workflow_runs.keys().cloned().collect();
+        for run_id in run_ids {
+            let (workflow, mut run) = match self.workflow_runs.remove(&run_id) {
+                Some(active) => active,
+                None => continue,
+            };
+            let mut changed = false;
+            for job_id in workflow.ready_steps(&run) {
+                let job = match self.jobs.get(&job_id) {
+                    Some(job) => job.clone(),
+                    None => {
+                        log::warn!("Workflow {}: job {} of a step no longer exists", workflow.name, job_id);
+                        if let Some(step) = run.step_mut(&job_id) {
+                            step.state = StepState::Failed;
+                            step.finished_at = Some(now.to_rfc3339());
+                        }
+                        changed = true;
+                        continue;
                     }
-                } else {
-                    None
+                };
+                let run_key = job.run_key(&RunParams::new());
+                if !self.can_start(&job, &run_key) {
+                    continue;
                 }
-            })
-            .collect();
-        
-        for job_id in retry_jobs {
-            if let Some(job) = self.jobs.get(&job_id) {
-                if !self.running_jobs.contains_key(&job_id) {
-                    log::info!("Retrying job: {} (attempt {})", job.name, 
-                        self.retry_state.get(&job_id).map(|s| s.attempt + 1).unwrap_or(1));
-                    
-                    let execution_id = Uuid::new_v4().to_string();
-                    let now = Utc::now();
-                    
-                    jobs_to_run.push(job.clone());
-                    self.running_jobs.insert(
-                        job_id.clone(),
-                        JobExecutionContext {
-                            execution_id,
-                            scheduled_time: now,
-                            start_time: now,
-                            pid: None,
-                        },
-                    );
+                let execution_id = self.begin_execution(&job, run_key, now, false);
+                log::info!("Workflow {}: starting step {} (execution_id: {})", workflow.name, job.name, execution_id);
+                if let Some(step) = run.step_mut(&job_id) {
+                    step.state = StepState::Running;
+                    step.execution_id = Some(execution_id.clone());
+                    step.started_at = Some(now.to_rfc3339());
                 }
+                to_run.push(PendingRun { job, params: RunParams::new(), execution_id });
+                changed = true;
             }
-        }
-        
-        for job in self.jobs.values() {
-            if !job.enabled {
-                continue;
+            for job_id in workflow.skip_blocked(&mut run) {
+                log::info!("Workflow {}: skipping step {}", workflow.name, job_id);
+                changed = true;
             }
-
-            // Concurrency check - use contains_key instead of hashset
-            if self.running_jobs.contains_key(&job.id.0) {
-                continue;
+            if run.status() != "running" {
+                run.finished_at = Some(now.to_rfc3339());
+                log::info!("Workflow {} run {} {}", workflow.name, run.run_id, run.status());
+                changed = true;
             }
+            if changed {
+                self.save_workflow_run(&run);
+            }
+            if run.finished_at.is_none() {
+                self.workflow_runs.insert(run_id, (workflow, run));
+            }
+        }
+        to_run
+    }
 
-            let last_run = self.last_runs.get(&job.id.0).cloned().unwrap_or(DateTime::<Utc>::MIN_UTC);
-            let mut next_run_time = now;
-
-            let should_run = match &job.schedule {
-                ScheduleConfig::Cron(expression) => {
-                    if let Ok(schedule) = Schedule::from_str(expression) {
-                        let start_time = if last_run == DateTime::<Utc>::MIN_UTC {
-                            now - Duration::seconds(1)
-                        } else {
-                            last_run
-                        };
-
-                        if let Some(next) = schedule.after(&start_time).next() {
-                            if next <= now {
-                                next_run_time = next;
-                                true
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                },
-                ScheduleConfig::Every(seconds) => {
-                    let interval = Duration::seconds(*seconds as i64);
-                    if last_run == DateTime::<Utc>::MIN_UTC {
-                        next_run_time = now;
-                        true 
-                    } else {
-                        let expected = last_run + interval;
-                        if expected <= now {
-                            next_run_time = expected;
-                            // Lag check: if we are behind by more than 10 intervals, reset to now
-                            if (now - expected) > (interval * 10) {
-                                log::warn!("Job {} is lagging significantly. Resetting schedule.", job.name);
-                                next_run_time = now;
-                            }
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                },
-                ScheduleConfig::Calendar(params) => {
-                    // Use configured timezone or Local time for calendar matching
-                    let now_local = if let Some(ref tz_str) = job.timezone {
-                        use chrono_tz::Tz;
-                        if let Ok(tz) = tz_str.parse::<Tz>() {
-                            now.with_timezone(&tz).naive_local()
-                        } else {
-                            log::warn!("Invalid timezone '{}' for job {}, using local time", tz_str, job.name);
-                            chrono::Local::now().naive_local()
-                        }
-                    } else {
-                        chrono::Local::now().naive_local()
-                    };
-                    
-                    // CRITICAL BUG FIX: Use minute-level precision and execution window tracking
-                    // Create a window identifier based on the current minute (not second)
-                    let current_window = now_local.with_second(0).unwrap().with_nanosecond(0).unwrap();
-                    let last_window = self.last_execution_windows
-                        .get(&job.id.0)
-                        .and_then(|dt| {
-                            if let Some(ref tz_str) = job.timezone {
-                                use chrono_tz::Tz;
-                                if let Ok(tz) = tz_str.parse::<Tz>() {
-                                    Some(dt.with_timezone(&tz).naive_local().with_second(0).unwrap().with_nanosecond(0).unwrap())
-                                } else {
-                                    None
-                                }
-                            } else {
-                                Some(dt.with_timezone(&chrono::Local).naive_local().with_second(0).unwrap().with_nanosecond(0).unwrap())
-                            }
-                        });
-                    
-                    // Prevent running twice in the same minute window
-                    if let Some(last_win) = last_window {
-                        if last_win == current_window {
-                            false
-                        } else {
-                            use chrono::{Datelike, Timelike};
-                            let (h, m, s) = params.time;
-                            
-                            if now_local.hour() == h && now_local.minute() == m && now_local.second() == s {
-                                let mut day_match = true;
-                                
-                                if let Some(days) = &params.days_of_week {
-                                    let current_iso_day = now_local.weekday().number_from_monday();
-                                    if !days.contains(&current_iso_day) {
-                                        day_match = false;
-                                    }
-                                }
-                                
-                                if let Some((n, weekday)) = params.nth_weekday {
-                                    let current_iso_day = now_local.weekday().number_from_monday();
-                                    if current_iso_day != weekday {
-                                        day_match = false;
-                                    } else {
-                                        let day = now_local.day();
-                                        let week_num = (day - 1) / 7 + 1;
-                                        if week_num != n {
-                                            day_match = false;
-                                        }
-                                    }
-                                }
-                                
-                                if day_match {
-                                    next_run_time = now;
-                                    true
-                                } else {
-                                    false
-                                }
-                            } else {
-                                false
-                            }
-                        }
-                    } else {
-                        // First run or no execution window recorded
-                        use chrono::{Datelike, Timelike};
-                        let (h, m, s) = params.time;
-                        
-                        if now_local.hour() == h && now_local.minute() == m && now_local.second() == s {
-                            let mut day_match = true;
-                            
-                            if let Some(days) = &params.days_of_week {
-                                let current_iso_day = now_local.weekday().number_from_monday();
-                                if !days.contains(&current_iso_day) {
-                                    day_match = false;
-                                }
-                            }
-                            
-                            if let Some((n, weekday)) = params.nth_weekday {
-                                let current_iso_day = now_local.weekday().number_from_monday();
-                                if current_iso_day != weekday {
-                                    day_match = false;
-                                } else {
-                                    let day = now_local.day();
-                                    let week_num = (day - 1) / 7 + 1;
-                                    if week_num != n {
-                                        day_match = false;
-                                    }
-                                }
-                            }
-                            
-                            if day_match {
-                                next_run_time = now;
-                                true
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    }
-                },
+    // Settle the workflow step this execution ran. A failure with a retry due keeps the step
+    // running until the retry (see track_workflow_retry) settles it.
+    fn finish_workflow_step(&mut self, ctx: &JobExecutionContext, success: bool) {
+        let retrying = self.retry_state.contains_key(&ctx.run_key);
+        let mut changed = Vec::new();
+        for (workflow, run) in self.workflow_runs.values_mut() {
+            let step = run.steps.iter_mut().find(|step| {
+                step.state == StepState::Running && step.execution_id.as_deref() == Some(ctx.execution_id.as_str())
+            });
+            let step = match step {
+                Some(step) => step,
+                None => continue,
             };
+            if retrying {
+                step.execution_id = None;
+            } else {
+                step.state = if success { StepState::Succeeded } else { StepState::Failed };
+                step.finished_at = Some(Utc::now().to_rfc3339());
+                log::info!("Workflow {}: step {} {}", workflow.name, step.job, step.state);
+                workflow.skip_blocked(run);
+            }
+            changed.push(run.clone());
+        }
+        for run in changed {
+            self.save_workflow_run(&run);
+        }
+    }
 
-            if should_run {
-                // Apply jitter if configured
-                if job.jitter_seconds > 0 {
-                    use rand::Rng;
-                    let jitter_ms = rand::thread_rng().gen_range(0..job.jitter_seconds * 1000);
-                    next_run_time = next_run_time + Duration::milliseconds(jitter_ms as i64);
-                    log::debug!("Applied jitter of {}ms to job {}", jitter_ms, job.name);
-                }
-                
-                // Create execution context
-                let execution_id = Uuid::new_v4().to_string();
-                log::info!("Scheduling job: {} (execution_id: {})", job.name, execution_id);
-                
-                jobs_to_run.push(job.clone());
-                self.last_runs.insert(job.id.0.clone(), next_run_time);
-                self.last_execution_windows.insert(job.id.0.clone(), next_run_time);
-                
-                // Insert execution context
-                self.running_jobs.insert(
-                    job.id.0.clone(),
-                    JobExecutionContext {
-                        execution_id,
-                        scheduled_time: next_run_time,
-                        start_time: now,
-                        pid: None,
-                    },
-                );
+    // Hand a retry execution to the workflow step waiting on it
+    fn track_workflow_retry(&mut self, job_id: &str, execution_id: &str) {
+        for (_, run) in self.workflow_runs.values_mut() {
+            if let Some(step) = run.steps.iter_mut().find(|s| s.state == StepState::Running && s.execution_id.is_none() && s.job == job_id) {
+                step.execution_id = Some(execution_id.to_string());
             }
         }
-        jobs_to_run
     }
 
-    pub fn finish_job(&mut self, id: &str) {
-        self.running_jobs.remove(id);
+    fn save_workflow_run(&self, run: &WorkflowRun) {
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.lock().unwrap().save_workflow_run(run) {
+                log::warn!("Failed to save run {} of workflow {}: {}", run.run_id, run.workflow, e);
+            }
+        }
     }
 
-    pub fn execute_job(scheduler: Arc<Mutex<Scheduler>>, job: &Job) {
-        let (current_attempt, db, retry_policy, hooks) = {
-            let sched = scheduler.lock().unwrap();
-            let current_attempt = sched.retry_state.get(&job.id.0).map(|s| s.attempt).unwrap_or(0);
+    pub fn execute_job(scheduler: Arc<RwLock<Scheduler>>, job: &Job, params: RunParams, execution_id: String) {
+        let job = scheduler.read().unwrap().effective_job(job);
+        let job = &job;
+        let run_key = job.run_key(&params);
+        let (current_attempt, db, history, retry_policy, hooks, mut redact_patterns, metrics, scheduled_at, live_outputs, events) = {
+            let sched = scheduler.read().unwrap();
+            let current_attempt = sched.retry_state.get(&run_key).map(|s| s.attempt).unwrap_or(0);
             let db = sched.db.clone();
-            (current_attempt, db, job.retry_policy.clone(), job.hooks.clone())
+            let scheduled_at = sched.running_jobs.get(&execution_id)
+                .map(|ctx| ctx.scheduled_time)
+                .unwrap_or_else(Utc::now);
+            (current_attempt, db, sched.history.clone(), job.retry_policy.clone(), job.hooks.clone(), sched.redact_patterns.clone(),
+             sched.metrics.clone(), scheduled_at, sched.live_outputs.clone(), sched.events.clone())
         };
+
+        // Values of secret env vars (job env and inherited daemon env) never reach logs or history
+        redact_patterns.extend(job.redact_env.iter().cloned());
+        let process_env: HashMap<String, String> = std::env::vars().collect();
+        let mut secrets = redact::secret_values(job.env.iter().chain(process_env.iter()), &redact_patterns);
+
+        // secret:// env values are decrypted only now, and redacted whatever their names
+        let (secret_store, agents, container_engine) = {
+            let sched = scheduler.read().unwrap();
+            (sched.secrets.clone(), sched.agents.clone(), sched.container_engine.clone())
+        };
+        let resolved_env = crate::secrets::resolve_env(&job.env, &job.owner, db.as_deref(), secret_store.as_deref());
+        if let Ok((_, ref values)) = resolved_env {
+            secrets.extend(values.iter().cloned());
+            secrets.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        }
         
         log::info!("Executing job: {} (owner: {}, attempt: {})", job.name, job.owner, current_attempt + 1);
+        if run_key != job.id.0 {
+            log::info!("Run key for job {}: {}", job.name, run_key);
+        }
         
         
-        // Construct full command string with args
-        let full_command = if job.args.is_empty() {
-            job.command.clone()
-        } else {
-            format!("{} {}", job.command, job.args.join(" "))
+        if job.capture_env {
+            if let Some(ref db) = db {
+                let snapshot = ExecutionSnapshot {
+                    execution_id: execution_id.clone(),
+                    job_id: job.id.0.clone(),
+                    command: job.command.clone(),
+                    args: job.args.clone(),
+                    env: job.env.clone(),
+                    params: params.clone(),
+                };
+                if let Err(e) = db.lock().unwrap().save_snapshot(&snapshot) {
+                    log::warn!("Failed to snapshot execution {} of job {}: {}", execution_id, job.name, e);
+                }
+            }
+        }
+
+        // Run as run_as, else the owner (defaults to "lunasched" if not specified)
+        let user = job.run_as_user();
+        let account = Account::lookup(user);
+
+        // A script job runs its script, written out for this execution, in place of the command;
+        // an agent writes out its own
+        let script = match (&job.script, &job.target, &account) {
+            (Some(body), None, Ok(account)) => {
+                let dir = scheduler.read().unwrap().paths.script_dir.clone();
+                let (uid, gid) = account.ids();
+                Some(ScriptFile::write(&dir, &execution_id, body, uid, gid))
+            }
+            _ => None,
+        }.transpose();
+        let program = match (&script, &job.executor) {
+            (Ok(Some(_)), Executor::Container { .. }) => script::CONTAINER_PATH.to_string(),
+            (Ok(Some(file)), _) => file.path().display().to_string(),
+            // Sent with the script, the agent gets the arguments alone
+            _ if job.script.is_some() && job.target.is_some() => String::new(),
+            _ => job.command.clone(),
         };
-        
-        // Prepare command with proper user switching using sudo
-        let mut cmd = tokio::process::Command::new("/usr/bin/sudo");
-        
-        // Run as specified user (defaults to "lunasched" if not specified)
-        let user = if job.owner.is_empty() { "lunasched" } else { &job.owner };
-        cmd.arg("-u");
-        cmd.arg(user);
+
+        // Construct full command string with args
+        let full_command = std::iter::once(program).filter(|program| !program.is_empty())
+            .chain(job.args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
         
         // Use shell to execute the command
-        cmd.arg("/bin/sh");
+        let mut cmd = tokio::process::Command::new("/bin/sh");
         cmd.arg("-c");
         cmd.arg(&full_command);
+
+        // Start from a clean login environment for the user rather than the daemon's own
+        if let Ok(ref account) = account {
+            cmd.env_clear();
+            cmd.envs(account.login_env());
+        }
+        if let Ok((ref env, _)) = resolved_env {
+            cmd.envs(env);
+        }
+        cmd.envs(&params);
+
+        // Hooks see what the job saw, plus which execution they follow
+        let mut hook_env = resolved_env.as_ref().map(|(env, _)| env.clone()).unwrap_or_default();
+        hook_env.extend(params.clone());
+        hook_env.insert("LUNASCHED_JOB_ID".to_string(), job.id.0.clone());
+        hook_env.insert("LUNASCHED_EXECUTION_ID".to_string(), execution_id.clone());
         
-        // Set environment variables (sudo will pass them through)
-        cmd.envs(&job.env);
-        
-        // Set working directory to /tmp (always accessible)
-        cmd.current_dir("/tmp");
+        // The job's working directory, else /tmp (always accessible)
+        let working_dir = job.working_dir.clone().unwrap_or_else(|| std::path::PathBuf::from("/tmp"));
+        cmd.current_dir(&working_dir);
+        if let Some(umask) = job.umask {
+            let mode = nix::sys::stat::Mode::from_bits_truncate(umask);
+            unsafe {
+                cmd.pre_exec(move || {
+                    nix::sys::stat::umask(mode);
+                    Ok(())
+                });
+            }
+        }
         
-        log::info!("Executing as user '{}': /bin/sh -c '{}'", user, full_command);
+        log::info!(job_id = job.id.0.as_str(), execution_id = execution_id.as_str(); "Executing as user '{}': /bin/sh -c '{}'", user, redact::redact(&full_command, &secrets));
+
+        // A session (and process group) of its own, so signals reach everything the job starts
+        unsafe {
+            cmd.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(std::io::Error::from));
+        }
 
         // Configure I/O
         cmd.stdin(std::process::Stdio::null());
@@ -416,69 +1891,290 @@ impl Scheduler {
         
         // Apply resource limits if configured
         let resource_limits = job.resource_limits.clone();
+        // Container engines and systemd apply the limits of the jobs they run themselves
+        let self_limited = job.target.is_none() && job.executor != Executor::Local;
+        // Limits apply on the daemon's host only
+        let cgroup = if job.target.is_some() || self_limited {
+            None
+        } else {
+            match ExecutionCgroup::create(&execution_id, &resource_limits) {
+                Ok(cgroup) => cgroup,
+                Err(e) => {
+                    log::warn!("Memory/CPU limits of job {} are not enforced: {}", job.name, e);
+                    None
+                }
+            }
+        };
+        if let Some(ref cgroup) = cgroup {
+            match cgroup.procs_file() {
+                Ok(procs) => {
+                    use std::io::Write;
+                    // Join the cgroup between fork and exec so nothing the job starts escapes it.
+                    // Writing to an already open file doesn't allocate, which keeps this fork-safe.
+                    unsafe {
+                        cmd.pre_exec(move || (&procs).write_all(b"0"));
+                    }
+                }
+                Err(e) => log::warn!("Failed to open cgroup for job {}: {}", job.name, e),
+            }
+        }
+        // Between the cgroup and the user switch, while the daemon's privileges are at hand
+        sandbox::apply(&job.sandbox, &mut cmd);
 
         let job_name = job.name.clone();
         let job_id = job.id.0.clone();
+        let output_target = crate::logging::job_output_target(&job.tags);
+
+
+        metrics.record_execution(&job_id);
+
+        let mut record = ExecutionRecord {
+            job_id: job_id.clone(),
+            execution_id: execution_id.clone(),
+            status: String::new(),
+            message: None,
+            exit_code: None,
+            signal: None,
+            attempt: Some(current_attempt + 1),
+            duration_ms: None,
+            scheduled_at,
+            started_at: Utc::now(),
+            stdout: None,
+            stderr: None,
+            stdout_bytes: None,
+            stderr_bytes: None,
+            log_path: None,
+            resource_events: None,
+            hook_result: None,
+            hook_output: None,
+        };
+        let timeline = Timeline::new();
+        timeline.record_at("scheduled", scheduled_at, None);
+        timeline.record("started", Some(format!("attempt {}", current_attempt + 1)));
 
+        let spawned = match (&script, &job.target, &job.executor) {
+            (Err(e), _, _) => Err(std::io::Error::new(e.kind(), format!("failed to write out the script: {}", e))),
+            // The agent runs the command as the job's user on its host, and enforces the timeout
+            (_, Some(target), _) => resolved_env.and_then(|(mut env, _)| {
+                env.extend(params.clone());
+                agents.dispatch(target, RemoteRun {
+                    execution_id: execution_id.clone(),
+                    job_id: job.id.0.clone(),
+                    job_name: job.name.clone(),
+                    command: full_command.clone(),
+                    env,
+                    run_as: user.to_string(),
+                    working_dir: job.working_dir.clone(),
+                    umask: job.umask,
+                    timeout_seconds: resource_limits.timeout_seconds,
+                    script: job.script.clone(),
+                })
+            }).map(Process::Remote),
+            // The engine runs as the daemon; the container runs as the job's user
+            (_, None, Executor::Container { .. }) => account.and_then(|account| {
+                let (mut env, _) = resolved_env?;
+                env.extend(params.clone());
+                let engine = container::engine(container_engine.as_deref())?;
+                let script = script.as_ref().ok().and_then(Option::as_ref).map(ScriptFile::path);
+                container::command(&engine, job, &account, &execution_id, &full_command, &env, script)?.spawn()
+            }).map(Process::Local),
+            // systemd-run runs as the daemon; the unit runs as the job's user
+            (_, None, Executor::Systemd { .. }) => account.and_then(|account| {
+                let (mut env, _) = resolved_env?;
+                env.extend(params.clone());
+                systemd_run::command(job, &account, &execution_id, &full_command, &env)?.spawn()
+            }).map(Process::Local),
+            // The user switch is registered last, after joining the cgroup
+            (_, None, Executor::Local) => account.and_then(|account| {
+                resolved_env?;
+                // Spawning would fail anyway; say why in history
+                if !working_dir.is_dir() {
+                    return Err(std::io::Error::new(std::io::ErrorKind::NotFound,
+                        format!("working directory {} does not exist", working_dir.display())));
+                }
+                account.switch_to(&mut cmd);
+                cmd.spawn()
+            }).map(Process::Local),
+        };
+        match spawned {
+            Ok(mut process) => {
+                // A local child called setsid, so it leads a process group of the same ID
+                let pgid = process.local_pid();
+                timeline.record("spawned", Some(process.describe()));
+                events.publish(job, JobEventKind::Started, Some(&execution_id),
+                    Some(format!("attempt {}, {}", current_attempt + 1, process.describe())));
+                notifier::dispatch(job, NotificationEvent::Start, format!("started (attempt {})", current_attempt + 1),
+                    Some(&execution_id), db.as_ref());
+                if let Some(pid) = pgid {
+                    if let Some(mut ctx) = scheduler.read().unwrap().running_jobs.get_mut(&execution_id) {
+                        ctx.pid = Some(pid);
+                        ctx.pgid = Some(pid);
+                    }
+                    if let Some(ref db) = db {
+                        let _ = db.lock().unwrap().set_execution_window_pid(&execution_id, pid);
+                    }
+                }
+
+                // Stream output into a live buffer so `lunasched logs` can tail it
+                let live = Arc::new(LiveOutput::new(&job_id, &execution_id));
+                live_outputs.insert(execution_id.clone(), live.clone());
+                let shared_secrets = Arc::new(secrets.clone());
+                let (max_output_bytes, execution_logs_kept, compress_execution_logs, log_dir) = {
+                    let sched = scheduler.read().unwrap();
+                    (sched.max_output_bytes, sched.execution_logs_kept, sched.compress_execution_logs,
+                        std::path::PathBuf::from(&sched.paths.execution_log_dir))
+                };
+                let output_limit = job.max_output_bytes.or(max_output_bytes).unwrap_or(output::DEFAULT_MAX_OUTPUT_BYTES);
 
-        match cmd.spawn() {
-            Ok(child) => {
-                let pid = child.id().unwrap();
+                // Full output goes to a log file of its own; history keeps a tail of it
+                let execution_log = match output::ExecutionLog::create(&log_dir, &job_id, &execution_id, execution_logs_kept) {
+                    Ok(log_file) => {
+                        record.log_path = Some(log_file.path.display().to_string());
+                        Some(Arc::new(log_file))
+                    }
+                    Err(e) => {
+                        log::warn!("No execution log for job {}: {}", job.name, e);
+                        None
+                    }
+                };
+                let (stdout, stderr) = process.take_output();
+                let stdout_reader = output::spawn_reader(stdout, live.clone(), execution_log.clone(),
+                    shared_secrets.clone(), output_limit);
+                let stderr_reader = output::spawn_reader(stderr, live.clone(), execution_log,
+                    shared_secrets, output_limit);
                 
-                // Spawn timeout enforcer if configured
-                if let Some(timeout_secs) = resource_limits.timeout_seconds {
-                    let pid_clone = pid;
-                    tokio::spawn(async move {
-                        if let Err(e) = enforce_timeout(pid_clone, timeout_secs).await {
-                            log::warn!("Timeout enforced: {}", e);
+                // Without a cgroup, the memory limit is up to the watchdog
+                let watchdog = match (resource_limits.max_memory_mb, &cgroup, pgid) {
+                    (Some(limit_mb), None, Some(pgid)) if !self_limited => Some(MemoryWatchdog::spawn(pgid, limit_mb, timeline.clone())),
+                    _ => None,
+                };
+
+                // Spawn timeout enforcer if configured; dropping `exited` calls it off
+                let (exited, exit_signal) = tokio::sync::oneshot::channel();
+                if let (Some(timeout_secs), Some(pgid)) = (resource_limits.timeout_seconds, pgid) {
+                    let timeline = timeline.clone();
+                    match job.executor {
+                        Executor::Local => {
+                            tokio::spawn(async move {
+                                if let Err(e) = enforce_timeout(pgid, timeout_secs, timeline, exit_signal).await {
+                                    log::warn!("Timeout enforced: {}", e);
+                                }
+                            });
                         }
-                    });
+                        Executor::Container { .. } => {
+                            let (engine, execution_id) = (container_engine.clone(), execution_id.clone());
+                            tokio::spawn(container::enforce_timeout(engine, execution_id, pgid, timeout_secs, timeline, exit_signal));
+                        }
+                        // RuntimeMaxSec of the unit
+                        Executor::Systemd { .. } => {}
+                    }
                 }
                 
+                let job = job.clone();
                 tokio::spawn(async move {
                     let start_time = std::time::Instant::now();
-                    match child.wait_with_output().await {
-                        Ok(output) => {
-                            let duration_ms = start_time.elapsed().as_millis() as i64;
-                            let success = output.status.success();
-                            let exit_code = output.status.code().unwrap_or(-1);
-                            
-                            let stdout = String::from_utf8_lossy(&output.stdout);
-                            let stderr = String::from_utf8_lossy(&output.stderr);
+                    let waited = process.wait().await;
+                    drop(exited);
+                    drop(script);
+                    let memory = watchdog.map(MemoryWatchdog::finish);
+                    // However the run ended (it exited, was killed at its timeout or couldn't be
+                    // waited for), its cgroup tells which limits it ran into
+                    if let Some(ref cgroup) = cgroup {
+                        let events = cgroup.events();
+                        if events.oom_kills > 0 {
+                            log::warn!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} hit its memory limit ({} OOM kill(s))", job_name, events.oom_kills);
+                        }
+                        record.resource_events = events.summary();
+                    }
+                    let final_status = match waited {
+                        Ok(exit_status) => {
+                            timeline.record("exited", Some(match (exit_status.code(), exit_status.signal()) {
+                                (Some(code), _) => format!("exit code {}", code),
+                                (None, Some(signal)) => format!("killed by signal {}", signal),
+                                (None, None) => "killed by signal".to_string(),
+                            }));
+                            let stdout = stdout_reader.await.unwrap_or_default();
+                            let stderr = stderr_reader.await.unwrap_or_default();
+                            // Both readers are done with the execution log, so it is complete
+                            if let (true, Some(path)) = (compress_execution_logs, record.log_path.clone()) {
+                                let source = std::path::PathBuf::from(&path);
+                                match tokio::task::spawn_blocking(move || crate::log_rotation::compress_file(&source)).await {
+                                    Ok(Ok(compressed)) => record.log_path = Some(compressed.display().to_string()),
+                                    Ok(Err(e)) => log::warn!("Failed to compress execution log {}: {}", path, e),
+                                    Err(e) => log::warn!("Failed to compress execution log {}: {}", path, e),
+                                }
+                            }
+                            record.stdout_bytes = Some(stdout.total_bytes as i64);
+                            record.stderr_bytes = Some(stderr.total_bytes as i64);
+                            let (stdout, stderr) = (stdout.text, stderr.text);
+                            if let Some(ref memory) = memory {
+                                if memory.killed {
+                                    log::warn!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} was killed for exceeding its memory limit ({})", job_name, memory.summary());
+                                }
+                                record.resource_events = Some(memory.summary());
+                            }
+                            let elapsed = start_time.elapsed();
+                            let duration_ms = elapsed.as_millis() as i64;
+                            let failure = memory.as_ref().and_then(|memory| memory.failure())
+                                .or_else(|| job.success_criteria.failure(exit_status.code(), &stdout, &stderr, elapsed));
+                            let success = failure.is_none();
+                            let exit_code = exit_status.code().unwrap_or(-1);
+                            if exit_status.success() && !success {
+                                log::warn!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} exited 0 but failed its success criteria", job_name);
+                            }
+
+                            let stdout = redact::redact(&stdout, &secrets);
+                            let stderr = redact::redact(&stderr, &secrets);
                             let log_output = format!("Stdout:\n{}\nStderr:\n{}", stdout, stderr);
                             
                             let status_str = if success { "success" } else { "failed" };
-                            log::info!("Job {} finished with status: {} (exit code: {}, duration: {}ms)", 
+                            record.status = status_str.to_string();
+                            record.message = failure.clone();
+                            record.exit_code = exit_status.code();
+                            record.signal = exit_status.signal();
+                            record.duration_ms = Some(duration_ms);
+                            record.stdout = Some(stdout);
+                            record.stderr = Some(stderr);
+                            log::info!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} finished with status: {} (exit code: {}, duration: {}ms)", 
                                 job_name, status_str, exit_code, duration_ms);
-                            log::info!(target: "job_output", "Job: {}\n{}", job_name, log_output);
+                            log::info!(target: &output_target, job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job: {}\n{}", job_name, log_output);
 
-                            // Log to database if configured
-                            if let Some(ref db) = db {
-                                // Metrics removed - keeping only history logging
+                            if success {
+                                metrics.record_success(&job_id, duration_ms as u64);
+                            } else {
+                                metrics.record_failure(&job_id, duration_ms as u64);
                             }
 
                             if success {
                                 // Job succeeded - clear retry state and run success hook
-                                {
-                                    let mut sched = scheduler.lock().unwrap();
-                                    sched.retry_state.remove(&job_id);
-                                }
-                                
-                                if let Some(ref db) = db {
-                                    let _ = db.lock().unwrap().log_history(&job_id, status_str, &log_output);
+                                let ended_streak = {
+                                    let mut sched = scheduler.write().unwrap();
+                                    sched.retry_state.remove(&run_key);
+                                    let cleared = sched.clear_dead_letter(&job_id);
+                                    if cleared.is_some() {
+                                        log::info!("Job {} succeeded; cleared its failed state", job_name);
+                                    }
+                                    cleared.map_or(0, |d| d.exhaustions)
+                                };
+
+                                if let Some(ref on_success) = hooks.on_success {
+                                    let outcome = hooks::run(&job, "on_success", on_success, &hook_env, &secrets).await;
+                                    timeline.record("hook", Some(outcome.result.clone()));
+                                    record.hook_result = Some(outcome.result);
+                                    record.hook_output = Some(outcome.output);
                                 }
                                 
-                                // Run success hook if configured
-                                if let Some(on_success) = hooks.on_success {
-                                    log::info!("Running success hook for job {}", job_name);
-                                    let _ = std::process::Command::new("sh")
-                                        .arg("-c")
-                                        .arg(&on_success)
-                                        .spawn();
+                                history.persist(PendingWrite::Execution(record.clone())).await;
+                                {
+                                    let mut sched = scheduler.write().unwrap();
+                                    sched.notify_run(&job_id, &execution_id, None, ended_streak);
+                                    sched.trigger_jobs(&job, &params, true);
                                 }
+                                events.publish(&job, JobEventKind::Finished, Some(&execution_id),
+                                    Some(format!("exit code 0, {}ms", duration_ms)));
                             } else {
                                 // Job failed - check retry policy
-                                let should_retry = current_attempt < retry_policy.max_attempts;
+                                let should_retry = current_attempt < retry_policy.retries();
                                 
                                 if should_retry {
                                     let next_attempt = current_attempt + 1;
@@ -488,75 +2184,417 @@ impl Scheduler {
                                         retry_policy.initial_delay_seconds,
                                         retry_policy.max_delay_seconds,
                                     );
+                                    let delay_secs = apply_backoff_jitter(delay_secs, retry_policy.jitter_percent,
+                                        retry_policy.max_delay_seconds);
                                     
                                     let next_attempt_at = Utc::now() + Duration::seconds(delay_secs as i64);
-                                    log::warn!("Job {} failed (attempt {}/{}). Retrying in {}s", 
-                                        job_name, next_attempt, retry_policy.max_attempts, delay_secs);
+                                    timeline.record("retry_scheduled", Some(format!("attempt {} at {}",
+                                        next_attempt + 1, next_attempt_at.format("%Y-%m-%d %H:%M:%S"))));
+                                    log::warn!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} failed (attempt {}/{}). Retrying in {}s", 
+                                        job_name, next_attempt, retry_policy.retries(), delay_secs);
                                     
                                     {
-                                        let mut sched = scheduler.lock().unwrap();
-                                        sched.retry_state.insert(job_id.clone(), RetryState {
+                                        let mut sched = scheduler.write().unwrap();
+                                        sched.retry_state.insert(run_key.clone(), RetryState {
+                                            job_id: job_id.clone(),
+                                            params,
                                             attempt: next_attempt,
                                             next_attempt_at: Some(next_attempt_at),
                                         });
                                     }
                                     
-                                    if let Some(ref db) = db {
-                                        let next_retry_str = next_attempt_at.format("%Y-%m-%d %H:%M:%S").to_string();
-                                        let _ = db.lock().unwrap().log_retry_attempt(
-                                            &job_id,
-                                            next_attempt,
-                                            Some(&next_retry_str),
-                                            &format!("Exit code: {}", exit_code)
-                                        );
+                                    let next_retry_str = next_attempt_at.format("%Y-%m-%d %H:%M:%S").to_string();
+                                    history.write(PendingWrite::RetryAttempt {
+                                        job_id: job_id.clone(),
+                                        attempt: next_attempt,
+                                        next_retry: Some(next_retry_str),
+                                        error: failure.clone().unwrap_or_default(),
+                                    });
+                                    events.publish(&job, JobEventKind::Retried, Some(&execution_id),
+                                        Some(format!("exit code {}, attempt {} in {}s", exit_code, next_attempt + 1, delay_secs)));
+                                    notifier::dispatch(&job, NotificationEvent::Retry, format!("attempt {} of {} failed ({}); retrying in {}s",
+                                        next_attempt, retry_policy.retries() + 1, failure.clone().unwrap_or_default(), delay_secs),
+                                        Some(&execution_id), db.as_ref());
+
+                                    // Failed attempts have no history entry of their own; the timeline keeps the outcome
+                                    if let Some(ref on_retry) = hooks.on_retry {
+                                        let outcome = hooks::run(&job, "on_retry", on_retry, &hook_env, &secrets).await;
+                                        timeline.record("hook", Some(outcome.result));
                                     }
                                 } else {
                                     // All retries exhausted
-                                    log::error!("Job {} failed after {} attempts", job_name, current_attempt + 1);
+                                    log::error!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} failed after {} attempts", job_name, current_attempt + 1);
                                     {
-                                        let mut sched = scheduler.lock().unwrap();
-                                        sched.retry_state.remove(&job_id);
+                                        let mut sched = scheduler.write().unwrap();
+                                        sched.retry_state.remove(&run_key);
+                                        sched.dead_letter(&job_id, current_attempt + 1, failure.clone().unwrap_or_default(),
+                                            retry_policy.disable_after_exhaustions);
                                     }
-                                    
-                                    if let Some(ref db) = db {
-                                        let _ = db.lock().unwrap().log_history(&job_id, "failed", &log_output);
+
+                                    if let Some(ref on_failure) = hooks.on_failure {
+                                        let outcome = hooks::run(&job, "on_failure", on_failure, &hook_env, &secrets).await;
+                                        timeline.record("hook", Some(outcome.result.clone()));
+                                        record.hook_result = Some(outcome.result);
+                                        record.hook_output = Some(outcome.output);
                                     }
                                     
-                                    // Run failure hook if configured
-                                    if let Some(on_failure) = hooks.on_failure {
-                                        log::info!("Running failure hook for job {}", job_name);
-                                        let _ = std::process::Command::new("sh")
-                                            .arg("-c")
-                                            .arg(&on_failure)
-                                            .spawn();
+                                    history.persist(PendingWrite::Execution(record.clone())).await;
+                                    {
+                                        let mut sched = scheduler.write().unwrap();
+                                        sched.notify_run(&job_id, &execution_id, Some(&failure.clone().unwrap_or_default()), 0);
+                                        sched.trigger_jobs(&job, &params, false);
                                     }
+                                    events.publish(&job, JobEventKind::Failed, Some(&execution_id),
+                                        Some(format!("exit code {}, {}ms", exit_code, duration_ms)));
                                 }
                             }
+                            status_str
                         }
                         Err(e) => {
                             let err_msg = format!("Failed to wait: {}", e);
-                            log::error!("Job {} {}", job_name, err_msg);
+                            log::error!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Job {} {}", job_name, err_msg);
+                            let duration_ms = start_time.elapsed().as_millis() as i64;
+                            metrics.record_failure(&job_id, duration_ms as u64);
                             
-                            if let Some(ref db) = db {
-                                let _ = db.lock().unwrap().log_history(&job_id, "Error", &err_msg);
+                            record.status = "Error".to_string();
+                            events.publish(&job, JobEventKind::Failed, Some(&execution_id), Some(err_msg.clone()));
+                            scheduler.write().unwrap().dead_letter(&job_id, current_attempt + 1, err_msg.clone(),
+                                retry_policy.disable_after_exhaustions);
+                            record.message = Some(err_msg.clone());
+                            record.duration_ms = Some(duration_ms);
+                            history.persist(PendingWrite::Execution(record.clone())).await;
+                            {
+                                let mut sched = scheduler.write().unwrap();
+                                sched.notify_run(&job_id, &execution_id, Some(&err_msg), 0);
+                                sched.trigger_jobs(&job, &params, false);
                             }
+                            "Error"
                         },
-                    }
+                    };
                     
+                    if let Some(cgroup) = cgroup {
+                        cgroup.remove();
+                    }
+
+                    if let Some(at) = live.first_output_at() {
+                        timeline.record_at("first_output", at, None);
+                    }
+                    timeline.record("finished", Some(final_status.to_string()));
+                    history.write(PendingWrite::Timeline {
+                        job_id: job_id.clone(),
+                        execution_id: execution_id.clone(),
+                        events: timeline.events(),
+                    });
+
                     // Mark job as finished
-                    scheduler.lock().unwrap().finish_job(&job_id);
+                    live.finish(final_status);
+                    live_outputs.remove(&execution_id);
+                    scheduler.write().unwrap().finish_job(&execution_id, final_status);
                 });
             }
             Err(e) => {
                 let err_msg = format!("Failed to spawn: {}", e);
-                log::error!("Failed to spawn job {}: {}", job.name, e);
+                log::error!(job_id = job_id.as_str(), execution_id = execution_id.as_str(); "Failed to spawn job {}: {}", job.name, e);
+                metrics.record_failure(&job_id, 0);
                 
-                if let Some(ref db) = db {
-                    let _ = db.lock().unwrap().log_history(&job_id, "SpawnError", &err_msg);
+                record.status = "SpawnError".to_string();
+                record.message = Some(err_msg.clone());
+                history.write(PendingWrite::Execution(record));
+                events.publish(job, JobEventKind::Failed, Some(&execution_id), Some(err_msg.clone()));
+                {
+                    let mut sched = scheduler.write().unwrap();
+                    sched.dead_letter(&job_id, current_attempt + 1, err_msg.clone(), retry_policy.disable_after_exhaustions);
+                    sched.notify_run(&job_id, &execution_id, Some(&err_msg), 0);
+                    sched.trigger_jobs(job, &params, false);
+                }
+                timeline.record("spawn_failed", Some(err_msg));
+                history.write(PendingWrite::Timeline {
+                    job_id: job_id.clone(),
+                    execution_id: execution_id.clone(),
+                    events: timeline.events(),
+                });
+                if let Some(cgroup) = cgroup {
+                    cgroup.remove();
                 }
                 
-                scheduler.lock().unwrap().finish_job(&job_id);
+                scheduler.write().unwrap().finish_job(&execution_id, "SpawnError");
             },
         }
     }
+
+    /// Deal with the executions the previous daemon started but never saw finish. A process
+    /// still running is adopted (it counts as running and its run is recorded once it exits)
+    /// or killed, per `policy`; runs whose process is gone are recorded as interrupted.
+    pub fn recover_executions(scheduler: Arc<RwLock<Scheduler>>, policy: OrphanPolicy) {
+        let sched = scheduler.read().unwrap();
+        let unfinished = match sched.db {
+            Some(ref db) => db.lock().unwrap().unfinished_executions(),
+            None => return,
+        };
+        let unfinished = match unfinished {
+            Ok(unfinished) => unfinished,
+            Err(e) => {
+                log::error!("Failed to load unfinished executions: {}", e);
+                return;
+            }
+        };
+
+        for execution in unfinished {
+            let (job_id, execution_id) = (execution.job_id.as_str(), execution.execution_id.as_str());
+            match (execution.pid.filter(|&pid| is_orphan(pid, execution.started_at)), policy) {
+                (Some(pid), OrphanPolicy::Adopt) => {
+                    log::warn!(job_id = job_id, execution_id = execution_id;
+                        "Adopting process {} of job {}, left running by the previous daemon", pid, job_id);
+                    let run_key = sched.jobs.get(job_id)
+                        .map(|job| job.run_key(&RunParams::new()))
+                        .unwrap_or_else(|| job_id.to_string());
+                    sched.running_jobs.insert(execution_id.to_string(), JobExecutionContext {
+                        execution_id: execution_id.to_string(),
+                        job_id: job_id.to_string(),
+                        run_key,
+                        scheduled_time: execution.scheduled_time,
+                        start_time: execution.started_at,
+                        pid: Some(pid),
+                        pgid: resource_manager::execution_group(pid),
+                    });
+                    let scheduler = scheduler.clone();
+                    tokio::spawn(async move {
+                        while is_orphan(pid, execution.started_at) {
+                            tokio::time::sleep(std::time::Duration::from_secs(ORPHAN_POLL_SECS)).await;
+                        }
+                        let mut sched = scheduler.write().unwrap();
+                        sched.record_unfinished(&execution, "unknown",
+                            "Adopted after a daemon restart; its exit status is unknown".to_string());
+                        sched.finish_job(&execution.execution_id, "unknown");
+                    });
+                }
+                (Some(pid), OrphanPolicy::Kill) => {
+                    log::warn!(job_id = job_id, execution_id = execution_id;
+                        "Killing process {} of job {}, left running by the previous daemon", pid, job_id);
+                    let scheduler = scheduler.clone();
+                    tokio::spawn(async move {
+                        terminate_orphan(pid, execution.started_at).await;
+                        scheduler.read().unwrap().record_unfinished(&execution, "interrupted",
+                            format!("Process {} was left running by a daemon restart and killed", pid));
+                    });
+                }
+                (None, _) => {
+                    log::warn!(job_id = job_id, execution_id = execution_id;
+                        "Execution of job {} was interrupted by a daemon restart", job_id);
+                    sched.record_unfinished(&execution, "interrupted",
+                        "The daemon stopped during this execution; its process was gone on restart".to_string());
+                }
+            }
+        }
+    }
+
+    /// Record the executions another daemon left unfinished as interrupted, on taking over as
+    /// leader from it (`ha`). Its processes ran on its host, out of this daemon's reach.
+    pub fn abandon_executions(&self, previous_leader: &str) {
+        let unfinished = match self.db {
+            Some(ref db) => db.lock().unwrap().unfinished_executions(),
+            None => return,
+        };
+        match unfinished {
+            Ok(unfinished) => {
+                for execution in unfinished.iter().filter(|e| !self.running_jobs.contains_key(&e.execution_id)) {
+                    log::warn!(job_id = execution.job_id.as_str(), execution_id = execution.execution_id.as_str();
+                        "Execution of job {} was left unfinished by {}, the previous leader", execution.job_id, previous_leader);
+                    self.record_unfinished(execution, "interrupted",
+                        format!("{}, the leader running it, stopped leading during this execution", previous_leader));
+                }
+            }
+            Err(e) => log::error!("Failed to load unfinished executions: {}", e),
+        }
+    }
+
+    // History for an execution the previous daemon didn't see finish. Its exit code and output
+    // are unknown, but its execution log may hold what it wrote.
+    fn record_unfinished(&self, execution: &UnfinishedExecution, status: &str, message: String) {
+        let log_dir = std::path::Path::new(&self.paths.execution_log_dir);
+        let log_path = output::execution_log_path(log_dir, &execution.job_id, &execution.execution_id);
+        let gz_path = std::path::PathBuf::from(format!("{}.gz", log_path.display()));
+        let log_path = [log_path, gz_path].into_iter().find(|path| path.exists());
+
+        let record = ExecutionRecord {
+            job_id: execution.job_id.clone(),
+            execution_id: execution.execution_id.clone(),
+            status: status.to_string(),
+            message: Some(message),
+            exit_code: None,
+            signal: None,
+            attempt: None,
+            duration_ms: Some((Utc::now() - execution.started_at).num_milliseconds()),
+            scheduled_at: execution.scheduled_time,
+            started_at: execution.started_at,
+            stdout: None,
+            stderr: None,
+            stdout_bytes: None,
+            stderr_bytes: None,
+            log_path: log_path.map(|path| path.display().to_string()),
+            resource_events: None,
+            hook_result: None,
+            hook_output: None,
+        };
+        self.history.write(PendingWrite::Execution(record));
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.lock().unwrap().finish_execution(&execution.execution_id) {
+                log::warn!("Failed to record end of execution {}: {}", execution.execution_id, e);
+            }
+        }
+    }
+}
+
+// How often an adopted process is checked for having exited
+const ORPHAN_POLL_SECS: u64 = 5;
+
+// A process counts as the one an execution started if it began within this long of the
+// recorded start; otherwise its PID has been reused
+const ORPHAN_START_SLACK_SECS: i64 = 60;
+
+/// Whether `pid` is still the process of an execution recorded as started at `started_at`
+fn is_orphan(pid: u32, started_at: DateTime<Utc>) -> bool {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_process_specifics(pid, ProcessRefreshKind::new());
+    match system.process(pid) {
+        Some(process) => {
+            let offset = process.start_time() as i64 - started_at.timestamp();
+            (-ORPHAN_START_SLACK_SECS..=ORPHAN_START_SLACK_SECS).contains(&offset)
+        }
+        None => false,
+    }
+}
+
+// SIGTERM, then SIGKILL if it is still there after a grace period, as for timeouts. The
+// whole process group goes if the process leads one.
+async fn terminate_orphan(pid: u32, started_at: DateTime<Utc>) {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    if let Some(pgid) = resource_manager::execution_group(pid) {
+        resource_manager::signal_group(pgid, Signal::SIGTERM);
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        if resource_manager::group_alive(pgid) {
+            resource_manager::signal_group(pgid, Signal::SIGKILL);
+        }
+        return;
+    }
+    let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    if is_orphan(pid, started_at) {
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_jitter_stays_in_spread() {
+        for _ in 0..1000 {
+            let delay = apply_backoff_jitter(100, Some(20), 1000);
+            assert!((80..=120).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn backoff_jitter_respects_max_delay() {
+        for _ in 0..1000 {
+            assert!(apply_backoff_jitter(100, Some(50), 110) <= 110);
+        }
+        // Over 100% spreads as 100%, never below zero
+        for _ in 0..1000 {
+            assert!(apply_backoff_jitter(10, Some(500), 1000) <= 20);
+        }
+    }
+
+    #[test]
+    fn backoff_jitter_off() {
+        assert_eq!(apply_backoff_jitter(100, None, 1000), 100);
+        assert_eq!(apply_backoff_jitter(100, Some(0), 1000), 100);
+        assert_eq!(apply_backoff_jitter(0, Some(50), 1000), 0);
+    }
+
+    // A job with its ID set to its name, owned by `owner`
+    fn job(name: &str, owner: &str, on_success: &[&str], on_failure: &[&str]) -> Job {
+        let definition: common::JobDefinition = serde_json::from_value(serde_json::json!({
+            "name": name, "schedule": "every 1h", "command": "true",
+            "on_success_trigger": on_success, "on_failure_trigger": on_failure,
+        })).unwrap();
+        let mut job = definition.into_job("test").unwrap();
+        job.id = JobId(name.to_string());
+        job.owner = owner.to_string();
+        job
+    }
+
+    fn jobs(list: Vec<Job>) -> HashMap<String, Job> {
+        list.into_iter().map(|job| (job.id.0.clone(), job)).collect()
+    }
+
+    #[test]
+    fn triggers_without_loops() {
+        let existing = jobs(vec![job("b", "alice", &["c"], &[]), job("c", "alice", &[], &[])]);
+        assert!(check_triggers_among(&existing, &job("a", "alice", &["b"], &["c"])).is_ok());
+        // Unknown targets are not followed
+        assert!(check_triggers_among(&existing, &job("a", "alice", &["missing"], &[])).is_ok());
+    }
+
+    #[test]
+    fn trigger_loops_are_refused() {
+        let existing = jobs(vec![job("b", "alice", &[], &["c"]), job("c", "alice", &["a"], &[])]);
+        let error = check_triggers_among(&existing, &job("a", "alice", &["b"], &[])).unwrap_err();
+        assert_eq!(error, "Trigger loop: a -> b -> c -> a");
+        let error = check_triggers_among(&existing, &job("a", "alice", &["a"], &[])).unwrap_err();
+        assert_eq!(error, "Trigger loop: a -> a");
+    }
+
+    #[test]
+    fn trigger_loop_through_replaced_job() {
+        // The job being updated replaces its stored version in the walk
+        let existing = jobs(vec![job("a", "alice", &[], &[]), job("b", "alice", &["a"], &[])]);
+        assert!(check_triggers_among(&existing, &job("a", "alice", &["b"], &[])).is_err());
+        assert!(check_triggers_among(&existing, &job("b", "alice", &[], &[])).is_ok());
+    }
+
+    #[test]
+    fn triggers_of_other_owners_jobs() {
+        let existing = jobs(vec![job("b", "bob", &[], &[])]);
+        let error = check_triggers_among(&existing, &job("a", "alice", &["b"], &[])).unwrap_err();
+        assert!(error.starts_with("Permission denied"), "{}", error);
+        assert!(check_triggers_among(&existing, &job("a", "root", &["b"], &[])).is_ok());
+    }
+
+    #[test]
+    fn tag_defaults_apply_to_the_effective_job_only() {
+        let mut sched = Scheduler::new(None);
+        let mut tagged = job("a", "alice", &[], &[]);
+        tagged.tags.push("production".to_string());
+        sched.add_job(tagged.clone());
+        let defaults = |timeout: u64| -> HashMap<String, TagDefaults> {
+            let defaults = serde_json::from_value(serde_json::json!({
+                "retry_policy": { "max_attempts": 3, "backoff_strategy": "Fixed", "initial_delay_seconds": 10, "max_delay_seconds": 10 },
+                "resource_limits": { "timeout_seconds": timeout },
+            })).unwrap();
+            HashMap::from([("production".to_string(), defaults)])
+        };
+
+        sched.tag_defaults = defaults(3600);
+        let effective = sched.effective_job(&sched.jobs["a"]);
+        assert_eq!(effective.resource_limits.timeout_seconds, Some(3600));
+        assert_eq!(effective.retry_policy.retries(), 3);
+        assert_eq!(sched.jobs["a"].resource_limits.timeout_seconds, None);
+
+        // Changed and removed defaults reach the job
+        sched.tag_defaults = defaults(1800);
+        assert_eq!(sched.effective_job(&sched.jobs["a"]).resource_limits.timeout_seconds, Some(1800));
+        sched.tag_defaults.clear();
+        assert_eq!(sched.effective_job(&sched.jobs["a"]).resource_limits.timeout_seconds, None);
+
+        // No retries set explicitly opts out
+        sched.tag_defaults = defaults(3600);
+        tagged.retry_policy.max_attempts = Some(0);
+        sched.add_job(tagged);
+        assert_eq!(sched.effective_job(&sched.jobs["a"]).retry_policy.retries(), 0);
+    }
 }
@@ -0,0 +1,4 @@
+/// Where a container job finds its script, mounted read-only
+pub const CONTAINER_PATH: &str = "/run/lunasched-script";
+
+pub use common::script::ScriptFile;
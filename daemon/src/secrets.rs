@@ -0,0 +1,129 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use crate::db::Db;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+/// Env values of this form are replaced by the named secret when the job is spawned
+pub const SECRET_PREFIX: &str = "secret://";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Encrypts secret values for the `secrets` table (ChaCha20-Poly1305, key in a root-only file).
+/// Secrets are per owner: each value is bound to its owner and name, so rows can't be swapped.
+pub struct SecretStore {
+    cipher: ChaCha20Poly1305,
+    key: Vec<u8>,
+}
+
+impl SecretStore {
+    /// Load the key, generating it (mode 0600) if the file doesn't exist yet
+    pub fn open(key_path: &str) -> anyhow::Result<Self> {
+        let path = Path::new(key_path);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+            file.write_all(&key)?;
+            log::info!("Generated secrets key at {}", key_path);
+        }
+
+        let mode = std::fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            log::warn!("Secrets key {} is accessible to other users (mode {:o}); it should be 0600", key_path, mode & 0o777);
+        }
+        let key = std::fs::read(path)?;
+        if key.len() != 32 {
+            return Err(anyhow::anyhow!("Secrets key {} must be 32 bytes, found {}", key_path, key.len()));
+        }
+        Ok(Self { cipher: ChaCha20Poly1305::new(Key::from_slice(&key)), key })
+    }
+
+    pub fn set(&self, db: &Db, owner: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        validate_name(name)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(owner, name);
+        let ciphertext = self.cipher.encrypt(&nonce, Payload { msg: value.as_bytes(), aad: aad.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt secret {}", name))?;
+        db.set_secret(owner, name, &nonce, &ciphertext)?;
+        Ok(())
+    }
+
+    pub fn get(&self, db: &Db, owner: &str, name: &str) -> anyhow::Result<Option<String>> {
+        let (nonce, ciphertext) = match db.get_secret(owner, name)? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        if nonce.len() != 12 {
+            return Err(anyhow::anyhow!("Secret {} is corrupt", name));
+        }
+        let aad = associated_data(owner, name);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Secret {} cannot be decrypted (was the key file replaced?)", name))?;
+        Ok(Some(String::from_utf8(plaintext)?))
+    }
+
+    /// The token that lets `POST /ping/<id>` ping heartbeat job `job_id`: an HMAC of the job's
+    /// ID under the secrets key, so it is stored nowhere and pings that one job only
+    pub fn ping_token(&self, job_id: &str) -> String {
+        let mut mac = <HmacSha256 as hmac::KeyInit>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        mac.update(format!("lunasched-ping:{}", job_id).as_bytes());
+        mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn check_ping_token(&self, job_id: &str, token: &str) -> bool {
+        crate::transport::token_matches(&self.ping_token(job_id), token)
+    }
+}
+
+fn associated_data(owner: &str, name: &str) -> String {
+    format!("lunasched-secret:{}:{}", owner, name)
+}
+
+pub fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid secret name '{}': use letters, digits, '_', '-' and '.'", name));
+    }
+    Ok(())
+}
+
+/// Replace `secret://NAME` env values with the owner's secrets. Also returns the values
+/// substituted, so they can be redacted from output whatever their env var is called.
+pub fn resolve_env(
+    env: &HashMap<String, String>,
+    owner: &str,
+    db: Option<&std::sync::Mutex<Db>>,
+    store: Option<&SecretStore>,
+) -> std::io::Result<(HashMap<String, String>, Vec<String>)> {
+    let mut resolved = env.clone();
+    let mut values = Vec::new();
+    for (key, value) in env {
+        let name = match value.strip_prefix(SECRET_PREFIX) {
+            Some(name) => name,
+            None => continue,
+        };
+        let fail = |reason: String| std::io::Error::new(std::io::ErrorKind::NotFound,
+            format!("env {} references secret {}: {}", key, name, reason));
+        let (db, store) = match (db, store) {
+            (Some(db), Some(store)) => (db, store),
+            (None, _) => return Err(fail("no database attached".to_string())),
+            (_, None) => return Err(fail("secrets are unavailable (no key)".to_string())),
+        };
+        let secret = store.get(&db.lock().unwrap(), owner, name)
+            .map_err(|e| fail(e.to_string()))?
+            .ok_or_else(|| fail(format!("not set for {}", owner)))?;
+        if !secret.is_empty() {
+            values.push(secret.clone());
+        }
+        resolved.insert(key.clone(), secret);
+    }
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    Ok((resolved, values))
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use common::Job;
+use tokio::sync::mpsc;
+use crate::db::{self, Db, ExecutionRecord};
+use crate::history_buffer::PendingWrite;
+use crate::profiler::SchedulerProfiler;
+
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// Where jobs and their history are kept: the job store proper, as tools outside the daemon
+/// see it. The daemon's own SQLite database is one (`SqliteStorage`); with the `postgres`
+/// feature a Postgres database can be another, which the daemon keeps up to date as a replica.
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Save a job, replacing the one with its ID
+    fn add_job(&self, job: Job) -> StorageFuture<'_, ()>;
+    fn remove_job(&self, job_id: String) -> StorageFuture<'_, ()>;
+    fn load_jobs(&self) -> StorageFuture<'_, HashMap<String, Job>>;
+    /// Record an event of a job that isn't an execution, e.g. a missed heartbeat
+    fn log_history(&self, job_id: String, status: String, message: String) -> StorageFuture<'_, ()>;
+    /// Record a finished execution, which counts in the job's metrics
+    fn log_execution(&self, record: ExecutionRecord) -> StorageFuture<'_, ()>;
+    /// Remove history recorded before `before`; returns how many entries went
+    fn prune_history(&self, before: DateTime<Utc>) -> StorageFuture<'_, usize>;
+}
+
+/// The daemon's SQLite database. Calls run on the blocking thread pool, as `db::query`s.
+pub struct SqliteStorage {
+    db: Arc<Mutex<Db>>,
+}
+
+impl SqliteStorage {
+    pub fn new(db: Arc<Mutex<Db>>) -> Self {
+        Self { db }
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn add_job(&self, job: Job) -> StorageFuture<'_, ()> {
+        Box::pin(async move { Ok(db::query(&self.db, move |db| db.add_job(&job)).await?) })
+    }
+
+    fn remove_job(&self, job_id: String) -> StorageFuture<'_, ()> {
+        Box::pin(async move { Ok(db::query(&self.db, move |db| db.remove_job(&job_id)).await?) })
+    }
+
+    fn load_jobs(&self) -> StorageFuture<'_, HashMap<String, Job>> {
+        Box::pin(async move { Ok(db::query(&self.db, |db| db.load_jobs()).await?) })
+    }
+
+    fn log_history(&self, job_id: String, status: String, message: String) -> StorageFuture<'_, ()> {
+        Box::pin(async move { Ok(db::query(&self.db, move |db| db.log_history(&job_id, &status, &message)).await?) })
+    }
+
+    fn log_execution(&self, record: ExecutionRecord) -> StorageFuture<'_, ()> {
+        Box::pin(async move { Ok(db::query(&self.db, move |db| db.log_execution(&record)).await?) })
+    }
+
+    fn prune_history(&self, before: DateTime<Utc>) -> StorageFuture<'_, usize> {
+        Box::pin(async move { Ok(db::query(&self.db, move |db| db.prune_history(&before)).await?) })
+    }
+}
+
+/// A change to the job store, for a replica to apply
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum Change {
+    Sync(Vec<Job>), // All of the daemon's jobs; the replica's others are removed
+    JobSaved(Job),
+    JobRemoved(String),
+    History(PendingWrite),
+}
+
+/// Keeps another store (Postgres) up to date with the daemon's. Changes are queued as they
+/// happen and applied in order by a task of their own, so neither the scheduler nor history
+/// writes wait on the replica. A change that fails is logged and not retried; the next sync
+/// (at the next daemon start) brings the replica's jobs back in line.
+#[derive(Debug, Clone)]
+pub struct Replica {
+    changes: mpsc::UnboundedSender<Change>,
+}
+
+impl Replica {
+    /// Start replicating to `store`. Its jobs are left as they are until the first `sync`.
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub fn start(store: Arc<dyn Storage>) -> Self {
+        let (changes, mut pending) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(change) = pending.recv().await {
+                let (what, result) = match change {
+                    Change::Sync(jobs) => {
+                        sync_jobs(store.as_ref(), jobs).await;
+                        continue;
+                    }
+                    Change::JobSaved(job) => (format!("job {}", job.id), store.add_job(job).await),
+                    Change::JobRemoved(job_id) => (format!("removal of job {}", job_id), store.remove_job(job_id).await),
+                    Change::History(PendingWrite::Execution(record)) => {
+                        (format!("execution {}", record.execution_id), store.log_execution(record).await)
+                    }
+                    Change::History(PendingWrite::Event { job_id, status, message }) => {
+                        (format!("{} event of job {}", status, job_id), store.log_history(job_id, status, message).await)
+                    }
+                    // Retry attempts and timelines stay in the daemon's database
+                    Change::History(_) => continue,
+                };
+                if let Err(e) = result {
+                    log::warn!("Failed to replicate {} to {}: {}", what, store.name(), e);
+                }
+            }
+        });
+        Self { changes }
+    }
+
+    /// Replace the replica's jobs with `jobs`, at startup and after a restore
+    pub fn sync(&self, jobs: Vec<Job>) {
+        let _ = self.changes.send(Change::Sync(jobs));
+    }
+
+    pub fn job_saved(&self, job: &Job) {
+        let _ = self.changes.send(Change::JobSaved(job.clone()));
+    }
+
+    pub fn job_removed(&self, job_id: &str) {
+        let _ = self.changes.send(Change::JobRemoved(job_id.to_string()));
+    }
+
+    pub fn recorded(&self, write: &PendingWrite) {
+        if matches!(write, PendingWrite::Execution(_) | PendingWrite::Event { .. }) {
+            let _ = self.changes.send(Change::History(write.clone()));
+        }
+    }
+}
+
+async fn sync_jobs(store: &dyn Storage, jobs: Vec<Job>) {
+    let stale = match store.load_jobs().await {
+        Ok(stored) => stored.into_keys().filter(|id| !jobs.iter().any(|job| job.id.0 == *id)).collect(),
+        Err(e) => {
+            log::warn!("Failed to read the jobs of {}: {}", store.name(), e);
+            Vec::new()
+        }
+    };
+    for job_id in stale {
+        if let Err(e) = store.remove_job(job_id.clone()).await {
+            log::warn!("Failed to remove job {} from {}: {}", job_id, store.name(), e);
+        }
+    }
+    let count = jobs.len();
+    for job in jobs {
+        let id = job.id.clone();
+        if let Err(e) = store.add_job(job).await {
+            log::warn!("Failed to replicate job {} to {}: {}", id, store.name(), e);
+        }
+    }
+    log::info!("Replicated {} jobs to {}", count, store.name());
+}
+
+/// How long records are kept, in days; None keeps them all
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub history_days: Option<u32>, // In each store
+    pub audit_days: Option<u32>, // In the daemon's database
+    pub snapshot_days: Option<u32>, // In the daemon's database
+}
+
+/// Remove records past their retention period, once a day: history from each store, the
+/// audit log and execution snapshots from the daemon's database `db`, and the profiler's
+/// statistics of jobs no longer evaluated
+pub fn spawn_retention(stores: Vec<Arc<dyn Storage>>, db: Option<Arc<Mutex<Db>>>, profiler: Arc<SchedulerProfiler>, retention: Retention) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+        loop {
+            interval.tick().await;
+            let pruned = profiler.prune_jobs(crate::profiler::JOB_STATS_RETENTION);
+            if pruned > 0 {
+                log::debug!("Dropped the profiling statistics of {} jobs no longer evaluated", pruned);
+            }
+            if let Some(days) = retention.history_days {
+                let before = Utc::now() - chrono::Duration::days(days as i64);
+                for store in &stores {
+                    match store.prune_history(before).await {
+                        Ok(0) => {}
+                        Ok(pruned) => log::info!("Removed {} history entries older than {} days from {}", pruned, days, store.name()),
+                        Err(e) => log::error!("Failed to prune history in {}: {}", store.name(), e),
+                    }
+                }
+            }
+            if let (Some(days), Some(ref db)) = (retention.audit_days, &db) {
+                let before = Utc::now() - chrono::Duration::days(days as i64);
+                match db::query(db, move |db| db.prune_audit_log(&before)).await {
+                    Ok(0) => {}
+                    Ok(pruned) => log::info!("Removed {} audit log entries older than {} days", pruned, days),
+                    Err(e) => log::error!("Failed to prune the audit log: {}", e),
+                }
+            }
+            if let (Some(days), Some(ref db)) = (retention.snapshot_days, &db) {
+                let before = Utc::now() - chrono::Duration::days(days as i64);
+                match db::query(db, move |db| db.prune_snapshots(&before)).await {
+                    Ok(0) => {}
+                    Ok(pruned) => log::info!("Removed {} execution snapshots older than {} days", pruned, days),
+                    Err(e) => log::error!("Failed to prune execution snapshots: {}", e),
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use common::{Executor, Job};
+use nix::unistd::Uid;
+use crate::account::Account;
+use crate::sandbox;
+
+// Seconds systemd gives a unit between SIGTERM and SIGKILL when it is stopped, as for local
+// jobs past their timeout
+const STOP_GRACE_SECS: u64 = 2;
+
+/// Name of an execution's transient unit, for `systemctl` and `journalctl -u`
+pub fn unit(execution_id: &str) -> String {
+    format!("lunasched-{}.service", execution_id)
+}
+
+/// `systemd-run` for an execution of a Systemd job: `command` through /bin/sh in a transient
+/// service, waited for, with its stdout and stderr piped back to the daemon. The job's limits
+/// and sandbox become unit properties (systemd stops the unit at its timeout), and
+/// ProtectSystem= and its own properties are added. `env` is passed by name only, so values stay off the command line.
+/// The system daemon runs the unit as the job's user; a user daemon runs it in its own manager.
+pub fn command(job: &Job, account: &Account, execution_id: &str, command: &str,
+               env: &HashMap<String, String>) -> std::io::Result<tokio::process::Command> {
+    let (protect_system, properties) = match job.executor {
+        Executor::Systemd { ref protect_system, ref properties } => (protect_system, properties),
+        _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("job {} does not run as a systemd unit", job.name))),
+    };
+
+    let mut cmd = tokio::process::Command::new("systemd-run");
+    cmd.args(["--unit", &unit(execution_id), "--description", &format!("lunasched job {}", job.name)]);
+    cmd.args(["--pipe", "--wait", "--collect", "--quiet"]);
+    if Uid::effective().is_root() {
+        let (uid, gid) = account.ids();
+        cmd.arg(format!("--uid={}", uid)).arg(format!("--gid={}", gid));
+    } else {
+        cmd.arg("--user");
+    }
+
+    let mut unit_properties = vec![
+        format!("WorkingDirectory={}", job.working_dir.as_deref().unwrap_or(std::path::Path::new("/tmp")).display()),
+        format!("TimeoutStopSec={}", STOP_GRACE_SECS),
+    ];
+    if let Some(umask) = job.umask {
+        unit_properties.push(format!("UMask={:04o}", umask));
+    }
+    let limits = &job.resource_limits;
+    if let Some(timeout) = limits.timeout_seconds {
+        unit_properties.push(format!("RuntimeMaxSec={}", timeout));
+    }
+    if let Some(memory_mb) = limits.max_memory_mb {
+        // No swap beyond the limit, as with the cgroup of a local job
+        unit_properties.push(format!("MemoryMax={}M", memory_mb));
+        unit_properties.push("MemorySwapMax=0".to_string());
+    }
+    if let Some(quota) = limits.cpu_quota {
+        unit_properties.push(format!("CPUQuota={}%", (quota * 100.0).round() as u32));
+    }
+    if let Some(mode) = protect_system {
+        unit_properties.push(format!("ProtectSystem={}", mode));
+    }
+    unit_properties.extend(sandbox::unit_properties(&job.sandbox));
+    unit_properties.extend(properties.iter().cloned());
+    for property in unit_properties {
+        cmd.arg(format!("--property={}", property));
+    }
+
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    for name in names {
+        cmd.arg(format!("--setenv={}", name));
+    }
+    cmd.envs(env);
+    cmd.args(["/bin/sh", "-c", command]);
+
+    cmd.current_dir("/");
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    // A process group of its own, as for local jobs, so `lunasched kill` finds it
+    unsafe {
+        cmd.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(std::io::Error::from));
+    }
+    Ok(cmd)
+}
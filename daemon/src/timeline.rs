@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, SecondsFormat, Utc};
+use common::TimelineEvent;
+
+/// Lifecycle events of one execution (scheduled, spawned, first output, killed, ...),
+/// collected while it runs and stored with its history record
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    events: Arc<Mutex<Vec<TimelineEvent>>>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, event: &str, detail: Option<String>) {
+        self.record_at(event, Utc::now(), detail);
+    }
+
+    pub fn record_at(&self, event: &str, at: DateTime<Utc>, detail: Option<String>) {
+        self.events.lock().unwrap().push(TimelineEvent {
+            event: event.to_string(),
+            at: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            detail,
+        });
+    }
+
+    /// Events in time order
+    pub fn events(&self) -> Vec<TimelineEvent> {
+        let mut events = self.events.lock().unwrap().clone();
+        events.sort_by(|a, b| a.at.cmp(&b.at));
+        events
+    }
+}
@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use nix::unistd::User;
+use tokio::net::{TcpListener, UnixListener};
+
+/// A bidirectional byte stream carrying JSON requests and responses
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Who is on the other end of an accepted connection
+#[derive(Debug, Clone)]
+pub struct Peer {
+    /// Effective uid used for ownership checks
+    pub uid: u32,
+    /// Human readable address for logs
+    pub addr: String,
+    /// Token the client must present with `Request::Authenticate` before anything else
+    pub required_token: Option<String>,
+    /// Address of a TCP client, which its connections count against until it authenticates
+    pub remote: Option<IpAddr>,
+}
+
+type AcceptFuture<'a> = Pin<Box<dyn Future<Output = std::io::Result<(Box<dyn Connection>, Peer)>> + Send + 'a>>;
+
+/// A listener the daemon accepts IPC connections on
+pub trait Transport: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn accept(&self) -> AcceptFuture<'_>;
+}
+
+/// Local clients on the Unix socket, identified by their peer credentials
+pub struct UnixTransport {
+    listener: UnixListener,
+}
+
+impl UnixTransport {
+    pub fn new(listener: UnixListener) -> Self {
+        Self { listener }
+    }
+}
+
+impl Transport for UnixTransport {
+    fn name(&self) -> &'static str {
+        "unix"
+    }
+
+    fn accept(&self) -> AcceptFuture<'_> {
+        Box::pin(async move {
+            let (socket, addr) = self.listener.accept().await?;
+            let uid = socket.peer_cred()?.uid();
+            let peer = Peer { uid, addr: format!("{:?}", addr), required_token: None, remote: None };
+            Ok((Box::new(socket) as Box<dyn Connection>, peer))
+        })
+    }
+}
+
+/// Clients over TCP on a loopback address (remote ones through a tunnel). They must
+/// authenticate with the shared secret and then act as the configured `user`, never root.
+pub struct TcpTransport {
+    listener: TcpListener,
+    token: String,
+    uid: u32,
+}
+
+impl TcpTransport {
+    pub async fn bind(addr: &str, token: String, user: Option<&str>) -> anyhow::Result<Self> {
+        if token.is_empty() {
+            return Err(anyhow::anyhow!("server.auth_token must be set to enable the TCP listener"));
+        }
+        let user = user.ok_or_else(|| anyhow::anyhow!("server.tcp_user must be set to enable the TCP listener"))?;
+        let uid = match User::from_name(user)? {
+            Some(account) => account.uid.as_raw(),
+            None => return Err(anyhow::anyhow!("server.tcp_user {} is not a user on this host", user)),
+        };
+        if uid == 0 {
+            return Err(anyhow::anyhow!("server.tcp_user can't be root: whoever holds the token would be"));
+        }
+        let listener = TcpListener::bind(addr).await?;
+        // The token travels in plain text, so it must not leave the host
+        if !listener.local_addr()?.ip().is_loopback() {
+            return Err(anyhow::anyhow!("server.tcp_bind {} is not a loopback address; reach the daemon \
+                through an SSH or WireGuard tunnel instead", addr));
+        }
+        Ok(Self { listener, token, uid })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn name(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn accept(&self) -> AcceptFuture<'_> {
+        Box::pin(async move {
+            let (socket, addr) = self.listener.accept().await?;
+            socket.set_nodelay(true)?;
+            let peer = Peer { uid: self.uid, addr: addr.to_string(), required_token: Some(self.token.clone()), remote: Some(addr.ip()) };
+            Ok((Box::new(socket) as Box<dyn Connection>, peer))
+        })
+    }
+}
+
+/// Compare a presented token to the expected one without short-circuiting on the first mismatch
+pub fn token_matches(expected: &str, presented: &str) -> bool {
+    let (a, b) = (expected.as_bytes(), presented.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}